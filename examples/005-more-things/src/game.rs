@@ -1,10 +1,13 @@
 use vulkust::cgmath;
+use vulkust::cgmath::prelude::*;
 use vulkust::core::application::Application as CoreAppTrait;
-use vulkust::core::event::{
-    Button, ButtonAction, Event, Keyboard, Mouse, Move, Touch, TouchGesture, Type as EventType,
-};
-use vulkust::core::gesture;
+use vulkust::core::event::Event;
+use vulkust::core::input::{Bindings, Manager as InputManager};
 use vulkust::core::types::Real;
+use vulkust::physics::body::{BodyType, RigidBody};
+use vulkust::physics::character::Controller as CharacterController;
+use vulkust::physics::collider::Cuboid;
+use vulkust::physics::world::World;
 use vulkust::render::camera::{Camera, Orthographic, Perspective};
 use vulkust::render::engine::Engine as Renderer;
 use vulkust::render::light::Sun;
@@ -26,17 +29,30 @@ pub struct MyGame {
     scene: Option<Arc<RwLock<GameScene>>>,
     ui_scene: Option<Arc<RwLock<UiScene>>>,
     camera: Option<Arc<RwLock<Camera>>>,
-    keys_state: Arc<RwLock<KeysState>>,
+    input: Arc<RwLock<InputManager>>,
+    world: World,
+    character: CharacterController,
 }
 
-#[cfg_attr(debug_assertions, derive(Debug))]
-struct KeysState {
-    pub w: bool,
-    pub s: bool,
-    pub a: bool,
-    pub d: bool,
-    pub lm: bool,
-}
+// Players can rebind the controls by writing their own bindings in this file
+const BINDINGS_FILE: &str = "bindings.txt";
+
+const DEFAULT_BINDINGS: &str = "
+action jump key Space(0)
+action jump gamepad South
+action look mouse Left
+action look touch drag
+axis forward 1 key W
+axis forward -1 key S
+axis right 1 key D
+axis right -1 key A
+axis forward -1 gamepad-axis left-y
+axis right 1 gamepad-axis left-x
+axis look-x 1.5 mouse-move x
+axis look-x 1.5 touch-drag x
+axis look-y 1.5 mouse-move y
+axis look-y 1.5 touch-drag y
+";
 
 impl MyGame {
     pub fn new() -> Self {
@@ -46,13 +62,9 @@ impl MyGame {
             scene: None,
             ui_scene: None,
             camera: None,
-            keys_state: Arc::new(RwLock::new(KeysState {
-                w: false,
-                a: false,
-                s: false,
-                d: false,
-                lm: false,
-            })),
+            input: Arc::new(RwLock::new(InputManager::new(load_bindings()))),
+            world: World::new(),
+            character: CharacterController::new(0.3, 1.6),
         }
     }
 }
@@ -76,15 +88,24 @@ impl CoreAppTrait for MyGame {
             vxresult!(asset_manager.get_camera_manager().write()).create();
         {
             let mut camera = vxresult!(camera.write());
-            camera.set_location(&cgmath::Vector3::new(0.0, 0.0, 4.0));
+            // z is up, so it looks at horizon
+            camera.rotate_local_x(std::f32::consts::FRAC_PI_2);
         }
+        self.world.gravity = cgmath::Vector3::new(0.0, 0.0, -9.81);
+        self.character.set_up(&cgmath::Vector3::new(0.0, 0.0, 1.0));
+        self.character
+            .set_position(&cgmath::Vector3::new(0.0, 0.0, GROUND_TOP + 0.8));
+        self.character.link(
+            Arc::downgrade(&camera),
+            &cgmath::Vector3::new(0.0, 0.0, 0.7),
+        );
         self.camera = Some(camera.clone());
 
         let sun = vxresult!(asset_manager.get_light_manager().write()).create::<Sun>();
         {
             let mut scn = vxresult!(scene.write());
             scn.add_camera(camera);
-            place_cubes(&mut *scn, &*renderer);
+            place_cubes(&mut *scn, &mut self.world, &*renderer);
             scn.add_light(sun);
         }
         self.scene = Some(scene);
@@ -115,108 +136,70 @@ impl CoreAppTrait for MyGame {
     }
 
     fn on_event(&self, e: Event) {
-        match e.event_type {
-            EventType::Move(m) => match m {
-                Move::Mouse {
-                    previous: _,
-                    current: _,
-                    delta,
-                } => {
-                    if vxresult!(self.keys_state.read()).lm {
-                        let mut camera = vxresult!(vxunwrap!(&self.camera).write());
-                        camera.rotate_local_x(delta.1 * 1.5);
-                        camera.rotate_global_z(delta.0 * 1.5);
-                    }
-                }
-                _ => (),
-            },
-            EventType::Button { button, action } => match action {
-                ButtonAction::Press => match button {
-                    Button::Keyboard(k) => match k {
-                        Keyboard::W => vxresult!(self.keys_state.write()).w = true,
-                        Keyboard::A => vxresult!(self.keys_state.write()).a = true,
-                        Keyboard::S => vxresult!(self.keys_state.write()).s = true,
-                        Keyboard::D => vxresult!(self.keys_state.write()).d = true,
-                        _ => (),
-                    },
-                    Button::Mouse(m) => match m {
-                        Mouse::Left => vxresult!(self.keys_state.write()).lm = true,
-                        _ => (),
-                    },
-                },
-                ButtonAction::Release => match button {
-                    Button::Keyboard(k) => match k {
-                        Keyboard::W => vxresult!(self.keys_state.write()).w = false,
-                        Keyboard::A => vxresult!(self.keys_state.write()).a = false,
-                        Keyboard::S => vxresult!(self.keys_state.write()).s = false,
-                        Keyboard::D => vxresult!(self.keys_state.write()).d = false,
-                        _ => (),
-                    },
-                    Button::Mouse(m) => match m {
-                        Mouse::Left => vxresult!(self.keys_state.write()).lm = false,
-                        _ => (),
-                    },
-                },
-            },
-            EventType::Touch(t) => match t {
-                Touch::Gesture {
-                    start_time: _,
-                    duration: _,
-                    state,
-                    gest,
-                } => match state {
-                    gesture::State::InMiddle => match gest {
-                        TouchGesture::Drag {
-                            index: _,
-                            start: _,
-                            previous: _,
-                            current: _,
-                            delta,
-                        } => {
-                            let mut camera = vxresult!(vxunwrap!(&self.camera).write());
-                            camera.rotate_local_x(delta.1 * 1.5);
-                            camera.rotate_global_z(delta.0 * 1.5);
-                        }
-                        _ => (),
-                    },
-                    _ => (),
-                },
-                _ => (),
-            },
-            _ => (),
-        }
+        vxresult!(self.input.write()).on_event(&e);
     }
 
     fn update(&mut self) {
-        let keys_state = vxresult!(self.keys_state.read());
-        if keys_state.w || keys_state.a || keys_state.s || keys_state.d {
+        let (delta, alpha) = {
+            let renderer = vxresult!(vxunwrap!(&self.renderer).read());
+            let timing = vxresult!(renderer.get_timing().read());
+            let n = timing.length_of_previous_frame.as_nanos();
+            (
+                (n as f64 / 1_000_000_000.0) as f32,
+                timing.get_interpolation_alpha(),
+            )
+        };
+        let mut input = vxresult!(self.input.write());
+        let (forward, right) = {
             let mut camera = vxresult!(vxunwrap!(&self.camera).write());
-            let delta = {
-                let renderer = vxresult!(vxunwrap!(&self.renderer).read());
-                let n = vxresult!(renderer.get_timing().read())
-                    .length_of_previous_frame
-                    .as_nanos();
-                (n as f64 / 1_000_000_000.0) as f32
-            };
-            if keys_state.w {
-                camera.move_local_z(delta * -1.7);
-            }
-            if keys_state.s {
-                camera.move_local_z(delta * 1.7);
-            }
-            if keys_state.a {
-                camera.move_local_x(delta * -1.7);
-            }
-            if keys_state.d {
-                camera.move_local_x(delta * 1.7);
+            if input.is_pressed("look") {
+                camera.rotate_local_x(input.axis_value("look-y"));
+                camera.rotate_global_z(input.axis_value("look-x"));
             }
+            let uniform = camera.get_uniform();
+            (-uniform.get_z_axis(), uniform.get_x_axis())
+        };
+        let mut velocity =
+            forward * input.axis_value("forward") + right * input.axis_value("right");
+        velocity.z = 0.0;
+        if velocity.magnitude2() > 0.0 {
+            velocity = velocity.normalize() * 1.7;
+        }
+        self.character.set_walk_velocity(&velocity);
+        if input.just_pressed("jump") {
+            self.character.jump();
         }
+        input.update();
+        self.world.interpolate(alpha);
+        self.character.update(&self.world, delta);
+    }
+
+    fn fixed_update(&mut self, dt: Real) {
+        self.world.step(dt);
     }
 
     fn terminate(&mut self) {}
 }
 
-fn place_cubes(scn: &mut Scene, eng: &Renderer) {
+const GROUND_TOP: Real = -3.0;
+
+fn load_bindings() -> Bindings {
+    if std::path::Path::new(BINDINGS_FILE).exists() {
+        return Bindings::load(BINDINGS_FILE);
+    }
+    return Bindings::new_with_text(DEFAULT_BINDINGS);
+}
+
+fn add_cube_body(world: &mut World, location: cgmath::Vector3<Real>, half_size: Real) {
+    let shape = Arc::new(RwLock::new(Cuboid::new(cgmath::Vector3::new(
+        half_size, half_size, half_size,
+    ))));
+    let mut body = RigidBody::new(BodyType::Static, shape);
+    body.set_position(&location);
+    world.add_body(body);
+}
+
+fn place_cubes(scn: &mut Scene, world: &mut World, eng: &Renderer) {
     let astmgr = eng.get_asset_manager();
     const GROUND_CUBE_ASPECT: Real = 2.0;
     const GROUND_CUBE_SPACING: Real = GROUND_CUBE_ASPECT * 0.1;
@@ -255,8 +238,13 @@ fn place_cubes(scn: &mut Scene, eng: &Renderer) {
                 let mut m = vxresult!(m.write());
                 let (mesh, mat) = &ground_meshes[ground_mesh_index];
                 m.add_mesh(mesh.clone(), mat.clone());
-                m.translate(&cgmath::Vector3::new(x, y, -5.0));
+                m.translate(&cgmath::Vector3::new(x, y, GROUND_TOP - GROUND_CUBE_ASPECT));
             }
+            add_cube_body(
+                world,
+                cgmath::Vector3::new(x, y, GROUND_TOP - GROUND_CUBE_ASPECT),
+                GROUND_CUBE_ASPECT,
+            );
             scn.add_model(m);
             x += ROW_INC;
             ground_mesh_index += 1;
@@ -271,8 +259,9 @@ fn place_cubes(scn: &mut Scene, eng: &Renderer) {
     for _ in 0..50 {
         let y = rng.gen_range(RANGE, -RANGE);
         let x = rng.gen_range(RANGE, -RANGE);
-        let z = rng.gen_range(0.0, 1.0);
         let s = rng.gen_range(0.25, 0.5);
+        // they stand on ground, so character can not walk through them
+        let z = GROUND_TOP + GROUND_CUBE_ASPECT * s;
         let m: Arc<RwLock<Model>> = mdlmgr.create::<ModelBase>();
         {
             let mut m = vxresult!(m.write());
@@ -281,6 +270,7 @@ fn place_cubes(scn: &mut Scene, eng: &Renderer) {
             m.translate(&cgmath::Vector3::new(x, y, z));
             m.scale(s);
         }
+        add_cube_body(world, cgmath::Vector3::new(x, y, z), GROUND_CUBE_ASPECT * s);
         scn.add_model(m);
         ground_mesh_index += 1;
         ground_mesh_index %= cs.len();
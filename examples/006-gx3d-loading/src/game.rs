@@ -74,11 +74,7 @@ impl CoreAppTrait for MyGame {
     fn on_event(&self, e: Event) {
         match e.event_type {
             EventType::Move(m) => match m {
-                Move::Mouse {
-                    previous: _,
-                    current: _,
-                    delta,
-                } => {
+                Move::Mouse { delta, .. } => {
                     if vxresult!(self.keys_state.read()).lm {
                         let mut camera = vxresult!(vxunwrap!(&self.camera).write());
                         camera.rotate_local_x(delta.1 * 2.5);
@@ -87,7 +83,7 @@ impl CoreAppTrait for MyGame {
                 }
                 _ => (),
             },
-            EventType::Button { button, action } => match action {
+            EventType::Button { button, action, .. } => match action {
                 ButtonAction::Press => match button {
                     Button::Keyboard(k) => match k {
                         Keyboard::W => vxresult!(self.keys_state.write()).w = true,
@@ -100,6 +96,7 @@ impl CoreAppTrait for MyGame {
                         Mouse::Left => vxresult!(self.keys_state.write()).lm = true,
                         _ => (),
                     },
+                    _ => (),
                 },
                 ButtonAction::Release => match button {
                     Button::Keyboard(k) => match k {
@@ -113,6 +110,7 @@ impl CoreAppTrait for MyGame {
                         Mouse::Left => vxresult!(self.keys_state.write()).lm = false,
                         _ => (),
                     },
+                    _ => (),
                 },
             },
             EventType::Touch(t) => match t {
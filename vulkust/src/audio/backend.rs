@@ -0,0 +1,202 @@
+use super::super::core::types::Real;
+#[cfg(target_os = "linux")]
+use super::super::system::linux::alsa::Device;
+use std::fs::File;
+use std::io::{BufWriter, Seek, SeekFrom, Write};
+
+// Output device of audio, mixer output is written to it
+pub trait Backend: Send {
+    fn get_sample_rate(&self) -> u32;
+    fn get_channels(&self) -> usize;
+    // Samples are interleaved frames between -1 and 1
+    fn write(&mut self, &[Real]);
+}
+
+// Output device of the platform, Null is returned when the platform has no usable device
+pub fn new_device(sample_rate: u32, channels: usize) -> Box<Backend> {
+    #[cfg(target_os = "linux")]
+    {
+        if let Some(device) = Device::new(sample_rate, channels) {
+            return Box::new(device);
+        }
+    }
+    return Box::new(Null::new(sample_rate, channels));
+}
+
+// Discards the output, it is for the platforms that do not have audio device or do not need it
+#[cfg_attr(debug_mode, derive(Debug))]
+pub struct Null {
+    sample_rate: u32,
+    channels: usize,
+    frames_count: u64,
+}
+
+impl Null {
+    pub fn new(sample_rate: u32, channels: usize) -> Self {
+        Null {
+            sample_rate,
+            channels,
+            frames_count: 0,
+        }
+    }
+
+    // Number of frames that have been written
+    pub fn get_frames_count(&self) -> u64 {
+        return self.frames_count;
+    }
+}
+
+impl Backend for Null {
+    fn get_sample_rate(&self) -> u32 {
+        return self.sample_rate;
+    }
+
+    fn get_channels(&self) -> usize {
+        return self.channels;
+    }
+
+    fn write(&mut self, samples: &[Real]) {
+        self.frames_count += (samples.len() / self.channels) as u64;
+    }
+}
+
+const WAV_HEADER_SIZE: u32 = 44;
+
+// Writes the output in a 16 bit PCM WAV, header is completed on finish or drop
+#[cfg_attr(debug_mode, derive(Debug))]
+pub struct WavFile<W>
+where
+    W: Write + Seek + Send,
+{
+    writer: W,
+    sample_rate: u32,
+    channels: usize,
+    data_size: u32,
+    buffer: Vec<u8>,
+}
+
+impl WavFile<BufWriter<File>> {
+    pub fn create(file_name: &str, sample_rate: u32, channels: usize) -> Self {
+        let file = vxresult!(File::create(file_name));
+        return Self::new(BufWriter::new(file), sample_rate, channels);
+    }
+}
+
+impl<W> WavFile<W>
+where
+    W: Write + Seek + Send,
+{
+    pub fn new(writer: W, sample_rate: u32, channels: usize) -> Self {
+        let mut wav = WavFile {
+            writer,
+            sample_rate,
+            channels,
+            data_size: 0,
+            buffer: Vec::new(),
+        };
+        wav.write_header();
+        return wav;
+    }
+
+    fn write_header(&mut self) {
+        let block_align = self.channels as u32 * 2;
+        let mut h = Vec::with_capacity(WAV_HEADER_SIZE as usize);
+        h.extend_from_slice(b"RIFF");
+        h.extend_from_slice(&u32_bytes(WAV_HEADER_SIZE - 8 + self.data_size));
+        h.extend_from_slice(b"WAVEfmt ");
+        h.extend_from_slice(&u32_bytes(16));
+        // PCM
+        h.extend_from_slice(&u32_bytes(1 | ((self.channels as u32) << 16))[..]);
+        h.extend_from_slice(&u32_bytes(self.sample_rate));
+        h.extend_from_slice(&u32_bytes(self.sample_rate * block_align));
+        h.extend_from_slice(&u32_bytes(block_align | (16 << 16)));
+        h.extend_from_slice(b"data");
+        h.extend_from_slice(&u32_bytes(self.data_size));
+        vxresult!(self.writer.write_all(&h));
+    }
+
+    // Updates the sizes in the header, writing can be continued after it
+    pub fn finish(&mut self) {
+        let current = vxresult!(self.writer.seek(SeekFrom::Current(0)));
+        vxresult!(self.writer.seek(SeekFrom::Start(0)));
+        self.write_header();
+        vxresult!(self.writer.seek(SeekFrom::Start(current)));
+        vxresult!(self.writer.flush());
+    }
+
+    pub fn get_writer(&self) -> &W {
+        return &self.writer;
+    }
+}
+
+impl<W> Backend for WavFile<W>
+where
+    W: Write + Seek + Send,
+{
+    fn get_sample_rate(&self) -> u32 {
+        return self.sample_rate;
+    }
+
+    fn get_channels(&self) -> usize {
+        return self.channels;
+    }
+
+    fn write(&mut self, samples: &[Real]) {
+        self.buffer.clear();
+        for s in samples {
+            let v = sample_to_i16(*s) as u16;
+            self.buffer.push(v as u8);
+            self.buffer.push((v >> 8) as u8);
+        }
+        vxresult!(self.writer.write_all(&self.buffer));
+        self.data_size += self.buffer.len() as u32;
+    }
+}
+
+impl<W> Drop for WavFile<W>
+where
+    W: Write + Seek + Send,
+{
+    fn drop(&mut self) {
+        self.finish();
+    }
+}
+
+pub(crate) fn sample_to_i16(s: Real) -> i16 {
+    return (s.max(-1.0).min(1.0) * 32767.0).round() as i16;
+}
+
+fn u32_bytes(v: u32) -> [u8; 4] {
+    return [v as u8, (v >> 8) as u8, (v >> 16) as u8, (v >> 24) as u8];
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::decoder::new_decoder;
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn wav_file_test() {
+        let mut wav = WavFile::new(Cursor::new(Vec::new()), 8000, 2);
+        wav.write(&[0.0, 1.0, -1.0, 0.5]);
+        wav.write(&[2.0, -0.25]);
+        wav.finish();
+        let data = wav.get_writer().get_ref().clone();
+        assert_eq!(data.len(), WAV_HEADER_SIZE as usize + 12);
+        let mut decoder = vxunwrap!(new_decoder(data));
+        assert_eq!(decoder.get_channels(), 2);
+        assert_eq!(decoder.get_sample_rate(), 8000);
+        let mut samples = Vec::new();
+        assert_eq!(decoder.decode(2, &mut samples), 2);
+        assert_eq!(decoder.decode(10, &mut samples), 1);
+        assert_eq!(decoder.decode(10, &mut samples), 0);
+        let expected = [0.0, 1.0, -1.0, 0.5, 1.0, -0.25];
+        for (s, e) in samples.iter().zip(expected.iter()) {
+            assert!((s - e).abs() < 1e-3);
+        }
+        decoder.rewind();
+        samples.clear();
+        assert_eq!(decoder.decode(10, &mut samples), 3);
+    }
+}
@@ -0,0 +1,145 @@
+use super::super::core::types::{Id, Real};
+
+// Linear change of a gain during a duration
+#[derive(Clone, Copy)]
+#[cfg_attr(debug_mode, derive(Debug))]
+pub struct Fade {
+    from: Real,
+    to: Real,
+    // In seconds
+    duration: Real,
+    elapsed: Real,
+}
+
+impl Fade {
+    pub fn new(from: Real, to: Real, duration: Real) -> Self {
+        Fade {
+            from,
+            to,
+            duration,
+            elapsed: 0.0,
+        }
+    }
+
+    // Returns the gain after passing the delta seconds
+    pub fn update(&mut self, delta: Real) -> Real {
+        self.elapsed += delta;
+        return self.get_value();
+    }
+
+    pub fn get_value(&self) -> Real {
+        if self.is_finished() {
+            return self.to;
+        }
+        return self.from + (self.to - self.from) * (self.elapsed / self.duration);
+    }
+
+    pub fn is_finished(&self) -> bool {
+        return self.elapsed >= self.duration;
+    }
+}
+
+// Volume groups of games, each one has a bus under the master bus
+#[derive(Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(debug_mode, derive(Debug))]
+pub enum Group {
+    Music = 0,
+    Sfx = 1,
+    Voice = 2,
+    Ui = 3,
+}
+
+pub const GROUPS_COUNT: usize = 4;
+
+// A node of mixer hierarchy, its gain is multiplied by the gains of its ancestors
+#[cfg_attr(debug_mode, derive(Debug))]
+pub struct Bus {
+    parent: Option<Id>,
+    gain: Real,
+    muted: bool,
+    fade: Option<Fade>,
+}
+
+impl Bus {
+    pub(super) fn new(parent: Option<Id>) -> Self {
+        Bus {
+            parent,
+            gain: 1.0,
+            muted: false,
+            fade: None,
+        }
+    }
+
+    pub fn get_parent(&self) -> Option<Id> {
+        return self.parent;
+    }
+
+    pub(super) fn set_parent(&mut self, parent: Option<Id>) {
+        self.parent = parent;
+    }
+
+    // It cancels the fade
+    pub fn set_gain(&mut self, gain: Real) {
+        self.gain = gain;
+        self.fade = None;
+    }
+
+    pub fn get_gain(&self) -> Real {
+        return self.gain;
+    }
+
+    pub fn set_muted(&mut self, muted: bool) {
+        self.muted = muted;
+    }
+
+    pub fn is_muted(&self) -> bool {
+        return self.muted;
+    }
+
+    // Duration is in seconds
+    pub fn fade(&mut self, gain: Real, duration: Real) {
+        if duration <= 0.0 {
+            self.set_gain(gain);
+            return;
+        }
+        self.fade = Some(Fade::new(self.gain, gain, duration));
+    }
+
+    pub fn is_fading(&self) -> bool {
+        return self.fade.is_some();
+    }
+
+    pub(super) fn update(&mut self, delta: Real) {
+        let finished = match &mut self.fade {
+            Some(fade) => {
+                self.gain = fade.update(delta);
+                fade.is_finished()
+            }
+            None => return,
+        };
+        if finished {
+            self.fade = None;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn fade_test() {
+        let mut bus = Bus::new(None);
+        bus.fade(0.0, 2.0);
+        bus.update(0.5);
+        assert_eq!(bus.get_gain(), 0.75);
+        assert!(bus.is_fading());
+        bus.update(2.0);
+        assert_eq!(bus.get_gain(), 0.0);
+        assert!(!bus.is_fading());
+        bus.fade(0.5, 1.0);
+        bus.set_gain(1.0);
+        bus.update(0.5);
+        assert_eq!(bus.get_gain(), 1.0);
+    }
+}
@@ -0,0 +1,376 @@
+use super::super::core::types::Real;
+use lewton::inside_ogg::OggStreamReader;
+use std::collections::VecDeque;
+use std::io::{Cursor, Error, ErrorKind, Read, Result, Seek, SeekFrom};
+
+// Decoders produce interleaved samples between -1 and 1
+pub trait Decoder: Send {
+    fn get_channels(&self) -> usize;
+    fn get_sample_rate(&self) -> u32;
+    // Appends at most frames_count frames to samples and returns the number of appended frames,
+    // zero means the end of stream.
+    fn decode(&mut self, frames_count: usize, samples: &mut Vec<Real>) -> usize;
+    fn rewind(&mut self);
+}
+
+// Creates the decoder that matches the header of data, None is returned for a wrong data
+pub fn new_decoder(data: Vec<u8>) -> Option<Box<Decoder>> {
+    if data.len() >= 4 && &data[0..4] == b"RIFF" {
+        return Wav::new(Cursor::new(data)).map(|d| Box::new(d) as Box<Decoder>);
+    } else if data.len() >= 4 && &data[0..4] == b"OggS" {
+        return Ogg::new(Cursor::new(data)).map(|d| Box::new(d) as Box<Decoder>);
+    }
+    vxloge!("Unsupported audio format.");
+    return None;
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(debug_mode, derive(Debug))]
+enum SampleFormat {
+    Int,
+    Float,
+}
+
+#[cfg_attr(debug_mode, derive(Debug))]
+pub struct Wav<R>
+where
+    R: Read + Seek + Send,
+{
+    reader: R,
+    channels: usize,
+    sample_rate: u32,
+    format: SampleFormat,
+    bits: usize,
+    // Data chunk in bytes
+    start: u64,
+    end: u64,
+    position: u64,
+    buffer: Vec<u8>,
+}
+
+impl<R> Wav<R>
+where
+    R: Read + Seek + Send,
+{
+    // None is returned when the header is wrong or its format is not supported
+    pub fn new(reader: R) -> Option<Self> {
+        match Self::read_header(reader) {
+            Ok(wav) => return Some(wav),
+            Err(e) => {
+                vxloge!("WAV can not be decoded: {}", e);
+                return None;
+            }
+        }
+    }
+
+    fn read_header(mut reader: R) -> Result<Self> {
+        let mut header = [0u8; 12];
+        reader.read_exact(&mut header)?;
+        if &header[0..4] != b"RIFF" || &header[8..12] != b"WAVE" {
+            return Err(wrong_data("Wrong WAV header."));
+        }
+        let mut format = None;
+        let mut start = 12u64;
+        let data_size;
+        loop {
+            let mut chunk = [0u8; 8];
+            reader.read_exact(&mut chunk)?;
+            let size = read_u32(&chunk[4..8]) as u64;
+            start += 8;
+            if &chunk[0..4] == b"data" {
+                data_size = size;
+                break;
+            } else if &chunk[0..4] == b"fmt " {
+                let mut fmt = vec![0u8; size as usize];
+                reader.read_exact(&mut fmt)?;
+                format = Some(fmt);
+            } else {
+                reader.seek(SeekFrom::Current(size as i64))?;
+            }
+            // chunks are word aligned
+            if size & 1 == 1 {
+                reader.seek(SeekFrom::Current(1))?;
+            }
+            start += size + (size & 1);
+        }
+        let fmt = match format {
+            Some(fmt) => fmt,
+            None => return Err(wrong_data("WAV has no format chunk.")),
+        };
+        if fmt.len() < 16 {
+            return Err(wrong_data("Wrong WAV format chunk."));
+        }
+        let mut tag = read_u16(&fmt[0..2]);
+        // extensible format keeps the actual tag in the start of its sub-format
+        if tag == 0xFFFE && fmt.len() >= 26 {
+            tag = read_u16(&fmt[24..26]);
+        }
+        let bits = read_u16(&fmt[14..16]) as usize;
+        let format = match (tag, bits) {
+            (1, 8) | (1, 16) | (1, 24) | (1, 32) => SampleFormat::Int,
+            (3, 32) | (3, 64) => SampleFormat::Float,
+            _ => {
+                let message = format!("Unsupported WAV format {} with {} bits.", tag, bits);
+                return Err(wrong_data(&message));
+            }
+        };
+        let channels = read_u16(&fmt[2..4]) as usize;
+        let sample_rate = read_u32(&fmt[4..8]);
+        if channels == 0 || sample_rate == 0 {
+            let message = format!(
+                "Wrong WAV with {} channels and {} sample rate.",
+                channels, sample_rate
+            );
+            return Err(wrong_data(&message));
+        }
+        Ok(Wav {
+            reader,
+            channels,
+            sample_rate,
+            format,
+            bits,
+            start,
+            end: start + data_size,
+            position: start,
+            buffer: Vec::new(),
+        })
+    }
+}
+
+impl<R> Decoder for Wav<R>
+where
+    R: Read + Seek + Send,
+{
+    fn get_channels(&self) -> usize {
+        return self.channels;
+    }
+
+    fn get_sample_rate(&self) -> u32 {
+        return self.sample_rate;
+    }
+
+    fn decode(&mut self, frames_count: usize, samples: &mut Vec<Real>) -> usize {
+        let sample_size = self.bits / 8;
+        let frame_size = sample_size * self.channels;
+        let remained = ((self.end - self.position) / frame_size as u64) as usize;
+        let frames_count = frames_count.min(remained);
+        if frames_count == 0 {
+            return 0;
+        }
+        let size = (frames_count * frame_size) as u64;
+        self.buffer.clear();
+        let result = self
+            .reader
+            .by_ref()
+            .take(size)
+            .read_to_end(&mut self.buffer);
+        // a truncated data ends in its last complete frame
+        let frames_count = self.buffer.len() / frame_size;
+        self.buffer.truncate(frames_count * frame_size);
+        self.position += self.buffer.len() as u64;
+        if let Err(e) = result {
+            vxloge!("Reading of WAV data failed: {}", e);
+            self.end = self.position;
+        } else if self.buffer.len() as u64 != size {
+            vxloge!("WAV data is truncated.");
+            self.end = self.position;
+        }
+        samples.reserve(frames_count * self.channels);
+        for s in self.buffer.chunks(sample_size) {
+            let v = match (self.format, self.bits) {
+                (SampleFormat::Int, 8) => (s[0] as Real - 128.0) / 128.0,
+                (SampleFormat::Int, 16) => read_u16(s) as i16 as Real / 32768.0,
+                (SampleFormat::Int, 24) => {
+                    ((read_u32(&[0, s[0], s[1], s[2]]) as i32) >> 8) as Real / 8388608.0
+                }
+                (SampleFormat::Int, 32) => read_u32(s) as i32 as Real / 2147483648.0,
+                (SampleFormat::Float, 32) => Real::from_bits(read_u32(s)),
+                (SampleFormat::Float, 64) => {
+                    let mut v = 0u64;
+                    for i in 0..8 {
+                        v |= (s[i] as u64) << (i * 8);
+                    }
+                    f64::from_bits(v) as Real
+                }
+                _ => vxunexpected!(),
+            };
+            samples.push(v);
+        }
+        return frames_count;
+    }
+
+    fn rewind(&mut self) {
+        if let Err(e) = self.reader.seek(SeekFrom::Start(self.start)) {
+            vxloge!("Rewinding of WAV failed: {}", e);
+            self.position = self.end;
+            return;
+        }
+        self.position = self.start;
+    }
+}
+
+fn wrong_data(message: &str) -> Error {
+    return Error::new(ErrorKind::InvalidData, message);
+}
+
+fn read_u16(b: &[u8]) -> u16 {
+    return b[0] as u16 | ((b[1] as u16) << 8);
+}
+
+fn read_u32(b: &[u8]) -> u32 {
+    return b[0] as u32 | ((b[1] as u32) << 8) | ((b[2] as u32) << 16) | ((b[3] as u32) << 24);
+}
+
+pub struct Ogg<R>
+where
+    R: Read + Seek + Send,
+{
+    reader: OggStreamReader<R>,
+    // Decoded samples of last packet that are not taken yet
+    pending: VecDeque<Real>,
+    // Stream is ended by an error, rewinding retries it
+    failed: bool,
+}
+
+#[cfg(debug_mode)]
+impl<R> std::fmt::Debug for Ogg<R>
+where
+    R: Read + Seek + Send,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "Ogg Vorbis decoder")
+    }
+}
+
+impl<R> Ogg<R>
+where
+    R: Read + Seek + Send,
+{
+    // None is returned when headers of stream are wrong
+    pub fn new(reader: R) -> Option<Self> {
+        match OggStreamReader::new(reader) {
+            Ok(reader) => {
+                return Some(Ogg {
+                    reader,
+                    pending: VecDeque::new(),
+                    failed: false,
+                })
+            }
+            Err(e) => {
+                vxloge!("Ogg Vorbis can not be decoded: {:?}", e);
+                return None;
+            }
+        }
+    }
+}
+
+impl<R> Decoder for Ogg<R>
+where
+    R: Read + Seek + Send,
+{
+    fn get_channels(&self) -> usize {
+        return self.reader.ident_hdr.audio_channels as usize;
+    }
+
+    fn get_sample_rate(&self) -> u32 {
+        return self.reader.ident_hdr.audio_sample_rate;
+    }
+
+    fn decode(&mut self, frames_count: usize, samples: &mut Vec<Real>) -> usize {
+        let channels = self.get_channels();
+        let count = frames_count * channels;
+        while !self.failed && self.pending.len() < count {
+            match self.reader.read_dec_packet_itl() {
+                Ok(Some(packet)) => self
+                    .pending
+                    .extend(packet.iter().map(|s| *s as Real / 32768.0)),
+                Ok(None) => break,
+                Err(e) => {
+                    vxloge!("Decoding of Ogg Vorbis failed: {:?}", e);
+                    self.failed = true;
+                }
+            }
+        }
+        let count = count.min(self.pending.len());
+        samples.extend(self.pending.drain(..count));
+        return count / channels;
+    }
+
+    fn rewind(&mut self) {
+        self.pending.clear();
+        if let Err(e) = self.reader.seek_absgp_pg(0) {
+            vxloge!("Rewinding of Ogg Vorbis failed: {:?}", e);
+            self.failed = true;
+            return;
+        }
+        self.failed = false;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn create_wav(tag: u16, channels: u16, bits: u16, data: &[u8]) -> Vec<u8> {
+        let mut wav = Vec::new();
+        wav.extend_from_slice(b"RIFF");
+        wav.extend_from_slice(&[0, 0, 0, 0]);
+        wav.extend_from_slice(b"WAVE");
+        // an odd sized chunk that must be skipped
+        wav.extend_from_slice(b"LIST");
+        wav.extend_from_slice(&[3, 0, 0, 0, 1, 2, 3, 0]);
+        wav.extend_from_slice(b"fmt ");
+        wav.extend_from_slice(&[16, 0, 0, 0]);
+        wav.extend_from_slice(&[tag as u8, 0, channels as u8, 0]);
+        wav.extend_from_slice(&[0x44, 0xAC, 0, 0, 0, 0, 0, 0]);
+        wav.extend_from_slice(&[(channels * bits / 8) as u8, 0, bits as u8, 0]);
+        wav.extend_from_slice(b"data");
+        wav.extend_from_slice(&[data.len() as u8, 0, 0, 0]);
+        wav.extend_from_slice(data);
+        return wav;
+    }
+
+    fn decode_all(data: Vec<u8>) -> (usize, u32, Vec<Real>) {
+        let mut decoder = vxunwrap!(new_decoder(data));
+        let mut samples = Vec::new();
+        while decoder.decode(1, &mut samples) > 0 {}
+        return (decoder.get_channels(), decoder.get_sample_rate(), samples);
+    }
+
+    #[test]
+    fn wav_test() {
+        let (channels, sample_rate, samples) = decode_all(create_wav(1, 1, 8, &[128, 0, 255, 192]));
+        assert_eq!(channels, 1);
+        assert_eq!(sample_rate, 44100);
+        assert_eq!(samples, vec![0.0, -1.0, 127.0 / 128.0, 0.5]);
+        let (channels, _, samples) = decode_all(create_wav(
+            1,
+            2,
+            24,
+            &[0, 0, 0x40, 0, 0, 0xC0, 0, 0, 0, 0xFF, 0xFF, 0x7F],
+        ));
+        assert_eq!(channels, 2);
+        assert_eq!(samples[0..3].to_vec(), vec![0.5, -0.5, 0.0]);
+        assert!((samples[3] - 1.0).abs() < 1e-6);
+        let (_, _, samples) = decode_all(create_wav(3, 1, 32, &[0, 0, 0x80, 0xBE]));
+        assert_eq!(samples, vec![-0.25]);
+    }
+
+    #[test]
+    fn wrong_wav_test() {
+        assert!(new_decoder(create_wav(1, 0, 16, &[0, 0])).is_none());
+        assert!(new_decoder(create_wav(2, 1, 16, &[0, 0])).is_none());
+        let mut wav = create_wav(1, 1, 16, &[0, 0x40, 0, 0xC0, 0, 0]);
+        assert!(new_decoder(wav[..20].to_vec()).is_none());
+        assert!(new_decoder(b"ID3 tag".to_vec()).is_none());
+        let size = wav.len();
+        wav.truncate(size - 3);
+        let mut decoder = vxunwrap!(new_decoder(wav));
+        let mut samples = Vec::new();
+        assert_eq!(decoder.decode(10, &mut samples), 1);
+        assert_eq!(decoder.decode(10, &mut samples), 0);
+        assert_eq!(samples, vec![0.5]);
+        decoder.rewind();
+        assert_eq!(decoder.decode(10, &mut samples), 1);
+    }
+}
@@ -1,35 +1,271 @@
-use std::sync::Arc;
-use super::super::system::file::File;
-use super::super::util::cache::FileCacher;
-use super::super::util::cell::DebugCell;
-use super::{Audio, Music, Voice};
+use super::super::core::gx3d::Table as Gx3dTable;
+use super::super::core::object::Object as CoreObject;
+use super::super::core::types::{Id, Real};
+use super::super::render::scene::Scene;
+use super::backend::{new_device, Backend};
+use super::bus::{Group, GROUPS_COUNT};
+use super::mixer::Mixer;
+use super::music::Music;
+use super::spatial::{Emitter, Listener, DEFAULT_SPEED_OF_SOUND};
+use super::Sound;
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::Read;
+use std::sync::{Arc, RwLock, Weak};
 
+const DEFAULT_SAMPLE_RATE: u32 = 44100;
+const DEFAULT_CHANNELS: usize = 2;
+
+// Keeps the loaded sounds and pumps the mixer output into the backend in each frame
 pub struct Manager {
-    pub cached: FileCacher<Audio>,
+    sounds: BTreeMap<Id, Weak<Sound>>,
+    gx3d_table: Option<Gx3dTable>,
+    mixer: Mixer,
+    backend: Box<Backend>,
+    // Fraction of a frame that has not been rendered yet
+    remainder: f64,
+    buffer: Vec<Real>,
+    // Emitters of spatialized playbacks
+    emitters: BTreeMap<Id, Emitter>,
+    listener: Listener,
+    // Active camera of this scene is the listener
+    listener_scene: Option<Weak<RwLock<Scene>>>,
+    speed_of_sound: Real,
+    // Buses of volume groups
+    groups: [Id; GROUPS_COUNT],
+    current_music: Option<Id>,
+}
+
+#[cfg(debug_mode)]
+impl std::fmt::Debug for Manager {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "Audio manager {:?}", self.mixer)
+    }
 }
 
 impl Manager {
-    pub fn new(file: Arc<DebugCell<File>>) -> Self {
+    pub fn new() -> Self {
+        let mut mixer = Mixer::new(DEFAULT_SAMPLE_RATE, DEFAULT_CHANNELS);
+        let master = mixer.get_master_bus();
+        let mut groups = [0; GROUPS_COUNT];
+        for g in &mut groups {
+            *g = mixer.add_bus(master);
+        }
         Manager {
-            cached: FileCacher::new(file),
+            sounds: BTreeMap::new(),
+            gx3d_table: None,
+            mixer,
+            backend: new_device(DEFAULT_SAMPLE_RATE, DEFAULT_CHANNELS),
+            remainder: 0.0,
+            buffer: Vec::new(),
+            emitters: BTreeMap::new(),
+            listener: Listener::new(),
+            listener_scene: None,
+            speed_of_sound: DEFAULT_SPEED_OF_SOUND,
+            groups,
+            current_music: None,
+        }
+    }
+
+    // None is returned when the sound can not be decoded
+    pub fn load_gx3d(&mut self, id: Id) -> Option<Arc<Sound>> {
+        if let Some(sound) = self.sounds.get(&id) {
+            if let Some(sound) = sound.upgrade() {
+                return Some(sound);
+            }
         }
+        let gx3d_table = vxunwrap!(self.gx3d_table.as_mut());
+        gx3d_table.goto(id);
+        let reader = gx3d_table.get_mut_reader();
+        let sound = Arc::new(Sound::new_with_gx3d(reader, id)?);
+        self.sounds.insert(id, Arc::downgrade(&sound));
+        return Some(sound);
+    }
+
+    // File must be a WAV or an Ogg Vorbis
+    pub fn load_file(&mut self, file_name: &str) -> Option<Arc<Sound>> {
+        let mut data = Vec::new();
+        if let Err(e) = File::open(file_name).and_then(|mut f| f.read_to_end(&mut data)) {
+            vxloge!("Sound file {} can not be read: {}", file_name, e);
+            return None;
+        }
+        let sound = Arc::new(Sound::new_with_data(data)?);
+        self.sounds.insert(sound.get_id(), Arc::downgrade(&sound));
+        return Some(sound);
+    }
+
+    // Music is streamed from the GX3D file
+    pub fn load_music_gx3d(&mut self, id: Id) -> Option<Music> {
+        return Music::new_with_gx3d(vxunwrap!(self.gx3d_table.as_mut()), id);
+    }
+
+    pub fn open_music(&self, file_name: &str) -> Option<Music> {
+        return Music::open(file_name);
+    }
+
+    // Mixer takes the format of backend
+    pub fn set_backend(&mut self, backend: Box<Backend>) {
+        self.mixer
+            .set_format(backend.get_sample_rate(), backend.get_channels());
+        self.backend = backend;
+        self.remainder = 0.0;
+    }
+
+    pub fn get_mixer(&self) -> &Mixer {
+        return &self.mixer;
+    }
+
+    pub fn get_mut_mixer(&mut self) -> &mut Mixer {
+        return &mut self.mixer;
+    }
+
+    // Sound is played in the SFX group
+    pub fn play(&mut self, sound: &Arc<Sound>) -> Id {
+        return self.play_in(sound, Group::Sfx, false);
+    }
+
+    pub fn play_in(&mut self, sound: &Arc<Sound>, group: Group, looping: bool) -> Id {
+        let id = self.mixer.play(sound, 1.0, looping);
+        self.mixer.set_bus(id, self.groups[group as usize]);
+        return id;
+    }
+
+    // Current music fades out while the new one fades in, cross fade is in seconds
+    pub fn play_music(&mut self, music: Music, cross_fade: Real) -> Id {
+        self.stop_music(cross_fade);
+        let id = if cross_fade > 0.0 {
+            let id = self.mixer.play_music(music, 0.0);
+            self.mixer.fade(id, 1.0, cross_fade);
+            id
+        } else {
+            self.mixer.play_music(music, 1.0)
+        };
+        self.mixer.set_bus(id, self.groups[Group::Music as usize]);
+        self.current_music = Some(id);
+        return id;
+    }
+
+    pub fn stop_music(&mut self, fade: Real) {
+        if let Some(id) = self.current_music.take() {
+            self.mixer.fade_out(id, fade);
+        }
+    }
+
+    pub fn get_group_bus(&self, group: Group) -> Id {
+        return self.groups[group as usize];
     }
 
-    pub fn read_table(&mut self) {
-        self.cached.read_offsets();
+    pub fn set_group_volume(&mut self, group: Group, volume: Real) {
+        let bus = self.groups[group as usize];
+        vxunwrap!(self.mixer.get_mut_bus(bus)).set_gain(volume);
     }
 
-    pub fn get(&mut self, id: u64) -> Arc<DebugCell<Audio>> {
-        let file = self.cached.get_file().clone();
-        self.cached.get(id, &|| {
-            let audio_type: u64 = file.borrow_mut().read_type();
-            match audio_type {
-                10 => Arc::new(DebugCell::new(Music::new(&file))),
-                20 => Arc::new(DebugCell::new(Voice::new(&file))),
-                _ => {
-                    logf!("Uexpected value");
-                }
+    pub fn get_group_volume(&self, group: Group) -> Real {
+        return vxunwrap!(self.mixer.get_bus(self.groups[group as usize])).get_gain();
+    }
+
+    pub fn set_group_muted(&mut self, group: Group, muted: bool) {
+        let bus = self.groups[group as usize];
+        vxunwrap!(self.mixer.get_mut_bus(bus)).set_muted(muted);
+    }
+
+    // Playback stops when the object that emitter is attached to is dropped
+    pub fn play_at(&mut self, sound: &Arc<Sound>, mut emitter: Emitter, looping: bool) -> Id {
+        let id = self.play_in(sound, Group::Sfx, looping);
+        if !self.mixer.is_playing(id) {
+            return id;
+        }
+        if !emitter.update(0.0) {
+            self.mixer.stop(id);
+            return id;
+        }
+        let spatial = emitter.spatialize(&self.listener, self.speed_of_sound);
+        self.mixer.set_spatial(id, Some(spatial));
+        self.emitters.insert(id, emitter);
+        return id;
+    }
+
+    pub fn get_emitter(&self, id: Id) -> Option<&Emitter> {
+        return self.emitters.get(&id);
+    }
+
+    pub fn get_mut_emitter(&mut self, id: Id) -> Option<&mut Emitter> {
+        return self.emitters.get_mut(&id);
+    }
+
+    pub fn set_listener_scene(&mut self, scene: &Arc<RwLock<Scene>>) {
+        self.listener_scene = Some(Arc::downgrade(scene));
+        self.listener.reset_velocity();
+    }
+
+    pub fn get_listener(&self) -> &Listener {
+        return &self.listener;
+    }
+
+    // Listener can be moved manually when there is no listener scene
+    pub fn get_mut_listener(&mut self) -> &mut Listener {
+        return &mut self.listener;
+    }
+
+    pub fn set_speed_of_sound(&mut self, speed_of_sound: Real) {
+        self.speed_of_sound = speed_of_sound;
+    }
+
+    fn update_listener(&mut self, delta: Real) {
+        let scene = match &self.listener_scene {
+            Some(scene) => scene.upgrade(),
+            None => return,
+        };
+        let scene = match scene {
+            Some(scene) => scene,
+            None => {
+                self.listener_scene = None;
+                return;
+            }
+        };
+        let scene = vxresult!(scene.read());
+        if let Some(camera) = scene.get_active_camera() {
+            if let Some(camera) = camera.upgrade() {
+                self.listener
+                    .update_with_camera(&*vxresult!(camera.read()), delta);
+            }
+        }
+    }
+
+    fn update_emitters(&mut self, delta: Real) {
+        let mut stopped = Vec::new();
+        for (id, emitter) in &mut self.emitters {
+            if !self.mixer.is_playing(*id) || !emitter.update(delta) {
+                stopped.push(*id);
+                continue;
             }
-        })
+            let spatial = emitter.spatialize(&self.listener, self.speed_of_sound);
+            self.mixer.set_spatial(*id, Some(spatial));
+        }
+        for id in stopped {
+            self.mixer.stop(id);
+            self.emitters.remove(&id);
+        }
+    }
+
+    // Mixes the output of passed time, delta is in seconds
+    pub fn update(&mut self, delta: Real) {
+        self.update_listener(delta);
+        self.update_emitters(delta);
+        let frames = delta as f64 * self.mixer.get_sample_rate() as f64 + self.remainder;
+        let frames_count = frames.floor();
+        self.remainder = frames - frames_count;
+        let frames_count = frames_count as usize;
+        if frames_count == 0 {
+            return;
+        }
+        self.buffer
+            .resize(frames_count * self.mixer.get_channels(), 0.0);
+        self.mixer.mix(&mut self.buffer);
+        self.backend.write(&self.buffer);
+    }
+
+    pub(crate) fn set_gx3d_table(&mut self, gx3d_table: Gx3dTable) {
+        self.gx3d_table = Some(gx3d_table);
     }
 }
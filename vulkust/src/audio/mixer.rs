@@ -0,0 +1,549 @@
+use super::super::core::object::create_id;
+use super::super::core::types::{Id, Real};
+use super::bus::{Bus, Fade};
+use super::music::Music;
+use super::spatial::Spatial;
+use super::Sound;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+#[cfg_attr(debug_mode, derive(Debug))]
+enum Source {
+    Sound(Arc<Sound>),
+    Music(Music),
+}
+
+#[cfg_attr(debug_mode, derive(Debug))]
+struct Voice {
+    source: Source,
+    bus: Id,
+    // Position is in frames of sound and it is fractional because of resampling
+    position: f64,
+    volume: Real,
+    pitch: Real,
+    // Music has its own looping
+    looping: bool,
+    paused: bool,
+    spatial: Option<Spatial>,
+    // Gains of last mix, they are ramped toward the spatial gains to avoid clicks
+    gains: [Real; 2],
+    // Gain of last mix with the volumes of buses, it is ramped in the same way
+    gain: Option<Real>,
+    fade: Option<Fade>,
+    stop_after_fade: bool,
+}
+
+impl Voice {
+    fn new(source: Source, bus: Id, volume: Real, looping: bool) -> Self {
+        Voice {
+            source,
+            bus,
+            position: 0.0,
+            volume,
+            pitch: 1.0,
+            looping,
+            paused: false,
+            spatial: None,
+            gains: [0.0; 2],
+            gain: None,
+            fade: None,
+            stop_after_fade: false,
+        }
+    }
+
+    fn get_channels(&self) -> usize {
+        return match &self.source {
+            &Source::Sound(ref sound) => sound.get_channels(),
+            &Source::Music(ref music) => music.get_channels(),
+        };
+    }
+
+    fn get_sample_rate(&self) -> u32 {
+        return match &self.source {
+            &Source::Sound(ref sound) => sound.get_sample_rate(),
+            &Source::Music(ref music) => music.get_sample_rate(),
+        };
+    }
+
+    // Sample of a channel of sound at the current position, it is linearly interpolated
+    fn get_sample(&self, channel: usize) -> Real {
+        let t = (self.position - self.position.floor()) as Real;
+        match &self.source {
+            &Source::Sound(ref sound) => {
+                let channels = sound.get_channels();
+                let frames_count = sound.get_frames_count();
+                let samples = sound.get_samples();
+                let i = self.position as usize;
+                let mut j = i + 1;
+                if j >= frames_count {
+                    if !self.looping {
+                        return samples[i * channels + channel] * (1.0 - t);
+                    }
+                    j = 0;
+                }
+                return samples[i * channels + channel] * (1.0 - t)
+                    + samples[j * channels + channel] * t;
+            }
+            &Source::Music(ref music) => {
+                let i = self.position as u64;
+                return music.get_sample(i, channel) * (1.0 - t)
+                    + music.get_sample(i + 1, channel) * t;
+            }
+        }
+    }
+
+    // Returns true when the voice is finished
+    fn advance(&mut self, step: f64) -> bool {
+        self.position += step;
+        match &mut self.source {
+            &mut Source::Sound(ref sound) => {
+                let frames_count = sound.get_frames_count() as f64;
+                if self.position >= frames_count {
+                    if !self.looping {
+                        return true;
+                    }
+                    self.position %= frames_count;
+                }
+                return false;
+            }
+            &mut Source::Music(ref mut music) => {
+                let i = self.position as u64;
+                music.prepare(i);
+                return music.is_finished(i);
+            }
+        }
+    }
+}
+
+// Mixes the playing sounds into interleaved frames of output,
+// sounds are resampled to the output sample rate and their channels are mapped to output channels.
+// Each voice plays in a bus and the buses make a tree under the master bus.
+#[cfg_attr(debug_mode, derive(Debug))]
+pub struct Mixer {
+    sample_rate: u32,
+    channels: usize,
+    voices: BTreeMap<Id, Voice>,
+    buses: BTreeMap<Id, Bus>,
+    master_bus: Id,
+    pub volume: Real,
+}
+
+impl Mixer {
+    pub fn new(sample_rate: u32, channels: usize) -> Self {
+        if sample_rate == 0 || channels == 0 {
+            vxlogf!("Mixer must have sample rate and channels.");
+        }
+        let master_bus = create_id();
+        let mut buses = BTreeMap::new();
+        buses.insert(master_bus, Bus::new(None));
+        Mixer {
+            sample_rate,
+            channels,
+            voices: BTreeMap::new(),
+            buses,
+            master_bus,
+            volume: 1.0,
+        }
+    }
+
+    pub fn set_format(&mut self, sample_rate: u32, channels: usize) {
+        if sample_rate == 0 || channels == 0 {
+            vxlogf!("Mixer must have sample rate and channels.");
+        }
+        self.sample_rate = sample_rate;
+        self.channels = channels;
+    }
+
+    pub fn get_sample_rate(&self) -> u32 {
+        return self.sample_rate;
+    }
+
+    pub fn get_channels(&self) -> usize {
+        return self.channels;
+    }
+
+    pub fn get_master_bus(&self) -> Id {
+        return self.master_bus;
+    }
+
+    pub fn add_bus(&mut self, parent: Id) -> Id {
+        if !self.buses.contains_key(&parent) {
+            vxlogf!("Parent bus {} does not exist.", parent);
+        }
+        let id = create_id();
+        self.buses.insert(id, Bus::new(Some(parent)));
+        return id;
+    }
+
+    // Children and voices of the bus are moved to its parent
+    pub fn remove_bus(&mut self, id: Id) {
+        if id == self.master_bus {
+            vxlogf!("Master bus can not be removed.");
+        }
+        let parent = match self.buses.remove(&id) {
+            Some(bus) => vxunwrap!(bus.get_parent()),
+            None => return,
+        };
+        for (_, bus) in &mut self.buses {
+            if bus.get_parent() == Some(id) {
+                bus.set_parent(Some(parent));
+            }
+        }
+        for (_, v) in &mut self.voices {
+            if v.bus == id {
+                v.bus = parent;
+            }
+        }
+    }
+
+    pub fn get_bus(&self, id: Id) -> Option<&Bus> {
+        return self.buses.get(&id);
+    }
+
+    pub fn get_mut_bus(&mut self, id: Id) -> Option<&mut Bus> {
+        return self.buses.get_mut(&id);
+    }
+
+    // Product of the gains of bus and its ancestors, a muted one makes it zero
+    pub fn get_bus_gain(&self, id: Id) -> Real {
+        let mut gain = 1.0;
+        let mut id = Some(id);
+        while let Some(i) = id {
+            let bus = vxunwrap!(self.buses.get(&i));
+            if bus.is_muted() {
+                return 0.0;
+            }
+            gain *= bus.get_gain();
+            id = bus.get_parent();
+        }
+        return gain;
+    }
+
+    // Returns the id of playback, it is needed for controlling the playback
+    pub fn play(&mut self, sound: &Arc<Sound>, volume: Real, looping: bool) -> Id {
+        let id = create_id();
+        if sound.get_frames_count() == 0 {
+            return id;
+        }
+        let voice = Voice::new(
+            Source::Sound(sound.clone()),
+            self.master_bus,
+            volume,
+            looping,
+        );
+        self.voices.insert(id, voice);
+        return id;
+    }
+
+    // Music is decoded during the mix
+    pub fn play_music(&mut self, mut music: Music, volume: Real) -> Id {
+        let id = create_id();
+        music.prepare(0);
+        if music.is_finished(0) {
+            return id;
+        }
+        let voice = Voice::new(Source::Music(music), self.master_bus, volume, false);
+        self.voices.insert(id, voice);
+        return id;
+    }
+
+    pub fn set_bus(&mut self, id: Id, bus: Id) {
+        if !self.buses.contains_key(&bus) {
+            vxlogf!("Bus {} does not exist.", bus);
+        }
+        if let Some(v) = self.voices.get_mut(&id) {
+            v.bus = bus;
+        }
+    }
+
+    pub fn stop(&mut self, id: Id) {
+        self.voices.remove(&id);
+    }
+
+    pub fn stop_all(&mut self) {
+        self.voices.clear();
+    }
+
+    pub fn pause(&mut self, id: Id) {
+        if let Some(v) = self.voices.get_mut(&id) {
+            v.paused = true;
+        }
+    }
+
+    pub fn resume(&mut self, id: Id) {
+        if let Some(v) = self.voices.get_mut(&id) {
+            v.paused = false;
+        }
+    }
+
+    // It cancels the fade
+    pub fn set_volume(&mut self, id: Id, volume: Real) {
+        if let Some(v) = self.voices.get_mut(&id) {
+            v.volume = volume;
+            v.fade = None;
+            v.stop_after_fade = false;
+        }
+    }
+
+    // Duration is in seconds
+    pub fn fade(&mut self, id: Id, volume: Real, duration: Real) {
+        if duration <= 0.0 {
+            self.set_volume(id, volume);
+            return;
+        }
+        if let Some(v) = self.voices.get_mut(&id) {
+            v.fade = Some(Fade::new(v.volume, volume, duration));
+            v.stop_after_fade = false;
+        }
+    }
+
+    // Playback stops at the end of fade
+    pub fn fade_out(&mut self, id: Id, duration: Real) {
+        if duration <= 0.0 {
+            self.stop(id);
+            return;
+        }
+        if let Some(v) = self.voices.get_mut(&id) {
+            v.fade = Some(Fade::new(v.volume, 0.0, duration));
+            v.stop_after_fade = true;
+        }
+    }
+
+    // Pitch is the speed of playback, two plays one octave higher
+    pub fn set_pitch(&mut self, id: Id, pitch: Real) {
+        if pitch <= 0.0 {
+            vxlogf!("Pitch must be positive.");
+        }
+        if let Some(v) = self.voices.get_mut(&id) {
+            v.pitch = pitch;
+        }
+    }
+
+    pub fn set_looping(&mut self, id: Id, looping: bool) {
+        if let Some(v) = self.voices.get_mut(&id) {
+            v.looping = looping;
+            if let Source::Music(music) = &mut v.source {
+                music.set_looping(looping);
+            }
+        }
+    }
+
+    // A spatialized voice is downmixed to mono and then panned by the gains,
+    // its pitch is multiplied by the spatial pitch.
+    pub fn set_spatial(&mut self, id: Id, spatial: Option<Spatial>) {
+        if let Some(v) = self.voices.get_mut(&id) {
+            if v.spatial.is_none() {
+                if let Some(s) = &spatial {
+                    v.gains = s.gains;
+                }
+            }
+            v.spatial = spatial;
+        }
+    }
+
+    // False when playback is stopped or it is finished
+    pub fn is_playing(&self, id: Id) -> bool {
+        return self.voices.contains_key(&id);
+    }
+
+    pub fn get_playing_count(&self) -> usize {
+        return self.voices.len();
+    }
+
+    // Output is overwritten with the mix, its length must be a multiple of channels
+    pub fn mix(&mut self, output: &mut [Real]) {
+        for s in output.iter_mut() {
+            *s = 0.0;
+        }
+        let channels = self.channels;
+        let frames_count = output.len() / channels;
+        if frames_count == 0 {
+            return;
+        }
+        let sample_rate = self.sample_rate;
+        let delta = frames_count as Real / sample_rate as Real;
+        for (_, bus) in &mut self.buses {
+            bus.update(delta);
+        }
+        let mut bus_gains = BTreeMap::new();
+        for id in self.buses.keys() {
+            bus_gains.insert(*id, self.get_bus_gain(*id) * self.volume);
+        }
+        let frames_delta = 1.0 / frames_count as Real;
+        let mut finished = Vec::new();
+        for (id, v) in &mut self.voices {
+            if v.paused {
+                continue;
+            }
+            let fade_finished = match &mut v.fade {
+                Some(fade) => {
+                    v.volume = fade.update(delta);
+                    fade.is_finished()
+                }
+                None => false,
+            };
+            if fade_finished {
+                v.fade = None;
+                if v.stop_after_fade {
+                    finished.push(*id);
+                }
+            }
+            let end_gain = v.volume * vxunwrap!(bus_gains.get(&v.bus));
+            let start_gain = v.gain.unwrap_or(end_gain);
+            v.gain = Some(end_gain);
+            let source_channels = v.get_channels();
+            let mut step = (v.get_sample_rate() as f64 / sample_rate as f64) * v.pitch as f64;
+            let start_gains = v.gains;
+            if let Some(s) = &v.spatial {
+                step *= s.pitch as f64;
+                v.gains = s.gains;
+            }
+            let mut ramp = 0.0;
+            for frame in output.chunks_mut(channels) {
+                ramp += frames_delta;
+                let gain = start_gain + (end_gain - start_gain) * ramp;
+                if v.spatial.is_some() {
+                    let left = start_gains[0] + (v.gains[0] - start_gains[0]) * ramp;
+                    let right = start_gains[1] + (v.gains[1] - start_gains[1]) * ramp;
+                    let mut s = 0.0;
+                    for c in 0..source_channels {
+                        s += v.get_sample(c);
+                    }
+                    s *= gain / source_channels as Real;
+                    if channels == 1 {
+                        frame[0] += s * (left * left + right * right).sqrt();
+                    } else {
+                        frame[0] += s * left;
+                        frame[1] += s * right;
+                    }
+                } else if channels == 1 {
+                    let mut s = 0.0;
+                    for c in 0..source_channels {
+                        s += v.get_sample(c);
+                    }
+                    frame[0] += s * gain / source_channels as Real;
+                } else {
+                    for c in 0..channels {
+                        frame[c] += v.get_sample(c.min(source_channels - 1)) * gain;
+                    }
+                }
+                if v.advance(step) {
+                    finished.push(*id);
+                    break;
+                }
+            }
+        }
+        for id in finished {
+            self.voices.remove(&id);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn mix_test() {
+        let mut mixer = Mixer::new(4, 2);
+        let mono = Arc::new(Sound::new(1, 2, vec![1.0, 0.0]));
+        let stereo = Arc::new(Sound::new(2, 4, vec![0.5, -0.5, 0.25, -0.25]));
+        let a = mixer.play(&mono, 1.0, false);
+        let b = mixer.play(&stereo, 0.5, true);
+        let mut output = vec![0.0; 8];
+        mixer.mix(&mut output);
+        // mono is resampled to the double rate and it is played on both channels
+        assert_eq!(
+            output,
+            vec![1.25, 0.75, 0.625, 0.375, 0.25, -0.25, 0.125, -0.125]
+        );
+        assert!(!mixer.is_playing(a));
+        assert!(mixer.is_playing(b));
+        mixer.set_pitch(b, 2.0);
+        mixer.mix(&mut output);
+        assert_eq!(
+            output,
+            vec![0.25, -0.25, 0.25, -0.25, 0.25, -0.25, 0.25, -0.25]
+        );
+        mixer.pause(b);
+        mixer.mix(&mut output);
+        assert_eq!(output, vec![0.0; 8]);
+        mixer.resume(b);
+        mixer.stop(b);
+        assert_eq!(mixer.get_playing_count(), 0);
+    }
+
+    #[test]
+    fn downmix_test() {
+        let mut mixer = Mixer::new(10, 1);
+        let stereo = Arc::new(Sound::new(2, 10, vec![1.0, 0.0, 0.0, 1.0]));
+        mixer.play(&stereo, 1.0, false);
+        let mut output = vec![1.0; 3];
+        mixer.mix(&mut output);
+        assert_eq!(output, vec![0.5, 0.5, 0.0]);
+    }
+
+    #[test]
+    fn bus_test() {
+        let mut mixer = Mixer::new(10, 1);
+        let master = mixer.get_master_bus();
+        let music = mixer.add_bus(master);
+        let theme = mixer.add_bus(music);
+        vxunwrap!(mixer.get_mut_bus(master)).set_gain(0.5);
+        vxunwrap!(mixer.get_mut_bus(music)).set_gain(0.5);
+        assert_eq!(mixer.get_bus_gain(theme), 0.25);
+        let sound = Arc::new(Sound::new(1, 10, vec![1.0; 4]));
+        let id = mixer.play(&sound, 1.0, true);
+        mixer.set_bus(id, theme);
+        let mut output = vec![0.0; 2];
+        mixer.mix(&mut output);
+        assert_eq!(output, vec![0.25, 0.25]);
+        // gain changes are ramped during a mix
+        vxunwrap!(mixer.get_mut_bus(music)).set_muted(true);
+        mixer.mix(&mut output);
+        assert_eq!(output, vec![0.125, 0.0]);
+        mixer.remove_bus(music);
+        assert_eq!(vxunwrap!(mixer.get_bus(theme)).get_parent(), Some(master));
+        mixer.mix(&mut output);
+        assert_eq!(output, vec![0.25, 0.5]);
+    }
+
+    #[test]
+    fn fade_test() {
+        let mut mixer = Mixer::new(10, 1);
+        let sound = Arc::new(Sound::new(1, 10, vec![1.0; 4]));
+        let a = mixer.play(&sound, 1.0, true);
+        let b = mixer.play(&sound, 0.0, true);
+        mixer.fade_out(a, 0.4);
+        mixer.fade(b, 1.0, 0.4);
+        let mut output = vec![0.0; 2];
+        mixer.mix(&mut output);
+        mixer.mix(&mut output);
+        // sum of cross fading voices stays constant
+        assert_eq!(output, vec![1.0, 1.0]);
+        assert!(!mixer.is_playing(a));
+        assert!(mixer.is_playing(b));
+    }
+
+    #[test]
+    fn music_test() {
+        use super::super::backend::{Backend, WavFile};
+        use std::io::Cursor;
+        let mut wav = WavFile::new(Cursor::new(Vec::new()), 10, 1);
+        wav.write(&[0.5, 0.25, -0.5]);
+        wav.finish();
+        let mut music = vxunwrap!(Music::new_with_data(wav.get_writer().get_ref().clone()));
+        music.set_looping(true);
+        let mut mixer = Mixer::new(10, 1);
+        let id = mixer.play_music(music, 1.0);
+        let mut output = vec![0.0; 5];
+        mixer.mix(&mut output);
+        let expected = [0.5, 0.25, -0.5, 0.5, 0.25];
+        for (o, e) in output.iter().zip(expected.iter()) {
+            assert!((o - e).abs() < 1e-3);
+        }
+        mixer.set_looping(id, false);
+        mixer.mix(&mut output);
+        assert!(!mixer.is_playing(id));
+    }
+}
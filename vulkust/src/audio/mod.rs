@@ -1,62 +1,95 @@
+pub mod backend;
+pub mod bus;
+pub mod decoder;
 pub mod manager;
+pub mod mixer;
+pub mod music;
+pub mod spatial;
 
-use std::sync::Arc;
-use super::system::file::File;
-use super::util::cell::DebugCell;
+use self::decoder::{new_decoder, Decoder};
+use super::core::gx3d::Gx3DReader;
+use super::core::object::{Base as CoreBase, Object as CoreObject};
+use super::core::types::{Id, Real};
 
-pub trait Audio {
-    fn play(&self);
+// Number of frames that are decoded in each step of decoding a whole sound
+const DECODE_CHUNK_FRAMES: usize = 4096;
+
+#[repr(u8)]
+#[cfg_attr(debug_mode, derive(Debug))]
+pub enum TypeId {
+    Music = 1,
+    Voice = 2,
 }
 
-struct Basic {
-    pub file_content: Vec<u8>,
+// A fully decoded sound, samples are interleaved
+#[cfg_attr(debug_mode, derive(Debug))]
+pub struct Sound {
+    obj_base: CoreBase,
+    channels: usize,
+    sample_rate: u32,
+    samples: Vec<Real>,
 }
 
-impl Basic {
-    pub fn new(file: &Arc<DebugCell<File>>) -> Self {
-        let size: u64 = file.borrow_mut().read_count();
-        let file_content = file.borrow_mut().read_bytes(size as usize);
-        Basic {
-            file_content: file_content,
+impl Sound {
+    pub fn new(channels: usize, sample_rate: u32, samples: Vec<Real>) -> Self {
+        if channels == 0 || samples.len() % channels != 0 {
+            vxlogf!("Samples of sound must be complete frames.");
+        }
+        Sound {
+            obj_base: CoreBase::new(),
+            channels,
+            sample_rate,
+            samples,
         }
     }
-    pub fn play(&self) {
-        logf!("Unimplemented!");
+
+    pub fn new_with_decoder(decoder: &mut Decoder) -> Self {
+        let mut samples = Vec::new();
+        while decoder.decode(DECODE_CHUNK_FRAMES, &mut samples) > 0 {}
+        return Self::new(decoder.get_channels(), decoder.get_sample_rate(), samples);
     }
-}
 
-pub struct Music {
-    b: Basic,
-}
+    // Data is the content of a WAV or an Ogg Vorbis file, None is returned for a wrong data
+    pub fn new_with_data(data: Vec<u8>) -> Option<Self> {
+        let mut decoder = new_decoder(data)?;
+        return Some(Self::new_with_decoder(&mut *decoder));
+    }
 
-impl Music {
-    pub fn new(file: &Arc<DebugCell<File>>) -> Self {
-        Music {
-            b: Basic::new(file),
+    pub fn new_with_gx3d(reader: &mut Gx3DReader, my_id: Id) -> Option<Self> {
+        let t = reader.read_type_id();
+        if t != TypeId::Music as u8 && t != TypeId::Voice as u8 {
+            vxunexpected!();
         }
+        let size: u64 = reader.read();
+        let mut sound = Self::new_with_data(reader.read_bytes(size))?;
+        sound.obj_base = CoreBase::new_with_id(my_id);
+        return Some(sound);
     }
-}
 
-impl Audio for Music {
-    fn play(&self) {
-        self.b.play();
+    pub fn get_channels(&self) -> usize {
+        return self.channels;
     }
-}
 
-pub struct Voice {
-    b: Basic,
-}
+    pub fn get_sample_rate(&self) -> u32 {
+        return self.sample_rate;
+    }
 
-impl Voice {
-    pub fn new(file: &Arc<DebugCell<File>>) -> Self {
-        Voice {
-            b: Basic::new(file),
-        }
+    pub fn get_samples(&self) -> &[Real] {
+        return &self.samples;
+    }
+
+    pub fn get_frames_count(&self) -> usize {
+        return self.samples.len() / self.channels;
+    }
+
+    // Length in seconds
+    pub fn get_duration(&self) -> Real {
+        return self.get_frames_count() as Real / self.sample_rate as Real;
     }
 }
 
-impl Audio for Voice {
-    fn play(&self) {
-        self.b.play();
+impl CoreObject for Sound {
+    fn get_id(&self) -> Id {
+        self.obj_base.get_id()
     }
 }
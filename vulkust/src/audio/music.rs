@@ -0,0 +1,280 @@
+use super::super::core::gx3d::Table as Gx3dTable;
+use super::super::core::types::{Id, Offset, Real, Size};
+use super::super::system::file::File;
+use super::decoder::{Decoder, Ogg, Wav};
+use super::TypeId;
+use std::io::{Cursor, Error, ErrorKind, Read, Result, Seek, SeekFrom};
+
+// Number of frames that are decoded in each step of streaming
+const STREAM_CHUNK_FRAMES: usize = 4096;
+
+// A part of a stream that is seen as a whole stream, it is used for reading an entry of GX3D file
+#[cfg_attr(debug_mode, derive(Debug))]
+pub struct Section<R>
+where
+    R: Read + Seek + Send,
+{
+    reader: R,
+    start: Offset,
+    size: Size,
+    position: Size,
+}
+
+impl<R> Section<R>
+where
+    R: Read + Seek + Send,
+{
+    pub fn new(mut reader: R, start: Offset, size: Size) -> Self {
+        vxresult!(reader.seek(SeekFrom::Start(start)));
+        Section {
+            reader,
+            start,
+            size,
+            position: 0,
+        }
+    }
+}
+
+impl<R> Read for Section<R>
+where
+    R: Read + Seek + Send,
+{
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        let remained = (self.size - self.position) as usize;
+        let count = buf.len().min(remained);
+        let count = self.reader.read(&mut buf[..count])?;
+        self.position += count as Size;
+        return Ok(count);
+    }
+}
+
+impl<R> Seek for Section<R>
+where
+    R: Read + Seek + Send,
+{
+    fn seek(&mut self, pos: SeekFrom) -> Result<u64> {
+        let position = match pos {
+            SeekFrom::Start(p) => p as i64,
+            SeekFrom::Current(p) => self.position as i64 + p,
+            SeekFrom::End(p) => self.size as i64 + p,
+        };
+        if position < 0 {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "Seek before the start of section.",
+            ));
+        }
+        self.position = (position as Size).min(self.size);
+        self.reader
+            .seek(SeekFrom::Start(self.start + self.position))?;
+        return Ok(self.position);
+    }
+}
+
+// Streamed sound, only a window of decoded frames around the playback position is kept
+pub struct Music {
+    decoder: Box<Decoder>,
+    channels: usize,
+    sample_rate: u32,
+    // Decoded samples, first frame of them is the frame at offset of stream
+    samples: Vec<Real>,
+    offset: u64,
+    looping: bool,
+    ended: bool,
+}
+
+#[cfg(debug_mode)]
+impl std::fmt::Debug for Music {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "Music channels: {}, sample rate: {}, offset: {}, looping: {}, ended: {}",
+            self.channels, self.sample_rate, self.offset, self.looping, self.ended
+        )
+    }
+}
+
+impl Music {
+    pub fn new(decoder: Box<Decoder>) -> Self {
+        Music {
+            channels: decoder.get_channels(),
+            sample_rate: decoder.get_sample_rate(),
+            decoder,
+            samples: Vec::new(),
+            offset: 0,
+            looping: false,
+            ended: false,
+        }
+    }
+
+    // Reader must contain a WAV or an Ogg Vorbis, its format is detected by its header.
+    // None is returned when it can not be decoded.
+    pub fn new_with_reader<R>(mut reader: R) -> Option<Self>
+    where
+        R: 'static + Read + Seek + Send,
+    {
+        let mut magic = [0u8; 4];
+        if let Err(e) = reader
+            .read_exact(&mut magic)
+            .and_then(|_| reader.seek(SeekFrom::Start(0)))
+        {
+            vxloge!("Music can not be read: {}", e);
+            return None;
+        }
+        if &magic == b"RIFF" {
+            return Wav::new(reader).map(|d| Self::new(Box::new(d)));
+        } else if &magic == b"OggS" {
+            return Ogg::new(reader).map(|d| Self::new(Box::new(d)));
+        }
+        vxloge!("Unsupported audio format.");
+        return None;
+    }
+
+    pub fn new_with_data(data: Vec<u8>) -> Option<Self> {
+        return Self::new_with_reader(Cursor::new(data));
+    }
+
+    pub fn open(file_name: &str) -> Option<Self> {
+        match File::open(file_name) {
+            Ok(file) => return Self::new_with_reader(file),
+            Err(e) => {
+                vxloge!("Music file {} can not be opened: {}", file_name, e);
+                return None;
+            }
+        }
+    }
+
+    // Entry is streamed from its own handle of GX3D file, so the table stays usable
+    pub(crate) fn new_with_gx3d(table: &mut Gx3dTable, id: Id) -> Option<Self> {
+        table.goto(id);
+        let start;
+        let size: Size;
+        {
+            let reader = table.get_mut_reader();
+            let t = reader.read_type_id();
+            if t != TypeId::Music as u8 && t != TypeId::Voice as u8 {
+                vxunexpected!();
+            }
+            size = reader.read();
+            start = reader.get_offset();
+        }
+        let file = match File::open(table.get_file_name()) {
+            Ok(file) => file,
+            Err(e) => {
+                vxloge!("GX3D file can not be opened for music {}: {}", id, e);
+                return None;
+            }
+        };
+        return Self::new_with_reader(Section::new(file, start, size));
+    }
+
+    pub fn set_looping(&mut self, looping: bool) {
+        self.looping = looping;
+    }
+
+    pub fn is_looping(&self) -> bool {
+        return self.looping;
+    }
+
+    pub fn get_channels(&self) -> usize {
+        return self.channels;
+    }
+
+    pub fn get_sample_rate(&self) -> u32 {
+        return self.sample_rate;
+    }
+
+    // Decodes until the frame and its next one are available and drops the frames before it.
+    pub(super) fn prepare(&mut self, frame: u64) {
+        let consumed = (frame.max(self.offset) - self.offset) as usize;
+        let decoded = self.samples.len() / self.channels;
+        if consumed >= STREAM_CHUNK_FRAMES {
+            let consumed = consumed.min(decoded);
+            self.samples.drain(..consumed * self.channels);
+            self.offset += consumed as u64;
+        }
+        while !self.ended && self.offset + (self.samples.len() / self.channels) as u64 <= frame + 1
+        {
+            if self.decoder.decode(STREAM_CHUNK_FRAMES, &mut self.samples) > 0 {
+                continue;
+            }
+            if !self.looping {
+                self.ended = true;
+                break;
+            }
+            self.decoder.rewind();
+            // an empty stream can not be looped
+            if self.decoder.decode(STREAM_CHUNK_FRAMES, &mut self.samples) == 0 {
+                self.ended = true;
+            }
+        }
+    }
+
+    // Frame must be prepared, zero is returned for the frames after the end
+    pub(super) fn get_sample(&self, frame: u64, channel: usize) -> Real {
+        if frame < self.offset {
+            vxunexpected!();
+        }
+        let index = (frame - self.offset) as usize * self.channels + channel;
+        if index < self.samples.len() {
+            return self.samples[index];
+        }
+        return 0.0;
+    }
+
+    pub(super) fn is_finished(&self, frame: u64) -> bool {
+        return self.ended && frame >= self.offset + (self.samples.len() / self.channels) as u64;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::backend::{Backend, WavFile};
+    use super::*;
+
+    fn create_wav(samples: &[Real]) -> Vec<u8> {
+        let mut wav = WavFile::new(Cursor::new(Vec::new()), 100, 1);
+        wav.write(samples);
+        wav.finish();
+        return wav.get_writer().get_ref().clone();
+    }
+
+    #[test]
+    fn section_test() {
+        let mut section = Section::new(Cursor::new(vec![0u8, 1, 2, 3, 4, 5, 6]), 2, 3);
+        let mut data = Vec::new();
+        vxresult!(section.read_to_end(&mut data));
+        assert_eq!(data, vec![2, 3, 4]);
+        assert_eq!(vxresult!(section.seek(SeekFrom::End(-1))), 2);
+        let mut b = [0u8; 4];
+        assert_eq!(vxresult!(section.read(&mut b)), 1);
+        assert_eq!(b[0], 4);
+    }
+
+    #[test]
+    fn stream_test() {
+        let samples: Vec<Real> = (0..10000).map(|i| (i % 100) as Real / 128.0).collect();
+        let mut data = vec![7u8; 5];
+        data.extend_from_slice(&create_wav(&samples));
+        let size = data.len() as Size - 5;
+        let mut music = vxunwrap!(Music::new_with_reader(Section::new(
+            Cursor::new(data),
+            5,
+            size
+        )));
+        music.prepare(0);
+        assert!((music.get_sample(1, 0) - samples[1]).abs() < 1e-3);
+        music.prepare(9000);
+        assert!((music.get_sample(9000, 0) - samples[9000]).abs() < 1e-3);
+        // frames before the window are dropped
+        assert!(music.samples.len() < samples.len());
+        music.prepare(9999);
+        assert!(!music.is_finished(9999));
+        assert!(music.is_finished(10000));
+        let mut music = vxunwrap!(Music::new_with_data(create_wav(&samples[0..3])));
+        music.set_looping(true);
+        music.prepare(7);
+        assert!((music.get_sample(7, 0) - samples[1]).abs() < 1e-3);
+        assert!(!music.is_finished(100000));
+    }
+}
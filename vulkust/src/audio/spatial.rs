@@ -0,0 +1,438 @@
+use super::super::core::types::Real;
+use super::super::render::camera::Camera;
+use super::super::render::light::Light;
+use super::super::render::model::Model;
+use std::f32::consts::PI;
+use std::sync::{Arc, RwLock, Weak};
+
+use cgmath;
+use cgmath::prelude::*;
+
+type Vec3 = cgmath::Vector3<Real>;
+
+// In meters per second, it is the speed of sound in air
+pub const DEFAULT_SPEED_OF_SOUND: Real = 343.3;
+
+// Doppler shift is limited, because a source near the speed of sound makes a meaningless pitch
+const MIN_DOPPLER_PITCH: Real = 0.25;
+const MAX_DOPPLER_PITCH: Real = 4.0;
+const EPSILON: Real = 0.0001;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(debug_mode, derive(Debug))]
+pub enum AttenuationModel {
+    None,
+    // reference / (reference + rolloff * (distance - reference))
+    Inverse,
+    // 1 - rolloff * (distance - reference) / (max - reference)
+    Linear,
+    // (distance / reference) ^ -rolloff
+    Exponential,
+}
+
+// Distance is clamped between the reference and the max distance before computing the gain
+#[derive(Clone, Copy)]
+#[cfg_attr(debug_mode, derive(Debug))]
+pub struct Attenuation {
+    pub model: AttenuationModel,
+    pub reference_distance: Real,
+    pub max_distance: Real,
+    pub rolloff: Real,
+}
+
+impl Attenuation {
+    pub fn new(
+        model: AttenuationModel,
+        reference_distance: Real,
+        max_distance: Real,
+        rolloff: Real,
+    ) -> Self {
+        if reference_distance <= 0.0 || max_distance < reference_distance {
+            vxlogf!("Reference distance must be positive and not bigger than max distance.");
+        }
+        Attenuation {
+            model,
+            reference_distance,
+            max_distance,
+            rolloff,
+        }
+    }
+
+    pub fn get_gain(&self, distance: Real) -> Real {
+        let r = self.reference_distance;
+        let d = distance.min(self.max_distance).max(r);
+        let gain = match self.model {
+            AttenuationModel::None => 1.0,
+            AttenuationModel::Inverse => r / (r + self.rolloff * (d - r)),
+            AttenuationModel::Linear => {
+                if self.max_distance - r > EPSILON {
+                    1.0 - self.rolloff * (d - r) / (self.max_distance - r)
+                } else {
+                    1.0
+                }
+            }
+            AttenuationModel::Exponential => (d / r).powf(-self.rolloff),
+        };
+        return gain.max(0.0).min(1.0);
+    }
+}
+
+impl Default for Attenuation {
+    fn default() -> Self {
+        Self::new(AttenuationModel::Inverse, 1.0, 1000.0, 1.0)
+    }
+}
+
+// Directional sources are loudest in front of them, gain goes linearly
+// from one in the inner cone to the outer gain in the outer cone.
+#[derive(Clone, Copy)]
+#[cfg_attr(debug_mode, derive(Debug))]
+pub struct Cone {
+    // Whole angles of cones in radians
+    pub inner_angle: Real,
+    pub outer_angle: Real,
+    pub outer_gain: Real,
+}
+
+impl Cone {
+    pub fn new(inner_angle: Real, outer_angle: Real, outer_gain: Real) -> Self {
+        if inner_angle > outer_angle {
+            vxlogf!("Inner angle of cone must not be bigger than its outer angle.");
+        }
+        Cone {
+            inner_angle,
+            outer_angle,
+            outer_gain,
+        }
+    }
+
+    // Both of vectors must be normalized
+    pub fn get_gain(&self, direction: &Vec3, to_listener: &Vec3) -> Real {
+        let angle = direction.dot(*to_listener).max(-1.0).min(1.0).acos() * 2.0;
+        if angle <= self.inner_angle {
+            return 1.0;
+        }
+        if angle >= self.outer_angle {
+            return self.outer_gain;
+        }
+        let t = (angle - self.inner_angle) / (self.outer_angle - self.inner_angle);
+        return 1.0 + (self.outer_gain - 1.0) * t;
+    }
+}
+
+// Result of spatialization for a voice of mixer
+#[derive(Clone, Copy, PartialEq)]
+#[cfg_attr(debug_mode, derive(Debug))]
+pub struct Spatial {
+    // Gains of left and right channels
+    pub gains: [Real; 2],
+    pub pitch: Real,
+}
+
+// Velocities are derived from the movements between updates
+#[cfg_attr(debug_mode, derive(Debug))]
+pub struct Listener {
+    location: Vec3,
+    velocity: Vec3,
+    right: Vec3,
+    is_located: bool,
+}
+
+impl Listener {
+    pub fn new() -> Self {
+        Listener {
+            location: Vec3::zero(),
+            velocity: Vec3::zero(),
+            right: Vec3::unit_x(),
+            is_located: false,
+        }
+    }
+
+    // Delta is in seconds
+    pub fn update(&mut self, location: &Vec3, right: &Vec3, delta: Real) {
+        self.velocity = if self.is_located && delta > 0.0 {
+            (location - self.location) / delta
+        } else {
+            Vec3::zero()
+        };
+        self.location = *location;
+        self.right = *right;
+        self.is_located = true;
+    }
+
+    pub fn update_with_camera(&mut self, camera: &Camera, delta: Real) {
+        let right = camera.get_uniform().get_x_axis();
+        self.update(&camera.get_location(), &right, delta);
+    }
+
+    // Next update does not produce velocity, it is needed after teleports
+    pub fn reset_velocity(&mut self) {
+        self.velocity = Vec3::zero();
+        self.is_located = false;
+    }
+
+    pub fn get_location(&self) -> &Vec3 {
+        return &self.location;
+    }
+
+    pub fn get_velocity(&self) -> &Vec3 {
+        return &self.velocity;
+    }
+
+    pub fn get_right(&self) -> &Vec3 {
+        return &self.right;
+    }
+}
+
+#[cfg_attr(debug_mode, derive(Debug))]
+enum Attachment {
+    Model(Weak<RwLock<Model>>),
+    Light(Weak<RwLock<Light>>),
+}
+
+// A positional source of sound
+#[cfg_attr(debug_mode, derive(Debug))]
+pub struct Emitter {
+    // When emitter is attached to a model these are relative to the model,
+    // for a light only the location is relative.
+    pub location: Vec3,
+    // Zero direction means the emitter is omni-directional and cone is ignored
+    pub direction: Vec3,
+    pub attenuation: Attenuation,
+    pub cone: Option<Cone>,
+    // Zero disables the doppler effect
+    pub doppler_factor: Real,
+    attachment: Option<Attachment>,
+    world_location: Vec3,
+    world_direction: Vec3,
+    velocity: Vec3,
+    is_located: bool,
+}
+
+impl Emitter {
+    pub fn new(location: Vec3) -> Self {
+        Emitter {
+            location,
+            direction: Vec3::zero(),
+            attenuation: Attenuation::default(),
+            cone: None,
+            doppler_factor: 1.0,
+            attachment: None,
+            world_location: location,
+            world_direction: Vec3::zero(),
+            velocity: Vec3::zero(),
+            is_located: false,
+        }
+    }
+
+    pub fn attach_model(&mut self, model: &Arc<RwLock<Model>>) {
+        self.attachment = Some(Attachment::Model(Arc::downgrade(model)));
+    }
+
+    pub fn attach_light(&mut self, light: &Arc<RwLock<Light>>) {
+        self.attachment = Some(Attachment::Light(Arc::downgrade(light)));
+    }
+
+    pub fn detach(&mut self) {
+        self.attachment = None;
+    }
+
+    // Returns false when the attached object is dropped, delta is in seconds
+    pub fn update(&mut self, delta: Real) -> bool {
+        let (location, direction) = match &self.attachment {
+            &None => (self.location, self.direction),
+            &Some(Attachment::Model(ref model)) => {
+                let model = match model.upgrade() {
+                    Some(model) => model,
+                    None => return false,
+                };
+                let m = *vxresult!(model.read()).get_uniform().get_model();
+                (
+                    (m * self.location.extend(1.0)).truncate(),
+                    (m * self.direction.extend(0.0)).truncate(),
+                )
+            }
+            &Some(Attachment::Light(ref light)) => {
+                let light = match light.upgrade() {
+                    Some(light) => light,
+                    None => return false,
+                };
+                let l = vxresult!(light.read()).get_location();
+                (l + self.location, self.direction)
+            }
+        };
+        self.velocity = if self.is_located && delta > 0.0 {
+            (location - self.world_location) / delta
+        } else {
+            Vec3::zero()
+        };
+        self.world_location = location;
+        self.world_direction = direction;
+        self.is_located = true;
+        return true;
+    }
+
+    pub fn get_world_location(&self) -> &Vec3 {
+        return &self.world_location;
+    }
+
+    pub fn get_velocity(&self) -> &Vec3 {
+        return &self.velocity;
+    }
+
+    // Pan is equal power and it only distinguishes left and right of listener
+    pub fn spatialize(&self, listener: &Listener, speed_of_sound: Real) -> Spatial {
+        let to_source = self.world_location - listener.location;
+        let distance = to_source.magnitude();
+        let mut gain = self.attenuation.get_gain(distance);
+        let mut pan = 0.0;
+        let mut pitch = 1.0;
+        if distance > EPSILON {
+            let to_listener = -to_source / distance;
+            pan = -to_listener.dot(listener.right).max(-1.0).min(1.0);
+            if let Some(cone) = &self.cone {
+                if self.world_direction.magnitude2() > EPSILON {
+                    gain *= cone.get_gain(&self.world_direction.normalize(), &to_listener);
+                }
+            }
+            if self.doppler_factor > 0.0 && speed_of_sound > 0.0 {
+                // Speeds along the line from source to listener,
+                // they are limited to keep the pitch finite.
+                let limit = speed_of_sound * 0.99;
+                let listener_speed = (listener.velocity.dot(to_listener) * self.doppler_factor)
+                    .max(-limit)
+                    .min(limit);
+                let source_speed = (self.velocity.dot(to_listener) * self.doppler_factor)
+                    .max(-limit)
+                    .min(limit);
+                pitch = ((speed_of_sound - listener_speed) / (speed_of_sound - source_speed))
+                    .max(MIN_DOPPLER_PITCH)
+                    .min(MAX_DOPPLER_PITCH);
+            }
+        }
+        let angle = (pan + 1.0) * PI * 0.25;
+        Spatial {
+            gains: [angle.cos() * gain, angle.sin() * gain],
+            pitch,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::backend::{Backend, WavFile};
+    use super::super::decoder::new_decoder;
+    use super::super::mixer::Mixer;
+    use super::super::Sound;
+    use super::*;
+    use std::io::Cursor;
+
+    fn close(a: Real, b: Real) -> bool {
+        return (a - b).abs() < 1e-4;
+    }
+
+    #[test]
+    fn attenuation_test() {
+        let inverse = Attenuation::new(AttenuationModel::Inverse, 2.0, 10.0, 1.0);
+        assert!(close(inverse.get_gain(1.0), 1.0));
+        assert!(close(inverse.get_gain(4.0), 0.5));
+        assert!(close(inverse.get_gain(100.0), 0.2));
+        let linear = Attenuation::new(AttenuationModel::Linear, 2.0, 10.0, 1.0);
+        assert!(close(linear.get_gain(6.0), 0.5));
+        assert!(close(linear.get_gain(20.0), 0.0));
+        let exponential = Attenuation::new(AttenuationModel::Exponential, 1.0, 10.0, 2.0);
+        assert!(close(exponential.get_gain(2.0), 0.25));
+        let none = Attenuation::new(AttenuationModel::None, 1.0, 10.0, 2.0);
+        assert!(close(none.get_gain(5.0), 1.0));
+    }
+
+    #[test]
+    fn cone_test() {
+        let cone = Cone::new(PI * 0.5, PI, 0.2);
+        let forward = Vec3::unit_z();
+        assert!(close(cone.get_gain(&forward, &forward), 1.0));
+        assert!(close(cone.get_gain(&forward, &Vec3::unit_x()), 0.2));
+        assert!(close(cone.get_gain(&forward, &-forward), 0.2));
+        let middle = Vec3::new(0.0, (PI * 0.375).sin(), (PI * 0.375).cos());
+        assert!(close(cone.get_gain(&forward, &middle), 0.6));
+    }
+
+    #[test]
+    fn pan_test() {
+        let mut listener = Listener::new();
+        listener.update(&Vec3::zero(), &Vec3::unit_x(), 0.0);
+        let mut emitter = Emitter::new(Vec3::new(1.0, 0.0, 0.0));
+        emitter.attenuation.model = AttenuationModel::None;
+        emitter.update(0.0);
+        let s = emitter.spatialize(&listener, DEFAULT_SPEED_OF_SOUND);
+        assert!(close(s.gains[0], 0.0));
+        assert!(close(s.gains[1], 1.0));
+        assert!(close(s.pitch, 1.0));
+        emitter.location = Vec3::new(0.0, 0.0, -1.0);
+        emitter.update(0.0);
+        let s = emitter.spatialize(&listener, DEFAULT_SPEED_OF_SOUND);
+        assert!(close(s.gains[0], s.gains[1]));
+        assert!(close(
+            s.gains[0] * s.gains[0] + s.gains[1] * s.gains[1],
+            1.0
+        ));
+        // directional source that looks away from listener
+        emitter.direction = Vec3::new(0.0, 0.0, -1.0);
+        emitter.cone = Some(Cone::new(PI * 0.5, PI, 0.5));
+        emitter.update(0.0);
+        let s = emitter.spatialize(&listener, DEFAULT_SPEED_OF_SOUND);
+        assert!(close(
+            s.gains[0] * s.gains[0] + s.gains[1] * s.gains[1],
+            0.25
+        ));
+    }
+
+    #[test]
+    fn doppler_test() {
+        let mut listener = Listener::new();
+        listener.update(&Vec3::zero(), &Vec3::unit_x(), 0.0);
+        let mut emitter = Emitter::new(Vec3::new(0.0, 0.0, -100.0));
+        emitter.update(0.0);
+        // source comes toward listener with a tenth of speed of sound
+        emitter.location.z += 10.0;
+        emitter.update(1.0);
+        let s = emitter.spatialize(&listener, 100.0);
+        assert!(close(s.pitch, 100.0 / 90.0));
+        // listener moves away with the same speed
+        listener.update(&Vec3::new(0.0, 0.0, 10.0), &Vec3::unit_x(), 1.0);
+        let s = emitter.spatialize(&listener, 100.0);
+        assert!(close(s.pitch, 1.0));
+        emitter.doppler_factor = 0.0;
+        listener.update(&Vec3::zero(), &Vec3::unit_x(), 1.0);
+        let s = emitter.spatialize(&listener, 100.0);
+        assert!(close(s.pitch, 1.0));
+    }
+
+    #[test]
+    fn render_test() {
+        let mut listener = Listener::new();
+        listener.update(&Vec3::zero(), &Vec3::unit_x(), 0.0);
+        let mut emitter = Emitter::new(Vec3::new(-2.0, 0.0, 0.0));
+        emitter.update(0.0);
+        let mut mixer = Mixer::new(100, 2);
+        let sound = Arc::new(Sound::new(1, 100, vec![1.0; 100]));
+        let id = mixer.play(&sound, 1.0, true);
+        mixer.set_spatial(
+            id,
+            Some(emitter.spatialize(&listener, DEFAULT_SPEED_OF_SOUND)),
+        );
+        let mut wav = WavFile::new(Cursor::new(Vec::new()), 100, 2);
+        let mut output = vec![0.0; 20];
+        mixer.mix(&mut output);
+        wav.write(&output);
+        wav.finish();
+        let mut decoder = vxunwrap!(new_decoder(wav.get_writer().get_ref().clone()));
+        let mut samples = Vec::new();
+        assert_eq!(decoder.decode(10, &mut samples), 10);
+        for frame in samples.chunks(2) {
+            // source is in the left of listener and two units away
+            assert!((frame[0] - 0.5).abs() < 1e-3);
+            assert!(frame[1].abs() < 1e-3);
+        }
+    }
+}
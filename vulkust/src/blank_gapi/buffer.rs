@@ -28,14 +28,28 @@ impl Dynamic {
         self.update_with_ptr(unsafe { transmute(data) }, frame_number);
     }
 
+    pub(crate) fn update_with_vec<T>(&mut self, _data: &[T], _frame_number: usize) {
+        vxunimplemented!();
+    }
+
     pub(crate) fn get_buffer(&self, _frame_number: usize) -> &Arc<RwLock<Buffer>> {
         vxunimplemented!();
     }
+
+    pub(crate) fn get_size(&self) -> isize {
+        return self.actual_size;
+    }
 }
 
 #[cfg_attr(debug_mode, derive(Debug))]
 pub(crate) struct Static {}
 
+impl Static {
+    pub(crate) fn get_buffer(&self) -> &Arc<RwLock<Buffer>> {
+        vxunimplemented!();
+    }
+}
+
 #[cfg_attr(debug_mode, derive(Debug))]
 pub(crate) struct Manager {}
 
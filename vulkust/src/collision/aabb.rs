@@ -1,4 +1,5 @@
 use super::super::core::types::Real;
+use super::super::math::ray::Ray3;
 
 use std::f32::MAX as F32MAX;
 use std::f32::MIN as F32MIN;
@@ -7,7 +8,7 @@ type Vec3 = cgmath::Vector3<Real>;
 
 #[derive(Clone, Copy)]
 #[cfg_attr(debug_mode, derive(Debug))]
-pub(crate) struct Aabb3 {
+pub struct Aabb3 {
     max: Vec3,
     min: Vec3,
 }
@@ -16,21 +17,21 @@ pub(crate) struct Aabb3 {
 // performance got a little decrease for better functionality
 
 impl Aabb3 {
-    pub(crate) fn new() -> Self {
+    pub fn new() -> Self {
         Self {
             max: Vec3::new(F32MIN, F32MIN, F32MIN),
             min: Vec3::new(F32MAX, F32MAX, F32MAX),
         }
     }
 
-    // pub(crate) fn new_with_points(pmin: &Vec3, pmax: &Vec3) -> Self {
-    //     Self {
-    //         max: *pmax,
-    //         min: *pmin,
-    //     }
-    // }
+    pub fn new_with_points(pmin: &Vec3, pmax: &Vec3) -> Self {
+        Self {
+            max: *pmax,
+            min: *pmin,
+        }
+    }
 
-    pub(crate) fn new_with_center_radius(c: &Vec3, r: Real) -> Self {
+    pub fn new_with_center_radius(c: &Vec3, r: Real) -> Self {
         let r = Vec3::new(r, r, r);
         Self {
             max: c + r,
@@ -38,7 +39,7 @@ impl Aabb3 {
         }
     }
 
-    pub(crate) fn insert(&mut self, p: &Vec3) {
+    pub fn insert(&mut self, p: &Vec3) {
         if p.x < self.min.x {
             self.min.x = p.x;
         }
@@ -59,7 +60,7 @@ impl Aabb3 {
         }
     }
 
-    pub(crate) fn insert_aabb(&mut self, o: &Self) {
+    pub fn insert_aabb(&mut self, o: &Self) {
         if o.min.x < self.min.x {
             self.min.x = o.min.x;
         }
@@ -80,18 +81,42 @@ impl Aabb3 {
         }
     }
 
-    // pub(crate) fn intersects_aabb(&self, o: &Self) -> bool {
-    //     let mr = (self.max - self.min) * 0.5;
-    //     let mc = (self.max + self.min) * 0.5;
-    //     let or = (o.max - o.min) * 0.5;
-    //     let oc = (o.max + o.min) * 0.5;
-    //     let r = or + mr;
-    //     let d = mc - oc;
-    //     let d = Vec3::new(d.x.abs(), d.y.abs(), d.z.abs());
-    //     return d.x < r.x || d.y < r.y || d.z < r.z;
-    // }
+    pub fn intersects_aabb(&self, o: &Self) -> bool {
+        return self.min.x <= o.max.x
+            && o.min.x <= self.max.x
+            && self.min.y <= o.max.y
+            && o.min.y <= self.max.y
+            && self.min.z <= o.max.z
+            && o.min.z <= self.max.z;
+    }
+
+    pub fn contains_aabb(&self, o: &Self) -> bool {
+        return self.min.x <= o.min.x
+            && o.max.x <= self.max.x
+            && self.min.y <= o.min.y
+            && o.max.y <= self.max.y
+            && self.min.z <= o.min.z
+            && o.max.z <= self.max.z;
+    }
+
+    pub fn contains_point(&self, p: &Vec3) -> bool {
+        return self.min.x <= p.x
+            && p.x <= self.max.x
+            && self.min.y <= p.y
+            && p.y <= self.max.y
+            && self.min.z <= p.z
+            && p.z <= self.max.z;
+    }
+
+    pub fn get_closest_point(&self, p: &Vec3) -> Vec3 {
+        return Vec3::new(
+            p.x.max(self.min.x).min(self.max.x),
+            p.y.max(self.min.y).min(self.max.y),
+            p.z.max(self.min.z).min(self.max.z),
+        );
+    }
 
-    pub(crate) fn get_intersection_with_aabb(&self, o: &Self) -> Self {
+    pub fn get_intersection_with_aabb(&self, o: &Self) -> Self {
         return Self {
             min: Vec3::new(
                 o.min.x.max(self.min.x),
@@ -106,28 +131,120 @@ impl Aabb3 {
         };
     }
 
-    pub(crate) fn intersects_center_radius(&self, c: &Vec3, r: Real) -> bool {
-        let mr = (self.max - self.min) * 0.5;
-        let mc = (self.max + self.min) * 0.5;
-        let r = mr + Vec3::new(r, r, r);
-        let d = mc - c;
-        let d = Vec3::new(d.x.abs(), d.y.abs(), d.z.abs());
-        return d.x < r.x || d.y < r.y || d.z < r.z;
+    pub fn intersects_center_radius(&self, c: &Vec3, r: Real) -> bool {
+        let d = self.get_closest_point(c) - c;
+        return d.x * d.x + d.y * d.y + d.z * d.z <= r * r;
     }
 
-    pub(crate) fn get_min_max_diff(&self) -> Vec3 {
+    // Slab test, returns distance of entrance, it is zero when origin is inside
+    pub fn intersects_ray(&self, r: &Ray3, tmax: Real) -> Option<Real> {
+        let mut tmin: Real = 0.0;
+        let mut tmax = tmax;
+        for i in 0..3 {
+            let t1 = (self.min[i] - r.o[i]) * r.invd[i];
+            let t2 = (self.max[i] - r.o[i]) * r.invd[i];
+            // NaN happens when origin is on the slab border and ray is parallel to it
+            if t1.is_nan() || t2.is_nan() {
+                continue;
+            }
+            tmin = tmin.max(t1.min(t2));
+            tmax = tmax.min(t1.max(t2));
+            if tmax < tmin {
+                return None;
+            }
+        }
+        return Some(tmin);
+    }
+
+    pub fn get_min_max_diff(&self) -> Vec3 {
         return self.max - self.min;
     }
 
-    pub(crate) fn get_center(&self) -> Vec3 {
+    pub fn get_center(&self) -> Vec3 {
         return (self.min + self.max) * 0.5;
     }
 
-    pub(crate) fn get_max(&self) -> Vec3 {
+    pub fn get_max(&self) -> Vec3 {
         return self.max;
     }
 
-    // pub(crate) fn get_min(&self) -> Vec3 {
-    //     return self.min;
-    // }
+    pub fn get_min(&self) -> Vec3 {
+        return self.min;
+    }
+
+    // Returns the box that bounds the transformed box, it is not exact for rotations
+    pub fn get_transformed(&self, m: &cgmath::Matrix4<Real>) -> Self {
+        let c = (m * self.get_center().extend(1.0)).truncate();
+        let h = self.get_min_max_diff() * 0.5;
+        let mut e = Vec3::new(0.0, 0.0, 0.0);
+        for i in 0..3 {
+            let a = m[i].truncate() * h[i];
+            e += Vec3::new(a.x.abs(), a.y.abs(), a.z.abs());
+        }
+        return Self {
+            max: c + e,
+            min: c - e,
+        };
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use cgmath::InnerSpace;
+
+    #[test]
+    fn aabb_aabb_test() {
+        let a = Aabb3::new_with_points(&Vec3::new(0.0, 0.0, 0.0), &Vec3::new(2.0, 2.0, 2.0));
+        let b = Aabb3::new_with_points(&Vec3::new(1.0, 1.0, 1.0), &Vec3::new(3.0, 3.0, 3.0));
+        let c = Aabb3::new_with_points(&Vec3::new(1.0, 5.0, 1.0), &Vec3::new(3.0, 6.0, 3.0));
+        let d = Aabb3::new_with_points(&Vec3::new(0.5, 0.5, 0.5), &Vec3::new(1.0, 1.0, 1.0));
+        assert!(a.intersects_aabb(&b));
+        assert!(b.intersects_aabb(&a));
+        // overlaps on x and z but not on y
+        assert!(!a.intersects_aabb(&c));
+        assert!(a.intersects_aabb(&d));
+        assert!(a.contains_aabb(&d));
+        assert!(!a.contains_aabb(&b));
+        let i = a.get_intersection_with_aabb(&b);
+        assert_eq!(i.get_min(), Vec3::new(1.0, 1.0, 1.0));
+        assert_eq!(i.get_max(), Vec3::new(2.0, 2.0, 2.0));
+    }
+
+    #[test]
+    fn aabb_sphere_test() {
+        let a = Aabb3::new_with_points(&Vec3::new(0.0, 0.0, 0.0), &Vec3::new(1.0, 1.0, 1.0));
+        assert!(a.intersects_center_radius(&Vec3::new(0.5, 0.5, 0.5), 0.1));
+        assert!(a.intersects_center_radius(&Vec3::new(1.5, 0.5, 0.5), 0.6));
+        assert!(!a.intersects_center_radius(&Vec3::new(1.5, 0.5, 0.5), 0.4));
+        // near the corner, the box of sphere intersects but the sphere itself does not
+        assert!(!a.intersects_center_radius(&Vec3::new(1.5, 1.5, 1.5), 0.8));
+        assert!(a.intersects_center_radius(&Vec3::new(1.5, 1.5, 1.5), 0.9));
+        // overlapping on one axis only
+        assert!(!a.intersects_center_radius(&Vec3::new(0.5, 5.0, 5.0), 1.0));
+    }
+
+    #[test]
+    fn aabb_ray_test() {
+        let a = Aabb3::new_with_points(&Vec3::new(-1.0, -1.0, -1.0), &Vec3::new(1.0, 1.0, 1.0));
+        let r = Ray3::new(Vec3::new(-5.0, 0.0, 0.0), Vec3::new(1.0, 0.0, 0.0));
+        assert!((a.intersects_ray(&r, 100.0).unwrap() - 4.0).abs() < 1e-5);
+        assert!(a.intersects_ray(&r, 3.0).is_none());
+        let r = Ray3::new(Vec3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 0.0, 1.0));
+        assert_eq!(a.intersects_ray(&r, 100.0), Some(0.0));
+        let r = Ray3::new(Vec3::new(-5.0, 0.0, 0.0), Vec3::new(-1.0, 0.0, 0.0));
+        assert!(a.intersects_ray(&r, 100.0).is_none());
+        let r = Ray3::new(Vec3::new(-5.0, 2.0, 0.0), Vec3::new(1.0, 0.0, 0.0));
+        assert!(a.intersects_ray(&r, 100.0).is_none());
+    }
+
+    #[test]
+    fn aabb_transformation_test() {
+        let a = Aabb3::new_with_points(&Vec3::new(-1.0, -1.0, -1.0), &Vec3::new(1.0, 1.0, 1.0));
+        let m = cgmath::Matrix4::from_translation(Vec3::new(3.0, 0.0, 0.0))
+            * cgmath::Matrix4::from_angle_z(cgmath::Rad(0.785398163 as Real));
+        let b = a.get_transformed(&m);
+        assert!((b.get_max() - Vec3::new(4.414213562, 1.414213562, 1.0)).magnitude() < 1e-4);
+        assert!((b.get_min() - Vec3::new(1.585786438, -1.414213562, -1.0)).magnitude() < 1e-4);
+    }
 }
@@ -1,24 +1,30 @@
 use super::super::core::types::Real;
+use super::aabb::Aabb3;
 use super::plane::{Plane, PlaneIntersectStatue};
 use cgmath;
 
+#[derive(Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(debug_mode, derive(Debug))]
+pub enum IntersectionStatus {
+    Inside,
+    Intersecting,
+    Outside,
+}
+
+// Normals of planes must be toward outside of frustum
 #[repr(C)]
 #[derive(Clone, Copy)]
 #[cfg_attr(debug_mode, derive(Debug))]
-pub(crate) struct Frustum {
+pub struct Frustum {
     planes: [Plane; 6],
 }
 
 impl Frustum {
-    pub(crate) fn new(planes: [Plane; 6]) -> Self {
+    pub fn new(planes: [Plane; 6]) -> Self {
         Self { planes }
     }
 
-    pub(crate) fn intersects_center_radius(
-        &self,
-        center: &cgmath::Vector3<Real>,
-        radius: Real,
-    ) -> bool {
+    pub fn intersects_center_radius(&self, center: &cgmath::Vector3<Real>, radius: Real) -> bool {
         for f in &self.planes {
             let s = f.intersect_sphere(radius, center);
             match s {
@@ -28,6 +34,26 @@ impl Frustum {
         }
         return true;
     }
+
+    pub fn intersect_aabb(&self, aabb: &Aabb3) -> IntersectionStatus {
+        let mut result = IntersectionStatus::Inside;
+        for f in &self.planes {
+            match f.intersect_aabb(aabb) {
+                PlaneIntersectStatue::Above => return IntersectionStatus::Outside,
+                PlaneIntersectStatue::Intersecting => result = IntersectionStatus::Intersecting,
+                PlaneIntersectStatue::Under => (),
+            }
+        }
+        return result;
+    }
+
+    pub fn intersects_aabb(&self, aabb: &Aabb3) -> bool {
+        return self.intersect_aabb(aabb) != IntersectionStatus::Outside;
+    }
+
+    pub fn get_planes(&self) -> &[Plane; 6] {
+        return &self.planes;
+    }
 }
 
 impl Default for Frustum {
@@ -44,3 +70,35 @@ impl Default for Frustum {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    // A box shaped frustum from (-1, -1, -1) to (1, 1, 1)
+    fn cube() -> Frustum {
+        let p = |x, y, z| cgmath::Vector3::new(x, y, z);
+        Frustum::new([
+            Plane::new_with_point_normal(p(1.0, 0.0, 0.0), p(1.0, 0.0, 0.0)),
+            Plane::new_with_point_normal(p(-1.0, 0.0, 0.0), p(-1.0, 0.0, 0.0)),
+            Plane::new_with_point_normal(p(0.0, 1.0, 0.0), p(0.0, 1.0, 0.0)),
+            Plane::new_with_point_normal(p(0.0, -1.0, 0.0), p(0.0, -1.0, 0.0)),
+            Plane::new_with_point_normal(p(0.0, 0.0, 1.0), p(0.0, 0.0, 1.0)),
+            Plane::new_with_point_normal(p(0.0, 0.0, -1.0), p(0.0, 0.0, -1.0)),
+        ])
+    }
+
+    #[test]
+    fn frustum_aabb_test() {
+        let f = cube();
+        let b = Aabb3::new_with_center_radius(&cgmath::Vector3::new(0.0, 0.0, 0.0), 0.5);
+        assert!(f.intersect_aabb(&b) == IntersectionStatus::Inside);
+        let b = Aabb3::new_with_center_radius(&cgmath::Vector3::new(1.0, 0.0, 0.0), 0.5);
+        assert!(f.intersect_aabb(&b) == IntersectionStatus::Intersecting);
+        let b = Aabb3::new_with_center_radius(&cgmath::Vector3::new(0.0, 3.0, 0.0), 0.5);
+        assert!(f.intersect_aabb(&b) == IntersectionStatus::Outside);
+        assert!(!f.intersects_aabb(&b));
+        assert!(f.intersects_center_radius(&cgmath::Vector3::new(0.0, 0.0, 1.4), 0.5));
+        assert!(!f.intersects_center_radius(&cgmath::Vector3::new(0.0, 0.0, 1.6), 0.5));
+    }
+}
@@ -1,3 +1,6 @@
-pub(crate) mod aabb;
-pub(crate) mod frustum;
-pub(crate) mod plane;
+pub mod aabb;
+pub mod frustum;
+pub mod obb;
+pub mod plane;
+pub mod sphere;
+pub mod tree;
@@ -0,0 +1,259 @@
+use super::super::core::types::Real;
+use super::super::math::ray::Ray3;
+use super::aabb::Aabb3;
+use cgmath;
+use cgmath::InnerSpace;
+
+type Vec3 = cgmath::Vector3<Real>;
+
+const EPSILON: Real = 1e-6;
+
+#[derive(Clone, Copy)]
+#[cfg_attr(debug_mode, derive(Debug))]
+pub struct Obb3 {
+    center: Vec3,
+    // must be orthonormal
+    axes: [Vec3; 3],
+    half_extents: Vec3,
+}
+
+impl Obb3 {
+    pub fn new(center: Vec3, axes: [Vec3; 3], half_extents: Vec3) -> Self {
+        Self {
+            center,
+            axes: [
+                axes[0].normalize(),
+                axes[1].normalize(),
+                axes[2].normalize(),
+            ],
+            half_extents,
+        }
+    }
+
+    // Transformation must not have shear
+    pub fn new_with_aabb_transformation(aabb: &Aabb3, m: &cgmath::Matrix4<Real>) -> Self {
+        let center = (m * aabb.get_center().extend(1.0)).truncate();
+        let half_extents = aabb.get_min_max_diff() * 0.5;
+        let x = m.x.truncate();
+        let y = m.y.truncate();
+        let z = m.z.truncate();
+        let scale = Vec3::new(x.magnitude(), y.magnitude(), z.magnitude());
+        Self {
+            center,
+            axes: [x / scale.x, y / scale.y, z / scale.z],
+            half_extents: Vec3::new(
+                half_extents.x * scale.x,
+                half_extents.y * scale.y,
+                half_extents.z * scale.z,
+            ),
+        }
+    }
+
+    pub fn get_center(&self) -> &Vec3 {
+        return &self.center;
+    }
+
+    pub fn get_axes(&self) -> &[Vec3; 3] {
+        return &self.axes;
+    }
+
+    pub fn get_half_extents(&self) -> &Vec3 {
+        return &self.half_extents;
+    }
+
+    pub fn get_aabb(&self) -> Aabb3 {
+        let mut e = Vec3::new(0.0, 0.0, 0.0);
+        for i in 0..3 {
+            let a = self.axes[i] * self.half_extents[i];
+            e += Vec3::new(a.x.abs(), a.y.abs(), a.z.abs());
+        }
+        return Aabb3::new_with_points(&(self.center - e), &(self.center + e));
+    }
+
+    pub fn get_closest_point(&self, p: &Vec3) -> Vec3 {
+        let d = p - self.center;
+        let mut result = self.center;
+        for i in 0..3 {
+            let dist = d
+                .dot(self.axes[i])
+                .max(-self.half_extents[i])
+                .min(self.half_extents[i]);
+            result += self.axes[i] * dist;
+        }
+        return result;
+    }
+
+    pub fn intersects_center_radius(&self, c: &Vec3, r: Real) -> bool {
+        return (self.get_closest_point(c) - c).magnitude2() <= r * r;
+    }
+
+    // Separating axis theorem, 3 + 3 face axes and 9 edge cross axes
+    pub fn intersects_obb(&self, o: &Self) -> bool {
+        let mut rot = [[0.0 as Real; 3]; 3];
+        let mut abs_rot = [[0.0 as Real; 3]; 3];
+        for i in 0..3 {
+            for j in 0..3 {
+                rot[i][j] = self.axes[i].dot(o.axes[j]);
+                // epsilon prevents false separation when two edges are parallel
+                abs_rot[i][j] = rot[i][j].abs() + EPSILON;
+            }
+        }
+        let t = o.center - self.center;
+        let t = [
+            t.dot(self.axes[0]),
+            t.dot(self.axes[1]),
+            t.dot(self.axes[2]),
+        ];
+        let a = &self.half_extents;
+        let b = &o.half_extents;
+        for i in 0..3 {
+            let ra = a[i];
+            let rb = b[0] * abs_rot[i][0] + b[1] * abs_rot[i][1] + b[2] * abs_rot[i][2];
+            if t[i].abs() > ra + rb {
+                return false;
+            }
+        }
+        for j in 0..3 {
+            let ra = a[0] * abs_rot[0][j] + a[1] * abs_rot[1][j] + a[2] * abs_rot[2][j];
+            let rb = b[j];
+            let d = t[0] * rot[0][j] + t[1] * rot[1][j] + t[2] * rot[2][j];
+            if d.abs() > ra + rb {
+                return false;
+            }
+        }
+        for i in 0..3 {
+            let i1 = (i + 1) % 3;
+            let i2 = (i + 2) % 3;
+            for j in 0..3 {
+                let j1 = (j + 1) % 3;
+                let j2 = (j + 2) % 3;
+                let ra = a[i1] * abs_rot[i2][j] + a[i2] * abs_rot[i1][j];
+                let rb = b[j1] * abs_rot[i][j2] + b[j2] * abs_rot[i][j1];
+                let d = t[i2] * rot[i1][j] - t[i1] * rot[i2][j];
+                if d.abs() > ra + rb {
+                    return false;
+                }
+            }
+        }
+        return true;
+    }
+
+    pub fn intersects_aabb(&self, aabb: &Aabb3) -> bool {
+        let o = Self {
+            center: aabb.get_center(),
+            axes: [
+                Vec3::new(1.0, 0.0, 0.0),
+                Vec3::new(0.0, 1.0, 0.0),
+                Vec3::new(0.0, 0.0, 1.0),
+            ],
+            half_extents: aabb.get_min_max_diff() * 0.5,
+        };
+        return self.intersects_obb(&o);
+    }
+
+    // Returns distance of entrance, it is zero when origin is inside
+    pub fn intersects_ray(&self, r: &Ray3, tmax: Real) -> Option<Real> {
+        let p = r.o - self.center;
+        let local = Ray3::new(
+            Vec3::new(
+                p.dot(self.axes[0]),
+                p.dot(self.axes[1]),
+                p.dot(self.axes[2]),
+            ),
+            Vec3::new(
+                r.d.dot(self.axes[0]),
+                r.d.dot(self.axes[1]),
+                r.d.dot(self.axes[2]),
+            ),
+        );
+        let aabb = Aabb3::new_with_points(&-self.half_extents, &self.half_extents);
+        return aabb.intersects_ray(&local, tmax);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn rotated_z(center: Vec3, angle: Real, half_extents: Vec3) -> Obb3 {
+        let (s, c) = angle.sin_cos();
+        Obb3::new(
+            center,
+            [
+                Vec3::new(c, s, 0.0),
+                Vec3::new(-s, c, 0.0),
+                Vec3::new(0.0, 0.0, 1.0),
+            ],
+            half_extents,
+        )
+    }
+
+    #[test]
+    fn obb_obb_test() {
+        let h = Vec3::new(1.0, 1.0, 1.0);
+        let a = rotated_z(Vec3::new(0.0, 0.0, 0.0), 0.0, h);
+        // rotated 45 degree, its corner reaches to 1.414 along x
+        let b = rotated_z(Vec3::new(2.3, 0.0, 0.0), 0.785398163, h);
+        assert!(a.intersects_obb(&b));
+        assert!(b.intersects_obb(&a));
+        let b = rotated_z(Vec3::new(2.5, 0.0, 0.0), 0.785398163, h);
+        assert!(!a.intersects_obb(&b));
+        // their AABBs overlap but they are separated diagonally
+        let a = rotated_z(
+            Vec3::new(0.0, 0.0, 0.0),
+            0.785398163,
+            Vec3::new(2.0, 0.2, 1.0),
+        );
+        let b = rotated_z(
+            Vec3::new(1.0, -1.0, 0.0),
+            0.785398163,
+            Vec3::new(2.0, 0.2, 1.0),
+        );
+        assert!(a.get_aabb().intersects_aabb(&b.get_aabb()));
+        assert!(!a.intersects_obb(&b));
+        // parallel boxes
+        let b = rotated_z(
+            Vec3::new(0.0, 0.3, 0.0),
+            0.785398163,
+            Vec3::new(2.0, 0.2, 1.0),
+        );
+        assert!(a.intersects_obb(&b));
+    }
+
+    #[test]
+    fn obb_transformation_test() {
+        let aabb = Aabb3::new_with_points(&Vec3::new(-1.0, -1.0, -1.0), &Vec3::new(1.0, 1.0, 1.0));
+        let m = cgmath::Matrix4::from_translation(Vec3::new(5.0, 0.0, 0.0))
+            * cgmath::Matrix4::from_angle_z(cgmath::Rad(0.785398163 as Real))
+            * cgmath::Matrix4::from_scale(2.0);
+        let o = Obb3::new_with_aabb_transformation(&aabb, &m);
+        assert!((o.get_center() - Vec3::new(5.0, 0.0, 0.0)).magnitude() < 1e-5);
+        assert!((o.get_half_extents() - Vec3::new(2.0, 2.0, 2.0)).magnitude() < 1e-5);
+        let b = o.get_aabb();
+        assert!((b.get_max().x - (5.0 + 2.0 * 1.414213562)).abs() < 1e-4);
+        assert!(o.intersects_center_radius(&Vec3::new(5.0, 3.0, 0.0), 0.2));
+        assert!(!o.intersects_center_radius(&Vec3::new(7.0, 2.0, 0.0), 0.5));
+        assert!(o.intersects_aabb(&Aabb3::new_with_center_radius(
+            &Vec3::new(5.0, 3.2, 0.0),
+            0.5
+        )));
+        assert!(!o.intersects_aabb(&Aabb3::new_with_center_radius(
+            &Vec3::new(7.0, 2.0, 0.0),
+            0.4
+        )));
+    }
+
+    #[test]
+    fn obb_ray_test() {
+        let o = rotated_z(
+            Vec3::new(5.0, 0.0, 0.0),
+            0.785398163,
+            Vec3::new(1.0, 1.0, 1.0),
+        );
+        let r = Ray3::new(Vec3::new(0.0, 0.0, 0.0), Vec3::new(1.0, 0.0, 0.0));
+        let t = o.intersects_ray(&r, 100.0).unwrap();
+        assert!((t - (5.0 - 1.414213562)).abs() < 1e-4);
+        let r = Ray3::new(Vec3::new(0.0, 1.5, 0.0), Vec3::new(1.0, 0.0, 0.0));
+        assert!(o.intersects_ray(&r, 100.0).is_none());
+    }
+}
@@ -1,11 +1,13 @@
 use super::super::core::types::Real;
+use super::super::math::ray::Ray3;
+use super::aabb::Aabb3;
 use cgmath;
 use cgmath::InnerSpace;
 
 #[repr(C)]
 #[derive(Clone, Copy)]
 #[cfg_attr(debug_mode, derive(Debug))]
-pub(crate) struct Plane {
+pub struct Plane {
     n: cgmath::Vector3<Real>,
     p: cgmath::Vector3<Real>,
     d: Real,
@@ -13,14 +15,14 @@ pub(crate) struct Plane {
 
 #[repr(u8)]
 #[cfg_attr(debug_mode, derive(Debug))]
-pub(crate) enum PlaneIntersectStatue {
+pub enum PlaneIntersectStatue {
     Above,
     Intersecting,
     Under,
 }
 
 impl Plane {
-    pub(crate) fn new(
+    pub fn new(
         p: cgmath::Vector3<Real>,
         f: cgmath::Vector3<Real>,
         s: cgmath::Vector3<Real>,
@@ -32,16 +34,21 @@ impl Plane {
         Self { n, p, d }
     }
 
-    // pub(crate) fn new_with_point_normal(
-    //     p: cgmath::Vector3<Real>,
-    //     n: cgmath::Vector3<Real>,
-    // ) -> Self {
-    //     let n = n.normalize();
-    //     let d = -(n.dot(p));
-    //     Self { n, p, d }
-    // }
+    pub fn new_with_point_normal(p: cgmath::Vector3<Real>, n: cgmath::Vector3<Real>) -> Self {
+        let n = n.normalize();
+        let d = -(n.dot(p));
+        Self { n, p, d }
+    }
+
+    pub fn get_normal(&self) -> &cgmath::Vector3<Real> {
+        return &self.n;
+    }
+
+    pub fn get_distance(&self, p: &cgmath::Vector3<Real>) -> Real {
+        return self.n.dot(*p) + self.d;
+    }
 
-    pub(crate) fn intersect_sphere(
+    pub fn intersect_sphere(
         &self,
         radius: Real,
         center: &cgmath::Vector3<Real>,
@@ -56,6 +63,27 @@ impl Plane {
         return PlaneIntersectStatue::Intersecting;
     }
 
+    pub fn intersect_aabb(&self, aabb: &Aabb3) -> PlaneIntersectStatue {
+        let c = aabb.get_center();
+        let e = aabb.get_max() - c;
+        // projection radius of box on the normal
+        let r = e.x * self.n.x.abs() + e.y * self.n.y.abs() + e.z * self.n.z.abs();
+        return self.intersect_sphere(r, &c);
+    }
+
+    // Returns distance along the ray, the back side of plane is hittable too
+    pub fn intersect_ray(&self, r: &Ray3, tmax: Real) -> Option<Real> {
+        let dn = self.n.dot(r.d);
+        if dn.abs() < 1e-7 {
+            return None;
+        }
+        let t = -(self.n.dot(r.o) + self.d) / dn;
+        if t < 0.0 || t > tmax {
+            return None;
+        }
+        return Some(t);
+    }
+
     // pub(crate) fn translate(&mut self, l: &cgmath::Vector3<Real>) {
     //     self.p += *l;
     //     self.d = -(self.n.dot(self.p));
@@ -85,3 +113,38 @@ impl Default for Plane {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn plane_test() {
+        let p = Plane::new_with_point_normal(
+            cgmath::Vector3::new(0.0, 1.0, 0.0),
+            cgmath::Vector3::new(0.0, 2.0, 0.0),
+        );
+        assert!((p.get_distance(&cgmath::Vector3::new(5.0, 3.0, -1.0)) - 2.0).abs() < 1e-5);
+        let r = Ray3::new(
+            cgmath::Vector3::new(0.0, 5.0, 0.0),
+            cgmath::Vector3::new(0.0, -1.0, 0.0),
+        );
+        assert!((p.intersect_ray(&r, 100.0).unwrap() - 4.0).abs() < 1e-5);
+        assert!(p.intersect_ray(&r, 3.0).is_none());
+        let r = Ray3::new(
+            cgmath::Vector3::new(0.0, 5.0, 0.0),
+            cgmath::Vector3::new(1.0, 0.0, 0.0),
+        );
+        assert!(p.intersect_ray(&r, 100.0).is_none());
+        let b = Aabb3::new_with_center_radius(&cgmath::Vector3::new(0.0, 3.0, 0.0), 1.0);
+        match p.intersect_aabb(&b) {
+            PlaneIntersectStatue::Above => (),
+            _ => panic!("Box must be above the plane."),
+        }
+        let b = Aabb3::new_with_center_radius(&cgmath::Vector3::new(0.0, 1.5, 0.0), 1.0);
+        match p.intersect_aabb(&b) {
+            PlaneIntersectStatue::Intersecting => (),
+            _ => panic!("Box must intersect the plane."),
+        }
+    }
+}
@@ -0,0 +1,114 @@
+use super::super::core::types::Real;
+use super::super::math::ray::Ray3;
+use super::aabb::Aabb3;
+use cgmath;
+use cgmath::InnerSpace;
+
+type Vec3 = cgmath::Vector3<Real>;
+
+#[derive(Clone, Copy)]
+#[cfg_attr(debug_mode, derive(Debug))]
+pub struct Sphere {
+    center: Vec3,
+    radius: Real,
+}
+
+impl Sphere {
+    pub fn new(center: Vec3, radius: Real) -> Self {
+        Self { center, radius }
+    }
+
+    pub fn get_center(&self) -> &Vec3 {
+        return &self.center;
+    }
+
+    pub fn get_radius(&self) -> Real {
+        return self.radius;
+    }
+
+    pub fn get_aabb(&self) -> Aabb3 {
+        return Aabb3::new_with_center_radius(&self.center, self.radius);
+    }
+
+    pub fn intersects_sphere(&self, o: &Self) -> bool {
+        let r = self.radius + o.radius;
+        return (self.center - o.center).magnitude2() <= r * r;
+    }
+
+    pub fn intersects_aabb(&self, aabb: &Aabb3) -> bool {
+        return aabb.intersects_center_radius(&self.center, self.radius);
+    }
+
+    pub fn contains_point(&self, p: &Vec3) -> bool {
+        return (self.center - p).magnitude2() <= self.radius * self.radius;
+    }
+
+    // Returns distance of entrance, it is zero when origin is inside
+    pub fn intersects_ray(&self, r: &Ray3, tmax: Real) -> Option<Real> {
+        let m = r.o - self.center;
+        let a = r.d.magnitude2();
+        let b = m.dot(r.d);
+        let c = m.magnitude2() - self.radius * self.radius;
+        if c <= 0.0 {
+            return Some(0.0);
+        }
+        if b > 0.0 {
+            return None;
+        }
+        let disc = b * b - a * c;
+        if disc < 0.0 {
+            return None;
+        }
+        let t = (-b - disc.sqrt()) / a;
+        if t > tmax {
+            return None;
+        }
+        return Some(t);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn sphere_sphere_test() {
+        let a = Sphere::new(Vec3::new(0.0, 0.0, 0.0), 1.0);
+        let b = Sphere::new(Vec3::new(1.5, 0.0, 0.0), 0.6);
+        let c = Sphere::new(Vec3::new(0.0, 2.0, 0.0), 0.9);
+        assert!(a.intersects_sphere(&b));
+        assert!(b.intersects_sphere(&a));
+        assert!(!a.intersects_sphere(&c));
+        assert!(a.contains_point(&Vec3::new(0.5, 0.5, 0.5)));
+        assert!(!a.contains_point(&Vec3::new(0.7, 0.7, 0.7)));
+    }
+
+    #[test]
+    fn sphere_aabb_test() {
+        let s = Sphere::new(Vec3::new(2.0, 2.0, 0.0), 1.0);
+        let a = Aabb3::new_with_points(&Vec3::new(0.0, 0.0, -1.0), &Vec3::new(1.2, 1.2, 1.0));
+        assert!(!s.intersects_aabb(&a));
+        let a = Aabb3::new_with_points(&Vec3::new(0.0, 0.0, -1.0), &Vec3::new(1.5, 1.5, 1.0));
+        assert!(s.intersects_aabb(&a));
+        let b = s.get_aabb();
+        assert_eq!(b.get_min(), Vec3::new(1.0, 1.0, -1.0));
+        assert_eq!(b.get_max(), Vec3::new(3.0, 3.0, 1.0));
+    }
+
+    #[test]
+    fn sphere_ray_test() {
+        let s = Sphere::new(Vec3::new(0.0, 0.0, -5.0), 1.0);
+        let r = Ray3::new(Vec3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 0.0, -1.0));
+        assert!((s.intersects_ray(&r, 100.0).unwrap() - 4.0).abs() < 1e-5);
+        assert!(s.intersects_ray(&r, 3.0).is_none());
+        // not normalized direction, distance is in the unit of direction length
+        let r = Ray3::new(Vec3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 0.0, -2.0));
+        assert!((s.intersects_ray(&r, 100.0).unwrap() - 2.0).abs() < 1e-5);
+        let r = Ray3::new(Vec3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 0.0, 1.0));
+        assert!(s.intersects_ray(&r, 100.0).is_none());
+        let r = Ray3::new(Vec3::new(0.0, 1.5, 0.0), Vec3::new(0.0, 0.0, -1.0));
+        assert!(s.intersects_ray(&r, 100.0).is_none());
+        let r = Ray3::new(Vec3::new(0.0, 0.0, -5.0), Vec3::new(1.0, 0.0, 0.0));
+        assert_eq!(s.intersects_ray(&r, 100.0), Some(0.0));
+    }
+}
@@ -0,0 +1,486 @@
+use super::super::core::types::Real;
+use super::super::math::ray::Ray3;
+use super::aabb::Aabb3;
+use super::frustum::{Frustum, IntersectionStatus};
+
+type Vec3 = cgmath::Vector3<Real>;
+
+#[cfg_attr(debug_mode, derive(Debug))]
+struct Node<T> {
+    aabb: Aabb3,
+    parent: Option<usize>,
+    children: Option<(usize, usize)>,
+    data: Option<T>,
+}
+
+// Dynamic AABB tree, leaves keep a fattened AABB so small movements
+// do not need any change in the structure of tree.
+#[cfg_attr(debug_mode, derive(Debug))]
+pub struct DynamicTree<T> {
+    nodes: Vec<Node<T>>,
+    free_nodes: Vec<usize>,
+    root: Option<usize>,
+    margin: Real,
+    leaves_count: usize,
+}
+
+fn get_area(aabb: &Aabb3) -> Real {
+    let d = aabb.get_min_max_diff();
+    return 2.0 * (d.x * d.y + d.y * d.z + d.z * d.x);
+}
+
+fn get_union(a: &Aabb3, b: &Aabb3) -> Aabb3 {
+    let mut u = *a;
+    u.insert_aabb(b);
+    return u;
+}
+
+impl<T> DynamicTree<T> {
+    pub fn new(margin: Real) -> Self {
+        Self {
+            nodes: Vec::new(),
+            free_nodes: Vec::new(),
+            root: None,
+            margin,
+            leaves_count: 0,
+        }
+    }
+
+    fn fatten(&self, aabb: &Aabb3) -> Aabb3 {
+        let m = Vec3::new(self.margin, self.margin, self.margin);
+        return Aabb3::new_with_points(&(aabb.get_min() - m), &(aabb.get_max() + m));
+    }
+
+    fn allocate(&mut self, aabb: Aabb3, data: Option<T>) -> usize {
+        let node = Node {
+            aabb,
+            parent: None,
+            children: None,
+            data,
+        };
+        if let Some(index) = self.free_nodes.pop() {
+            self.nodes[index] = node;
+            return index;
+        }
+        self.nodes.push(node);
+        return self.nodes.len() - 1;
+    }
+
+    fn free(&mut self, index: usize) {
+        self.nodes[index].parent = None;
+        self.nodes[index].children = None;
+        self.nodes[index].data = None;
+        self.free_nodes.push(index);
+    }
+
+    pub fn insert(&mut self, aabb: &Aabb3, data: T) -> usize {
+        let aabb = self.fatten(aabb);
+        let leaf = self.allocate(aabb, Some(data));
+        self.insert_leaf(leaf);
+        self.leaves_count += 1;
+        return leaf;
+    }
+
+    pub fn remove(&mut self, proxy: usize) -> T {
+        self.remove_leaf(proxy);
+        let data = vxunwrap!(self.nodes[proxy].data.take());
+        self.free(proxy);
+        self.leaves_count -= 1;
+        return data;
+    }
+
+    // Returns true when the tree structure changed
+    pub fn update(&mut self, proxy: usize, aabb: &Aabb3) -> bool {
+        if self.nodes[proxy].aabb.contains_aabb(aabb) {
+            return false;
+        }
+        self.remove_leaf(proxy);
+        self.nodes[proxy].aabb = self.fatten(aabb);
+        self.insert_leaf(proxy);
+        return true;
+    }
+
+    pub fn get_data(&self, proxy: usize) -> &T {
+        return vxunwrap!(&self.nodes[proxy].data);
+    }
+
+    pub fn get_fat_aabb(&self, proxy: usize) -> &Aabb3 {
+        return &self.nodes[proxy].aabb;
+    }
+
+    pub fn get_root_aabb(&self) -> Option<&Aabb3> {
+        match self.root {
+            Some(root) => Some(&self.nodes[root].aabb),
+            None => None,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        return self.leaves_count;
+    }
+
+    fn insert_leaf(&mut self, leaf: usize) {
+        let root = match self.root {
+            Some(root) => root,
+            None => {
+                self.root = Some(leaf);
+                self.nodes[leaf].parent = None;
+                return;
+            }
+        };
+        let leaf_aabb = self.nodes[leaf].aabb;
+        // finding the best sibling with surface area heuristic
+        let mut index = root;
+        while let Some((c1, c2)) = self.nodes[index].children {
+            let area = get_area(&self.nodes[index].aabb);
+            let combined_area = get_area(&get_union(&self.nodes[index].aabb, &leaf_aabb));
+            let cost = 2.0 * combined_area;
+            let inheritance_cost = 2.0 * (combined_area - area);
+            let child_cost = |c: usize| {
+                let aabb = &self.nodes[c].aabb;
+                let union_area = get_area(&get_union(aabb, &leaf_aabb));
+                if self.nodes[c].children.is_none() {
+                    return union_area + inheritance_cost;
+                }
+                return union_area - get_area(aabb) + inheritance_cost;
+            };
+            let cost1 = child_cost(c1);
+            let cost2 = child_cost(c2);
+            if cost < cost1 && cost < cost2 {
+                break;
+            }
+            index = if cost1 < cost2 { c1 } else { c2 };
+        }
+        let sibling = index;
+        let old_parent = self.nodes[sibling].parent;
+        let new_parent = self.allocate(get_union(&leaf_aabb, &self.nodes[sibling].aabb), None);
+        self.nodes[new_parent].parent = old_parent;
+        self.nodes[new_parent].children = Some((sibling, leaf));
+        self.nodes[sibling].parent = Some(new_parent);
+        self.nodes[leaf].parent = Some(new_parent);
+        match old_parent {
+            Some(p) => {
+                let (c1, c2) = vxunwrap!(self.nodes[p].children);
+                self.nodes[p].children = if c1 == sibling {
+                    Some((new_parent, c2))
+                } else {
+                    Some((c1, new_parent))
+                };
+                self.refit(p);
+            }
+            None => self.root = Some(new_parent),
+        }
+    }
+
+    fn remove_leaf(&mut self, leaf: usize) {
+        if self.root == Some(leaf) {
+            self.root = None;
+            return;
+        }
+        let parent = vxunwrap!(self.nodes[leaf].parent);
+        let (c1, c2) = vxunwrap!(self.nodes[parent].children);
+        let sibling = if c1 == leaf { c2 } else { c1 };
+        let grand_parent = self.nodes[parent].parent;
+        self.nodes[sibling].parent = grand_parent;
+        self.nodes[leaf].parent = None;
+        match grand_parent {
+            Some(g) => {
+                let (g1, g2) = vxunwrap!(self.nodes[g].children);
+                self.nodes[g].children = if g1 == parent {
+                    Some((sibling, g2))
+                } else {
+                    Some((g1, sibling))
+                };
+                self.refit(g);
+            }
+            None => self.root = Some(sibling),
+        }
+        self.free(parent);
+    }
+
+    fn refit(&mut self, index: usize) {
+        let mut index = Some(index);
+        while let Some(i) = index {
+            let (c1, c2) = vxunwrap!(self.nodes[i].children);
+            self.nodes[i].aabb = get_union(&self.nodes[c1].aabb, &self.nodes[c2].aabb);
+            index = self.nodes[i].parent;
+        }
+    }
+
+    fn collect<F>(&self, index: usize, f: &mut F)
+    where
+        F: FnMut(&T),
+    {
+        let mut stack = vec![index];
+        while let Some(i) = stack.pop() {
+            match self.nodes[i].children {
+                Some((c1, c2)) => {
+                    stack.push(c1);
+                    stack.push(c2);
+                }
+                None => f(vxunwrap!(&self.nodes[i].data)),
+            }
+        }
+    }
+
+    // Whenever the test says a node is completely inside, its subtree does not get tested
+    pub fn query<F, G>(&self, test: &mut F, f: &mut G)
+    where
+        F: FnMut(&Aabb3) -> IntersectionStatus,
+        G: FnMut(&T),
+    {
+        let mut stack = match self.root {
+            Some(root) => vec![root],
+            None => return,
+        };
+        while let Some(i) = stack.pop() {
+            let node = &self.nodes[i];
+            match test(&node.aabb) {
+                IntersectionStatus::Outside => continue,
+                IntersectionStatus::Inside => self.collect(i, f),
+                IntersectionStatus::Intersecting => match node.children {
+                    Some((c1, c2)) => {
+                        stack.push(c1);
+                        stack.push(c2);
+                    }
+                    None => f(vxunwrap!(&node.data)),
+                },
+            }
+        }
+    }
+
+    pub fn query_aabb<G>(&self, aabb: &Aabb3, f: &mut G)
+    where
+        G: FnMut(&T),
+    {
+        self.query(
+            &mut |b: &Aabb3| {
+                if aabb.contains_aabb(b) {
+                    IntersectionStatus::Inside
+                } else if aabb.intersects_aabb(b) {
+                    IntersectionStatus::Intersecting
+                } else {
+                    IntersectionStatus::Outside
+                }
+            },
+            f,
+        );
+    }
+
+    pub fn query_sphere<G>(&self, center: &Vec3, radius: Real, f: &mut G)
+    where
+        G: FnMut(&T),
+    {
+        self.query(
+            &mut |b: &Aabb3| {
+                if b.intersects_center_radius(center, radius) {
+                    IntersectionStatus::Intersecting
+                } else {
+                    IntersectionStatus::Outside
+                }
+            },
+            f,
+        );
+    }
+
+    pub fn query_frustum<G>(&self, frustum: &Frustum, f: &mut G)
+    where
+        G: FnMut(&T),
+    {
+        self.query(&mut |b: &Aabb3| frustum.intersect_aabb(b), f);
+    }
+
+    // Callback receives entrance distance of leaf's AABB and returns the new maximum distance,
+    // so the rest of tree can be culled after an exact hit.
+    pub fn query_ray<G>(&self, ray: &Ray3, tmax: Real, f: &mut G)
+    where
+        G: FnMut(&T, Real) -> Real,
+    {
+        let mut stack = match self.root {
+            Some(root) => vec![root],
+            None => return,
+        };
+        let mut tmax = tmax;
+        while let Some(i) = stack.pop() {
+            let node = &self.nodes[i];
+            let t = match node.aabb.intersects_ray(ray, tmax) {
+                Some(t) => t,
+                None => continue,
+            };
+            match node.children {
+                Some((c1, c2)) => {
+                    stack.push(c1);
+                    stack.push(c2);
+                }
+                None => tmax = tmax.min(f(vxunwrap!(&node.data), t)),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::plane::Plane;
+    use super::*;
+    use rand::distributions::Distribution;
+
+    fn random_boxes(count: usize) -> Vec<Aabb3> {
+        let position = rand::distributions::Uniform::from(-50f32..50f32);
+        let size = rand::distributions::Uniform::from(0.1f32..3f32);
+        let mut rng = rand::thread_rng();
+        let mut result = Vec::with_capacity(count);
+        for _ in 0..count {
+            let p = Vec3::new(
+                position.sample(&mut rng),
+                position.sample(&mut rng),
+                position.sample(&mut rng),
+            );
+            let s = Vec3::new(
+                size.sample(&mut rng),
+                size.sample(&mut rng),
+                size.sample(&mut rng),
+            );
+            result.push(Aabb3::new_with_points(&p, &(p + s)));
+        }
+        return result;
+    }
+
+    fn query_sorted(tree: &DynamicTree<usize>, aabb: &Aabb3) -> Vec<usize> {
+        let mut result = Vec::new();
+        tree.query_aabb(aabb, &mut |i: &usize| result.push(*i));
+        result.sort();
+        return result;
+    }
+
+    #[test]
+    fn insert_query_remove_test() {
+        let mut boxes = random_boxes(500);
+        let mut tree = DynamicTree::new(0.0);
+        let mut proxies = Vec::new();
+        for (i, b) in boxes.iter().enumerate() {
+            proxies.push(tree.insert(b, i));
+        }
+        assert_eq!(tree.len(), 500);
+        let queries = random_boxes(50);
+        let check = |tree: &DynamicTree<usize>, boxes: &[Aabb3], alive: &[bool]| {
+            for q in &queries {
+                let q = Aabb3::new_with_points(
+                    &q.get_min(),
+                    &(q.get_max() + Vec3::new(10.0, 10.0, 10.0)),
+                );
+                let mut expected = Vec::new();
+                for (i, b) in boxes.iter().enumerate() {
+                    if alive[i] && q.intersects_aabb(b) {
+                        expected.push(i);
+                    }
+                }
+                assert_eq!(query_sorted(tree, &q), expected);
+            }
+        };
+        let mut alive = vec![true; 500];
+        check(&tree, &boxes, &alive);
+        for i in 0..250 {
+            let j = i * 2;
+            assert_eq!(tree.remove(proxies[j]), j);
+            alive[j] = false;
+        }
+        assert_eq!(tree.len(), 250);
+        check(&tree, &boxes, &alive);
+        let moved = random_boxes(500);
+        for i in 0..250 {
+            let j = i * 2 + 1;
+            boxes[j] = moved[j];
+            tree.update(proxies[j], &boxes[j]);
+        }
+        check(&tree, &boxes, &alive);
+    }
+
+    #[test]
+    fn margin_test() {
+        let mut tree = DynamicTree::new(1.0);
+        let b = Aabb3::new_with_points(&Vec3::new(0.0, 0.0, 0.0), &Vec3::new(1.0, 1.0, 1.0));
+        let p = tree.insert(&b, 0usize);
+        tree.insert(
+            &Aabb3::new_with_center_radius(&Vec3::new(10.0, 0.0, 0.0), 1.0),
+            1usize,
+        );
+        let moved = Aabb3::new_with_points(&Vec3::new(0.5, 0.0, 0.0), &Vec3::new(1.5, 1.0, 1.0));
+        assert!(!tree.update(p, &moved));
+        let moved = Aabb3::new_with_points(&Vec3::new(5.0, 0.0, 0.0), &Vec3::new(6.0, 1.0, 1.0));
+        assert!(tree.update(p, &moved));
+        assert_eq!(
+            query_sorted(
+                &tree,
+                &Aabb3::new_with_center_radius(&Vec3::new(0.0, 0.0, 0.0), 0.5)
+            ),
+            Vec::<usize>::new()
+        );
+        assert_eq!(
+            query_sorted(
+                &tree,
+                &Aabb3::new_with_center_radius(&Vec3::new(5.5, 0.5, 0.5), 0.1)
+            ),
+            vec![0]
+        );
+        let r = tree.get_root_aabb().unwrap();
+        assert_eq!(r.get_max(), Vec3::new(12.0, 2.0, 2.0));
+    }
+
+    #[test]
+    fn frustum_sphere_ray_test() {
+        let boxes = random_boxes(300);
+        let mut tree = DynamicTree::new(0.0);
+        for (i, b) in boxes.iter().enumerate() {
+            tree.insert(b, i);
+        }
+        let p = |x, y, z| Vec3::new(x, y, z);
+        let frustum = Frustum::new([
+            Plane::new_with_point_normal(p(20.0, 0.0, 0.0), p(1.0, 0.0, 0.0)),
+            Plane::new_with_point_normal(p(-20.0, 0.0, 0.0), p(-1.0, 0.0, 0.0)),
+            Plane::new_with_point_normal(p(0.0, 20.0, 0.0), p(0.0, 1.0, 0.0)),
+            Plane::new_with_point_normal(p(0.0, -20.0, 0.0), p(0.0, -1.0, 0.0)),
+            Plane::new_with_point_normal(p(0.0, 0.0, 20.0), p(0.0, 0.0, 1.0)),
+            Plane::new_with_point_normal(p(0.0, 0.0, -20.0), p(0.0, 0.0, -1.0)),
+        ]);
+        let mut result = Vec::new();
+        tree.query_frustum(&frustum, &mut |i: &usize| result.push(*i));
+        result.sort();
+        let mut expected = Vec::new();
+        for (i, b) in boxes.iter().enumerate() {
+            if frustum.intersects_aabb(b) {
+                expected.push(i);
+            }
+        }
+        assert_eq!(result, expected);
+
+        let c = p(3.0, -4.0, 5.0);
+        let mut result = Vec::new();
+        tree.query_sphere(&c, 15.0, &mut |i: &usize| result.push(*i));
+        result.sort();
+        let mut expected = Vec::new();
+        for (i, b) in boxes.iter().enumerate() {
+            if b.intersects_center_radius(&c, 15.0) {
+                expected.push(i);
+            }
+        }
+        assert_eq!(result, expected);
+
+        let ray = Ray3::new(p(-60.0, 1.0, 2.0), p(1.0, 0.01, -0.02));
+        let mut nearest = (1000.0 as Real, None);
+        tree.query_ray(&ray, 1000.0, &mut |i: &usize, t: Real| {
+            if t < nearest.0 {
+                nearest = (t, Some(*i));
+            }
+            nearest.0
+        });
+        let mut expected = (1000.0 as Real, None);
+        for (i, b) in boxes.iter().enumerate() {
+            if let Some(t) = b.intersects_ray(&ray, 1000.0) {
+                if t < expected.0 {
+                    expected = (t, Some(i));
+                }
+            }
+        }
+        assert_eq!(nearest.1, expected.1);
+    }
+}
@@ -3,14 +3,19 @@ use super::super::system::os::application::Application as OsApp;
 use super::config::Configurations;
 use super::debug::Debug;
 use super::event::Event;
+use super::types::Real;
 use std::sync::{Arc, RwLock};
 
 pub trait Application: Debug {
     fn set_os_app(&mut self, _app: Arc<RwLock<OsApp>>) {}
     fn set_renderer(&mut self, _renderer: Arc<RwLock<RenderEngine>>) {}
     fn initialize(&mut self) {}
+    // Events that handlers of the event dispatcher of OS application do not consume
     fn on_event(&self, _e: Event) {}
+    // Called once per frame after fixed updates and before rendering
     fn update(&mut self) {}
+    // Called zero or more times per frame with a constant dt, deterministic things go here
+    fn fixed_update(&mut self, _dt: Real) {}
     fn terminate(&mut self) {}
     fn get_config(&self) -> Configurations {
         Configurations::default()
@@ -1,9 +1,12 @@
+use super::super::audio::manager::Manager as AudioManager;
+use super::super::physics::joint::Manager as JointManager;
 use super::super::render::camera::Manager as CameraManager;
 use super::super::render::engine::Engine;
 use super::super::render::font::Manager as FontManager;
 use super::super::render::light::Manager as LightManager;
 use super::super::render::mesh::Manager as MeshManager;
 use super::super::render::model::Manager as ModelManager;
+use super::super::render::particle::Manager as ParticleManager;
 use super::super::render::scene::Manager as SceneManager;
 use super::super::render::skybox::Manager as SkyboxManager;
 use super::super::render::texture::Manager as TextureManager;
@@ -24,6 +27,9 @@ pub struct Manager {
     mesh_manager: Arc<RwLock<MeshManager>>,
     font_manager: Arc<RwLock<FontManager>>,
     model_manager: Arc<RwLock<ModelManager>>,
+    joint_manager: Arc<RwLock<JointManager>>,
+    particle_manager: Arc<RwLock<ParticleManager>>,
+    audio_manager: Arc<RwLock<AudioManager>>,
 }
 
 impl Manager {
@@ -36,6 +42,9 @@ impl Manager {
         let mesh_manager = Arc::new(RwLock::new(MeshManager::new()));
         let font_manager = Arc::new(RwLock::new(FontManager::new()));
         let model_manager = Arc::new(RwLock::new(ModelManager::new()));
+        let joint_manager = Arc::new(RwLock::new(JointManager::new()));
+        let particle_manager = Arc::new(RwLock::new(ParticleManager::new()));
+        let audio_manager = Arc::new(RwLock::new(AudioManager::new()));
 
         let mut gx3d_file = Gx3DReader::new(config.get_gx3d_file_name());
         if let Some(gx3d_file) = &mut gx3d_file {
@@ -49,14 +58,14 @@ impl Manager {
                 }};
             }
             set_table!(camera_manager);
-            let _audio_table = Gx3dTable::new(gx3d_file, config);
+            set_table!(audio_manager);
             set_table!(light_manager);
             set_table!(texture_manager);
             set_table!(font_manager);
             set_table!(mesh_manager);
             set_table!(model_manager);
             set_table!(skybox_manager);
-            let _constraint_table = Gx3dTable::new(gx3d_file, config);
+            set_table!(joint_manager);
             set_table!(scene_manager);
         }
 
@@ -69,6 +78,9 @@ impl Manager {
             font_manager,
             model_manager,
             skybox_manager,
+            joint_manager,
+            particle_manager,
+            audio_manager,
         }
     }
 
@@ -113,6 +125,18 @@ impl Manager {
     pub fn get_skybox_manager(&self) -> &Arc<RwLock<SkyboxManager>> {
         return &self.skybox_manager;
     }
+
+    pub fn get_joint_manager(&self) -> &Arc<RwLock<JointManager>> {
+        return &self.joint_manager;
+    }
+
+    pub fn get_particle_manager(&self) -> &Arc<RwLock<ParticleManager>> {
+        return &self.particle_manager;
+    }
+
+    pub fn get_audio_manager(&self) -> &Arc<RwLock<AudioManager>> {
+        return &self.audio_manager;
+    }
 }
 
 unsafe impl Send for Manager {}
@@ -1,4 +1,6 @@
 use super::super::render::config::Configurations as RenderConfig;
+use super::timing::DEFAULT_FIXED_UPDATE_RATE;
+use super::types::Real;
 
 #[derive(Clone)]
 #[cfg_attr(debug_mode, derive(Debug))]
@@ -9,6 +11,10 @@ pub struct Configurations {
     fullscreen: bool,
     render: RenderConfig,
     application_name: String,
+    fixed_update_rate: Real,
+    input_record_file_name: Option<String>,
+    input_replay_file_name: Option<String>,
+    mouse_touch_emulation: bool,
 }
 
 impl Configurations {
@@ -59,6 +65,43 @@ impl Configurations {
     pub fn set_application_name(&mut self, name: String) {
         self.application_name = name;
     }
+
+    // Number of fixed updates per second
+    pub fn get_fixed_update_rate(&self) -> Real {
+        return self.fixed_update_rate;
+    }
+
+    pub fn set_fixed_update_rate(&mut self, rate: Real) {
+        self.fixed_update_rate = rate;
+    }
+
+    // When it is set, OS application writes all of the input events to this file
+    pub fn get_input_record_file_name(&self) -> Option<&str> {
+        return self.input_record_file_name.as_ref().map(|s| s.as_str());
+    }
+
+    pub fn set_input_record_file_name(&mut self, name: Option<String>) {
+        self.input_record_file_name = name;
+    }
+
+    // When it is set, OS application gives the events of this file instead of the live input
+    pub fn get_input_replay_file_name(&self) -> Option<&str> {
+        return self.input_replay_file_name.as_ref().map(|s| s.as_str());
+    }
+
+    pub fn set_input_replay_file_name(&mut self, name: Option<String>) {
+        self.input_replay_file_name = name;
+    }
+
+    // When it is set, desktop OS applications give the touches and gestures that mouse emulates
+    // after its events, so touch input can be tested without a touch screen
+    pub fn get_mouse_touch_emulation(&self) -> bool {
+        return self.mouse_touch_emulation;
+    }
+
+    pub fn set_mouse_touch_emulation(&mut self, b: bool) {
+        self.mouse_touch_emulation = b;
+    }
 }
 
 impl Default for Configurations {
@@ -70,6 +113,10 @@ impl Default for Configurations {
             fullscreen: false,
             render: RenderConfig::default(),
             application_name: "Vulkust Application".to_string(),
+            fixed_update_rate: DEFAULT_FIXED_UPDATE_RATE,
+            input_record_file_name: None,
+            input_replay_file_name: None,
+            mouse_touch_emulation: false,
         }
     }
 }
@@ -0,0 +1,252 @@
+use super::application::Application;
+use super::debug::Debug;
+use super::event::{Event, Type as EventType};
+use super::object::create_id;
+use super::types::Id;
+use std::any::Any;
+use std::mem::replace;
+use std::sync::{Arc, Mutex, RwLock};
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(debug_mode, derive(Debug))]
+pub enum Category {
+    Move,
+    Button,
+    Scroll,
+    Text,
+    Touch,
+    Gamepad,
+    Window,
+    Trigger,
+    Custom,
+    Quit,
+}
+
+pub const ALL_CATEGORIES: [Category; 10] = [
+    Category::Move,
+    Category::Button,
+    Category::Scroll,
+    Category::Text,
+    Category::Touch,
+    Category::Gamepad,
+    Category::Window,
+    Category::Trigger,
+    Category::Custom,
+    Category::Quit,
+];
+
+impl Category {
+    pub fn new_with_event_type(event_type: &EventType) -> Self {
+        return match event_type {
+            &EventType::Move(_) => Category::Move,
+            &EventType::Button { .. } => Category::Button,
+            &EventType::Scroll { .. } => Category::Scroll,
+            &EventType::Text(_) => Category::Text,
+            &EventType::Touch(_) => Category::Touch,
+            &EventType::Gamepad(_) => Category::Gamepad,
+            &EventType::Window(_) => Category::Window,
+            &EventType::Trigger { .. } => Category::Trigger,
+            &EventType::Custom(_) => Category::Custom,
+            &EventType::Quit => Category::Quit,
+        };
+    }
+}
+
+pub trait Handler: Debug {
+    // Returns true when it consumes the event, then handlers with lower priorities and the
+    // application do not receive it
+    fn handle(&mut self, e: &Event) -> bool;
+}
+
+#[cfg_attr(debug_mode, derive(Debug))]
+struct Subscription {
+    id: Id,
+    categories: Vec<Category>,
+    priority: i32,
+    handler: Arc<RwLock<Handler>>,
+}
+
+// Events of OS pass through the subscribed handlers before they reach the application, e.g. UI
+// subscribes with a higher priority than game and consumes the clicks on its widgets.
+// Methods take a shared reference, so handlers can subscribe, unsubscribe and post while they
+// are handling an event.
+#[cfg_attr(debug_mode, derive(Debug))]
+pub struct Dispatcher {
+    subscriptions: RwLock<Vec<Subscription>>,
+    posted: Mutex<Vec<Event>>,
+}
+
+impl Dispatcher {
+    pub fn new() -> Self {
+        Dispatcher {
+            subscriptions: RwLock::new(Vec::new()),
+            posted: Mutex::new(Vec::new()),
+        }
+    }
+
+    // Handlers with higher priorities receive events sooner, equal ones in order of subscription
+    pub fn subscribe(
+        &self,
+        categories: &[Category],
+        priority: i32,
+        handler: Arc<RwLock<Handler>>,
+    ) -> Id {
+        let id = create_id();
+        let mut subscriptions = vxresult!(self.subscriptions.write());
+        let position = subscriptions
+            .iter()
+            .position(|s| s.priority < priority)
+            .unwrap_or(subscriptions.len());
+        subscriptions.insert(
+            position,
+            Subscription {
+                id,
+                categories: categories.to_vec(),
+                priority,
+                handler,
+            },
+        );
+        return id;
+    }
+
+    pub fn unsubscribe(&self, id: Id) {
+        vxresult!(self.subscriptions.write()).retain(|s| s.id != id);
+    }
+
+    // Returns the event when no handler consumes it
+    pub fn dispatch(&self, e: Event) -> Option<Event> {
+        let category = Category::new_with_event_type(&e.event_type);
+        let handlers: Vec<Arc<RwLock<Handler>>> = vxresult!(self.subscriptions.read())
+            .iter()
+            .filter(|s| s.categories.contains(&category))
+            .map(|s| s.handler.clone())
+            .collect();
+        for handler in handlers {
+            if vxresult!(handler.write()).handle(&e) {
+                return None;
+            }
+        }
+        return Some(e);
+    }
+
+    // OS applications give their events to the application with it,
+    // the application receives the event when no handler consumes it
+    pub fn send(&self, e: Event, app: &Arc<RwLock<Application>>) {
+        if let Some(e) = self.dispatch(e) {
+            vxresult!(app.read()).on_event(e);
+        }
+    }
+
+    // Posted events are dispatched with the events of next frame
+    pub fn post(&self, e: Event) {
+        vxresult!(self.posted.lock()).push(e);
+    }
+
+    pub fn post_custom<T: Any + Send + Sync>(&self, value: T) {
+        self.post(Event::new_custom(value));
+    }
+
+    // It is called by OS application at the start of each frame
+    pub fn fetch_posted(&self) -> Vec<Event> {
+        return replace(&mut *vxresult!(self.posted.lock()), Vec::new());
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::event::{Button, ButtonAction, Modifiers, Mouse};
+    use super::*;
+
+    #[cfg_attr(debug_mode, derive(Debug))]
+    struct Recorder {
+        name: &'static str,
+        consume: bool,
+        log: Arc<Mutex<Vec<&'static str>>>,
+    }
+
+    impl Handler for Recorder {
+        fn handle(&mut self, _e: &Event) -> bool {
+            vxresult!(self.log.lock()).push(self.name);
+            return self.consume;
+        }
+    }
+
+    #[cfg_attr(debug_mode, derive(Debug))]
+    struct Score(u32);
+
+    #[cfg_attr(debug_mode, derive(Debug))]
+    struct Game {
+        received: Mutex<usize>,
+    }
+
+    impl Application for Game {
+        fn on_event(&self, _e: Event) {
+            *vxresult!(self.received.lock()) += 1;
+        }
+    }
+
+    fn click() -> Event {
+        return Event::new(EventType::Button {
+            button: Button::Mouse(Mouse::Left),
+            action: ButtonAction::Press,
+            modifiers: Modifiers::empty(),
+            is_repeat: false,
+        });
+    }
+
+    fn recorder(
+        name: &'static str,
+        consume: bool,
+        log: &Arc<Mutex<Vec<&'static str>>>,
+    ) -> Arc<RwLock<Recorder>> {
+        return Arc::new(RwLock::new(Recorder {
+            name,
+            consume,
+            log: log.clone(),
+        }));
+    }
+
+    #[test]
+    fn dispatch_test() {
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let dispatcher = Dispatcher::new();
+        dispatcher.subscribe(&ALL_CATEGORIES, 0, recorder("game", false, &log));
+        let ui = dispatcher.subscribe(&[Category::Button], 10, recorder("ui", true, &log));
+        dispatcher.subscribe(&[Category::Button], 10, recorder("ui-2", false, &log));
+        dispatcher.subscribe(&[Category::Custom], 20, recorder("custom", false, &log));
+        assert!(dispatcher.dispatch(click()).is_none());
+        assert_eq!(*vxresult!(log.lock()), vec!["ui"]);
+        vxresult!(log.lock()).clear();
+        dispatcher.unsubscribe(ui);
+        assert!(dispatcher.dispatch(click()).is_some());
+        assert_eq!(*vxresult!(log.lock()), vec!["ui-2", "game"]);
+        vxresult!(log.lock()).clear();
+        dispatcher.post_custom(Score(3));
+        assert!(dispatcher.dispatch(Event::new(EventType::Quit)).is_some());
+        let posted = dispatcher.fetch_posted();
+        assert!(dispatcher.fetch_posted().is_empty());
+        assert_eq!(posted.len(), 1);
+        assert_eq!(vxunwrap!(posted[0].get_custom::<Score>()).0, 3);
+        assert!(posted[0].get_custom::<u32>().is_none());
+        for e in posted {
+            assert!(dispatcher.dispatch(e).is_some());
+        }
+        assert_eq!(*vxresult!(log.lock()), vec!["game", "custom", "game"]);
+    }
+
+    #[test]
+    fn send_test() {
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let dispatcher = Dispatcher::new();
+        dispatcher.subscribe(&[Category::Button], 10, recorder("ui", true, &log));
+        let game = Arc::new(RwLock::new(Game {
+            received: Mutex::new(0),
+        }));
+        let app: Arc<RwLock<Application>> = game.clone();
+        dispatcher.send(click(), &app);
+        assert_eq!(*vxresult!(vxresult!(game.read()).received.lock()), 0);
+        dispatcher.send(Event::new(EventType::Quit), &app);
+        assert_eq!(*vxresult!(vxresult!(game.read()).received.lock()), 1);
+        assert_eq!(*vxresult!(log.lock()), vec!["ui"]);
+    }
+}
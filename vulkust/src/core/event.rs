@@ -1,11 +1,14 @@
 use super::gesture::State as GestureState;
 use super::object::{create_id, Object};
 use super::types::{Id, Real};
+use std::any::Any;
+use std::ops::BitOr;
+use std::sync::Arc;
 use std::time::{Duration, Instant};
 
 pub type FingerIndexType = i64;
 
-#[derive(Clone)]
+#[derive(Clone, PartialEq, Eq)]
 #[cfg_attr(debug_mode, derive(Debug))]
 pub enum Mouse {
     Left,
@@ -16,7 +19,7 @@ pub enum Mouse {
     Offic,
 }
 
-#[derive(Clone)]
+#[derive(Clone, PartialEq, Eq)]
 #[cfg_attr(debug_mode, derive(Debug))]
 pub enum Keyboard {
     A,
@@ -89,11 +92,99 @@ pub enum Keyboard {
     Unknown,
 }
 
+// Standard layout of gamepads, face buttons are named by their positions
+#[derive(Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(debug_mode, derive(Debug))]
+pub enum GamepadButton {
+    South,
+    East,
+    North,
+    West,
+    LeftBumper,
+    RightBumper,
+    LeftTrigger,
+    RightTrigger,
+    Select,
+    Start,
+    Mode,
+    LeftThumb,
+    RightThumb,
+    DpadUp,
+    DpadDown,
+    DpadLeft,
+    DpadRight,
+}
+
+// Sticks are between -1 and 1, their y is positive downward, triggers are between 0 and 1
+#[derive(Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(debug_mode, derive(Debug))]
+pub enum GamepadAxis {
+    LeftX,
+    LeftY,
+    RightX,
+    RightY,
+    LeftTrigger,
+    RightTrigger,
+}
+
 #[derive(Clone)]
 #[cfg_attr(debug_mode, derive(Debug))]
 pub enum Button {
     Mouse(Mouse),
     Keyboard(Keyboard),
+    Gamepad { id: Id, button: GamepadButton },
+}
+
+// Set of held modifier keys, they are combined with |
+#[derive(Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(debug_mode, derive(Debug))]
+pub struct Modifiers {
+    bits: u8,
+}
+
+impl Modifiers {
+    pub const SHIFT: Modifiers = Modifiers { bits: 1 };
+    pub const CONTROL: Modifiers = Modifiers { bits: 2 };
+    pub const ALT: Modifiers = Modifiers { bits: 4 };
+    pub const SUPER: Modifiers = Modifiers { bits: 8 };
+
+    pub fn empty() -> Self {
+        Modifiers { bits: 0 }
+    }
+
+    pub fn new_with_bits(bits: u8) -> Self {
+        Modifiers { bits }
+    }
+
+    pub fn get_bits(&self) -> u8 {
+        return self.bits;
+    }
+
+    pub fn is_empty(&self) -> bool {
+        return self.bits == 0;
+    }
+
+    pub fn contains(&self, other: Modifiers) -> bool {
+        return self.bits & other.bits == other.bits;
+    }
+
+    pub fn insert(&mut self, other: Modifiers) {
+        self.bits |= other.bits;
+    }
+
+    pub fn remove(&mut self, other: Modifiers) {
+        self.bits &= !other.bits;
+    }
+}
+
+impl BitOr for Modifiers {
+    type Output = Modifiers;
+
+    fn bitor(self, other: Modifiers) -> Modifiers {
+        Modifiers {
+            bits: self.bits | other.bits,
+        }
+    }
 }
 
 #[derive(Clone)]
@@ -116,6 +207,7 @@ pub enum Move {
         previous: (Real, Real),
         current: (Real, Real),
         delta: (Real, Real),
+        modifiers: Modifiers,
     },
     Touch {
         index: FingerIndexType,
@@ -140,10 +232,40 @@ pub enum TouchAction {
     Release,
 }
 
+// Directions are on screen, y of touch points grows downward
+#[derive(Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(debug_mode, derive(Debug))]
+pub enum SwipeDirection {
+    Left,
+    Right,
+    Up,
+    Down,
+}
+
 #[derive(Clone)]
 #[cfg_attr(debug_mode, derive(Debug))]
 pub enum TouchGesture {
-    Tap, // todo
+    Tap {
+        index: FingerIndexType,
+        point: (Real, Real),
+    },
+    // It comes after the tap of its second touch
+    DoubleTap {
+        index: FingerIndexType,
+        point: (Real, Real),
+    },
+    LongPress {
+        index: FingerIndexType,
+        point: (Real, Real),
+    },
+    // Velocity is in the units of touch points per second
+    Swipe {
+        index: FingerIndexType,
+        direction: SwipeDirection,
+        start: (Real, Real),
+        end: (Real, Real),
+        velocity: (Real, Real),
+    },
     Drag {
         index: FingerIndexType,
         start: (Real, Real),
@@ -159,6 +281,23 @@ pub enum TouchGesture {
         current: Real,
         delta: Real,
     },
+    // Angles are in radians and they are not wrapped
+    Rotate {
+        first: (FingerIndexType, (Real, Real)),
+        second: (FingerIndexType, (Real, Real)),
+        start: Real,
+        previous: Real,
+        current: Real,
+        delta: Real,
+    },
+    // Points are the centers of fingers
+    Pan {
+        fingers: u8,
+        start: (Real, Real),
+        previous: (Real, Real),
+        current: (Real, Real),
+        delta: (Real, Real),
+    },
 }
 
 #[derive(Clone)]
@@ -177,6 +316,31 @@ pub enum Touch {
     },
 }
 
+#[derive(Clone)]
+#[cfg_attr(debug_mode, derive(Debug))]
+pub enum Gamepad {
+    Connected {
+        id: Id,
+        name: String,
+    },
+    Disconnected {
+        id: Id,
+    },
+    Axis {
+        id: Id,
+        axis: GamepadAxis,
+        value: Real,
+    },
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(debug_mode, derive(Debug))]
+pub enum TriggerAction {
+    Enter,
+    Stay,
+    Exit,
+}
+
 #[derive(Clone)]
 #[cfg_attr(debug_mode, derive(Debug))]
 pub enum Type {
@@ -184,9 +348,28 @@ pub enum Type {
     Button {
         button: Button,
         action: ButtonAction,
+        modifiers: Modifiers,
+        // Presses that are generated by holding a key
+        is_repeat: bool,
     },
+    // Wheel and horizontal scroll, x is positive to right, y is positive to up and a notch is 1
+    Scroll {
+        delta: (Real, Real),
+        modifiers: Modifiers,
+    },
+    // Typed characters in UTF-8, keyboard layout, modifiers and compose sequences are applied
+    Text(String),
     Touch(Touch),
+    Gamepad(Gamepad),
     Window(Window),
+    // Ids of trigger body and the body that overlaps it
+    Trigger {
+        trigger: Id,
+        other: Id,
+        action: TriggerAction,
+    },
+    // Events of application, they are posted to the event dispatcher
+    Custom(Arc<Any + Send + Sync>),
     Quit,
 }
 
@@ -204,6 +387,18 @@ impl Event {
             event_type,
         }
     }
+
+    pub fn new_custom<T: Any + Send + Sync>(value: T) -> Self {
+        Self::new(Type::Custom(Arc::new(value)))
+    }
+
+    // Returns the value of a custom event when it has the type
+    pub fn get_custom<T: Any>(&self) -> Option<&T> {
+        return match &self.event_type {
+            &Type::Custom(ref value) => value.downcast_ref::<T>(),
+            _ => None,
+        };
+    }
 }
 
 impl Object for Event {
@@ -1,358 +1,1085 @@
-use super::event::{Event, Move, Touch, TouchAction, TouchGesture, Type as EventType};
+use super::event::{
+    Button, ButtonAction, Event, FingerIndexType, Modifiers, Mouse, Move, SwipeDirection, Touch,
+    TouchAction, TouchGesture, Type as EventType,
+};
 use super::types::Real;
 
 use std::collections::BTreeMap;
-use std::time::Instant;
+use std::f32::consts::PI;
+use std::time::{Duration, Instant};
 
+// Finger indices of the touches that are emulated with mouse
+pub const MOUSE_FINGER_INDEX: FingerIndexType = -2;
+pub const MOUSE_SECOND_FINGER_INDEX: FingerIndexType = -3;
+
+const MOUSE_PARALLEL_FINGER_OFFSET: Real = 0.1;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
 #[cfg_attr(debug_mode, derive(Debug))]
-pub struct Translator {
-    pub drag: TouchDragTranslator,
-    pub scale: TouchScaleTranslator,
+pub enum State {
+    Started,
+    InMiddle,
+    Ended,
+    Canceled,
 }
 
-impl Translator {
-    pub fn new() -> Self {
-        Translator {
-            drag: TouchDragTranslator::new(),
-            scale: TouchScaleTranslator::new(),
-        }
-    }
+// Thresholds of recognition, distances are in the units of touch points
+#[derive(Clone)]
+#[cfg_attr(debug_mode, derive(Debug))]
+pub struct Configurations {
+    // A finger that moves less than this is still held in place for taps and long presses
+    pub tap_max_distance: Real,
+    pub tap_max_duration: Duration,
+    pub double_tap_interval: Duration,
+    pub double_tap_max_distance: Real,
+    pub long_press_duration: Duration,
+    pub swipe_min_distance: Real,
+    pub swipe_min_velocity: Real,
+    pub swipe_max_duration: Duration,
+    pub scale_min_distance: Real,
+    pub rotate_min_angle: Real,
+    pub pan_min_distance: Real,
+    pub pan_min_fingers: usize,
+}
 
-    pub fn receive(&mut self, e: &Event) -> Vec<Event> {
-        let mut v = Vec::new();
-        if let Some(e) = self.drag.receive(e) {
-            v.push(e);
-        }
-        if let Some(e) = self.scale.receive(e) {
-            v.push(e);
+impl Default for Configurations {
+    fn default() -> Self {
+        Configurations {
+            tap_max_distance: 0.02,
+            tap_max_duration: Duration::from_millis(300),
+            double_tap_interval: Duration::from_millis(300),
+            double_tap_max_distance: 0.05,
+            long_press_duration: Duration::from_millis(500),
+            swipe_min_distance: 0.1,
+            swipe_min_velocity: 0.5,
+            swipe_max_duration: Duration::from_millis(500),
+            scale_min_distance: 0.02,
+            rotate_min_angle: 0.1,
+            pan_min_distance: 0.02,
+            pan_min_fingers: 2,
         }
-        return v;
     }
 }
 
-#[derive(Clone)]
 #[cfg_attr(debug_mode, derive(Debug))]
-pub enum State {
-    Started,
-    InMiddle,
-    Ended,
-    Canceled,
+struct Finger {
+    start: (Real, Real),
+    current: (Real, Real),
+    start_time: Instant,
+    moved: bool,
+}
+
+#[cfg_attr(debug_mode, derive(Debug))]
+struct LongPress {
+    index: FingerIndexType,
+    started: bool,
 }
 
 #[cfg_attr(debug_mode, derive(Debug))]
-pub struct TouchDragTranslator {
-    pub state: State,
-    pub number: i8,
-    pub index: i64,
-    pub current: (Real, Real),
-    pub start: (Real, Real),
-    pub start_time: Instant,
+struct Drag {
+    index: FingerIndexType,
+    start_time: Instant,
+    current: (Real, Real),
 }
 
-impl TouchDragTranslator {
+// Gestures of the fingers that are down together, it is replaced when their number changes
+#[cfg_attr(debug_mode, derive(Debug))]
+struct Multi {
+    start_time: Instant,
+    fingers: usize,
+    first: FingerIndexType,
+    second: FingerIndexType,
+    start_distance: Real,
+    distance: Real,
+    scale_started: bool,
+    start_angle: Real,
+    angle: Real,
+    rotate_started: bool,
+    start_center: (Real, Real),
+    center: (Real, Real),
+    pan_started: bool,
+}
+
+#[derive(Clone, Copy)]
+#[cfg_attr(debug_mode, derive(Debug))]
+enum EmulatedFinger {
+    Direct,
+    Mirrored,
+    Parallel,
+}
+
+// Recognizes the gestures of touch events.
+// Tap, double tap and swipe are reported once with the ended state, others are reported with
+// started, in middle and then ended or canceled states. A gesture is canceled when another
+// finger is pressed or the finger moves while it needs the finger to be held in place.
+// For testing on desktop, mouse can emulate touches: left button is a finger, with control
+// a second finger mirrored around the center of screen is added for scale and rotate, and with
+// control and shift the second finger moves beside the first one for pan.
+#[cfg_attr(debug_mode, derive(Debug))]
+pub struct Translator {
+    pub config: Configurations,
+    pub mouse_emulation: bool,
+    fingers: BTreeMap<FingerIndexType, Finger>,
+    // most fingers that have been down together since the first one is pressed
+    max_fingers: usize,
+    last_tap: Option<(Instant, (Real, Real))>,
+    long_press: Option<LongPress>,
+    drag: Option<Drag>,
+    multi: Option<Multi>,
+    mouse_position: (Real, Real),
+    mouse_fingers: Vec<(FingerIndexType, EmulatedFinger)>,
+}
+
+impl Translator {
     pub fn new() -> Self {
-        TouchDragTranslator {
-            state: State::Ended,
-            number: 0,
-            index: -1,
-            current: (0.0, 0.0),
-            start: (0.0, 0.0),
-            start_time: Instant::now(),
+        Self::new_with_config(Configurations::default())
+    }
+
+    // Desktop OS applications create it when mouse emulation of touches is configured
+    pub fn new_with_mouse_emulation() -> Self {
+        let mut translator = Self::new();
+        translator.mouse_emulation = true;
+        return translator;
+    }
+
+    pub fn new_with_config(config: Configurations) -> Self {
+        Translator {
+            config,
+            mouse_emulation: false,
+            fingers: BTreeMap::new(),
+            max_fingers: 0,
+            last_tap: None,
+            long_press: None,
+            drag: None,
+            multi: None,
+            mouse_position: (0.0, 0.0),
+            mouse_fingers: Vec::new(),
         }
     }
 
-    pub fn receive(&mut self, e: &Event) -> Option<Event> {
+    // With mouse emulation, emulated touch events come before their gestures in the result
+    pub fn receive(&mut self, e: &Event) -> Vec<Event> {
+        return self.receive_at(e, Instant::now());
+    }
+
+    pub fn receive_at(&mut self, e: &Event, now: Instant) -> Vec<Event> {
+        if self.mouse_emulation {
+            if let Some(mut touches) = self.emulate(e) {
+                let mut gestures = Vec::new();
+                for t in &touches {
+                    self.translate(t, now, &mut gestures);
+                }
+                touches.extend(gestures);
+                return touches;
+            }
+        }
+        let mut result = Vec::new();
+        self.translate(e, now, &mut result);
+        return result;
+    }
+
+    // Long press needs the time to pass without any event, so it must be called every frame
+    pub fn update(&mut self) -> Vec<Event> {
+        return self.update_at(Instant::now());
+    }
+
+    pub fn update_at(&mut self, now: Instant) -> Vec<Event> {
+        let mut result = Vec::new();
+        self.check_long_press(now, &mut result);
+        return result;
+    }
+
+    // Cancels all the gestures in progress and forgets the fingers, e.g. when window loses focus
+    pub fn cancel(&mut self) -> Vec<Event> {
+        return self.cancel_at(Instant::now());
+    }
+
+    pub fn cancel_at(&mut self, now: Instant) -> Vec<Event> {
+        let mut result = Vec::new();
+        self.cancel_single(now, &mut result);
+        self.end_multi(State::Canceled, now, &mut result);
+        self.fingers.clear();
+        self.max_fingers = 0;
+        self.last_tap = None;
+        self.mouse_fingers.clear();
+        return result;
+    }
+
+    fn translate(&mut self, e: &Event, now: Instant, result: &mut Vec<Event>) {
+        self.check_long_press(now, result);
         match &e.event_type {
-            EventType::Touch(t) => match t {
-                Touch::Raw {
-                    index: _,
-                    action,
-                    point: _,
-                } => match action {
-                    TouchAction::Press => {
-                        self.number += 1;
-                    }
-                    TouchAction::Release => {
-                        self.number -= 1;
-                    }
-                    _ => (),
-                },
-                _ => (),
+            &EventType::Touch(Touch::Raw {
+                index,
+                ref action,
+                point,
+            }) => match action {
+                &TouchAction::Press | &TouchAction::HardPress => {
+                    self.press(index, point, now, result)
+                }
+                &TouchAction::Release => self.release(index, point, now, result),
             },
+            &EventType::Move(Move::Touch { index, current, .. }) => {
+                self.move_finger(index, current, now, result)
+            }
             _ => (),
         }
-        let state = self.state.clone();
-        match state {
-            State::Started => match &e.event_type {
-                EventType::Touch(t) => match t {
-                    Touch::Raw {
-                        index,
-                        action,
-                        point,
-                    } => {
-                        if *index == self.index {
-                            match action {
-                                TouchAction::Release => {
-                                    let result = Event::new(EventType::Touch(Touch::Gesture {
-                                        start_time: self.start_time,
-                                        duration: Instant::now().duration_since(self.start_time),
-                                        state: State::Ended,
-                                        gest: TouchGesture::Drag {
-                                            index: self.index,
-                                            start: self.start,
-                                            previous: self.current,
-                                            current: *point,
-                                            delta: (
-                                                point.0 - self.current.0,
-                                                point.1 - self.current.1,
-                                            ),
-                                        },
-                                    }));
-                                    self.index = -1;
-                                    self.state = State::Ended;
-                                    self.current = (0.0, 0.0);
-                                    self.start = (0.0, 0.0);
-                                    return Some(result);
-                                }
-                                _ => (),
-                            }
-                        } else {
-                            match action {
-                                TouchAction::Press | TouchAction::HardPress => {
-                                    let result = Event::new(EventType::Touch(Touch::Gesture {
-                                        start_time: self.start_time,
-                                        duration: Instant::now().duration_since(self.start_time),
-                                        state: State::Canceled,
-                                        gest: TouchGesture::Drag {
-                                            index: self.index,
-                                            start: self.start,
-                                            previous: self.current,
-                                            current: self.current,
-                                            delta: (0.0, 0.0),
-                                        },
-                                    }));
-                                    self.index = -1;
-                                    self.state = State::Ended;
-                                    self.current = (0.0, 0.0);
-                                    self.start = (0.0, 0.0);
-                                    return Some(result);
-                                }
-                                _ => (),
-                            }
-                        }
-                    }
-                    _ => (),
-                },
-                EventType::Move(m) => match m {
-                    Move::Touch {
-                        index,
-                        previous,
-                        current,
-                        delta,
-                    } => {
-                        if *index != self.index {
-                            vxunexpected!();
-                        }
-                        self.current = *current;
-                        return Some(Event::new(EventType::Touch(Touch::Gesture {
-                            start_time: self.start_time,
-                            duration: Instant::now().duration_since(self.start_time),
-                            state: State::InMiddle,
-                            gest: TouchGesture::Drag {
-                                index: self.index,
-                                start: self.start,
-                                previous: *previous,
-                                current: *current,
-                                delta: *delta,
-                            },
-                        })));
-                    }
-                    _ => (),
-                },
-                _ => (),
+    }
+
+    fn press(
+        &mut self,
+        index: FingerIndexType,
+        point: (Real, Real),
+        now: Instant,
+        result: &mut Vec<Event>,
+    ) {
+        if self.fingers.contains_key(&index) {
+            return;
+        }
+        self.cancel_single(now, result);
+        self.end_multi(State::Canceled, now, result);
+        self.fingers.insert(
+            index,
+            Finger {
+                start: point,
+                current: point,
+                start_time: now,
+                moved: false,
             },
-            State::InMiddle => vxunexpected!(),
-            State::Ended => match &e.event_type {
-                EventType::Touch(t) => match t {
-                    Touch::Raw {
-                        index,
-                        action,
-                        point,
-                    } => match action {
-                        TouchAction::Press => {
-                            if self.number == 1 {
-                                let result = Event::new(EventType::Touch(Touch::Gesture {
-                                    start_time: self.start_time,
-                                    duration: Instant::now().duration_since(self.start_time),
-                                    state: State::Ended,
-                                    gest: TouchGesture::Drag {
-                                        index: self.index,
-                                        start: self.start,
-                                        previous: self.current,
-                                        current: *point,
-                                        delta: (point.0 - self.current.0, point.1 - self.current.1),
-                                    },
-                                }));
-                                self.index = *index;
-                                self.state = State::Started;
-                                self.current = *point;
-                                self.start = *point;
-                                self.start_time = Instant::now();
-                                return Some(result);
-                            }
-                        }
-                        _ => (),
-                    },
-                    _ => (),
+        );
+        if self.fingers.len() == 1 {
+            self.max_fingers = 1;
+            self.long_press = Some(LongPress {
+                index,
+                started: false,
+            });
+        } else {
+            self.max_fingers = self.max_fingers.max(self.fingers.len());
+            self.start_multi(now);
+        }
+    }
+
+    fn release(
+        &mut self,
+        index: FingerIndexType,
+        point: (Real, Real),
+        now: Instant,
+        result: &mut Vec<Event>,
+    ) {
+        if !self.fingers.contains_key(&index) {
+            return;
+        }
+        if self.fingers.len() > 1 {
+            self.end_multi(State::Ended, now, result);
+            self.fingers.remove(&index);
+            if self.fingers.len() > 1 {
+                self.start_multi(now);
+            }
+            return;
+        }
+        let finger = vxunwrap!(self.fingers.remove(&index));
+        let duration = now.duration_since(finger.start_time);
+        let moved = finger.moved || distance(finger.start, point) > self.config.tap_max_distance;
+        if let Some(drag) = self.drag.take() {
+            result.push(gesture_event(
+                drag.start_time,
+                now,
+                State::Ended,
+                TouchGesture::Drag {
+                    index,
+                    start: finger.start,
+                    previous: drag.current,
+                    current: point,
+                    delta: sub(point, drag.current),
                 },
-                _ => (),
-            },
-            State::Canceled => vxunexpected!(),
+            ));
         }
-        return None;
+        let long_pressed = match self.long_press.take() {
+            Some(LongPress { started: true, .. }) => {
+                result.push(gesture_event(
+                    finger.start_time,
+                    now,
+                    State::Ended,
+                    TouchGesture::LongPress { index, point },
+                ));
+                true
+            }
+            _ => false,
+        };
+        if !moved && !long_pressed && self.max_fingers == 1 {
+            if duration <= self.config.tap_max_duration {
+                self.tap(index, point, finger.start_time, now, result);
+            }
+        } else {
+            self.last_tap = None;
+        }
+        if self.max_fingers == 1 {
+            self.swipe(index, &finger, point, now, result);
+        }
+        self.max_fingers = 0;
     }
-}
 
-#[cfg_attr(debug_mode, derive(Debug))]
-pub struct TouchScaleTranslator {
-    pub state: State,
-    pub fingers: BTreeMap<i64, (Real, Real)>,
-    pub first: i64,
-    pub second: i64,
-    pub current: Real,
-    pub start: Real,
-    pub start_time: Instant,
-}
+    fn tap(
+        &mut self,
+        index: FingerIndexType,
+        point: (Real, Real),
+        start_time: Instant,
+        now: Instant,
+        result: &mut Vec<Event>,
+    ) {
+        result.push(gesture_event(
+            start_time,
+            now,
+            State::Ended,
+            TouchGesture::Tap { index, point },
+        ));
+        if let Some((last_time, last_point)) = self.last_tap.take() {
+            if start_time.duration_since(last_time) <= self.config.double_tap_interval
+                && distance(last_point, point) <= self.config.double_tap_max_distance
+            {
+                result.push(gesture_event(
+                    last_time,
+                    now,
+                    State::Ended,
+                    TouchGesture::DoubleTap { index, point },
+                ));
+                return;
+            }
+        }
+        self.last_tap = Some((now, point));
+    }
 
-impl TouchScaleTranslator {
-    pub fn new() -> Self {
-        TouchScaleTranslator {
-            state: State::Ended,
-            fingers: BTreeMap::new(),
-            first: -1,
-            second: -1,
-            current: 0.0,
-            start: 0.0,
-            start_time: Instant::now(),
+    fn swipe(
+        &self,
+        index: FingerIndexType,
+        finger: &Finger,
+        end: (Real, Real),
+        now: Instant,
+        result: &mut Vec<Event>,
+    ) {
+        let duration = now.duration_since(finger.start_time);
+        let length = distance(finger.start, end);
+        if duration > self.config.swipe_max_duration || length < self.config.swipe_min_distance {
+            return;
         }
+        let seconds = duration_to_seconds(duration).max(0.001);
+        if length / seconds < self.config.swipe_min_velocity {
+            return;
+        }
+        let d = sub(end, finger.start);
+        let direction = if d.0.abs() >= d.1.abs() {
+            if d.0 > 0.0 {
+                SwipeDirection::Right
+            } else {
+                SwipeDirection::Left
+            }
+        } else if d.1 > 0.0 {
+            SwipeDirection::Down
+        } else {
+            SwipeDirection::Up
+        };
+        result.push(gesture_event(
+            finger.start_time,
+            now,
+            State::Ended,
+            TouchGesture::Swipe {
+                index,
+                direction,
+                start: finger.start,
+                end,
+                velocity: (d.0 / seconds, d.1 / seconds),
+            },
+        ));
     }
 
-    pub fn receive(&mut self, e: &Event) -> Option<Event> {
-        match &e.event_type {
-            EventType::Touch(t) => match t {
-                Touch::Raw {
+    fn move_finger(
+        &mut self,
+        index: FingerIndexType,
+        point: (Real, Real),
+        now: Instant,
+        result: &mut Vec<Event>,
+    ) {
+        let tap_max_distance = self.config.tap_max_distance;
+        let (start, start_time, moved) = match self.fingers.get_mut(&index) {
+            Some(finger) => {
+                finger.current = point;
+                if distance(finger.start, point) > tap_max_distance {
+                    finger.moved = true;
+                }
+                (finger.start, finger.start_time, finger.moved)
+            }
+            None => return,
+        };
+        if self.fingers.len() > 1 {
+            self.update_multi(now, result);
+            return;
+        }
+        if !moved {
+            return;
+        }
+        let long_press_started = match &self.long_press {
+            &Some(LongPress { started, .. }) => Some(started),
+            &None => None,
+        };
+        if let Some(started) = long_press_started {
+            self.long_press = None;
+            if started {
+                result.push(gesture_event(
+                    start_time,
+                    now,
+                    State::Canceled,
+                    TouchGesture::LongPress { index, point },
+                ));
+            }
+        }
+        let (state, previous) = match &mut self.drag {
+            &mut Some(ref mut drag) => {
+                let previous = drag.current;
+                drag.current = point;
+                (State::InMiddle, previous)
+            }
+            &mut None => (State::Started, start),
+        };
+        if state == State::Started {
+            self.drag = Some(Drag {
+                index,
+                start_time,
+                current: point,
+            });
+        }
+        result.push(gesture_event(
+            start_time,
+            now,
+            state,
+            TouchGesture::Drag {
+                index,
+                start,
+                previous,
+                current: point,
+                delta: sub(point, previous),
+            },
+        ));
+    }
+
+    fn check_long_press(&mut self, now: Instant, result: &mut Vec<Event>) {
+        let index = match &self.long_press {
+            &Some(LongPress {
+                index,
+                started: false,
+            }) => index,
+            _ => return,
+        };
+        {
+            let finger = vxunwrap!(self.fingers.get(&index));
+            if finger.moved
+                || now.duration_since(finger.start_time) < self.config.long_press_duration
+            {
+                return;
+            }
+            result.push(gesture_event(
+                finger.start_time,
+                now,
+                State::Started,
+                TouchGesture::LongPress {
                     index,
-                    action,
-                    point,
-                } => match action {
-                    TouchAction::Press => {
-                        self.fingers.insert(*index, *point);
-                    }
-                    TouchAction::Release => {
-                        self.fingers.remove(index);
-                    }
-                    _ => (),
+                    point: finger.current,
                 },
-                _ => (),
-            },
-            _ => (),
+            ));
         }
-        let state = self.state.clone();
-        match state {
-            State::Started => {
-                if self.fingers.len() != 2 {
-                    let result = Event::new(EventType::Touch(Touch::Gesture {
-                        start_time: self.start_time,
-                        duration: Instant::now().duration_since(self.start_time),
-                        state: State::Ended,
-                        gest: TouchGesture::Scale {
-                            first: (-1, (0.0, 0.0)),
-                            second: (-1, (0.0, 0.0)),
-                            start: self.start,
-                            previous: self.current,
-                            current: self.current,
-                            delta: 0.0,
+        self.long_press = Some(LongPress {
+            index,
+            started: true,
+        });
+        self.last_tap = None;
+    }
+
+    // Cancels the gestures of a single finger when another one is pressed
+    fn cancel_single(&mut self, now: Instant, result: &mut Vec<Event>) {
+        if let Some(drag) = self.drag.take() {
+            if let Some(finger) = self.fingers.get(&drag.index) {
+                result.push(gesture_event(
+                    drag.start_time,
+                    now,
+                    State::Canceled,
+                    TouchGesture::Drag {
+                        index: drag.index,
+                        start: finger.start,
+                        previous: drag.current,
+                        current: drag.current,
+                        delta: (0.0, 0.0),
+                    },
+                ));
+            }
+        }
+        if let Some(long_press) = self.long_press.take() {
+            if long_press.started {
+                if let Some(finger) = self.fingers.get(&long_press.index) {
+                    result.push(gesture_event(
+                        finger.start_time,
+                        now,
+                        State::Canceled,
+                        TouchGesture::LongPress {
+                            index: long_press.index,
+                            point: finger.current,
                         },
-                    }));
-                    self.first = -1;
-                    self.second = -1;
-                    self.state = State::Ended;
-                    self.current = 0.0;
-                    self.start = 0.0;
-                    return Some(result);
+                    ));
                 }
-                match &e.event_type {
-                    EventType::Move(m) => match m {
-                        Move::Touch {
-                            index,
-                            previous: _,
-                            current,
-                            delta: _,
-                        } => {
-                            self.fingers.insert(*index, *current);
-                            let f1 = vxunwrap!(self.fingers.get(&self.first));
-                            let f2 = vxunwrap!(self.fingers.get(&self.second));
-                            let csx = f1.0 - f2.0;
-                            let csy = f1.1 - f2.1;
-                            let cs = (csx * csx + csy * csy).sqrt();
-                            if cs != self.current {
-                                let result = Event::new(EventType::Touch(Touch::Gesture {
-                                    start_time: self.start_time,
-                                    duration: Instant::now().duration_since(self.start_time),
-                                    state: State::InMiddle,
-                                    gest: TouchGesture::Scale {
-                                        first: (self.first, *f1),
-                                        second: (self.second, *f2),
-                                        start: self.start,
-                                        previous: self.current,
-                                        current: cs,
-                                        delta: cs - self.current,
-                                    },
-                                }));
-                                self.current = cs;
-                                return Some(result);
-                            }
-                        }
-                        _ => (),
+            }
+        }
+    }
+
+    fn start_multi(&mut self, now: Instant) {
+        let mut indices = self.fingers.keys();
+        let first = *vxunwrap!(indices.next());
+        let second = *vxunwrap!(indices.next());
+        let p1 = self.fingers[&first].current;
+        let p2 = self.fingers[&second].current;
+        let d = distance(p1, p2);
+        let a = angle(p1, p2);
+        let c = self.center();
+        self.multi = Some(Multi {
+            start_time: now,
+            fingers: self.fingers.len(),
+            first,
+            second,
+            start_distance: d,
+            distance: d,
+            scale_started: false,
+            start_angle: a,
+            angle: a,
+            rotate_started: false,
+            start_center: c,
+            center: c,
+            pan_started: false,
+        });
+    }
+
+    fn update_multi(&mut self, now: Instant, result: &mut Vec<Event>) {
+        let center = self.center();
+        let config = self.config.clone();
+        let multi = match &mut self.multi {
+            &mut Some(ref mut multi) => multi,
+            &mut None => return,
+        };
+        let first = (multi.first, self.fingers[&multi.first].current);
+        let second = (multi.second, self.fingers[&multi.second].current);
+        if multi.fingers == 2 {
+            let d = distance(first.1, second.1);
+            let previous = multi.distance;
+            if !multi.scale_started {
+                if (d - multi.start_distance).abs() >= config.scale_min_distance {
+                    multi.scale_started = true;
+                    result.push(gesture_event(
+                        multi.start_time,
+                        now,
+                        State::Started,
+                        TouchGesture::Scale {
+                            first,
+                            second,
+                            start: multi.start_distance,
+                            previous,
+                            current: d,
+                            delta: d - previous,
+                        },
+                    ));
+                }
+            } else if d != previous {
+                result.push(gesture_event(
+                    multi.start_time,
+                    now,
+                    State::InMiddle,
+                    TouchGesture::Scale {
+                        first,
+                        second,
+                        start: multi.start_distance,
+                        previous,
+                        current: d,
+                        delta: d - previous,
                     },
-                    _ => (),
+                ));
+            }
+            multi.distance = d;
+            let previous = multi.angle;
+            let a = previous + wrap_angle(angle(first.1, second.1) - previous);
+            if !multi.rotate_started {
+                if (a - multi.start_angle).abs() >= config.rotate_min_angle {
+                    multi.rotate_started = true;
+                    result.push(gesture_event(
+                        multi.start_time,
+                        now,
+                        State::Started,
+                        TouchGesture::Rotate {
+                            first,
+                            second,
+                            start: multi.start_angle,
+                            previous,
+                            current: a,
+                            delta: a - previous,
+                        },
+                    ));
                 }
+            } else if a != previous {
+                result.push(gesture_event(
+                    multi.start_time,
+                    now,
+                    State::InMiddle,
+                    TouchGesture::Rotate {
+                        first,
+                        second,
+                        start: multi.start_angle,
+                        previous,
+                        current: a,
+                        delta: a - previous,
+                    },
+                ));
+            }
+            multi.angle = a;
+        }
+        if multi.fingers >= config.pan_min_fingers {
+            let previous = multi.center;
+            let state = if multi.pan_started {
+                State::InMiddle
+            } else if distance(multi.start_center, center) >= config.pan_min_distance {
+                multi.pan_started = true;
+                State::Started
+            } else {
+                return;
+            };
+            if state == State::InMiddle && center == previous {
+                return;
+            }
+            multi.center = center;
+            result.push(gesture_event(
+                multi.start_time,
+                now,
+                state,
+                TouchGesture::Pan {
+                    fingers: multi.fingers as u8,
+                    start: multi.start_center,
+                    previous,
+                    current: center,
+                    delta: sub(center, previous),
+                },
+            ));
+        }
+    }
+
+    fn end_multi(&mut self, state: State, now: Instant, result: &mut Vec<Event>) {
+        let multi = match self.multi.take() {
+            Some(multi) => multi,
+            None => return,
+        };
+        let first = match self.fingers.get(&multi.first) {
+            Some(finger) => (multi.first, finger.current),
+            None => (multi.first, (0.0, 0.0)),
+        };
+        let second = match self.fingers.get(&multi.second) {
+            Some(finger) => (multi.second, finger.current),
+            None => (multi.second, (0.0, 0.0)),
+        };
+        if multi.scale_started {
+            result.push(gesture_event(
+                multi.start_time,
+                now,
+                state,
+                TouchGesture::Scale {
+                    first,
+                    second,
+                    start: multi.start_distance,
+                    previous: multi.distance,
+                    current: multi.distance,
+                    delta: 0.0,
+                },
+            ));
+        }
+        if multi.rotate_started {
+            result.push(gesture_event(
+                multi.start_time,
+                now,
+                state,
+                TouchGesture::Rotate {
+                    first,
+                    second,
+                    start: multi.start_angle,
+                    previous: multi.angle,
+                    current: multi.angle,
+                    delta: 0.0,
+                },
+            ));
+        }
+        if multi.pan_started {
+            result.push(gesture_event(
+                multi.start_time,
+                now,
+                state,
+                TouchGesture::Pan {
+                    fingers: multi.fingers as u8,
+                    start: multi.start_center,
+                    previous: multi.center,
+                    current: multi.center,
+                    delta: (0.0, 0.0),
+                },
+            ));
+        }
+    }
+
+    fn center(&self) -> (Real, Real) {
+        let mut c = (0.0, 0.0);
+        for f in self.fingers.values() {
+            c.0 += f.current.0;
+            c.1 += f.current.1;
+        }
+        let n = self.fingers.len().max(1) as Real;
+        return (c.0 / n, c.1 / n);
+    }
+
+    // Returns the touch events of a mouse event, None for other events
+    fn emulate(&mut self, e: &Event) -> Option<Vec<Event>> {
+        match &e.event_type {
+            &EventType::Move(Move::Mouse { current, .. }) => {
+                let previous = self.mouse_position;
+                self.mouse_position = current;
+                return Some(
+                    self.mouse_fingers
+                        .iter()
+                        .map(|&(index, finger)| {
+                            let p = emulated_point(finger, previous);
+                            let c = emulated_point(finger, current);
+                            Event::new(EventType::Move(Move::Touch {
+                                index,
+                                previous: p,
+                                current: c,
+                                delta: sub(c, p),
+                            }))
+                        })
+                        .collect(),
+                );
             }
-            State::Ended => {
-                if self.fingers.len() == 2 {
-                    let mut fi = 0;
-                    for f in &self.fingers {
-                        if fi == 0 {
-                            self.first = *f.0;
-                        } else {
-                            self.second = *f.0;
+            &EventType::Button {
+                button: Button::Mouse(Mouse::Left),
+                ref action,
+                modifiers,
+                ..
+            } => {
+                let action = match action {
+                    &ButtonAction::Press => {
+                        if self.mouse_fingers.len() > 0 {
+                            return Some(Vec::new());
                         }
-                        fi += 1;
+                        self.mouse_fingers
+                            .push((MOUSE_FINGER_INDEX, EmulatedFinger::Direct));
+                        if modifiers.contains(Modifiers::CONTROL) {
+                            let finger = if modifiers.contains(Modifiers::SHIFT) {
+                                EmulatedFinger::Parallel
+                            } else {
+                                EmulatedFinger::Mirrored
+                            };
+                            self.mouse_fingers.push((MOUSE_SECOND_FINGER_INDEX, finger));
+                        }
+                        TouchAction::Press
                     }
-                    let f1 = vxunwrap!(self.fingers.get(&self.first));
-                    let f2 = vxunwrap!(self.fingers.get(&self.second));
-                    let csx = f1.0 - f2.0;
-                    let csy = f1.1 - f2.1;
-                    self.current = (csx * csx + csy * csy).sqrt();
-                    self.start = self.current;
-                    self.start_time = Instant::now();
-                    return Some(Event::new(EventType::Touch(Touch::Gesture {
-                        start_time: self.start_time,
-                        duration: Instant::now().duration_since(self.start_time),
-                        state: State::Started,
-                        gest: TouchGesture::Scale {
-                            first: (self.first, *f1),
-                            second: (self.second, *f2),
-                            start: self.start,
-                            previous: self.current,
-                            current: self.current,
-                            delta: 0.0,
-                        },
-                    })));
+                    &ButtonAction::Release => TouchAction::Release,
+                };
+                let position = self.mouse_position;
+                let events = self
+                    .mouse_fingers
+                    .iter()
+                    .map(|&(index, finger)| {
+                        Event::new(EventType::Touch(Touch::Raw {
+                            index,
+                            action: action.clone(),
+                            point: emulated_point(finger, position),
+                        }))
+                    })
+                    .collect();
+                if let TouchAction::Release = action {
+                    self.mouse_fingers.clear();
                 }
+                return Some(events);
             }
-            _ => vxunexpected!(),
+            _ => return None,
+        }
+    }
+}
+
+fn gesture_event(start_time: Instant, now: Instant, state: State, gest: TouchGesture) -> Event {
+    return Event::new(EventType::Touch(Touch::Gesture {
+        start_time,
+        duration: now.duration_since(start_time),
+        state,
+        gest,
+    }));
+}
+
+fn emulated_point(finger: EmulatedFinger, p: (Real, Real)) -> (Real, Real) {
+    return match finger {
+        EmulatedFinger::Direct => p,
+        EmulatedFinger::Mirrored => (1.0 - p.0, 1.0 - p.1),
+        EmulatedFinger::Parallel => (p.0 + MOUSE_PARALLEL_FINGER_OFFSET, p.1),
+    };
+}
+
+fn sub(a: (Real, Real), b: (Real, Real)) -> (Real, Real) {
+    return (a.0 - b.0, a.1 - b.1);
+}
+
+fn distance(a: (Real, Real), b: (Real, Real)) -> Real {
+    let d = sub(a, b);
+    return (d.0 * d.0 + d.1 * d.1).sqrt();
+}
+
+fn angle(a: (Real, Real), b: (Real, Real)) -> Real {
+    return (b.1 - a.1).atan2(b.0 - a.0);
+}
+
+fn wrap_angle(a: Real) -> Real {
+    let mut a = a % (2.0 * PI);
+    if a > PI {
+        a -= 2.0 * PI;
+    } else if a < -PI {
+        a += 2.0 * PI;
+    }
+    return a;
+}
+
+fn duration_to_seconds(d: Duration) -> Real {
+    return d.as_secs() as Real + d.subsec_nanos() as Real * 1e-9;
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn raw(index: FingerIndexType, action: TouchAction, point: (Real, Real)) -> Event {
+        Event::new(EventType::Touch(Touch::Raw {
+            index,
+            action,
+            point,
+        }))
+    }
+
+    fn touch_move(index: FingerIndexType, previous: (Real, Real), current: (Real, Real)) -> Event {
+        Event::new(EventType::Move(Move::Touch {
+            index,
+            previous,
+            current,
+            delta: sub(current, previous),
+        }))
+    }
+
+    fn gestures(events: &[Event]) -> Vec<(State, TouchGesture)> {
+        events
+            .iter()
+            .filter_map(|e| match &e.event_type {
+                &EventType::Touch(Touch::Gesture {
+                    state, ref gest, ..
+                }) => Some((state, gest.clone())),
+                _ => None,
+            })
+            .collect()
+    }
+
+    fn ms(t: Instant, m: u64) -> Instant {
+        t + Duration::from_millis(m)
+    }
+
+    #[test]
+    fn tap_test() {
+        let mut t = Translator::new();
+        let s = Instant::now();
+        assert!(t
+            .receive_at(&raw(0, TouchAction::Press, (0.5, 0.5)), s)
+            .is_empty());
+        let g = gestures(&t.receive_at(&raw(0, TouchAction::Release, (0.5, 0.5)), ms(s, 100)));
+        assert_eq!(g.len(), 1);
+        match &g[0] {
+            &(State::Ended, TouchGesture::Tap { index: 0, .. }) => (),
+            _ => panic!("tap is expected"),
+        }
+        t.receive_at(&raw(1, TouchAction::Press, (0.51, 0.5)), ms(s, 200));
+        let g = gestures(&t.receive_at(&raw(1, TouchAction::Release, (0.51, 0.5)), ms(s, 250)));
+        assert_eq!(g.len(), 2);
+        match &g[1] {
+            &(State::Ended, TouchGesture::DoubleTap { index: 1, .. }) => (),
+            _ => panic!("double tap is expected"),
+        }
+        // a third tap starts a new sequence
+        t.receive_at(&raw(2, TouchAction::Press, (0.51, 0.5)), ms(s, 300));
+        let g = gestures(&t.receive_at(&raw(2, TouchAction::Release, (0.51, 0.5)), ms(s, 350)));
+        assert_eq!(g.len(), 1);
+        // too long for a tap
+        t.receive_at(&raw(3, TouchAction::Press, (0.5, 0.5)), ms(s, 2000));
+        let g = gestures(&t.receive_at(&raw(3, TouchAction::Release, (0.5, 0.5)), ms(s, 2400)));
+        assert!(g.is_empty());
+    }
+
+    #[test]
+    fn long_press_test() {
+        let mut t = Translator::new();
+        let s = Instant::now();
+        t.receive_at(&raw(0, TouchAction::Press, (0.5, 0.5)), s);
+        assert!(t.update_at(ms(s, 400)).is_empty());
+        let g = gestures(&t.update_at(ms(s, 600)));
+        match &g[..] {
+            &[(State::Started, TouchGesture::LongPress { index: 0, .. })] => (),
+            _ => panic!("long press is expected"),
+        }
+        assert!(t.update_at(ms(s, 700)).is_empty());
+        let g = gestures(&t.receive_at(&raw(0, TouchAction::Release, (0.5, 0.5)), ms(s, 800)));
+        match &g[..] {
+            &[(State::Ended, TouchGesture::LongPress { .. })] => (),
+            _ => panic!("only the end of long press is expected"),
+        }
+        // moving cancels it
+        t.receive_at(&raw(0, TouchAction::Press, (0.5, 0.5)), ms(s, 1000));
+        t.update_at(ms(s, 1600));
+        let g = gestures(&t.receive_at(&touch_move(0, (0.5, 0.5), (0.6, 0.5)), ms(s, 1700)));
+        match &g[..] {
+            &[(State::Canceled, TouchGesture::LongPress { .. }), (State::Started, TouchGesture::Drag { .. })] => {
+                ()
+            }
+            _ => panic!("canceled long press and started drag are expected"),
+        }
+    }
+
+    #[test]
+    fn swipe_and_drag_test() {
+        let mut t = Translator::new();
+        let s = Instant::now();
+        t.receive_at(&raw(0, TouchAction::Press, (0.5, 0.5)), s);
+        assert!(t
+            .receive_at(&touch_move(0, (0.5, 0.5), (0.51, 0.5)), ms(s, 10))
+            .is_empty());
+        t.receive_at(&touch_move(0, (0.51, 0.5), (0.5, 0.4)), ms(s, 50));
+        let g = gestures(&t.receive_at(&touch_move(0, (0.5, 0.4), (0.5, 0.3)), ms(s, 100)));
+        match &g[..] {
+            &[(State::InMiddle, TouchGesture::Drag { delta, .. })] => {
+                assert!((delta.1 + 0.1).abs() < 1e-5)
+            }
+            _ => panic!("drag is expected"),
+        }
+        let g = gestures(&t.receive_at(&raw(0, TouchAction::Release, (0.5, 0.3)), ms(s, 200)));
+        match &g[..] {
+            &[(State::Ended, TouchGesture::Drag { .. }), (
+                State::Ended,
+                TouchGesture::Swipe {
+                    direction: SwipeDirection::Up,
+                    velocity,
+                    ..
+                },
+            )] => assert!((velocity.1 + 1.0).abs() < 1e-3),
+            _ => panic!("end of drag and swipe are expected"),
+        }
+        // second finger cancels drag and nothing is reported on release
+        t.receive_at(&raw(0, TouchAction::Press, (0.2, 0.2)), ms(s, 1000));
+        t.receive_at(&touch_move(0, (0.2, 0.2), (0.3, 0.2)), ms(s, 1010));
+        let g = gestures(&t.receive_at(&raw(1, TouchAction::Press, (0.8, 0.8)), ms(s, 1020)));
+        match &g[..] {
+            &[(State::Canceled, TouchGesture::Drag { .. })] => (),
+            _ => panic!("canceled drag is expected"),
+        }
+        t.receive_at(&raw(1, TouchAction::Release, (0.8, 0.8)), ms(s, 1030));
+        let g = gestures(&t.receive_at(&raw(0, TouchAction::Release, (0.3, 0.2)), ms(s, 1040)));
+        assert!(g.is_empty());
+    }
+
+    #[test]
+    fn multi_finger_test() {
+        let mut t = Translator::new();
+        let s = Instant::now();
+        t.receive_at(&raw(0, TouchAction::Press, (0.4, 0.5)), s);
+        t.receive_at(&raw(1, TouchAction::Press, (0.6, 0.5)), s);
+        // rotating around the center by a quarter
+        let g = gestures(&t.receive_at(&touch_move(1, (0.6, 0.5), (0.5, 0.6)), ms(s, 10)));
+        let mut rotated = false;
+        for &(state, ref gest) in &g {
+            match gest {
+                &TouchGesture::Rotate { current, start, .. } => {
+                    assert_eq!(state, State::Started);
+                    assert!((current - start - PI / 4.0).abs() < 1e-4);
+                    rotated = true;
+                }
+                &TouchGesture::Scale { .. } | &TouchGesture::Pan { .. } => (),
+                _ => panic!("unexpected gesture"),
+            }
+        }
+        assert!(rotated);
+        let g = gestures(&t.receive_at(&raw(2, TouchAction::Press, (0.5, 0.9)), ms(s, 20)));
+        assert!(g.iter().all(|&(state, _)| state == State::Canceled));
+        assert!(g.iter().any(|&(_, ref gest)| match gest {
+            &TouchGesture::Rotate { .. } => true,
+            _ => false,
+        }));
+        // three fingers move together
+        t.receive_at(&touch_move(0, (0.4, 0.5), (0.4, 0.6)), ms(s, 30));
+        t.receive_at(&touch_move(1, (0.5, 0.6), (0.5, 0.7)), ms(s, 30));
+        let g = gestures(&t.receive_at(&touch_move(2, (0.5, 0.9), (0.5, 1.0)), ms(s, 30)));
+        match &g[..] {
+            &[(
+                State::InMiddle,
+                TouchGesture::Pan {
+                    fingers: 3,
+                    start,
+                    current,
+                    ..
+                },
+            )] => assert!((current.1 - start.1 - 0.1).abs() < 1e-5),
+            _ => panic!("pan is expected"),
+        }
+        let g = gestures(&t.receive_at(&raw(2, TouchAction::Release, (0.5, 1.0)), ms(s, 40)));
+        match &g[..] {
+            &[(State::Ended, TouchGesture::Pan { fingers: 3, .. })] => (),
+            _ => panic!("end of pan is expected"),
+        }
+    }
+
+    #[test]
+    fn mouse_emulation_test() {
+        let mouse_move = |previous: (Real, Real), current: (Real, Real)| {
+            Event::new(EventType::Move(Move::Mouse {
+                previous,
+                current,
+                delta: sub(current, previous),
+                modifiers: Modifiers::empty(),
+            }))
+        };
+        let button = |action: ButtonAction, modifiers: Modifiers| {
+            Event::new(EventType::Button {
+                button: Button::Mouse(Mouse::Left),
+                action,
+                modifiers,
+                is_repeat: false,
+            })
+        };
+        let mut t = Translator::new();
+        let s = Instant::now();
+        assert!(t
+            .receive_at(&mouse_move((0.0, 0.0), (0.3, 0.5)), s)
+            .is_empty());
+        t.mouse_emulation = true;
+        t.receive_at(&mouse_move((0.0, 0.0), (0.3, 0.5)), s);
+        let r = t.receive_at(&button(ButtonAction::Press, Modifiers::CONTROL), s);
+        assert_eq!(r.len(), 2);
+        match &r[1].event_type {
+            &EventType::Touch(Touch::Raw {
+                index: MOUSE_SECOND_FINGER_INDEX,
+                point,
+                ..
+            }) => assert!((point.0 - 0.7).abs() < 1e-5),
+            _ => panic!("mirrored finger is expected"),
         }
-        return None;
+        let r = t.receive_at(&mouse_move((0.3, 0.5), (0.2, 0.5)), ms(s, 10));
+        let scale = gestures(&r[2..])
+            .into_iter()
+            .filter_map(|(_, gest)| match gest {
+                TouchGesture::Scale { start, current, .. } => Some(current - start),
+                _ => None,
+            })
+            .last();
+        assert!((vxunwrap!(scale) - 0.2).abs() < 1e-5);
+        let r = t.receive_at(
+            &button(ButtonAction::Release, Modifiers::empty()),
+            ms(s, 20),
+        );
+        assert!(gestures(&r).iter().all(|&(state, _)| state == State::Ended));
     }
 }
@@ -153,6 +153,10 @@ impl Gx3DReader {
         }
     }
 
+    pub fn get_offset(&mut self) -> Offset {
+        return vxresult!(self.file.seek(SeekFrom::Current(0)));
+    }
+
     pub fn read_bytes(&mut self, count: Size) -> Vec<u8> {
         let mut data = vec![0u8; count as usize];
         let _n = vxresult!(self.file.read(&mut data));
@@ -170,6 +174,7 @@ impl Gx3DReader {
 pub(crate) struct Table {
     reader: Gx3DReader,
     id_offset: BTreeMap<Id, Offset>,
+    file_name: String,
 }
 
 impl Table {
@@ -179,9 +184,11 @@ impl Table {
         for _ in 0..count {
             id_offset.insert(reader.read::<Id>(), reader.read::<Offset>());
         }
+        let file_name = config.get_gx3d_file_name().to_string();
         Table {
-            reader: vxunwrap!(Gx3DReader::new(config.get_gx3d_file_name())),
+            reader: vxunwrap!(Gx3DReader::new(&file_name)),
             id_offset,
+            file_name,
         }
     }
 
@@ -193,4 +200,9 @@ impl Table {
     pub fn get_mut_reader(&mut self) -> &mut Gx3DReader {
         return &mut self.reader;
     }
+
+    // It is needed for opening another handle of file, e.g. for streaming an entry
+    pub fn get_file_name(&self) -> &str {
+        return &self.file_name;
+    }
 }
@@ -0,0 +1,925 @@
+use super::event::{
+    Button, ButtonAction, Event, Gamepad, GamepadAxis, GamepadButton, Keyboard, Mouse, Move, Touch,
+    TouchGesture, Type as EventType,
+};
+use super::gesture::State as GestureState;
+use super::types::{Id, Real};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs::File;
+use std::io::{Read, Write};
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(debug_mode, derive(Debug))]
+pub enum Axis {
+    X,
+    Y,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(debug_mode, derive(Debug))]
+pub enum Gesture {
+    Tap,
+    DoubleTap,
+    LongPress,
+    Swipe,
+    Drag,
+    Scale,
+    Rotate,
+    Pan,
+}
+
+const GESTURES: [(Gesture, &str); 8] = [
+    (Gesture::Tap, "tap"),
+    (Gesture::DoubleTap, "double-tap"),
+    (Gesture::LongPress, "long-press"),
+    (Gesture::Swipe, "swipe"),
+    (Gesture::Drag, "drag"),
+    (Gesture::Scale, "scale"),
+    (Gesture::Rotate, "rotate"),
+    (Gesture::Pan, "pan"),
+];
+
+#[derive(Clone, PartialEq)]
+#[cfg_attr(debug_mode, derive(Debug))]
+pub enum Binding {
+    Key(Keyboard),
+    Mouse(Mouse),
+    // It is held while the gesture is in progress, taps and swipes are pressed and released in
+    // the same frame
+    Touch(Gesture),
+    // Movements are only meaningful for axes, their deltas are accumulated during a frame
+    MouseMove(Axis),
+    TouchDrag(Axis),
+    TouchScale,
+    // In radians
+    TouchRotate,
+    TouchPan(Axis),
+    Scroll(Axis),
+    // Buttons and axes of gamepads are not bound to a specific gamepad
+    Gamepad(GamepadButton),
+    // Its value is kept until the gamepad reports a new one
+    GamepadAxis(GamepadAxis),
+}
+
+const KEYS: [(Keyboard, &str); 52] = [
+    (Keyboard::A, "A"),
+    (Keyboard::B, "B"),
+    (Keyboard::C, "C"),
+    (Keyboard::D, "D"),
+    (Keyboard::E, "E"),
+    (Keyboard::F, "F"),
+    (Keyboard::G, "G"),
+    (Keyboard::H, "H"),
+    (Keyboard::I, "I"),
+    (Keyboard::J, "J"),
+    (Keyboard::K, "K"),
+    (Keyboard::L, "L"),
+    (Keyboard::M, "M"),
+    (Keyboard::N, "N"),
+    (Keyboard::O, "O"),
+    (Keyboard::P, "P"),
+    (Keyboard::Q, "Q"),
+    (Keyboard::R, "R"),
+    (Keyboard::S, "S"),
+    (Keyboard::T, "T"),
+    (Keyboard::U, "U"),
+    (Keyboard::V, "V"),
+    (Keyboard::W, "W"),
+    (Keyboard::X, "X"),
+    (Keyboard::Y, "Y"),
+    (Keyboard::Z, "Z"),
+    (Keyboard::PrintScreen, "PrintScreen"),
+    (Keyboard::ScrollLock, "ScrollLock"),
+    (Keyboard::PauseBreak, "PauseBreak"),
+    (Keyboard::BackQuote, "BackQuote"),
+    (Keyboard::Backspace, "Backspace"),
+    (Keyboard::Delete, "Delete"),
+    (Keyboard::Insert, "Insert"),
+    (Keyboard::Home, "Home"),
+    (Keyboard::End, "End"),
+    (Keyboard::PageUp, "PageUp"),
+    (Keyboard::PageDown, "PageDown"),
+    (Keyboard::NumLock, "NumLock"),
+    (Keyboard::Star, "Star"),
+    (Keyboard::Tab, "Tab"),
+    (Keyboard::SquareBracketLeft, "SquareBracketLeft"),
+    (Keyboard::SquareBracketRight, "SquareBracketRight"),
+    (Keyboard::CapseLock, "CapseLock"),
+    (Keyboard::SemiColon, "SemiColon"),
+    (Keyboard::Quotem, "Quotem"),
+    (Keyboard::Comma, "Comma"),
+    (Keyboard::ArrowUp, "ArrowUp"),
+    (Keyboard::ArrowDown, "ArrowDown"),
+    (Keyboard::ArrowLeft, "ArrowLeft"),
+    (Keyboard::ArrowRight, "ArrowRight"),
+    (Keyboard::Equal, "Equal"),
+    (Keyboard::Unknown, "Unknown"),
+];
+
+const MICE: [(Mouse, &str); 6] = [
+    (Mouse::Left, "Left"),
+    (Mouse::Right, "Right"),
+    (Mouse::Middle, "Middle"),
+    (Mouse::Back, "Back"),
+    (Mouse::Forward, "Forward"),
+    (Mouse::Offic, "Offic"),
+];
+
+// Keys that have an index are written like Shift(1)
+fn key_to_string(key: &Keyboard) -> String {
+    let (name, index) = match key {
+        &Keyboard::Escape(i) => ("Escape", i),
+        &Keyboard::Function(i) => ("Function", i),
+        &Keyboard::Number { number, padd } => (if padd { "PadNumber" } else { "Number" }, number),
+        &Keyboard::Slash(i) => ("Slash", i),
+        &Keyboard::Plus(i) => ("Plus", i),
+        &Keyboard::Minus(i) => ("Minus", i),
+        &Keyboard::Enter(i) => ("Enter", i),
+        &Keyboard::Period(i) => ("Period", i),
+        &Keyboard::BackSlash(i) => ("BackSlash", i),
+        &Keyboard::Shift(i) => ("Shift", i),
+        &Keyboard::Control(i) => ("Control", i),
+        &Keyboard::Alt(i) => ("Alt", i),
+        &Keyboard::Space(i) => ("Space", i),
+        &Keyboard::Command(i) => ("Command", i),
+        &Keyboard::Super(i) => ("Super", i),
+        &Keyboard::Properties(i) => ("Properties", i),
+        _ => {
+            for &(ref k, n) in KEYS.iter() {
+                if k == key {
+                    return n.to_string();
+                }
+            }
+            vxunexpected!();
+        }
+    };
+    return format!("{}({})", name, index);
+}
+
+fn key_from_str(s: &str) -> Option<Keyboard> {
+    if let Some(open) = s.find('(') {
+        if !s.ends_with(')') {
+            return None;
+        }
+        let i: u8 = match s[open + 1..s.len() - 1].parse() {
+            Ok(i) => i,
+            Err(_) => return None,
+        };
+        return Some(match &s[..open] {
+            "Escape" => Keyboard::Escape(i),
+            "Function" => Keyboard::Function(i),
+            "Number" => Keyboard::Number {
+                number: i,
+                padd: false,
+            },
+            "PadNumber" => Keyboard::Number {
+                number: i,
+                padd: true,
+            },
+            "Slash" => Keyboard::Slash(i),
+            "Plus" => Keyboard::Plus(i),
+            "Minus" => Keyboard::Minus(i),
+            "Enter" => Keyboard::Enter(i),
+            "Period" => Keyboard::Period(i),
+            "BackSlash" => Keyboard::BackSlash(i),
+            "Shift" => Keyboard::Shift(i),
+            "Control" => Keyboard::Control(i),
+            "Alt" => Keyboard::Alt(i),
+            "Space" => Keyboard::Space(i),
+            "Command" => Keyboard::Command(i),
+            "Super" => Keyboard::Super(i),
+            "Properties" => Keyboard::Properties(i),
+            _ => return None,
+        });
+    }
+    for &(ref k, n) in KEYS.iter() {
+        if n == s {
+            return Some(k.clone());
+        }
+    }
+    return None;
+}
+
+const GAMEPAD_BUTTONS: [(GamepadButton, &str); 17] = [
+    (GamepadButton::South, "South"),
+    (GamepadButton::East, "East"),
+    (GamepadButton::North, "North"),
+    (GamepadButton::West, "West"),
+    (GamepadButton::LeftBumper, "LeftBumper"),
+    (GamepadButton::RightBumper, "RightBumper"),
+    (GamepadButton::LeftTrigger, "LeftTrigger"),
+    (GamepadButton::RightTrigger, "RightTrigger"),
+    (GamepadButton::Select, "Select"),
+    (GamepadButton::Start, "Start"),
+    (GamepadButton::Mode, "Mode"),
+    (GamepadButton::LeftThumb, "LeftThumb"),
+    (GamepadButton::RightThumb, "RightThumb"),
+    (GamepadButton::DpadUp, "DpadUp"),
+    (GamepadButton::DpadDown, "DpadDown"),
+    (GamepadButton::DpadLeft, "DpadLeft"),
+    (GamepadButton::DpadRight, "DpadRight"),
+];
+
+const GAMEPAD_AXES: [(GamepadAxis, &str); 6] = [
+    (GamepadAxis::LeftX, "left-x"),
+    (GamepadAxis::LeftY, "left-y"),
+    (GamepadAxis::RightX, "right-x"),
+    (GamepadAxis::RightY, "right-y"),
+    (GamepadAxis::LeftTrigger, "left-trigger"),
+    (GamepadAxis::RightTrigger, "right-trigger"),
+];
+
+fn axis_from_str(s: &str) -> Option<Axis> {
+    return match s {
+        "x" => Some(Axis::X),
+        "y" => Some(Axis::Y),
+        _ => None,
+    };
+}
+
+fn axis_to_str(a: Axis) -> &'static str {
+    return match a {
+        Axis::X => "x",
+        Axis::Y => "y",
+    };
+}
+
+impl Binding {
+    // Words are like: key W, mouse Left, touch drag, mouse-move x, touch-drag y, touch-scale,
+    // touch-rotate, touch-pan x, scroll y, gamepad South, gamepad-axis left-x
+    pub fn new_with_words(words: &[&str]) -> Option<Self> {
+        return match words {
+            &["key", k] => key_from_str(k).map(Binding::Key),
+            &["mouse", m] => {
+                for &(ref mouse, n) in MICE.iter() {
+                    if n == m {
+                        return Some(Binding::Mouse(mouse.clone()));
+                    }
+                }
+                None
+            }
+            &["touch", g] => {
+                for &(gesture, n) in GESTURES.iter() {
+                    if n == g {
+                        return Some(Binding::Touch(gesture));
+                    }
+                }
+                None
+            }
+            &["mouse-move", a] => axis_from_str(a).map(Binding::MouseMove),
+            &["touch-drag", a] => axis_from_str(a).map(Binding::TouchDrag),
+            &["touch-scale"] => Some(Binding::TouchScale),
+            &["touch-rotate"] => Some(Binding::TouchRotate),
+            &["touch-pan", a] => axis_from_str(a).map(Binding::TouchPan),
+            &["scroll", a] => axis_from_str(a).map(Binding::Scroll),
+            &["gamepad", b] => {
+                for &(button, n) in GAMEPAD_BUTTONS.iter() {
+                    if n == b {
+                        return Some(Binding::Gamepad(button));
+                    }
+                }
+                None
+            }
+            &["gamepad-axis", a] => {
+                for &(axis, n) in GAMEPAD_AXES.iter() {
+                    if n == a {
+                        return Some(Binding::GamepadAxis(axis));
+                    }
+                }
+                None
+            }
+            _ => None,
+        };
+    }
+
+    pub fn to_words(&self) -> String {
+        return match self {
+            &Binding::Key(ref k) => format!("key {}", key_to_string(k)),
+            &Binding::Mouse(ref m) => {
+                let mut name = "";
+                for &(ref mouse, n) in MICE.iter() {
+                    if mouse == m {
+                        name = n;
+                    }
+                }
+                format!("mouse {}", name)
+            }
+            &Binding::Touch(g) => {
+                let mut name = "";
+                for &(gesture, n) in GESTURES.iter() {
+                    if gesture == g {
+                        name = n;
+                    }
+                }
+                format!("touch {}", name)
+            }
+            &Binding::MouseMove(a) => format!("mouse-move {}", axis_to_str(a)),
+            &Binding::TouchDrag(a) => format!("touch-drag {}", axis_to_str(a)),
+            &Binding::TouchScale => "touch-scale".to_string(),
+            &Binding::TouchRotate => "touch-rotate".to_string(),
+            &Binding::TouchPan(a) => format!("touch-pan {}", axis_to_str(a)),
+            &Binding::Scroll(a) => format!("scroll {}", axis_to_str(a)),
+            &Binding::Gamepad(b) => {
+                let mut name = "";
+                for &(button, n) in GAMEPAD_BUTTONS.iter() {
+                    if button == b {
+                        name = n;
+                    }
+                }
+                format!("gamepad {}", name)
+            }
+            &Binding::GamepadAxis(a) => {
+                let mut name = "";
+                for &(axis, n) in GAMEPAD_AXES.iter() {
+                    if axis == a {
+                        name = n;
+                    }
+                }
+                format!("gamepad-axis {}", name)
+            }
+        };
+    }
+
+    pub fn is_analog(&self) -> bool {
+        return match self {
+            &Binding::MouseMove(_)
+            | &Binding::TouchDrag(_)
+            | &Binding::TouchScale
+            | &Binding::TouchRotate
+            | &Binding::TouchPan(_)
+            | &Binding::Scroll(_)
+            | &Binding::GamepadAxis(_) => true,
+            _ => false,
+        };
+    }
+}
+
+// Named actions and axes with their bindings, in text each line is one of:
+//     action <name> <binding>
+//     axis <name> <scale> <binding>
+// and # starts a comment.
+#[cfg_attr(debug_mode, derive(Debug))]
+pub struct Bindings {
+    actions: BTreeMap<String, Vec<Binding>>,
+    axes: BTreeMap<String, Vec<(Binding, Real)>>,
+}
+
+impl Bindings {
+    pub fn new() -> Self {
+        Bindings {
+            actions: BTreeMap::new(),
+            axes: BTreeMap::new(),
+        }
+    }
+
+    // Lines that can not be parsed are logged and skipped
+    pub fn new_with_text(text: &str) -> Self {
+        let mut bindings = Self::new();
+        for (line_number, line) in text.lines().enumerate() {
+            let line = match line.find('#') {
+                Some(i) => &line[..i],
+                None => line,
+            };
+            let words: Vec<&str> = line.split_whitespace().collect();
+            if words.len() == 0 {
+                continue;
+            }
+            if words.len() > 2 && words[0] == "action" {
+                if let Some(binding) = Binding::new_with_words(&words[2..]) {
+                    bindings.bind_action(words[1], binding);
+                    continue;
+                }
+            } else if words.len() > 3 && words[0] == "axis" {
+                let scale = words[2].parse::<Real>().ok();
+                let binding = Binding::new_with_words(&words[3..]);
+                if let (Some(scale), Some(binding)) = (scale, binding) {
+                    bindings.bind_axis(words[1], binding, scale);
+                    continue;
+                }
+            }
+            vxloge!(
+                "Wrong binding in line {} is skipped: {}",
+                line_number + 1,
+                line
+            );
+        }
+        return bindings;
+    }
+
+    pub fn load(file_name: &str) -> Self {
+        let mut text = String::new();
+        vxresult!(vxresult!(File::open(file_name)).read_to_string(&mut text));
+        return Self::new_with_text(&text);
+    }
+
+    pub fn to_text(&self) -> String {
+        let mut text = String::new();
+        for (name, bindings) in &self.actions {
+            for b in bindings {
+                text.push_str(&format!("action {} {}\n", name, b.to_words()));
+            }
+        }
+        for (name, bindings) in &self.axes {
+            for &(ref b, scale) in bindings {
+                text.push_str(&format!("axis {} {} {}\n", name, scale, b.to_words()));
+            }
+        }
+        return text;
+    }
+
+    pub fn save(&self, file_name: &str) {
+        let mut file = vxresult!(File::create(file_name));
+        vxresult!(file.write_all(self.to_text().as_bytes()));
+    }
+
+    pub fn bind_action(&mut self, name: &str, binding: Binding) {
+        let bindings = self.actions.entry(name.to_string()).or_insert(Vec::new());
+        if !bindings.contains(&binding) {
+            bindings.push(binding);
+        }
+    }
+
+    pub fn unbind_action(&mut self, name: &str, binding: &Binding) {
+        if let Some(bindings) = self.actions.get_mut(name) {
+            bindings.retain(|b| b != binding);
+        }
+    }
+
+    pub fn clear_action(&mut self, name: &str) {
+        self.actions.remove(name);
+    }
+
+    pub fn get_action(&self, name: &str) -> &[Binding] {
+        return match self.actions.get(name) {
+            Some(bindings) => bindings,
+            None => &[],
+        };
+    }
+
+    // Binding of axis replaces its previous scale
+    pub fn bind_axis(&mut self, name: &str, binding: Binding, scale: Real) {
+        let bindings = self.axes.entry(name.to_string()).or_insert(Vec::new());
+        bindings.retain(|b| b.0 != binding);
+        bindings.push((binding, scale));
+    }
+
+    pub fn unbind_axis(&mut self, name: &str, binding: &Binding) {
+        if let Some(bindings) = self.axes.get_mut(name) {
+            bindings.retain(|b| &b.0 != binding);
+        }
+    }
+
+    pub fn clear_axis(&mut self, name: &str) {
+        self.axes.remove(name);
+    }
+
+    pub fn get_axis(&self, name: &str) -> &[(Binding, Real)] {
+        return match self.axes.get(name) {
+            Some(bindings) => bindings,
+            None => &[],
+        };
+    }
+}
+
+// Keeps the state of bindings from the event stream, queries are about the current frame and
+// update must be called at the end of each frame.
+#[cfg_attr(debug_mode, derive(Debug))]
+pub struct Manager {
+    bindings: Bindings,
+    held: Vec<Binding>,
+    pressed: BTreeSet<String>,
+    released: BTreeSet<String>,
+    mouse_move: (Real, Real),
+    touch_drag: (Real, Real),
+    touch_scale: Real,
+    touch_rotate: Real,
+    touch_pan: (Real, Real),
+    scroll: (Real, Real),
+    // A gamepad button is held while any of gamepads holds it
+    gamepad_buttons: Vec<(Id, GamepadButton)>,
+    gamepad_axes: Vec<(Id, GamepadAxis, Real)>,
+    text: String,
+}
+
+impl Manager {
+    pub fn new(bindings: Bindings) -> Self {
+        Manager {
+            bindings,
+            held: Vec::new(),
+            pressed: BTreeSet::new(),
+            released: BTreeSet::new(),
+            mouse_move: (0.0, 0.0),
+            touch_drag: (0.0, 0.0),
+            touch_scale: 0.0,
+            touch_rotate: 0.0,
+            touch_pan: (0.0, 0.0),
+            scroll: (0.0, 0.0),
+            gamepad_buttons: Vec::new(),
+            gamepad_axes: Vec::new(),
+            text: String::new(),
+        }
+    }
+
+    pub fn get_bindings(&self) -> &Bindings {
+        return &self.bindings;
+    }
+
+    pub fn get_mut_bindings(&mut self) -> &mut Bindings {
+        return &mut self.bindings;
+    }
+
+    pub fn on_event(&mut self, e: &Event) {
+        match &e.event_type {
+            &EventType::Button {
+                ref button,
+                ref action,
+                ..
+            } => {
+                let binding = match button {
+                    &Button::Keyboard(ref k) => Binding::Key(k.clone()),
+                    &Button::Mouse(ref m) => Binding::Mouse(m.clone()),
+                    &Button::Gamepad { id, button } => {
+                        match action {
+                            &ButtonAction::Press => self.press_gamepad(id, button),
+                            &ButtonAction::Release => self.release_gamepad(id, button),
+                        }
+                        return;
+                    }
+                };
+                match action {
+                    &ButtonAction::Press => self.press(binding),
+                    &ButtonAction::Release => self.release(&binding),
+                }
+            }
+            &EventType::Move(Move::Mouse { delta, .. }) => {
+                self.mouse_move.0 += delta.0;
+                self.mouse_move.1 += delta.1;
+            }
+            &EventType::Touch(Touch::Gesture {
+                ref state,
+                ref gest,
+                ..
+            }) => {
+                let gesture = match gest {
+                    &TouchGesture::Tap { .. }
+                    | &TouchGesture::DoubleTap { .. }
+                    | &TouchGesture::Swipe { .. } => {
+                        let gesture = match gest {
+                            &TouchGesture::Tap { .. } => Gesture::Tap,
+                            &TouchGesture::DoubleTap { .. } => Gesture::DoubleTap,
+                            _ => Gesture::Swipe,
+                        };
+                        self.press(Binding::Touch(gesture));
+                        self.release(&Binding::Touch(gesture));
+                        return;
+                    }
+                    &TouchGesture::LongPress { .. } => Gesture::LongPress,
+                    &TouchGesture::Drag { delta, .. } => {
+                        self.touch_drag.0 += delta.0;
+                        self.touch_drag.1 += delta.1;
+                        Gesture::Drag
+                    }
+                    &TouchGesture::Scale { delta, .. } => {
+                        self.touch_scale += delta;
+                        Gesture::Scale
+                    }
+                    &TouchGesture::Rotate { delta, .. } => {
+                        self.touch_rotate += delta;
+                        Gesture::Rotate
+                    }
+                    &TouchGesture::Pan { delta, .. } => {
+                        self.touch_pan.0 += delta.0;
+                        self.touch_pan.1 += delta.1;
+                        Gesture::Pan
+                    }
+                };
+                match state {
+                    &GestureState::Started | &GestureState::InMiddle => {
+                        self.press(Binding::Touch(gesture))
+                    }
+                    &GestureState::Ended | &GestureState::Canceled => {
+                        self.release(&Binding::Touch(gesture))
+                    }
+                }
+            }
+            &EventType::Scroll { delta, .. } => {
+                self.scroll.0 += delta.0;
+                self.scroll.1 += delta.1;
+            }
+            &EventType::Text(ref text) => self.text.push_str(text),
+            &EventType::Gamepad(Gamepad::Axis { id, axis, value }) => {
+                self.gamepad_axes.retain(|a| a.0 != id || a.1 != axis);
+                if value != 0.0 {
+                    self.gamepad_axes.push((id, axis, value));
+                }
+            }
+            &EventType::Gamepad(Gamepad::Disconnected { id }) => {
+                self.gamepad_axes.retain(|a| a.0 != id);
+                let buttons: Vec<GamepadButton> = self
+                    .gamepad_buttons
+                    .iter()
+                    .filter(|b| b.0 == id)
+                    .map(|b| b.1)
+                    .collect();
+                for b in buttons {
+                    self.release_gamepad(id, b);
+                }
+            }
+            _ => (),
+        }
+    }
+
+    // Ends the current frame
+    pub fn update(&mut self) {
+        self.pressed.clear();
+        self.released.clear();
+        self.mouse_move = (0.0, 0.0);
+        self.touch_drag = (0.0, 0.0);
+        self.touch_scale = 0.0;
+        self.touch_rotate = 0.0;
+        self.touch_pan = (0.0, 0.0);
+        self.scroll = (0.0, 0.0);
+        self.text.clear();
+    }
+
+    pub fn is_pressed(&self, action: &str) -> bool {
+        for b in self.bindings.get_action(action) {
+            if self.held.contains(b) {
+                return true;
+            }
+        }
+        return false;
+    }
+
+    // True when action became pressed in this frame, even if it is released in the same frame
+    pub fn just_pressed(&self, action: &str) -> bool {
+        return self.pressed.contains(action);
+    }
+
+    pub fn just_released(&self, action: &str) -> bool {
+        return self.released.contains(action);
+    }
+
+    // Text that is typed in this frame
+    pub fn get_text(&self) -> &str {
+        return &self.text;
+    }
+
+    // Sum of held buttons and gamepad axes is clamped between -1 and 1 and then deltas of
+    // movements are added
+    pub fn axis_value(&self, axis: &str) -> Real {
+        let mut buttons: Real = 0.0;
+        let mut deltas = 0.0;
+        for &(ref b, scale) in self.bindings.get_axis(axis) {
+            match b {
+                &Binding::MouseMove(Axis::X) => deltas += self.mouse_move.0 * scale,
+                &Binding::MouseMove(Axis::Y) => deltas += self.mouse_move.1 * scale,
+                &Binding::TouchDrag(Axis::X) => deltas += self.touch_drag.0 * scale,
+                &Binding::TouchDrag(Axis::Y) => deltas += self.touch_drag.1 * scale,
+                &Binding::TouchScale => deltas += self.touch_scale * scale,
+                &Binding::TouchRotate => deltas += self.touch_rotate * scale,
+                &Binding::TouchPan(Axis::X) => deltas += self.touch_pan.0 * scale,
+                &Binding::TouchPan(Axis::Y) => deltas += self.touch_pan.1 * scale,
+                &Binding::Scroll(Axis::X) => deltas += self.scroll.0 * scale,
+                &Binding::Scroll(Axis::Y) => deltas += self.scroll.1 * scale,
+                &Binding::GamepadAxis(a) => buttons += self.get_gamepad_axis(a) * scale,
+                _ => {
+                    if self.held.contains(b) {
+                        buttons += scale;
+                    }
+                }
+            }
+        }
+        return buttons.max(-1.0).min(1.0) + deltas;
+    }
+
+    // When several gamepads report an axis, the farthest one from rest is taken
+    pub fn get_gamepad_axis(&self, axis: GamepadAxis) -> Real {
+        let mut value: Real = 0.0;
+        for &(_, a, v) in &self.gamepad_axes {
+            if a == axis && v.abs() > value.abs() {
+                value = v;
+            }
+        }
+        return value;
+    }
+
+    fn press_gamepad(&mut self, id: Id, button: GamepadButton) {
+        if !self.gamepad_buttons.contains(&(id, button)) {
+            self.gamepad_buttons.push((id, button));
+        }
+        self.press(Binding::Gamepad(button));
+    }
+
+    fn release_gamepad(&mut self, id: Id, button: GamepadButton) {
+        self.gamepad_buttons.retain(|b| *b != (id, button));
+        for b in &self.gamepad_buttons {
+            if b.1 == button {
+                return;
+            }
+        }
+        self.release(&Binding::Gamepad(button));
+    }
+
+    fn press(&mut self, binding: Binding) {
+        if self.held.contains(&binding) {
+            return;
+        }
+        for (name, bindings) in &self.bindings.actions {
+            if bindings.contains(&binding) && !self.is_pressed(name) {
+                self.pressed.insert(name.clone());
+            }
+        }
+        self.held.push(binding);
+    }
+
+    fn release(&mut self, binding: &Binding) {
+        if !self.held.contains(binding) {
+            return;
+        }
+        self.held.retain(|b| b != binding);
+        for (name, bindings) in &self.bindings.actions {
+            if bindings.contains(binding) && !self.is_pressed(name) {
+                self.released.insert(name.clone());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::event::Modifiers;
+    use super::*;
+
+    const TEXT: &str = "
+        # movement
+        action jump key Space(0)
+        action look mouse Left
+        action look touch drag
+        axis forward 1 key W
+        axis forward -1 key S
+        axis forward 1 key ArrowUp
+        axis look-x 1.5 mouse-move x
+        axis look-x 2 touch-drag x
+        axis zoom 0.5 scroll y
+        action jump gamepad South
+        axis forward -1 gamepad-axis left-y
+    ";
+
+    fn button(k: Keyboard, action: ButtonAction) -> Event {
+        return Event::new(EventType::Button {
+            button: Button::Keyboard(k),
+            action,
+            modifiers: Modifiers::empty(),
+            is_repeat: false,
+        });
+    }
+
+    fn gamepad(id: Id, b: GamepadButton, action: ButtonAction) -> Event {
+        return Event::new(EventType::Button {
+            button: Button::Gamepad { id, button: b },
+            action,
+            modifiers: Modifiers::empty(),
+            is_repeat: false,
+        });
+    }
+
+    #[test]
+    fn bindings_test() {
+        let bindings = Bindings::new_with_text(TEXT);
+        assert_eq!(bindings.get_action("look").len(), 2);
+        assert_eq!(bindings.get_axis("forward").len(), 4);
+        let again = Bindings::new_with_text(&bindings.to_text());
+        assert_eq!(again.to_text(), bindings.to_text());
+        let skipped = Bindings::new_with_text(
+            "action jump key Nothing\n\
+             axis forward fast key W\n\
+             axis forward 1 key\n\
+             bind jump key Space(0)\n\
+             action jump key Space(0)\n",
+        );
+        assert_eq!(skipped.get_action("jump").len(), 1);
+        assert_eq!(skipped.get_axis("forward").len(), 0);
+        assert!(again.get_action("jump")[0] == Binding::Key(Keyboard::Space(0)));
+        assert!(
+            key_from_str("PadNumber(5)")
+                == Some(Keyboard::Number {
+                    number: 5,
+                    padd: true
+                })
+        );
+        assert!(key_from_str("Shift(x)").is_none());
+        assert!(key_from_str("Nothing").is_none());
+        for &(ref k, _) in KEYS.iter() {
+            assert!(key_from_str(&key_to_string(k)).as_ref() == Some(k));
+        }
+        for &(g, _) in GESTURES.iter() {
+            let binding = Binding::Touch(g);
+            let words = binding.to_words();
+            let words: Vec<&str> = words.split_whitespace().collect();
+            assert!(Binding::new_with_words(&words) == Some(binding));
+        }
+        for &(b, _) in GAMEPAD_BUTTONS.iter() {
+            let binding = Binding::Gamepad(b);
+            let words = binding.to_words();
+            let words: Vec<&str> = words.split_whitespace().collect();
+            assert!(Binding::new_with_words(&words) == Some(binding));
+        }
+        assert!(
+            Binding::new_with_words(&["gamepad-axis", "right-trigger"])
+                == Some(Binding::GamepadAxis(GamepadAxis::RightTrigger))
+        );
+    }
+
+    #[test]
+    fn actions_test() {
+        let mut input = Manager::new(Bindings::new_with_text(TEXT));
+        input.on_event(&button(Keyboard::Space(0), ButtonAction::Press));
+        assert!(input.is_pressed("jump"));
+        assert!(input.just_pressed("jump"));
+        input.update();
+        // repeated press is ignored
+        input.on_event(&button(Keyboard::Space(0), ButtonAction::Press));
+        assert!(input.is_pressed("jump"));
+        assert!(!input.just_pressed("jump"));
+        input.on_event(&button(Keyboard::Space(0), ButtonAction::Release));
+        assert!(!input.is_pressed("jump"));
+        assert!(input.just_released("jump"));
+        input.update();
+        assert!(!input.just_released("jump"));
+        // rebinding
+        input
+            .get_mut_bindings()
+            .unbind_action("jump", &Binding::Key(Keyboard::Space(0)));
+        input
+            .get_mut_bindings()
+            .bind_action("jump", Binding::Key(Keyboard::J));
+        input.on_event(&button(Keyboard::Space(0), ButtonAction::Press));
+        assert!(!input.is_pressed("jump"));
+        input.on_event(&button(Keyboard::J, ButtonAction::Press));
+        assert!(input.just_pressed("jump"));
+        input.on_event(&Event::new(EventType::Text("J".to_string())));
+        input.on_event(&Event::new(EventType::Text("é".to_string())));
+        assert_eq!(input.get_text(), "Jé");
+        input.update();
+        assert_eq!(input.get_text(), "");
+    }
+
+    #[test]
+    fn axes_test() {
+        let mut input = Manager::new(Bindings::new_with_text(TEXT));
+        input.on_event(&button(Keyboard::W, ButtonAction::Press));
+        input.on_event(&button(Keyboard::ArrowUp, ButtonAction::Press));
+        assert_eq!(input.axis_value("forward"), 1.0);
+        input.on_event(&button(Keyboard::S, ButtonAction::Press));
+        assert_eq!(input.axis_value("forward"), 1.0);
+        input.on_event(&button(Keyboard::ArrowUp, ButtonAction::Release));
+        assert_eq!(input.axis_value("forward"), 0.0);
+        for _ in 0..2 {
+            input.on_event(&Event::new(EventType::Move(Move::Mouse {
+                previous: (0.0, 0.0),
+                current: (0.0, 0.0),
+                delta: (0.25, 0.5),
+                modifiers: Modifiers::SHIFT,
+            })));
+        }
+        assert_eq!(input.axis_value("look-x"), 0.75);
+        input.on_event(&Event::new(EventType::Scroll {
+            delta: (0.0, -3.0),
+            modifiers: Modifiers::CONTROL | Modifiers::ALT,
+        }));
+        assert_eq!(input.axis_value("zoom"), -1.5);
+        input.update();
+        assert_eq!(input.axis_value("look-x"), 0.0);
+        assert_eq!(input.axis_value("zoom"), 0.0);
+        assert_eq!(input.axis_value("nothing"), 0.0);
+    }
+
+    #[test]
+    fn gamepad_test() {
+        let mut input = Manager::new(Bindings::new_with_text(TEXT));
+        input.on_event(&gamepad(1, GamepadButton::South, ButtonAction::Press));
+        input.on_event(&gamepad(2, GamepadButton::South, ButtonAction::Press));
+        assert!(input.just_pressed("jump"));
+        input.update();
+        // it is held while the second gamepad holds it
+        input.on_event(&gamepad(1, GamepadButton::South, ButtonAction::Release));
+        assert!(input.is_pressed("jump"));
+        input.on_event(&Event::new(EventType::Gamepad(Gamepad::Axis {
+            id: 2,
+            axis: GamepadAxis::LeftY,
+            value: 0.5,
+        })));
+        input.update();
+        // axis keeps its value between frames
+        assert_eq!(input.axis_value("forward"), -0.5);
+        input.on_event(&button(Keyboard::W, ButtonAction::Press));
+        assert_eq!(input.axis_value("forward"), 0.5);
+        input.on_event(&Event::new(EventType::Gamepad(Gamepad::Disconnected {
+            id: 2,
+        })));
+        assert!(!input.is_pressed("jump"));
+        assert!(input.just_released("jump"));
+        assert_eq!(input.axis_value("forward"), 1.0);
+    }
+}
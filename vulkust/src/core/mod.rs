@@ -5,10 +5,13 @@ pub mod asset;
 pub mod config;
 pub mod constants;
 pub mod debug;
+pub mod dispatcher;
 pub mod event;
 pub mod gesture;
 pub mod gx3d;
+pub mod input;
 pub mod object;
+pub mod replay;
 pub mod storage;
 pub mod string;
 pub mod timing;
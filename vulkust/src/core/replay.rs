@@ -0,0 +1,965 @@
+use super::event::{
+    Button, ButtonAction, Event, Gamepad, Modifiers, Move, SwipeDirection, Touch, TouchAction,
+    TouchGesture, Type as EventType, Window,
+};
+use super::gesture::State as GestureState;
+use super::input::Binding;
+use super::timing::Timing;
+use super::types::Real;
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{BufWriter, Read, Write};
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+// Strings are kept in one word, bytes of white spaces, controls and % are written like %0A
+fn escape(s: &str) -> String {
+    let mut result = String::with_capacity(s.len());
+    for c in s.chars() {
+        if c == '%' || c.is_whitespace() || c.is_control() {
+            let mut bytes = [0u8; 4];
+            for b in c.encode_utf8(&mut bytes).bytes() {
+                result.push_str(&format!("%{:02X}", b));
+            }
+        } else {
+            result.push(c);
+        }
+    }
+    return result;
+}
+
+fn unescape(s: &str) -> Option<String> {
+    let mut bytes = Vec::with_capacity(s.len());
+    let mut iter = s.bytes();
+    while let Some(b) = iter.next() {
+        if b != b'%' {
+            bytes.push(b);
+            continue;
+        }
+        let hex = [iter.next()?, iter.next()?];
+        let hex = String::from_utf8(hex.to_vec()).ok()?;
+        bytes.push(u8::from_str_radix(&hex, 16).ok()?);
+    }
+    return String::from_utf8(bytes).ok();
+}
+
+fn action_to_str(action: &ButtonAction) -> &'static str {
+    return match action {
+        &ButtonAction::Press => "press",
+        &ButtonAction::Release => "release",
+    };
+}
+
+fn touch_action_to_str(action: &TouchAction) -> &'static str {
+    return match action {
+        &TouchAction::Press => "press",
+        &TouchAction::HardPress => "hard-press",
+        &TouchAction::Release => "release",
+    };
+}
+
+fn direction_to_str(direction: SwipeDirection) -> &'static str {
+    return match direction {
+        SwipeDirection::Left => "left",
+        SwipeDirection::Right => "right",
+        SwipeDirection::Up => "up",
+        SwipeDirection::Down => "down",
+    };
+}
+
+fn state_to_str(state: &GestureState) -> &'static str {
+    return match state {
+        &GestureState::Started => "started",
+        &GestureState::InMiddle => "in-middle",
+        &GestureState::Ended => "ended",
+        &GestureState::Canceled => "canceled",
+    };
+}
+
+// Times are kept relative to the start of recording, so they can be before it
+fn instant_to_nanos(start: Instant, t: Instant) -> i64 {
+    if t >= start {
+        return t.duration_since(start).as_nanos() as i64;
+    }
+    return -(start.duration_since(t).as_nanos() as i64);
+}
+
+fn nanos_to_instant(start: Instant, nanos: i64) -> Instant {
+    if nanos >= 0 {
+        return start + Duration::from_nanos(nanos as u64);
+    }
+    return start - Duration::from_nanos((-nanos) as u64);
+}
+
+// Events that are not input, like triggers of physics, are generated again in replay, so they
+// are not encoded.
+pub fn encode(e: &EventType, start: Instant) -> Option<String> {
+    return Some(match e {
+        &EventType::Move(Move::Mouse {
+            previous,
+            current,
+            delta,
+            modifiers,
+        }) => format!(
+            "mouse-move {} {} {} {} {} {} {}",
+            previous.0,
+            previous.1,
+            current.0,
+            current.1,
+            delta.0,
+            delta.1,
+            modifiers.get_bits()
+        ),
+        &EventType::Move(Move::Touch {
+            index,
+            previous,
+            current,
+            delta,
+        }) => format!(
+            "touch-move {} {} {} {} {} {} {}",
+            index, previous.0, previous.1, current.0, current.1, delta.0, delta.1
+        ),
+        &EventType::Button {
+            ref button,
+            ref action,
+            modifiers,
+            is_repeat,
+        } => {
+            let button = match button {
+                &Button::Keyboard(ref k) => Binding::Key(k.clone()).to_words(),
+                &Button::Mouse(ref m) => Binding::Mouse(m.clone()).to_words(),
+                &Button::Gamepad { id, button } => {
+                    let words = Binding::Gamepad(button).to_words();
+                    let name = vxunwrap!(words.split_whitespace().nth(1));
+                    format!("gamepad {} {}", id, name)
+                }
+            };
+            format!(
+                "button {} {} {} {}",
+                action_to_str(action),
+                modifiers.get_bits(),
+                is_repeat as u8,
+                button
+            )
+        }
+        &EventType::Text(ref text) => format!("text {}", escape(text)),
+        &EventType::Scroll { delta, modifiers } => {
+            format!("scroll {} {} {}", delta.0, delta.1, modifiers.get_bits())
+        }
+        &EventType::Touch(Touch::Raw {
+            index,
+            ref action,
+            point,
+        }) => format!(
+            "touch-raw {} {} {} {}",
+            index,
+            touch_action_to_str(action),
+            point.0,
+            point.1
+        ),
+        &EventType::Touch(Touch::Gesture {
+            start_time,
+            duration,
+            ref state,
+            ref gest,
+        }) => {
+            let gesture = match gest {
+                &TouchGesture::Tap { index, point } => {
+                    format!("tap {} {} {}", index, point.0, point.1)
+                }
+                &TouchGesture::DoubleTap { index, point } => {
+                    format!("double-tap {} {} {}", index, point.0, point.1)
+                }
+                &TouchGesture::LongPress { index, point } => {
+                    format!("long-press {} {} {}", index, point.0, point.1)
+                }
+                &TouchGesture::Swipe {
+                    index,
+                    direction,
+                    start,
+                    end,
+                    velocity,
+                } => format!(
+                    "swipe {} {} {} {} {} {} {} {}",
+                    index,
+                    direction_to_str(direction),
+                    start.0,
+                    start.1,
+                    end.0,
+                    end.1,
+                    velocity.0,
+                    velocity.1
+                ),
+                &TouchGesture::Drag {
+                    index,
+                    start,
+                    previous,
+                    current,
+                    delta,
+                } => format!(
+                    "drag {} {} {} {} {} {} {} {} {}",
+                    index,
+                    start.0,
+                    start.1,
+                    previous.0,
+                    previous.1,
+                    current.0,
+                    current.1,
+                    delta.0,
+                    delta.1
+                ),
+                &TouchGesture::Scale {
+                    first,
+                    second,
+                    start,
+                    previous,
+                    current,
+                    delta,
+                } => format!(
+                    "scale {} {} {} {} {} {} {} {} {} {}",
+                    first.0,
+                    (first.1).0,
+                    (first.1).1,
+                    second.0,
+                    (second.1).0,
+                    (second.1).1,
+                    start,
+                    previous,
+                    current,
+                    delta
+                ),
+                &TouchGesture::Rotate {
+                    first,
+                    second,
+                    start,
+                    previous,
+                    current,
+                    delta,
+                } => format!(
+                    "rotate {} {} {} {} {} {} {} {} {} {}",
+                    first.0,
+                    (first.1).0,
+                    (first.1).1,
+                    second.0,
+                    (second.1).0,
+                    (second.1).1,
+                    start,
+                    previous,
+                    current,
+                    delta
+                ),
+                &TouchGesture::Pan {
+                    fingers,
+                    start,
+                    previous,
+                    current,
+                    delta,
+                } => format!(
+                    "pan {} {} {} {} {} {} {} {} {}",
+                    fingers,
+                    start.0,
+                    start.1,
+                    previous.0,
+                    previous.1,
+                    current.0,
+                    current.1,
+                    delta.0,
+                    delta.1
+                ),
+            };
+            format!(
+                "gesture {} {} {} {}",
+                instant_to_nanos(start, start_time),
+                duration.as_nanos(),
+                state_to_str(state),
+                gesture
+            )
+        }
+        &EventType::Gamepad(Gamepad::Connected { id, ref name }) => {
+            format!("gamepad-connected {} {}", id, escape(name))
+        }
+        &EventType::Gamepad(Gamepad::Disconnected { id }) => {
+            format!("gamepad-disconnected {}", id)
+        }
+        &EventType::Gamepad(Gamepad::Axis { id, axis, value }) => {
+            let words = Binding::GamepadAxis(axis).to_words();
+            let name = vxunwrap!(words.split_whitespace().nth(1));
+            format!("gamepad-axis {} {} {}", id, name, value)
+        }
+        &EventType::Window(Window::SizeChange {
+            w,
+            h,
+            ratio,
+            pre_w,
+            pre_h,
+            pre_ratio,
+        }) => format!(
+            "window-size {} {} {} {} {} {}",
+            w, h, ratio, pre_w, pre_h, pre_ratio
+        ),
+        &EventType::Quit => "quit".to_string(),
+        &EventType::Trigger { .. } | &EventType::Custom(_) => return None,
+    });
+}
+
+struct Words<'a> {
+    words: Vec<&'a str>,
+    index: usize,
+}
+
+impl<'a> Words<'a> {
+    fn word(&mut self) -> Option<&'a str> {
+        let w = *self.words.get(self.index)?;
+        self.index += 1;
+        return Some(w);
+    }
+
+    fn parse<T: FromStr>(&mut self) -> Option<T> {
+        return self.word()?.parse().ok();
+    }
+
+    fn pair(&mut self) -> Option<(Real, Real)> {
+        return Some((self.parse()?, self.parse()?));
+    }
+
+    fn modifiers(&mut self) -> Option<Modifiers> {
+        return Some(Modifiers::new_with_bits(self.parse()?));
+    }
+
+    fn is_finished(&self) -> bool {
+        return self.index == self.words.len();
+    }
+}
+
+pub fn decode(s: &str, start: Instant) -> Option<EventType> {
+    let mut w = Words {
+        words: s.split_whitespace().collect(),
+        index: 0,
+    };
+    let e = match w.word()? {
+        "mouse-move" => EventType::Move(Move::Mouse {
+            previous: w.pair()?,
+            current: w.pair()?,
+            delta: w.pair()?,
+            modifiers: w.modifiers()?,
+        }),
+        "touch-move" => EventType::Move(Move::Touch {
+            index: w.parse()?,
+            previous: w.pair()?,
+            current: w.pair()?,
+            delta: w.pair()?,
+        }),
+        "button" => {
+            let action = match w.word()? {
+                "press" => ButtonAction::Press,
+                "release" => ButtonAction::Release,
+                _ => return None,
+            };
+            let modifiers = w.modifiers()?;
+            let is_repeat = w.parse::<u8>()? != 0;
+            let button = match w.word()? {
+                "gamepad" => {
+                    let id = w.parse()?;
+                    match Binding::new_with_words(&["gamepad", w.word()?])? {
+                        Binding::Gamepad(button) => Button::Gamepad { id, button },
+                        _ => return None,
+                    }
+                }
+                kind => match Binding::new_with_words(&[kind, w.word()?])? {
+                    Binding::Key(k) => Button::Keyboard(k),
+                    Binding::Mouse(m) => Button::Mouse(m),
+                    _ => return None,
+                },
+            };
+            EventType::Button {
+                button,
+                action,
+                modifiers,
+                is_repeat,
+            }
+        }
+        "text" => EventType::Text(unescape(w.word()?)?),
+        "scroll" => EventType::Scroll {
+            delta: w.pair()?,
+            modifiers: w.modifiers()?,
+        },
+        "touch-raw" => EventType::Touch(Touch::Raw {
+            index: w.parse()?,
+            action: match w.word()? {
+                "press" => TouchAction::Press,
+                "hard-press" => TouchAction::HardPress,
+                "release" => TouchAction::Release,
+                _ => return None,
+            },
+            point: w.pair()?,
+        }),
+        "gesture" => {
+            let start_time = nanos_to_instant(start, w.parse()?);
+            let duration = Duration::from_nanos(w.parse()?);
+            let state = match w.word()? {
+                "started" => GestureState::Started,
+                "in-middle" => GestureState::InMiddle,
+                "ended" => GestureState::Ended,
+                "canceled" => GestureState::Canceled,
+                _ => return None,
+            };
+            let gest = match w.word()? {
+                "tap" => TouchGesture::Tap {
+                    index: w.parse()?,
+                    point: w.pair()?,
+                },
+                "double-tap" => TouchGesture::DoubleTap {
+                    index: w.parse()?,
+                    point: w.pair()?,
+                },
+                "long-press" => TouchGesture::LongPress {
+                    index: w.parse()?,
+                    point: w.pair()?,
+                },
+                "swipe" => TouchGesture::Swipe {
+                    index: w.parse()?,
+                    direction: match w.word()? {
+                        "left" => SwipeDirection::Left,
+                        "right" => SwipeDirection::Right,
+                        "up" => SwipeDirection::Up,
+                        "down" => SwipeDirection::Down,
+                        _ => return None,
+                    },
+                    start: w.pair()?,
+                    end: w.pair()?,
+                    velocity: w.pair()?,
+                },
+                "drag" => TouchGesture::Drag {
+                    index: w.parse()?,
+                    start: w.pair()?,
+                    previous: w.pair()?,
+                    current: w.pair()?,
+                    delta: w.pair()?,
+                },
+                "scale" => TouchGesture::Scale {
+                    first: (w.parse()?, w.pair()?),
+                    second: (w.parse()?, w.pair()?),
+                    start: w.parse()?,
+                    previous: w.parse()?,
+                    current: w.parse()?,
+                    delta: w.parse()?,
+                },
+                "rotate" => TouchGesture::Rotate {
+                    first: (w.parse()?, w.pair()?),
+                    second: (w.parse()?, w.pair()?),
+                    start: w.parse()?,
+                    previous: w.parse()?,
+                    current: w.parse()?,
+                    delta: w.parse()?,
+                },
+                "pan" => TouchGesture::Pan {
+                    fingers: w.parse()?,
+                    start: w.pair()?,
+                    previous: w.pair()?,
+                    current: w.pair()?,
+                    delta: w.pair()?,
+                },
+                _ => return None,
+            };
+            EventType::Touch(Touch::Gesture {
+                start_time,
+                duration,
+                state,
+                gest,
+            })
+        }
+        "gamepad-connected" => EventType::Gamepad(Gamepad::Connected {
+            id: w.parse()?,
+            name: unescape(w.word()?)?,
+        }),
+        "gamepad-disconnected" => EventType::Gamepad(Gamepad::Disconnected { id: w.parse()? }),
+        "gamepad-axis" => {
+            let id = w.parse()?;
+            let axis = match Binding::new_with_words(&["gamepad-axis", w.word()?])? {
+                Binding::GamepadAxis(axis) => axis,
+                _ => return None,
+            };
+            EventType::Gamepad(Gamepad::Axis {
+                id,
+                axis,
+                value: w.parse()?,
+            })
+        }
+        "window-size" => EventType::Window(Window::SizeChange {
+            w: w.parse()?,
+            h: w.parse()?,
+            ratio: w.parse()?,
+            pre_w: w.parse()?,
+            pre_h: w.parse()?,
+            pre_ratio: w.parse()?,
+        }),
+        "quit" => EventType::Quit,
+        _ => return None,
+    };
+    if !w.is_finished() {
+        return None;
+    }
+    return Some(e);
+}
+
+// Writes the events in lines of: <frame> <nanoseconds from start> <event>
+// and the length of each frame in a line of: <frame> <nanoseconds from start> delta <nanoseconds>
+#[cfg_attr(debug_mode, derive(Debug))]
+pub struct Recorder {
+    writer: BufWriter<File>,
+    start: Instant,
+}
+
+impl Recorder {
+    pub fn new(file_name: &str) -> Self {
+        Recorder {
+            writer: BufWriter::new(vxresult!(File::create(file_name))),
+            start: Instant::now(),
+        }
+    }
+
+    pub fn record(&mut self, frame: u64, e: &Event) {
+        let encoded = match encode(&e.event_type, self.start) {
+            Some(encoded) => encoded,
+            None => return,
+        };
+        let time = Instant::now().duration_since(self.start).as_nanos();
+        vxresult!(writeln!(self.writer, "{} {} {}", frame, time, encoded));
+    }
+
+    pub fn record_delta(&mut self, frame: u64, delta: Duration) {
+        let time = Instant::now().duration_since(self.start).as_nanos();
+        vxresult!(writeln!(
+            self.writer,
+            "{} {} delta {}",
+            frame,
+            time,
+            delta.as_nanos()
+        ));
+    }
+
+    pub fn flush(&mut self) {
+        vxresult!(self.writer.flush());
+    }
+}
+
+impl Drop for Recorder {
+    fn drop(&mut self) {
+        let _ = self.writer.flush();
+    }
+}
+
+// Gives back the recorded events and lengths of frames in their frames,
+// timestamps of lines are informative only
+#[cfg_attr(debug_mode, derive(Debug))]
+pub struct Player {
+    events: VecDeque<(u64, EventType)>,
+    deltas: VecDeque<(u64, Duration)>,
+}
+
+impl Player {
+    pub fn new_with_text(text: &str) -> Self {
+        let start = Instant::now();
+        let mut events = VecDeque::new();
+        let mut deltas = VecDeque::new();
+        for (line_number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.len() == 0 {
+                continue;
+            }
+            let mut parts = line.splitn(3, ' ');
+            let frame: Option<u64> = parts.next().and_then(|f| f.parse().ok());
+            let time: Option<u64> = parts.next().and_then(|t| t.parse().ok());
+            let rest = parts.next().unwrap_or("");
+            if rest.starts_with("delta ") {
+                let delta: Option<u64> = rest["delta ".len()..].parse().ok();
+                match (frame, time, delta) {
+                    (Some(frame), Some(_), Some(delta)) => {
+                        deltas.push_back((frame, Duration::from_nanos(delta)))
+                    }
+                    _ => vxloge!(
+                        "Wrong recorded delta in line {} is skipped: {}",
+                        line_number + 1,
+                        line
+                    ),
+                }
+                continue;
+            }
+            match (frame, time, decode(rest, start)) {
+                (Some(frame), Some(_), Some(e)) => events.push_back((frame, e)),
+                _ => vxloge!(
+                    "Wrong recorded event in line {} is skipped: {}",
+                    line_number + 1,
+                    line
+                ),
+            }
+        }
+        Player { events, deltas }
+    }
+
+    pub fn load(file_name: &str) -> Self {
+        let mut text = String::new();
+        vxresult!(vxresult!(File::open(file_name)).read_to_string(&mut text));
+        return Self::new_with_text(&text);
+    }
+
+    // Events of the frame and the frames before it that are not fetched yet
+    pub fn fetch(&mut self, frame: u64) -> Vec<Event> {
+        let mut events = Vec::new();
+        while self.events.front().map(|e| e.0 <= frame).unwrap_or(false) {
+            let (_, e) = vxunwrap!(self.events.pop_front());
+            events.push(Event::new(e));
+        }
+        return events;
+    }
+
+    // Recorded length of the frame, frames before it that are not fetched are skipped
+    pub fn fetch_delta(&mut self, frame: u64) -> Option<Duration> {
+        while self.deltas.front().map(|d| d.0 < frame).unwrap_or(false) {
+            self.deltas.pop_front();
+        }
+        if self.deltas.front().map(|d| d.0 == frame).unwrap_or(false) {
+            return Some(vxunwrap!(self.deltas.pop_front()).1);
+        }
+        return None;
+    }
+
+    pub fn is_finished(&self) -> bool {
+        return self.events.is_empty() && self.deltas.is_empty();
+    }
+}
+
+// It sits between OS and application, live events pass through it to be recorded and in replay
+// only quit of live events passes and the recorded events are given in their frames.
+// Lengths of frames are recorded too and in replay they drive the timing.
+// When all of recorded lines are given back, live events pass again.
+#[cfg_attr(debug_mode, derive(Debug))]
+pub struct Session {
+    recorder: Option<Recorder>,
+    player: Option<Player>,
+    frame: u64,
+}
+
+impl Session {
+    pub fn new(record_file_name: Option<&str>, replay_file_name: Option<&str>) -> Self {
+        Session {
+            recorder: record_file_name.map(Recorder::new),
+            player: replay_file_name.map(Player::load),
+            frame: 0,
+        }
+    }
+
+    pub fn is_replaying(&self) -> bool {
+        return self.player.is_some();
+    }
+
+    pub fn get_frame(&self) -> u64 {
+        return self.frame;
+    }
+
+    pub fn filter(&mut self, e: Event) -> Option<Event> {
+        if self.player.is_some() {
+            return match &e.event_type {
+                &EventType::Quit => Some(e),
+                _ => None,
+            };
+        }
+        if let Some(recorder) = &mut self.recorder {
+            recorder.record(self.frame, &e);
+        }
+        return Some(e);
+    }
+
+    // Recorded events of the current frame, it must be called once in each frame
+    pub fn fetch_replayed(&mut self) -> Vec<Event> {
+        let events = match &mut self.player {
+            Some(player) => player.fetch(self.frame),
+            None => return Vec::new(),
+        };
+        if let Some(recorder) = &mut self.recorder {
+            for e in &events {
+                recorder.record(self.frame, e);
+            }
+        }
+        return events;
+    }
+
+    // It must be called before the update of timing in each frame
+    pub fn begin_frame(&mut self, timing: &mut Timing) {
+        if let Some(player) = &mut self.player {
+            if let Some(delta) = player.fetch_delta(self.frame) {
+                timing.set_replayed_frame_length(delta);
+            }
+        }
+    }
+
+    // It must be called after the update of timing in each frame
+    pub fn end_frame(&mut self, timing: &Timing) {
+        if let Some(recorder) = &mut self.recorder {
+            recorder.record_delta(self.frame, timing.length_of_previous_frame);
+            recorder.flush();
+        }
+        self.frame += 1;
+        if self
+            .player
+            .as_ref()
+            .map(|p| p.is_finished())
+            .unwrap_or(false)
+        {
+            vxlogi!("Replay is finished, live events pass again.");
+            self.player = None;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::event::{GamepadAxis, GamepadButton, Keyboard, Mouse};
+    use super::*;
+    use std::thread::sleep;
+
+    fn temp_file_name(name: &str) -> String {
+        return vxunwrap!(std::env::temp_dir()
+            .join(format!("vulkust-{}-{}.txt", name, std::process::id()))
+            .to_str())
+        .to_string();
+    }
+
+    fn next_frame(session: &mut Session, timing: &mut Timing) {
+        session.begin_frame(timing);
+        timing.update();
+        session.end_frame(timing);
+    }
+
+    // A small game that moves with the speed of last scroll in its fixed updates,
+    // it gives back the state of each frame.
+    fn play(session: &mut Session) -> Vec<(Real, Real, u64)> {
+        let mut timing = Timing::new();
+        timing.set_fixed_update_rate(500.0);
+        let mut speed = 0.0;
+        let mut position = 0.0;
+        let mut states = Vec::new();
+        for frame in 0..30 {
+            let mut events = Vec::new();
+            if frame % 7 == 3 {
+                let e = Event::new(EventType::Scroll {
+                    delta: (0.0, frame as Real),
+                    modifiers: Modifiers::empty(),
+                });
+                events.extend(session.filter(e));
+            }
+            events.append(&mut session.fetch_replayed());
+            for e in events {
+                if let EventType::Scroll { delta, .. } = e.event_type {
+                    speed = delta.1;
+                }
+            }
+            session.begin_frame(&mut timing);
+            sleep(Duration::from_millis(1 + frame % 3));
+            timing.update();
+            for _ in 0..timing.get_fixed_updates_count() {
+                position += speed * timing.get_fixed_delta();
+            }
+            states.push((
+                position,
+                timing.get_game_time(),
+                timing.get_fixed_updates_total(),
+            ));
+            session.end_frame(&timing);
+        }
+        return states;
+    }
+
+    fn events(start: Instant) -> Vec<EventType> {
+        return vec![
+            EventType::Move(Move::Mouse {
+                previous: (0.1, 0.2),
+                current: (0.3, 0.4),
+                delta: (0.2, 0.2),
+                modifiers: Modifiers::SHIFT | Modifiers::SUPER,
+            }),
+            EventType::Button {
+                button: Button::Keyboard(Keyboard::Number {
+                    number: 3,
+                    padd: true,
+                }),
+                action: ButtonAction::Press,
+                modifiers: Modifiers::CONTROL,
+                is_repeat: true,
+            },
+            EventType::Button {
+                button: Button::Mouse(Mouse::Right),
+                action: ButtonAction::Release,
+                modifiers: Modifiers::empty(),
+                is_repeat: false,
+            },
+            EventType::Button {
+                button: Button::Gamepad {
+                    id: 9,
+                    button: GamepadButton::DpadLeft,
+                },
+                action: ButtonAction::Press,
+                modifiers: Modifiers::empty(),
+                is_repeat: false,
+            },
+            EventType::Text("a b%\n€".to_string()),
+            EventType::Scroll {
+                delta: (-1.0, 0.5),
+                modifiers: Modifiers::ALT,
+            },
+            EventType::Touch(Touch::Raw {
+                index: 2,
+                action: TouchAction::HardPress,
+                point: (0.5, 1.0 / 3.0),
+            }),
+            EventType::Touch(Touch::Gesture {
+                start_time: start - Duration::from_millis(5),
+                duration: Duration::from_millis(7),
+                state: GestureState::InMiddle,
+                gest: TouchGesture::Scale {
+                    first: (0, (0.1, 0.2)),
+                    second: (1, (0.3, 0.4)),
+                    start: 0.2,
+                    previous: 0.25,
+                    current: 0.3,
+                    delta: 0.05,
+                },
+            }),
+            EventType::Touch(Touch::Gesture {
+                start_time: start,
+                duration: Duration::from_millis(120),
+                state: GestureState::Ended,
+                gest: TouchGesture::Swipe {
+                    index: 1,
+                    direction: SwipeDirection::Left,
+                    start: (0.6, 0.5),
+                    end: (0.4, 0.5),
+                    velocity: (-1.5, 0.0),
+                },
+            }),
+            EventType::Touch(Touch::Gesture {
+                start_time: start,
+                duration: Duration::from_millis(30),
+                state: GestureState::Canceled,
+                gest: TouchGesture::Pan {
+                    fingers: 3,
+                    start: (0.5, 0.5),
+                    previous: (0.5, 0.6),
+                    current: (0.5, 0.6),
+                    delta: (0.0, 0.0),
+                },
+            }),
+            EventType::Gamepad(Gamepad::Connected {
+                id: 9,
+                name: "Pad 1".to_string(),
+            }),
+            EventType::Gamepad(Gamepad::Axis {
+                id: 9,
+                axis: GamepadAxis::RightTrigger,
+                value: 0.75,
+            }),
+            EventType::Window(Window::SizeChange {
+                w: 800.0,
+                h: 600.0,
+                ratio: 800.0 / 600.0,
+                pre_w: 0.0,
+                pre_h: 0.0,
+                pre_ratio: 0.0,
+            }),
+            EventType::Quit,
+        ];
+    }
+
+    #[test]
+    fn encoding_test() {
+        assert_eq!(unescape(&escape("a b%\n€")), Some("a b%\n€".to_string()));
+        assert!(unescape("%4").is_none());
+        let start = Instant::now();
+        for e in events(start) {
+            let encoded = vxunwrap!(encode(&e, start));
+            let decoded = vxunwrap!(decode(&encoded, start));
+            // encoding keeps every field, so the second encoding is the same
+            assert_eq!(vxunwrap!(encode(&decoded, start)), encoded);
+        }
+        assert!(decode("quit now", start).is_none());
+        assert!(decode("button press 0 0 key Nothing", start).is_none());
+        assert!(encode(
+            &EventType::Trigger {
+                trigger: 1,
+                other: 2,
+                action: super::super::event::TriggerAction::Enter,
+            },
+            start
+        )
+        .is_none());
+    }
+
+    #[test]
+    fn session_test() {
+        let file_name = temp_file_name("replay-test");
+        let start = Instant::now();
+        let mut timing = Timing::new();
+        {
+            let mut session = Session::new(Some(&file_name), None);
+            for (frame, e) in events(start).into_iter().enumerate() {
+                // some frames are without any event
+                if frame % 3 == 0 {
+                    next_frame(&mut session, &mut timing);
+                }
+                assert!(session.filter(Event::new(e)).is_some());
+                next_frame(&mut session, &mut timing);
+            }
+        }
+        let mut session = Session::new(None, Some(&file_name));
+        assert!(session.is_replaying());
+        assert!(session
+            .filter(Event::new(EventType::Text("x".to_string())))
+            .is_none());
+        let mut replayed = Vec::new();
+        for _ in 0..100 {
+            let events = session.fetch_replayed();
+            assert!(events.len() < 2);
+            replayed.extend(events);
+            next_frame(&mut session, &mut timing);
+        }
+        vxresult!(std::fs::remove_file(&file_name));
+        let expected = events(start);
+        assert_eq!(replayed.len(), expected.len());
+        for (r, e) in replayed.iter().zip(expected.iter()) {
+            match e {
+                // its start time is relative to the start of session
+                &EventType::Touch(Touch::Gesture { .. }) => continue,
+                _ => assert_eq!(encode(&r.event_type, start), encode(e, start)),
+            }
+        }
+    }
+
+    #[test]
+    fn wrong_lines_test() {
+        let mut player = Player::new_with_text(
+            "0 10 quit\n\
+             1 20 quit now\n\
+             2 delta 5\n\
+             3 40 delta 7\n\
+             4 50 scroll 1",
+        );
+        assert_eq!(player.fetch(4).len(), 1);
+        assert!(player.fetch_delta(3) == Some(Duration::from_nanos(7)));
+        assert!(player.is_finished());
+    }
+
+    #[test]
+    fn replay_test() {
+        let file_name = temp_file_name("replay-state-test");
+        let recorded = play(&mut Session::new(Some(&file_name), None));
+        let mut session = Session::new(None, Some(&file_name));
+        let replayed = play(&mut session);
+        vxresult!(std::fs::remove_file(&file_name));
+        assert!(!session.is_replaying());
+        assert!(recorded[recorded.len() - 1].0 > 0.0);
+        assert_eq!(replayed.len(), recorded.len());
+        for (r, e) in replayed.iter().zip(recorded.iter()) {
+            assert_eq!(r, e);
+        }
+    }
+}
@@ -1,10 +1,46 @@
+use super::types::Real;
+use std::collections::VecDeque;
+use std::f32::MAX as F32MAX;
+use std::f32::MIN as F32MIN;
+use std::thread::sleep;
 use std::time::{Duration, Instant};
 
+pub const DEFAULT_FIXED_UPDATE_RATE: Real = 60.0;
+// More fixed updates than this are skipped, so a slow frame can not make the next one slower
+const DEFAULT_MAX_FIXED_UPDATES: usize = 5;
+const DEFAULT_STATISTICS_WINDOW: usize = 120;
+
+// Frame times are in seconds and they are over the frames of statistics window
+#[derive(Clone, Copy)]
+#[cfg_attr(debug_mode, derive(Debug))]
+pub struct FrameStatistics {
+    pub average: Real,
+    pub min: Real,
+    pub max: Real,
+    pub frames_per_second: Real,
+}
+
+// There are two clocks, the real one that always goes on and the game one that can be
+// paused and scaled. Fixed updates follow the game clock.
 #[cfg_attr(debug_mode, derive(Debug))]
 pub struct Timing {
     pub start_of_previous_frame: Instant,
     pub start_of_current_frame: Instant,
     pub length_of_previous_frame: Duration,
+    pub max_fixed_updates: usize,
+    start: Instant,
+    fixed_time_step: Duration,
+    accumulator: Duration,
+    fixed_updates_count: usize,
+    fixed_updates_total: u64,
+    paused: bool,
+    time_scale: Real,
+    game_time: Duration,
+    game_delta: Duration,
+    min_frame_length: Option<Duration>,
+    frame_lengths: VecDeque<Duration>,
+    statistics_window: usize,
+    replayed_frame_length: Option<Duration>,
 }
 
 impl Timing {
@@ -17,14 +53,288 @@ impl Timing {
             start_of_previous_frame,
             start_of_current_frame,
             length_of_previous_frame,
+            max_fixed_updates: DEFAULT_MAX_FIXED_UPDATES,
+            start: start_of_current_frame,
+            fixed_time_step: to_duration(1.0 / DEFAULT_FIXED_UPDATE_RATE),
+            accumulator: Duration::new(0, 0),
+            fixed_updates_count: 0,
+            fixed_updates_total: 0,
+            paused: false,
+            time_scale: 1.0,
+            game_time: Duration::new(0, 0),
+            game_delta: Duration::new(0, 0),
+            min_frame_length: None,
+            frame_lengths: VecDeque::with_capacity(DEFAULT_STATISTICS_WINDOW),
+            statistics_window: DEFAULT_STATISTICS_WINDOW,
+            replayed_frame_length: None,
         }
     }
 
     pub fn update(&mut self) {
+        if let Some(min_frame_length) = self.min_frame_length {
+            let length = self.start_of_current_frame.elapsed();
+            if length < min_frame_length {
+                sleep(min_frame_length - length);
+            }
+        }
         self.start_of_previous_frame = self.start_of_current_frame;
         self.start_of_current_frame = Instant::now();
-        self.length_of_previous_frame = self
-            .start_of_current_frame
-            .duration_since(self.start_of_previous_frame);
+        self.length_of_previous_frame = match self.replayed_frame_length.take() {
+            Some(length) => length,
+            None => self
+                .start_of_current_frame
+                .duration_since(self.start_of_previous_frame),
+        };
+        let length = self.length_of_previous_frame;
+        self.advance(length);
+    }
+
+    // In replay, the next update takes the recorded length of frame instead of the clock one,
+    // so the game clock and fixed updates are the same as the recorded ones.
+    pub fn set_replayed_frame_length(&mut self, length: Duration) {
+        self.replayed_frame_length = Some(length);
+    }
+
+    fn advance(&mut self, length: Duration) {
+        if self.frame_lengths.len() >= self.statistics_window {
+            self.frame_lengths.pop_front();
+        }
+        self.frame_lengths.push_back(length);
+        self.game_delta = if self.paused {
+            Duration::new(0, 0)
+        } else {
+            to_duration(to_seconds(length) * self.time_scale)
+        };
+        self.game_time += self.game_delta;
+        let game_delta = self.game_delta;
+        self.accumulate(game_delta);
+    }
+
+    // Time is consumed in fixed steps, the remaining is kept for the next frames
+    fn accumulate(&mut self, length: Duration) {
+        self.accumulator += length;
+        self.fixed_updates_count = 0;
+        while self.accumulator >= self.fixed_time_step {
+            self.accumulator -= self.fixed_time_step;
+            if self.fixed_updates_count < self.max_fixed_updates {
+                self.fixed_updates_count += 1;
+            }
+        }
+        self.fixed_updates_total += self.fixed_updates_count as u64;
+    }
+
+    // Game clock does not go on during pause, so there is no fixed update
+    pub fn pause(&mut self) {
+        self.paused = true;
+    }
+
+    pub fn resume(&mut self) {
+        self.paused = false;
+    }
+
+    pub fn is_paused(&self) -> bool {
+        return self.paused;
+    }
+
+    // Less than one is slow motion and more than one is fast forward
+    pub fn set_time_scale(&mut self, scale: Real) {
+        if scale < 0.0 {
+            vxlogf!("Time scale can not be negative.");
+        }
+        self.time_scale = scale;
+    }
+
+    pub fn get_time_scale(&self) -> Real {
+        return self.time_scale;
+    }
+
+    // Seconds from the start of timing, it is not affected by pause and time scale
+    pub fn get_real_time(&self) -> Real {
+        return to_seconds(self.start_of_current_frame.duration_since(self.start));
+    }
+
+    pub fn get_real_delta(&self) -> Real {
+        return to_seconds(self.length_of_previous_frame);
+    }
+
+    pub fn get_game_time(&self) -> Real {
+        return to_seconds(self.game_time);
+    }
+
+    // Scaled length of previous frame, it is zero when paused
+    pub fn get_game_delta(&self) -> Real {
+        return to_seconds(self.game_delta);
+    }
+
+    // Update waits so frames are not shorter than the limit, None removes the limit
+    pub fn set_frame_rate_limit(&mut self, rate: Option<Real>) {
+        self.min_frame_length = match rate {
+            Some(rate) => {
+                if rate <= 0.0 {
+                    vxlogf!("Frame rate limit must be positive.");
+                }
+                Some(to_duration(1.0 / rate))
+            }
+            None => None,
+        };
+    }
+
+    pub fn get_frame_rate_limit(&self) -> Option<Real> {
+        return match self.min_frame_length {
+            Some(l) => Some(1.0 / to_seconds(l)),
+            None => None,
+        };
+    }
+
+    // Number of last frames that statistics are computed over
+    pub fn set_statistics_window(&mut self, frames: usize) {
+        if frames == 0 {
+            vxlogf!("Statistics window can not be empty.");
+        }
+        self.statistics_window = frames;
+        while self.frame_lengths.len() > frames {
+            self.frame_lengths.pop_front();
+        }
+    }
+
+    pub fn get_frame_statistics(&self) -> FrameStatistics {
+        let mut sum = 0.0;
+        let mut min = F32MAX;
+        let mut max = F32MIN;
+        for l in &self.frame_lengths {
+            let l = to_seconds(*l);
+            sum += l;
+            min = min.min(l);
+            max = max.max(l);
+        }
+        if self.frame_lengths.len() == 0 || sum <= 0.0 {
+            return FrameStatistics {
+                average: 0.0,
+                min: 0.0,
+                max: 0.0,
+                frames_per_second: 0.0,
+            };
+        }
+        let average = sum / self.frame_lengths.len() as Real;
+        return FrameStatistics {
+            average,
+            min,
+            max,
+            frames_per_second: 1.0 / average,
+        };
+    }
+
+    // Frame time in seconds that the given percent of frames are not longer than,
+    // e.g. 99 gives the time of the slowest one percent.
+    pub fn get_frame_time_percentile(&self, percent: Real) -> Real {
+        if self.frame_lengths.len() == 0 {
+            return 0.0;
+        }
+        let mut lengths: Vec<Duration> = self.frame_lengths.iter().cloned().collect();
+        lengths.sort();
+        let rank = (percent.max(0.0).min(100.0) * 0.01 * lengths.len() as Real).ceil() as usize;
+        let index = if rank > 0 { rank - 1 } else { 0 };
+        return to_seconds(lengths[index]);
+    }
+
+    pub fn set_fixed_update_rate(&mut self, rate: Real) {
+        if rate <= 0.0 {
+            vxlogf!("Fixed update rate must be positive.");
+        }
+        self.fixed_time_step = to_duration(1.0 / rate);
+    }
+
+    pub fn get_fixed_time_step(&self) -> Duration {
+        return self.fixed_time_step;
+    }
+
+    // Fixed time step in seconds, it is the dt of fixed updates
+    pub fn get_fixed_delta(&self) -> Real {
+        return to_seconds(self.fixed_time_step);
+    }
+
+    // Number of fixed updates that must be done in the current frame
+    pub fn get_fixed_updates_count(&self) -> usize {
+        return self.fixed_updates_count;
+    }
+
+    // Number of fixed updates from the start, it can be used as the tick of simulation
+    pub fn get_fixed_updates_total(&self) -> u64 {
+        return self.fixed_updates_total;
+    }
+
+    // How far the current frame is between the last fixed update and the next one,
+    // renderable transforms must be interpolated with it between the last two fixed states.
+    pub fn get_interpolation_alpha(&self) -> Real {
+        return to_seconds(self.accumulator) / to_seconds(self.fixed_time_step);
+    }
+}
+
+fn to_duration(seconds: Real) -> Duration {
+    let nanos = (seconds as f64 * 1_000_000_000.0) as u64;
+    return Duration::new(nanos / 1_000_000_000, (nanos % 1_000_000_000) as u32);
+}
+
+fn to_seconds(d: Duration) -> Real {
+    return (d.as_secs() as f64 + d.subsec_nanos() as f64 / 1_000_000_000.0) as Real;
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn fixed_updates_test() {
+        let mut timing = Timing::new();
+        timing.set_fixed_update_rate(100.0);
+        timing.accumulate(to_duration(0.025));
+        assert_eq!(timing.get_fixed_updates_count(), 2);
+        assert!((timing.get_interpolation_alpha() - 0.5).abs() < 1e-3);
+        timing.accumulate(to_duration(0.004));
+        assert_eq!(timing.get_fixed_updates_count(), 0);
+        assert!((timing.get_interpolation_alpha() - 0.9).abs() < 1e-3);
+        timing.accumulate(to_duration(0.001));
+        assert_eq!(timing.get_fixed_updates_count(), 1);
+        assert_eq!(timing.get_fixed_updates_total(), 3);
+        // a long frame does not make too many updates
+        timing.accumulate(to_duration(1.0));
+        assert_eq!(timing.get_fixed_updates_count(), timing.max_fixed_updates);
+        assert!(timing.get_interpolation_alpha() < 1.0);
+    }
+
+    #[test]
+    fn game_clock_test() {
+        let mut timing = Timing::new();
+        timing.set_fixed_update_rate(100.0);
+        timing.set_time_scale(0.5);
+        timing.advance(to_duration(0.04));
+        assert!((timing.get_game_delta() - 0.02).abs() < 1e-4);
+        assert_eq!(timing.get_fixed_updates_count(), 2);
+        timing.pause();
+        timing.advance(to_duration(0.04));
+        assert_eq!(timing.get_game_delta(), 0.0);
+        assert_eq!(timing.get_fixed_updates_count(), 0);
+        timing.resume();
+        timing.set_time_scale(2.0);
+        timing.advance(to_duration(0.01));
+        assert!((timing.get_game_time() - 0.04).abs() < 1e-4);
+        assert_eq!(timing.get_fixed_updates_count(), 2);
+    }
+
+    #[test]
+    fn statistics_test() {
+        let mut timing = Timing::new();
+        timing.set_statistics_window(10);
+        for i in 0..20 {
+            timing.advance(to_duration(0.001 * (i + 1) as Real));
+        }
+        let s = timing.get_frame_statistics();
+        assert!((s.min - 0.011).abs() < 1e-5);
+        assert!((s.max - 0.02).abs() < 1e-5);
+        assert!((s.average - 0.0155).abs() < 1e-5);
+        assert!((s.frames_per_second - 1.0 / 0.0155).abs() < 0.1);
+        assert!((timing.get_frame_time_percentile(50.0) - 0.015).abs() < 1e-5);
+        assert!((timing.get_frame_time_percentile(90.0) - 0.019).abs() < 1e-5);
+        assert!((timing.get_frame_time_percentile(100.0) - 0.02).abs() < 1e-5);
     }
 }
@@ -1,4 +1,5 @@
-use super::buffer::{Buffer as BufBuffer, Static as StaticBuffer};
+use super::super::render::buffer::IndexType;
+use super::buffer::Buffer as BufBuffer;
 use super::descriptor::Set as DescriptorSet;
 use super::device::Device;
 use super::framebuffer::Framebuffer;
@@ -114,7 +115,11 @@ impl Buffer {
         vxunimplemented!();
     }
 
-    pub(crate) fn bind_index_buffer(&mut self, _buffer: &Arc<RwLock<BufBuffer>>) {
+    pub(crate) fn bind_index_buffer(
+        &mut self,
+        _buffer: &Arc<RwLock<BufBuffer>>,
+        _index_type: IndexType,
+    ) {
         vxunimplemented!();
     }
 
@@ -152,9 +157,10 @@ impl Buffer {
 
     pub(crate) fn render_gbuff(
         &mut self,
-        _vertex_buffer: &StaticBuffer,
-        _index_buffer: &StaticBuffer,
+        _vertex_buffer: &Arc<RwLock<BufBuffer>>,
+        _index_buffer: &Arc<RwLock<BufBuffer>>,
         _indices_count: u32,
+        _index_type: IndexType,
     ) {
         vxunimplemented!();
     }
@@ -229,9 +235,10 @@ impl Buffer {
 
     pub(crate) fn render_shadow_mapper(
         &mut self,
-        _vertex_buffer: &StaticBuffer,
-        _index_buffer: &StaticBuffer,
+        _vertex_buffer: &Arc<RwLock<BufBuffer>>,
+        _index_buffer: &Arc<RwLock<BufBuffer>>,
         _indices_count: u32,
+        _index_type: IndexType,
     ) {
         vxunimplemented!();
     }
@@ -6,6 +6,7 @@
 pub extern crate cgmath;
 pub extern crate gltf;
 pub extern crate image;
+pub extern crate lewton;
 pub extern crate libc;
 pub extern crate num_cpus;
 pub extern crate rand;
@@ -29,13 +30,14 @@ pub extern crate ash;
 #[macro_use]
 pub mod macros;
 
-// pub mod audio;
+pub mod audio;
 #[cfg(blank_gapi)]
 pub mod blank_gapi;
 pub mod collision;
 pub mod core;
 #[cfg(directx12_api)]
 pub mod d3d12;
+pub mod math;
 pub mod physics;
 pub mod render;
 pub mod system;
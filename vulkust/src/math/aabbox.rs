@@ -1,94 +1,155 @@
-extern crate num;
+use super::super::core::types::Real;
+use super::ray::Ray3;
 
-use ::math::num::{
-    min,
-    max,
-};
+use std::f32::MAX as F32MAX;
+use std::f32::MIN as F32MIN;
 
-use ::math::vector::{
-    Vec3,
-    Axis,
-    MathVector,
-    VectorElement,
-};
+use cgmath;
 
-use ::math::ray::Ray3;
+type Vec3 = cgmath::Vector3<Real>;
 
-pub trait ExpandableToOther {
-    fn expand(&mut self, o: &Self);
+#[repr(usize)]
+#[derive(Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(debug_mode, derive(Debug))]
+pub enum Axis {
+    X = 0,
+    Y = 1,
+    Z = 2,
 }
 
-pub trait ExpandableToPoint3<T> where T: VectorElement, Vec3<T>: MathVector<T> {
-    fn expand(&mut self, o: &Vec3<T>);
+#[derive(Clone, Copy)]
+#[cfg_attr(debug_mode, derive(Debug))]
+pub struct AABBox3 {
+    pub blf: Vec3,
+    pub trr: Vec3,
 }
 
-#[derive(Debug, Clone, Copy)]
-pub struct AABBox3<T> where T: VectorElement, Vec3<T>: MathVector<T> {
-    pub blf: Vec3<T>,
-    pub trr: Vec3<T>
-}
-
-impl<T> ExpandableToOther for AABBox3<T> where T: VectorElement, Vec3<T>: MathVector<T> {
-
-    fn expand(&mut self, o : &Self) {
-        if o.blf.x < self.blf.x { self.blf.x = o.blf.x; }
-        if o.blf.y < self.blf.y { self.blf.y = o.blf.y; }
-        if o.blf.z < self.blf.z { self.blf.z = o.blf.z; }
-
-        if o.trr.x > self.trr.x { self.trr.x = o.trr.x; }
-        if o.trr.y > self.trr.y { self.trr.y = o.trr.y; }
-        if o.trr.z > self.trr.z { self.trr.z = o.trr.z; }
+impl AABBox3 {
+    pub fn new() -> Self {
+        Self {
+            blf: Vec3::new(F32MAX, F32MAX, F32MAX),
+            trr: Vec3::new(F32MIN, F32MIN, F32MIN),
+        }
     }
-}
-
-impl<T> ExpandableToPoint3<T> for AABBox3<T> where T: VectorElement, Vec3<T>: MathVector<T> {
 
-    fn expand(&mut self, p : &Vec3<T>) {
-        if p.x < self.blf.x { self.blf.x = p.x; }
-        if p.y < self.blf.y { self.blf.y = p.y; }
-        if p.z < self.blf.z { self.blf.z = p.z; }
+    pub fn new_with_points(blf: &Vec3, trr: &Vec3) -> Self {
+        Self {
+            blf: *blf,
+            trr: *trr,
+        }
     }
-}
 
-impl<T> AABBox3<T> where T: VectorElement, Vec3<T>: MathVector<T> {
+    pub fn expand(&mut self, o: &Self) {
+        self.expand_point(&o.blf);
+        self.expand_point(&o.trr);
+    }
 
-    pub fn new() -> AABBox3<T> {
-        AABBox3 {
-            blf: Vec3::new(num::cast(0).unwrap()),
-            trr: Vec3::new(num::cast(0).unwrap()),
+    pub fn expand_point(&mut self, p: &Vec3) {
+        if p.x < self.blf.x {
+            self.blf.x = p.x;
+        }
+        if p.y < self.blf.y {
+            self.blf.y = p.y;
+        }
+        if p.z < self.blf.z {
+            self.blf.z = p.z;
+        }
+        if p.x > self.trr.x {
+            self.trr.x = p.x;
         }
+        if p.y > self.trr.y {
+            self.trr.y = p.y;
+        }
+        if p.z > self.trr.z {
+            self.trr.z = p.z;
+        }
+    }
+
+    pub fn get_center(&self) -> Vec3 {
+        return (self.blf + self.trr) * 0.5;
     }
 
     pub fn get_longest_axis(&self) -> Axis {
-        let diff = self.trr - self.blf; // TODO check for occurance, if it is too much store it in box
-        if diff.x > diff.y && diff.x > diff.z { return Axis::X; }
-        if diff.y > diff.x && diff.y > diff.z { return Axis::Y; }
+        let diff = self.trr - self.blf;
+        if diff.x >= diff.y && diff.x >= diff.z {
+            return Axis::X;
+        }
+        if diff.y >= diff.z {
+            return Axis::Y;
+        }
         return Axis::Z;
     }
 
-    // Check if ray intersects with box. Returns true/false and stores distance in t
-    pub fn intersection(&self, r: &Ray3<T>) -> (bool, T) {
+    pub fn contains(&self, p: &Vec3) -> bool {
+        return self.blf.x <= p.x
+            && p.x <= self.trr.x
+            && self.blf.y <= p.y
+            && p.y <= self.trr.y
+            && self.blf.z <= p.z
+            && p.z <= self.trr.z;
+    }
 
+    // Slab test, returns the distance of entrance along the ray,
+    // if the origin is inside the box it returns 0
+    pub fn intersection(&self, r: &Ray3) -> Option<Real> {
         let tx1 = (self.blf.x - r.o.x) * r.invd.x;
         let tx2 = (self.trr.x - r.o.x) * r.invd.x;
 
-        let mut tmin = min(tx1, tx2);
-        let mut tmax = max(tx1, tx2);
+        let mut tmin = tx1.min(tx2);
+        let mut tmax = tx1.max(tx2);
 
         let ty1 = (self.blf.y - r.o.y) * r.invd.y;
         let ty2 = (self.trr.y - r.o.y) * r.invd.y;
 
-        tmin = max(tmin, min(ty1, ty2));
-        tmax = min(tmax, max(ty1, ty2));
+        tmin = tmin.max(ty1.min(ty2));
+        tmax = tmax.min(ty1.max(ty2));
 
         let tz1 = (self.blf.z - r.o.z) * r.invd.z;
         let tz2 = (self.trr.z - r.o.z) * r.invd.z;
 
-        tmin = max(tmin, min(tz1, tz2));
-        tmax = min(tmax, max(tz1, tz2));
+        tmin = tmin.max(tz1.min(tz2));
+        tmax = tmax.min(tz1.max(tz2));
+
+        if tmax < tmin || tmax < 0.0 {
+            return None;
+        }
+        return Some(tmin.max(0.0));
+    }
+}
 
-        let t = tmin;
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn expand_test() {
+        let mut b = AABBox3::new();
+        b.expand_point(&Vec3::new(1.0, -2.0, 3.0));
+        b.expand_point(&Vec3::new(-1.0, 2.0, 0.0));
+        assert_eq!(b.blf, Vec3::new(-1.0, -2.0, 0.0));
+        assert_eq!(b.trr, Vec3::new(1.0, 2.0, 3.0));
+        b.expand(&AABBox3::new_with_points(
+            &Vec3::new(0.0, 0.0, -5.0),
+            &Vec3::new(7.0, 0.0, 0.0),
+        ));
+        assert_eq!(b.blf, Vec3::new(-1.0, -2.0, -5.0));
+        assert_eq!(b.trr, Vec3::new(7.0, 2.0, 3.0));
+        assert!(b.get_longest_axis() == Axis::X);
+        assert!(b.contains(&Vec3::new(0.0, 0.0, 0.0)));
+        assert!(!b.contains(&Vec3::new(0.0, 3.0, 0.0)));
+    }
 
-        return (tmax >= tmin, t);
+    #[test]
+    fn intersection_test() {
+        let b = AABBox3::new_with_points(&Vec3::new(-1.0, -1.0, -1.0), &Vec3::new(1.0, 1.0, 1.0));
+        let r = Ray3::new(Vec3::new(-5.0, 0.0, 0.0), Vec3::new(1.0, 0.0, 0.0));
+        let t = b.intersection(&r).unwrap();
+        assert!((t - 4.0).abs() < 1e-5);
+        let r = Ray3::new(Vec3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 1.0, 0.0));
+        assert_eq!(b.intersection(&r), Some(0.0));
+        let r = Ray3::new(Vec3::new(-5.0, 0.0, 0.0), Vec3::new(-1.0, 0.0, 0.0));
+        assert!(b.intersection(&r).is_none());
+        let r = Ray3::new(Vec3::new(-5.0, 3.0, 0.0), Vec3::new(1.0, 0.0, 0.0));
+        assert!(b.intersection(&r).is_none());
     }
 }
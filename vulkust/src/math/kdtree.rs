@@ -1,133 +1,255 @@
-extern crate num;
-
-use std;
-
-use ::math::aabbox::{
-    AABBox3,
-    ExpandableToOther,
-};
-use ::math::triangle::{
-    Triangle,
-};
-use ::math::ray::Ray3;
-use ::math::vector::{
-    Vec3,
-    MathVector,
-    VectorElement,
-    Axis,
-};
-use ::render::vertex::{
-    HasPosition,
-};
-
-pub struct KDNode<E> where E: VectorElement {
-    pub area:    AABBox3<E>,
-    pub left:    Option<Box<KDNode<E>>>,
-    pub right:   Option<Box<KDNode<E>>>,
+use super::super::core::types::Real;
+use super::aabbox::AABBox3;
+use super::ray::Ray3;
+use super::triangle::{SolidTriangle, Triangle};
+
+use cgmath;
+
+type Vec3 = cgmath::Vector3<Real>;
+
+const MAX_LEAF_TRIANGLES: usize = 4;
+
+#[cfg_attr(debug_mode, derive(Debug))]
+pub struct KDNode {
+    pub area: AABBox3,
+    pub left: Option<Box<KDNode>>,
+    pub right: Option<Box<KDNode>>,
     pub indices: Vec<usize>,
 }
 
-impl<E> KDNode<E> where E: VectorElement {
-
-    pub fn new() -> KDNode<E> {
-        KDNode {
-            area:    AABBox3::new(),
-            left:    None,
-            right:   None,
+impl KDNode {
+    pub fn new() -> Self {
+        Self {
+            area: AABBox3::new(),
+            left: None,
+            right: None,
             indices: Vec::new(),
         }
     }
 
-    pub fn build<V, T>(indices: &Vec<usize>, vertices: &Vec<V>, triangles: &Vec<T>) -> Option<Box<KDNode<E>>> where V: HasPosition<E>, T: Triangle<E> {
-        let mut node = KDNode::new();
+    pub fn build<T>(indices: &[usize], positions: &[Vec3], triangles: &[T]) -> Option<Box<Self>>
+    where
+        T: Triangle,
+    {
         if indices.len() < 1 {
             return None;
         }
-        if indices.len() < 2 {
-            node.indices.push(indices[0]);
-            node.area = triangles[indices[0]].get_aabb(vertices);
-            return Some(Box::new(node));
-        }
-
-        node.area = triangles[indices[0]].get_aabb(vertices);
-        let mut midpt = Vec3::new(num::cast(0).unwrap());
-        let tris_recp = num::cast::<i8, E>(1).unwrap() / num::cast(indices.len()).unwrap();
-
+        let mut node = Self::new();
+        let mut midpt = Vec3::new(0.0, 0.0, 0.0);
+        let tris_recp = 1.0 / indices.len() as Real;
         for index in indices {
-            node.area.expand(&triangles[index].get_aabb(vertices));
-            midpt += triangles[index].get_midpoint(vertices) * tris_recp;
+            node.area.expand(&triangles[*index].get_aabb(positions));
+            midpt += triangles[*index].get_midpoint(positions) * tris_recp;
+        }
+        if indices.len() <= MAX_LEAF_TRIANGLES {
+            node.indices = indices.to_vec();
+            return Some(Box::new(node));
         }
 
+        let axis = node.area.get_longest_axis() as usize;
         let mut left_indices = Vec::new();
         let mut right_indices = Vec::new();
-        let axis = node.area.get_longest_axis();
-
-        // TODO for performance imporvement: put match block out of the for loop.
         for index in indices {
-            match axis {
-                Axis::X => if midpt.x >= triangles[index].get_midpoint(vertices).x { right_indices.push(index); } else { left_indices.push(index); },
-                Axis::Y => if midpt.y >= triangles[index].get_midpoint(vertices).y { right_indices.push(index); } else { left_indices.push(index); },
-                Axis::Z => if midpt.z >= triangles[index].get_midpoint(vertices).z { right_indices.push(index); } else { left_indices.push(index); },
-                _ => logf!("Unexpected Axis value.")
+            if midpt[axis] >= triangles[*index].get_midpoint(positions)[axis] {
+                right_indices.push(*index);
+            } else {
+                left_indices.push(*index);
             }
         }
 
         if left_indices.len() == 0 || right_indices.len() == 0 {
-            node.indices = indices.clone();
-            // TODO for performance imporvement: I thinck these following three lines have redundant calculating,
-            //      I already calulated them, I ave doubt about it..
+            node.indices = indices.to_vec();
             return Some(Box::new(node));
         }
-        node.left = KDNode::build(&left_indices, vertices, triangles);
-        node.right = KDNode::build(&right_indices, vertices, triangles);
-        Some(Box::new(node))
+        node.left = Self::build(&left_indices, positions, triangles);
+        node.right = Self::build(&right_indices, positions, triangles);
+        return Some(Box::new(node));
     }
 
-    pub fn hit<V, T>(node: &KDNode<E>, ray: &Ray3<E>, tmin: &E, vertices: &Vec<V>, triangles: &Vec<T>) -> Option<(E, E, E, usize)> where V: HasPosition<E>, T: Triangle<E> {
-        let (does_inter, dist) = node.area.intersection(ray);
-        if !does_inter {
-            return None;
+    // Returns the nearest hit as (t, u, v, triangle index)
+    pub fn hit<T>(
+        &self,
+        ray: &Ray3,
+        tmax: Real,
+        positions: &[Vec3],
+        triangles: &[T],
+    ) -> Option<(Real, Real, Real, usize)>
+    where
+        T: Triangle,
+    {
+        match self.area.intersection(ray) {
+            Some(dist) => {
+                if dist > tmax {
+                    return None;
+                }
+            }
+            None => return None,
         }
-        if dist.gt(tmin) {
-            return None;
+        let mut result = None;
+        let mut tmax = tmax;
+        for index in &self.indices {
+            if let Some((t, u, v)) = triangles[*index].intersect(ray, tmax, positions) {
+                tmax = t;
+                result = Some((t, u, v, *index));
+            }
         }
-        match node.left {
-            Some(ref left_node_p) => {
-                let l = KDNode::hit(&*left_node_p, ray, tmin, vertices);
-                if l.is_some() {
-                    return l;
-                }
+        if let Some(ref left) = self.left {
+            if let Some(h) = left.hit(ray, tmax, positions, triangles) {
+                tmax = h.0;
+                result = Some(h);
             }
-            None => {}
         }
-        match node.right {
-            Some(ref right_node_p) => {
-                return KDNode::hit(&*right_node_p, ray, tmin, vertices);
+        if let Some(ref right) = self.right {
+            if let Some(h) = right.hit(ray, tmax, positions, triangles) {
+                result = Some(h);
             }
-            None => {}
-        }
-        let mut hit_tri = false;
-        let mut tri_ind: usize = 0;
-        let mut t = *tmin;
-        let mut u: E;
-        let mut v: E;
-        for index in node.indices {
-            match triangles[index].intersect(ray, tmin, vertices) {
-                Some((_t, _u, _v)) => {
-                    if t.lt(&_t) {
-                        hit_tri = true;
-                        tri_ind = index;
-                        t = _t;
-                        u = _u;
-                        v = _v;
-                    }
-                }
-                None => {}
+        }
+        return result;
+    }
+}
+
+#[cfg_attr(debug_mode, derive(Debug))]
+pub struct KDTree<T>
+where
+    T: Triangle,
+{
+    root: Option<Box<KDNode>>,
+    positions: Vec<Vec3>,
+    triangles: Vec<T>,
+}
+
+impl<T> KDTree<T>
+where
+    T: Triangle,
+{
+    pub fn new(positions: Vec<Vec3>, triangles: Vec<T>) -> Self {
+        let indices: Vec<usize> = (0..triangles.len()).collect();
+        let root = KDNode::build(&indices, &positions, &triangles);
+        Self {
+            root,
+            positions,
+            triangles,
+        }
+    }
+
+    pub fn hit(&self, ray: &Ray3, tmax: Real) -> Option<(Real, Real, Real, usize)> {
+        match &self.root {
+            &Some(ref root) => root.hit(ray, tmax, &self.positions, &self.triangles),
+            &None => None,
+        }
+    }
+
+    pub fn get_aabb(&self) -> Option<&AABBox3> {
+        match &self.root {
+            &Some(ref root) => Some(&root.area),
+            &None => None,
+        }
+    }
+
+    pub fn get_positions(&self) -> &[Vec3] {
+        return &self.positions;
+    }
+
+    pub fn get_triangles(&self) -> &[T] {
+        return &self.triangles;
+    }
+}
+
+impl KDTree<SolidTriangle> {
+    pub fn new_with_indices(positions: Vec<Vec3>, indices: &[u32]) -> Self {
+        let mut triangles = Vec::with_capacity(indices.len() / 3);
+        for i in indices.chunks(3) {
+            if i.len() < 3 {
+                break;
+            }
+            let i = [i[0] as usize, i[1] as usize, i[2] as usize];
+            triangles.push(SolidTriangle::new(&i, &positions));
+        }
+        return Self::new(positions, triangles);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::f32::MAX as F32MAX;
+
+    // A grid of n * n quads on the XY plane, each one made of two triangles
+    fn grid(n: usize) -> (Vec<Vec3>, Vec<u32>) {
+        let mut positions = Vec::new();
+        for y in 0..(n + 1) {
+            for x in 0..(n + 1) {
+                positions.push(Vec3::new(x as Real, y as Real, 0.0));
+            }
+        }
+        let mut indices = Vec::new();
+        let w = (n + 1) as u32;
+        for y in 0..(n as u32) {
+            for x in 0..(n as u32) {
+                let i = y * w + x;
+                indices.extend_from_slice(&[i, i + 1, i + w, i + 1, i + w + 1, i + w]);
+            }
+        }
+        return (positions, indices);
+    }
+
+    fn brute_force(tree: &KDTree<SolidTriangle>, ray: &Ray3) -> Option<(Real, usize)> {
+        let mut result = None;
+        let mut tmax = F32MAX;
+        for (i, t) in tree.get_triangles().iter().enumerate() {
+            if let Some((d, _, _)) = t.intersect(ray, tmax, tree.get_positions()) {
+                tmax = d;
+                result = Some((d, i));
             }
         }
-        if hit_tri {
-            return Some((t, u, v, tri_ind));
+        return result;
+    }
+
+    #[test]
+    fn grid_hit_test() {
+        let (positions, indices) = grid(16);
+        let tree = KDTree::new_with_indices(positions, &indices);
+        let aabb = tree.get_aabb().unwrap();
+        assert_eq!(aabb.blf, Vec3::new(0.0, 0.0, 0.0));
+        assert_eq!(aabb.trr, Vec3::new(16.0, 16.0, 0.0));
+        let mut x = 0.13;
+        while x < 16.0 {
+            let ray = Ray3::new(
+                Vec3::new(x, 16.0 - x * 0.7, 5.0),
+                Vec3::new(0.1, 0.05, -1.0),
+            );
+            let h = tree.hit(&ray, F32MAX);
+            let b = brute_force(&tree, &ray);
+            match (h, b) {
+                (Some(h), Some(b)) => {
+                    assert!((h.0 - b.0).abs() < 1e-4);
+                    assert!((h.0 - 5.0).abs() < 1e-4);
+                }
+                (None, None) => {}
+                _ => panic!("Tree and brute force results are different."),
+            }
+            x += 0.37;
         }
-        return None;
+        let ray = Ray3::new(Vec3::new(8.0, 8.0, 5.0), Vec3::new(0.0, 0.0, 1.0));
+        assert!(tree.hit(&ray, F32MAX).is_none());
+        let ray = Ray3::new(Vec3::new(8.5, 8.25, 5.0), Vec3::new(0.0, 0.0, -1.0));
+        assert!(tree.hit(&ray, 2.0).is_none());
+    }
+
+    #[test]
+    fn nearest_hit_test() {
+        let positions = vec![
+            Vec3::new(-1.0, -1.0, 0.0),
+            Vec3::new(1.0, -1.0, 0.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            Vec3::new(-1.0, -1.0, -2.0),
+            Vec3::new(1.0, -1.0, -2.0),
+            Vec3::new(0.0, 1.0, -2.0),
+        ];
+        let tree = KDTree::new_with_indices(positions, &[3, 4, 5, 0, 1, 2]);
+        let ray = Ray3::new(Vec3::new(0.0, 0.0, 3.0), Vec3::new(0.0, 0.0, -1.0));
+        let (t, _, _, i) = tree.hit(&ray, F32MAX).unwrap();
+        assert!((t - 3.0).abs() < 1e-5);
+        assert_eq!(i, 1);
     }
 }
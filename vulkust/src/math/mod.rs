@@ -1,7 +1,7 @@
-//pub mod aabbox;
-//pub mod kdtree;
-pub mod matrix;
+pub mod aabbox;
+pub mod kdtree;
+// pub mod matrix;
 pub mod number;
-//pub mod ray;
-//pub mod triangle;
-pub mod vector;
+pub mod ray;
+pub mod triangle;
+// pub mod vector;
@@ -1,33 +1,27 @@
-extern crate num;
+use super::super::core::types::Real;
 
-use ::math::vector::{
-    Vec3,
-    VectorElement,
-};
+use cgmath;
 
-macro_rules! ncu8 {
-    ($var:expr) => (
-        num::cast::<u8, E>($var).unwrap()
-    )
-}
+type Vec3 = cgmath::Vector3<Real>;
 
-#[derive(Debug, Clone, Copy)]
-pub struct Ray3<E> where E: VectorElement {
-    pub o:    Vec3<E>,
-    pub d:    Vec3<E>,
-    pub invd: Vec3<E>,
+#[derive(Clone, Copy)]
+#[cfg_attr(debug_mode, derive(Debug))]
+pub struct Ray3 {
+    pub o: Vec3,
+    pub d: Vec3,
+    pub invd: Vec3,
 }
 
-impl<E> Ray3<E> where E: VectorElement {
-    pub fn new(o: Vec3<E>, d: Vec3<E>) -> Ray3<E> {
-        Ray3 {
-            o: o,
-            d: d,
-            invd: Vec3 {
-                x: ncu8!(1) / d.x,
-                y: ncu8!(1) / d.y,
-                z: ncu8!(1) / d.z,
-            }
+impl Ray3 {
+    pub fn new(o: Vec3, d: Vec3) -> Self {
+        Self {
+            o,
+            d,
+            invd: Vec3::new(1.0 / d.x, 1.0 / d.y, 1.0 / d.z),
         }
     }
+
+    pub fn get_point(&self, t: Real) -> Vec3 {
+        return self.o + self.d * t;
+    }
 }
@@ -1,155 +1,200 @@
-extern crate num;
-
-use ::math::vector::{
-    Vec3,
-    Vec2,
-    MathVector,
-    VectorElement,
-};
-use ::math::num::{
-    min,
-    max,
-};
-use ::math::aabbox::AABBox3;
-use ::math::ray::Ray3;
-use ::render::vertex::{
-    HasPosition,
-    HasNormal,
-    HasUV,
-};
-
-pub trait Triangle<T>: Sized + Clone + Copy where T: VectorElement {
-    fn get_aabb<V>(&self, vertices: &Vec<V>) -> AABBox3<T> where V: HasPosition<T>;
-    fn get_midpoint<V>(&self, vertices: &Vec<V>) -> Vec3<T> where V: HasPosition<T>;
-    fn intersect<V>(&self, r: &Ray3<T>, tmin: &T, vertices: &Vec<V>) -> Option<(T, T, T)> where V: HasPosition<T>;
-    fn barycentric<V>(&self, p: &Vec3<T>, vertices: &Vec<V>) -> Vec3<T> where V: HasPosition<T>;
+use super::super::core::types::Real;
+use super::aabbox::AABBox3;
+use super::ray::Ray3;
+
+use cgmath;
+use cgmath::InnerSpace;
+
+type Vec2 = cgmath::Vector2<Real>;
+type Vec3 = cgmath::Vector3<Real>;
+
+const INTERSECTION_EPSILON: Real = 1e-7;
+
+pub trait Triangle: Sized + Clone + Copy {
+    fn get_indices(&self) -> &[usize; 3];
+    fn get_aabb(&self, positions: &[Vec3]) -> AABBox3;
+    fn get_midpoint(&self, positions: &[Vec3]) -> Vec3;
+    fn get_normal(&self) -> Vec3;
+    // Möller–Trumbore, returns (t, u, v) for hits nearer than tmax
+    fn intersect(&self, r: &Ray3, tmax: Real, positions: &[Vec3]) -> Option<(Real, Real, Real)>;
+    fn barycentric(&self, p: &Vec3, positions: &[Vec3]) -> Vec3;
 }
 
 macro_rules! tri {
-    ($stru:ident) => (
-        impl<T> Triangle<T> for $stru<T> where T: VectorElement {
-            fn get_aabb<V>(&self, vertices: &Vec<V>) -> AABBox3<T> where V: HasPosition<T> {
-                AABBox3 {
-                    blf: Vec3 {
-                        x: min((*vertices[self.ind[0]].get_pos()).x, min((*vertices[self.ind[1]].get_pos()).x, (*vertices[self.ind[2]].get_pos()).x)),
-                        y: min((*vertices[self.ind[0]].get_pos()).y, min((*vertices[self.ind[1]].get_pos()).y, (*vertices[self.ind[2]].get_pos()).y)),
-                        z: min((*vertices[self.ind[0]].get_pos()).z, min((*vertices[self.ind[1]].get_pos()).z, (*vertices[self.ind[2]].get_pos()).z)),
-                    },
-                    trr: Vec3 {
-                        x: max((*vertices[self.ind[0]].get_pos()).x, max((*vertices[self.ind[1]].get_pos()).x, (*vertices[self.ind[2]].get_pos()).x)),
-                        y: max((*vertices[self.ind[0]].get_pos()).y, max((*vertices[self.ind[1]].get_pos()).y, (*vertices[self.ind[2]].get_pos()).y)),
-                        z: max((*vertices[self.ind[0]].get_pos()).z, max((*vertices[self.ind[1]].get_pos()).z, (*vertices[self.ind[2]].get_pos()).z)),
-                    },
+    ($stru:ident) => {
+        impl Triangle for $stru {
+            fn get_indices(&self) -> &[usize; 3] {
+                return &self.ind;
+            }
+
+            fn get_aabb(&self, positions: &[Vec3]) -> AABBox3 {
+                let mut aabb = AABBox3::new();
+                for i in &self.ind {
+                    aabb.expand_point(&positions[*i]);
                 }
+                return aabb;
             }
 
-            fn get_midpoint<V>(&self, vertices: &Vec<V>) -> Vec3<T> where V: HasPosition<T> {
-                ((*vertices[self.ind[0]].get_pos()) + (*vertices[self.ind[1]].get_pos()) + (*vertices[self.ind[2]].get_pos())) / num::cast::<u8, T>(3).unwrap()
+            fn get_midpoint(&self, positions: &[Vec3]) -> Vec3 {
+                return (positions[self.ind[0]] + positions[self.ind[1]] + positions[self.ind[2]])
+                    / 3.0;
             }
 
-            /// return: It will t, u, v,
-            fn intersect<V>(&self, r: &Ray3<T>, tmin: &T, vertices: &Vec<V>) -> Option<(T, T, T)> where V: HasPosition<T> {
+            fn get_normal(&self) -> Vec3 {
+                return self.edg[0].cross(self.edg[1]).normalize();
+            }
 
-                let pvec = r.d.cross(&self.edg[1]);
-                let det = self.edg[0].dot(&pvec);
-                if det == num::cast(0).unwrap() {
+            fn intersect(
+                &self,
+                r: &Ray3,
+                tmax: Real,
+                positions: &[Vec3],
+            ) -> Option<(Real, Real, Real)> {
+                let pvec = r.d.cross(self.edg[1]);
+                let det = self.edg[0].dot(pvec);
+                if det.abs() < INTERSECTION_EPSILON {
                     return None;
                 }
-                let inv_det: T;
-                inv_det = num::cast::<i8, T>(1).unwrap() / det;
-                let tvec = r.o - *(vertices[self.ind[0]].get_pos());
-                let u = tvec.dot(&pvec) * inv_det;
-                if u < num::cast(0).unwrap() || u > num::cast(1).unwrap() {
+                let inv_det = 1.0 / det;
+                let tvec = r.o - positions[self.ind[0]];
+                let u = tvec.dot(pvec) * inv_det;
+                if u < 0.0 || u > 1.0 {
                     return None;
                 }
-                let qvec = tvec.cross(&self.edg[0]);
-                let v = r.d.dot(&qvec) * inv_det;
-                if v < num::cast(0).unwrap() || u + v > num::cast(1).unwrap() {
+                let qvec = tvec.cross(self.edg[0]);
+                let v = r.d.dot(qvec) * inv_det;
+                if v < 0.0 || u + v > 1.0 {
                     return None;
                 }
-                let t = self.edg[1].dot(&qvec) * inv_det; // Set distance along ray to intersection
-                if t < *tmin {
-                    if t > num::cast(1e-9).unwrap() {
-                        return Some((t, u, v));
-                    }
+                let t = self.edg[1].dot(qvec) * inv_det;
+                if t < tmax && t > INTERSECTION_EPSILON {
+                    return Some((t, u, v));
                 }
-                None
+                return None;
             }
 
             // Returns barycentric coordinates of point p on the triangle
-            fn barycentric<V>(&self, p: &Vec3<T>, vertices: &Vec<V>) -> Vec3<T> where V: HasPosition<T> {
-                let v2_ = *p - (*vertices[self.ind[0]].get_pos());
-                let d00 = self.edg[0].dot(&self.edg[0]);
-                let d01 = self.edg[0].dot(&self.edg[1]);
-                let d11 = self.edg[1].dot(&self.edg[1]);
-                let d20 = v2_.dot(&self.edg[0]);
-                let d21 = v2_.dot(&self.edg[1]);
-                let d = d00*d11 - d01*d01;
-                let v = (d11*d20 - d01*d21) / d;
-                let w = (d00*d21 - d01*d20) / d;
-                let u = num::cast::<i8, T>(1).unwrap() - v - w;
-                return Vec3 {
-                    x: u,
-                    y: v,
-                    z: w,
-                };
+            fn barycentric(&self, p: &Vec3, positions: &[Vec3]) -> Vec3 {
+                let v2 = *p - positions[self.ind[0]];
+                let d00 = self.edg[0].dot(self.edg[0]);
+                let d01 = self.edg[0].dot(self.edg[1]);
+                let d11 = self.edg[1].dot(self.edg[1]);
+                let d20 = v2.dot(self.edg[0]);
+                let d21 = v2.dot(self.edg[1]);
+                let d = d00 * d11 - d01 * d01;
+                let v = (d11 * d20 - d01 * d21) / d;
+                let w = (d00 * d21 - d01 * d20) / d;
+                return Vec3::new(1.0 - v - w, v, w);
             }
         }
-    )
+    };
 }
 
-#[derive(Debug, Clone, Copy)]
-pub struct TexturedTriangle<T> where T: VectorElement {
-    edg: [Vec3<T>; 2],
+#[derive(Clone, Copy)]
+#[cfg_attr(debug_mode, derive(Debug))]
+pub struct TexturedTriangle {
+    edg: [Vec3; 2],
     ind: [usize; 3],
-    tedg: [Vec2<T>; 2],
+    uv0: Vec2,
+    tedg: [Vec2; 2],
 }
 
-impl<T> TexturedTriangle<T> where T: VectorElement, Vec3<T>: MathVector<T> {
-    pub fn new<V>(inds: &[usize; 3], vertices: &Vec<V>) -> TexturedTriangle<T> where V: HasPosition<T> + HasNormal<T> + HasUV<T> {
-        TexturedTriangle {
-            edg : [
-                *vertices[inds[1]].get_pos() - *vertices[inds[0]].get_pos(),
-                *vertices[inds[2]].get_pos() - *vertices[inds[0]].get_pos(),
-            ],
-            ind: [
-                inds[0],
-                inds[1],
-                inds[2],
+impl TexturedTriangle {
+    pub fn new(inds: &[usize; 3], positions: &[Vec3], uvs: &[Vec2]) -> Self {
+        Self {
+            edg: [
+                positions[inds[1]] - positions[inds[0]],
+                positions[inds[2]] - positions[inds[0]],
             ],
-            tedg: [
-                *vertices[inds[1]].get_uv() - *vertices[inds[0]].get_uv(),
-                *vertices[inds[2]].get_uv() - *vertices[inds[0]].get_uv(),
-            ]
+            ind: *inds,
+            uv0: uvs[inds[0]],
+            tedg: [uvs[inds[1]] - uvs[inds[0]], uvs[inds[2]] - uvs[inds[0]]],
         }
     }
-    pub fn get_texture_coord(&self, u: T, v: T) -> Vec2<T> {
-        self.tedg[0] * u + self.tedg[1] * v
+
+    // u and v are the ones that intersect returns
+    pub fn get_texture_coord(&self, u: Real, v: Real) -> Vec2 {
+        return self.uv0 + self.tedg[0] * u + self.tedg[1] * v;
     }
 }
 
 tri!(TexturedTriangle);
 
-#[derive(Debug, Clone, Copy)]
-pub struct SolidTriangle<T> where T: VectorElement {
-    edg: [Vec3<T>; 2],
+#[derive(Clone, Copy)]
+#[cfg_attr(debug_mode, derive(Debug))]
+pub struct SolidTriangle {
+    edg: [Vec3; 2],
     ind: [usize; 3],
 }
 
-impl<T> SolidTriangle<T> where T: VectorElement, Vec3<T>: MathVector<T> {
-    pub fn new<V>(inds: &[usize; 3], vertices: &Vec<V>) -> SolidTriangle<T> where V: HasPosition<T> + HasNormal<T> {
-        SolidTriangle {
+impl SolidTriangle {
+    pub fn new(inds: &[usize; 3], positions: &[Vec3]) -> Self {
+        Self {
             edg: [
-                *vertices[inds[1]].get_pos() - *vertices[inds[0]].get_pos(),
-                *vertices[inds[2]].get_pos() - *vertices[inds[0]].get_pos(),
-            ],
-            ind: [
-                inds[0],
-                inds[1],
-                inds[2],
+                positions[inds[1]] - positions[inds[0]],
+                positions[inds[2]] - positions[inds[0]],
             ],
+            ind: *inds,
         }
     }
 }
 
 tri!(SolidTriangle);
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn positions() -> Vec<Vec3> {
+        vec![
+            Vec3::new(0.0, 0.0, 0.0),
+            Vec3::new(1.0, 0.0, 0.0),
+            Vec3::new(0.0, 1.0, 0.0),
+        ]
+    }
+
+    #[test]
+    fn intersect_test() {
+        let p = positions();
+        let t = SolidTriangle::new(&[0, 1, 2], &p);
+        let r = Ray3::new(Vec3::new(0.25, 0.25, 1.0), Vec3::new(0.0, 0.0, -1.0));
+        let (d, u, v) = t.intersect(&r, 100.0, &p).unwrap();
+        assert!((d - 1.0).abs() < 1e-5);
+        assert!((u - 0.25).abs() < 1e-5);
+        assert!((v - 0.25).abs() < 1e-5);
+        assert!(t.intersect(&r, 0.5, &p).is_none());
+        let r = Ray3::new(Vec3::new(0.75, 0.75, 1.0), Vec3::new(0.0, 0.0, -1.0));
+        assert!(t.intersect(&r, 100.0, &p).is_none());
+        let r = Ray3::new(Vec3::new(0.25, 0.25, 1.0), Vec3::new(1.0, 0.0, 0.0));
+        assert!(t.intersect(&r, 100.0, &p).is_none());
+        assert!((t.get_normal() - Vec3::new(0.0, 0.0, 1.0)).magnitude() < 1e-5);
+    }
+
+    #[test]
+    fn barycentric_test() {
+        let p = positions();
+        let t = SolidTriangle::new(&[0, 1, 2], &p);
+        let b = t.barycentric(&Vec3::new(0.25, 0.5, 0.0), &p);
+        assert!((b - Vec3::new(0.25, 0.25, 0.5)).magnitude() < 1e-5);
+        let b = t.barycentric(&p[1], &p);
+        assert!((b - Vec3::new(0.0, 1.0, 0.0)).magnitude() < 1e-5);
+    }
+
+    #[test]
+    fn aabb_and_uv_test() {
+        let p = positions();
+        let uvs = vec![
+            Vec2::new(0.5, 0.5),
+            Vec2::new(1.0, 0.5),
+            Vec2::new(0.5, 1.0),
+        ];
+        let t = TexturedTriangle::new(&[0, 1, 2], &p, &uvs);
+        let aabb = t.get_aabb(&p);
+        assert_eq!(aabb.blf, Vec3::new(0.0, 0.0, 0.0));
+        assert_eq!(aabb.trr, Vec3::new(1.0, 1.0, 0.0));
+        let m = t.get_midpoint(&p);
+        assert!((m - Vec3::new(1.0 / 3.0, 1.0 / 3.0, 0.0)).magnitude() < 1e-5);
+        let uv = t.get_texture_coord(0.5, 0.5);
+        assert!((uv - Vec2::new(0.75, 0.75)).magnitude() < 1e-5);
+    }
+}
@@ -0,0 +1,403 @@
+use super::super::core::object::{Base as CoreBase, Object as CoreObject};
+use super::super::core::types::{Id, Real};
+use super::super::render::model::Model;
+use super::super::render::object::Transferable;
+use super::collider::{Collider, Cuboid, Ghost};
+use std::sync::{Arc, RwLock, Weak};
+
+use cgmath;
+use cgmath::prelude::*;
+
+type Vec3 = cgmath::Vector3<Real>;
+type Mat3 = cgmath::Matrix3<Real>;
+type Mat4 = cgmath::Matrix4<Real>;
+type Quat = cgmath::Quaternion<Real>;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(debug_mode, derive(Debug))]
+pub enum BodyType {
+    // Never moves
+    Static,
+    // Moves only with its velocity, it is not affected by forces and contacts
+    Kinematic,
+    Dynamic,
+}
+
+// Ghosts of GX3D files do not have a volume, the box around their model becomes their
+// trigger volume, otherwise they would never report any overlap.
+fn get_model_collider(model: &Model) -> Arc<RwLock<Collider>> {
+    let collider = model.get_collider();
+    let has_volume = match vxresult!(collider.read()).to_ghost() {
+        Some(ghost) => ghost.volume.is_some(),
+        None => true,
+    };
+    let r = model.get_occlusion_culling_radius();
+    if has_volume || r <= 0.0 {
+        return collider.clone();
+    }
+    let volume: Arc<RwLock<Collider>> = Arc::new(RwLock::new(Cuboid::new(Vec3::new(r, r, r))));
+    return Arc::new(RwLock::new(Ghost::new_with_volume(volume)));
+}
+
+// Something that follows the body, returns false when it does not exist anymore
+struct Link {
+    sync: Box<Fn(&Vec3, &Quat) -> bool>,
+}
+
+#[cfg(debug_mode)]
+impl std::fmt::Debug for Link {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "Rigid body link")
+    }
+}
+
+#[cfg_attr(debug_mode, derive(Debug))]
+pub struct RigidBody {
+    obj_base: CoreBase,
+    body_type: BodyType,
+    collider: Arc<RwLock<Collider>>,
+    position: Vec3,
+    orientation: Quat,
+    // Pose at the start of the last step, links are interpolated between it and current pose
+    previous_position: Vec3,
+    previous_orientation: Quat,
+    linear_velocity: Vec3,
+    angular_velocity: Vec3,
+    force: Vec3,
+    torque: Vec3,
+    mass: Real,
+    inverse_mass: Real,
+    inertia: Vec3,
+    inverse_inertia: Vec3,
+    pub friction: Real,
+    pub restitution: Real,
+    pub gravity_scale: Real,
+    pub linear_damping: Real,
+    pub angular_damping: Real,
+    pub can_sleep: bool,
+    sleeping: bool,
+    pub(super) sleep_time: Real,
+    pub(super) proxy: Option<usize>,
+    // Placed by user, so broadphase must be updated even if it is not moving
+    pub(super) teleported: bool,
+    pub(super) outdated_links: bool,
+    model_id: Option<Id>,
+    links: Vec<Link>,
+}
+
+impl RigidBody {
+    pub fn new(body_type: BodyType, collider: Arc<RwLock<Collider>>) -> Self {
+        let mut body = RigidBody {
+            obj_base: CoreBase::new(),
+            body_type,
+            collider,
+            position: Vec3::new(0.0, 0.0, 0.0),
+            orientation: Quat::one(),
+            previous_position: Vec3::new(0.0, 0.0, 0.0),
+            previous_orientation: Quat::one(),
+            linear_velocity: Vec3::new(0.0, 0.0, 0.0),
+            angular_velocity: Vec3::new(0.0, 0.0, 0.0),
+            force: Vec3::new(0.0, 0.0, 0.0),
+            torque: Vec3::new(0.0, 0.0, 0.0),
+            mass: 0.0,
+            inverse_mass: 0.0,
+            inertia: Vec3::new(0.0, 0.0, 0.0),
+            inverse_inertia: Vec3::new(0.0, 0.0, 0.0),
+            friction: 0.5,
+            restitution: 0.0,
+            gravity_scale: 1.0,
+            linear_damping: 0.01,
+            angular_damping: 0.05,
+            can_sleep: true,
+            sleeping: false,
+            sleep_time: 0.0,
+            proxy: None,
+            teleported: false,
+            outdated_links: true,
+            model_id: None,
+            links: Vec::new(),
+        };
+        if body_type == BodyType::Dynamic {
+            body.set_mass(1.0);
+        }
+        return body;
+    }
+
+    // Takes collider and location of model and makes model follow the body
+    pub fn new_with_model(body_type: BodyType, model: &Arc<RwLock<Model>>) -> Self {
+        let (collider, location, id) = {
+            let model = vxresult!(model.read());
+            (
+                get_model_collider(&*model),
+                model.get_location(),
+                model.get_id(),
+            )
+        };
+        let mut body = Self::new(body_type, collider);
+        body.position = location;
+        body.previous_position = location;
+        body.model_id = Some(id);
+        body.link(Arc::downgrade(model));
+        return body;
+    }
+
+    // Linked object gets location and orientation of body in each World::interpolate
+    pub fn link<T>(&mut self, target: Weak<RwLock<T>>)
+    where
+        T: Transferable + ?Sized + 'static,
+    {
+        self.links.push(Link {
+            sync: Box::new(move |location, orientation| {
+                let target = match target.upgrade() {
+                    Some(t) => t,
+                    None => return false,
+                };
+                let mut target = vxresult!(target.write());
+                target.set_location(location);
+                target.set_orientation(orientation);
+                return true;
+            }),
+        });
+    }
+
+    // Alpha is the fraction of the last step that linked objects show
+    pub(super) fn sync_links(&mut self, alpha: Real) {
+        let position = self.previous_position + (self.position - self.previous_position) * alpha;
+        let orientation = self.previous_orientation.nlerp(self.orientation, alpha);
+        self.links.retain(|l| (l.sync)(&position, &orientation));
+        self.outdated_links = false;
+    }
+
+    // Id of the model that body is created for
+    pub fn get_model_id(&self) -> Option<Id> {
+        return self.model_id;
+    }
+
+    pub fn get_type(&self) -> BodyType {
+        return self.body_type;
+    }
+
+    pub fn is_dynamic(&self) -> bool {
+        return self.body_type == BodyType::Dynamic;
+    }
+
+    pub fn get_collider(&self) -> &Arc<RwLock<Collider>> {
+        return &self.collider;
+    }
+
+    // Mass of dynamic body must be positive, inertia is computed from collider
+    pub fn set_mass(&mut self, mass: Real) {
+        if self.body_type != BodyType::Dynamic {
+            vxlogf!("Only dynamic bodies can have mass.");
+        }
+        if mass <= 0.0 {
+            vxlogf!("Mass must be positive.");
+        }
+        self.mass = mass;
+        self.inverse_mass = 1.0 / mass;
+        let inertia = vxresult!(self.collider.read()).get_inertia(mass);
+        self.set_inertia(&inertia);
+    }
+
+    pub fn get_mass(&self) -> Real {
+        return self.mass;
+    }
+
+    pub fn get_inverse_mass(&self) -> Real {
+        if self.sleeping {
+            return 0.0;
+        }
+        return self.inverse_mass;
+    }
+
+    // Diagonal of inertia tensor in local space
+    pub fn set_inertia(&mut self, inertia: &Vec3) {
+        self.inertia = *inertia;
+        for i in 0..3 {
+            self.inverse_inertia[i] = if inertia[i] > 0.0 {
+                1.0 / inertia[i]
+            } else {
+                0.0
+            };
+        }
+    }
+
+    pub fn get_inertia(&self) -> &Vec3 {
+        return &self.inertia;
+    }
+
+    // Inverse of inertia tensor in world space
+    pub fn get_inverse_inertia(&self) -> Mat3 {
+        if self.sleeping || self.body_type != BodyType::Dynamic {
+            return Mat3::zero();
+        }
+        let r = Mat3::from(self.orientation);
+        return r * Mat3::from_diagonal(self.inverse_inertia) * r.transpose();
+    }
+
+    pub fn set_position(&mut self, position: &Vec3) {
+        self.position = *position;
+        self.previous_position = *position;
+        self.teleported = true;
+        self.outdated_links = true;
+        self.wake_up();
+    }
+
+    pub fn get_position(&self) -> &Vec3 {
+        return &self.position;
+    }
+
+    pub fn set_orientation(&mut self, orientation: &Quat) {
+        self.orientation = orientation.normalize();
+        self.previous_orientation = self.orientation;
+        self.teleported = true;
+        self.outdated_links = true;
+        self.wake_up();
+    }
+
+    pub fn get_orientation(&self) -> &Quat {
+        return &self.orientation;
+    }
+
+    pub fn get_transform(&self) -> Mat4 {
+        return Mat4::from_translation(self.position) * Mat4::from(self.orientation);
+    }
+
+    pub fn set_linear_velocity(&mut self, v: &Vec3) {
+        if self.body_type == BodyType::Static {
+            return;
+        }
+        self.linear_velocity = *v;
+        self.wake_up();
+    }
+
+    pub fn get_linear_velocity(&self) -> &Vec3 {
+        return &self.linear_velocity;
+    }
+
+    pub fn set_angular_velocity(&mut self, w: &Vec3) {
+        if self.body_type == BodyType::Static {
+            return;
+        }
+        self.angular_velocity = *w;
+        self.wake_up();
+    }
+
+    pub fn get_angular_velocity(&self) -> &Vec3 {
+        return &self.angular_velocity;
+    }
+
+    // Velocity of a world space point that is attached to body
+    pub fn get_point_velocity(&self, point: &Vec3) -> Vec3 {
+        return self.linear_velocity + self.angular_velocity.cross(point - self.position);
+    }
+
+    // Forces and torques are cleared after each step
+    pub fn apply_force(&mut self, force: &Vec3) {
+        if self.body_type != BodyType::Dynamic {
+            return;
+        }
+        self.force += *force;
+        self.wake_up();
+    }
+
+    pub fn apply_force_at(&mut self, force: &Vec3, point: &Vec3) {
+        if self.body_type != BodyType::Dynamic {
+            return;
+        }
+        self.force += *force;
+        self.torque += (point - self.position).cross(*force);
+        self.wake_up();
+    }
+
+    pub fn apply_torque(&mut self, torque: &Vec3) {
+        if self.body_type != BodyType::Dynamic {
+            return;
+        }
+        self.torque += *torque;
+        self.wake_up();
+    }
+
+    pub fn apply_impulse(&mut self, impulse: &Vec3, point: &Vec3) {
+        if self.body_type != BodyType::Dynamic {
+            return;
+        }
+        self.wake_up();
+        self.linear_velocity += impulse * self.inverse_mass;
+        self.angular_velocity +=
+            self.get_inverse_inertia() * (point - self.position).cross(*impulse);
+    }
+
+    // Moves like an impulse but changes position and orientation instead of velocities
+    pub(super) fn apply_position_impulse(&mut self, impulse: &Vec3, r: &Vec3) {
+        if self.body_type != BodyType::Dynamic || self.sleeping {
+            return;
+        }
+        self.position += impulse * self.inverse_mass;
+        let w = self.get_inverse_inertia() * r.cross(*impulse) * 0.5;
+        let dq = Quat::from_sv(0.0, w) * self.orientation;
+        self.orientation = (self.orientation + dq).normalize();
+    }
+
+    pub fn is_sleeping(&self) -> bool {
+        return self.sleeping;
+    }
+
+    pub fn wake_up(&mut self) {
+        self.sleeping = false;
+        self.sleep_time = 0.0;
+    }
+
+    pub fn sleep(&mut self) {
+        if self.body_type != BodyType::Dynamic {
+            return;
+        }
+        self.sleeping = true;
+        self.previous_position = self.position;
+        self.previous_orientation = self.orientation;
+        self.outdated_links = true;
+        self.linear_velocity = Vec3::zero();
+        self.angular_velocity = Vec3::zero();
+    }
+
+    // Simulated by world, static and sleeping bodies are not moving
+    pub fn is_moving(&self) -> bool {
+        return self.body_type != BodyType::Static && !self.sleeping;
+    }
+
+    pub(super) fn integrate_velocity(&mut self, gravity: &Vec3, dt: Real) {
+        if self.body_type != BodyType::Dynamic || self.sleeping {
+            return;
+        }
+        self.linear_velocity +=
+            (gravity * self.gravity_scale + self.force * self.inverse_mass) * dt;
+        self.angular_velocity += self.get_inverse_inertia() * self.torque * dt;
+        self.linear_velocity *= 1.0 / (1.0 + dt * self.linear_damping);
+        self.angular_velocity *= 1.0 / (1.0 + dt * self.angular_damping);
+        self.force = Vec3::zero();
+        self.torque = Vec3::zero();
+    }
+
+    pub(super) fn integrate_position(&mut self, dt: Real) {
+        self.previous_position = self.position;
+        self.previous_orientation = self.orientation;
+        if !self.is_moving() {
+            return;
+        }
+        self.position += self.linear_velocity * dt;
+        let w = self.angular_velocity * (0.5 * dt);
+        let dq = Quat::from_sv(0.0, w) * self.orientation;
+        self.orientation = (self.orientation + dq).normalize();
+    }
+
+    pub(super) fn set_velocities(&mut self, v: &Vec3, w: &Vec3) {
+        self.linear_velocity = *v;
+        self.angular_velocity = *w;
+    }
+}
+
+impl CoreObject for RigidBody {
+    fn get_id(&self) -> Id {
+        self.obj_base.get_id()
+    }
+}
@@ -0,0 +1,506 @@
+use super::super::core::types::Real;
+use super::super::render::object::Transferable;
+use super::collider::{Capsule, Collider, Sphere};
+use super::narrowphase::{get_contacts, get_distance};
+use super::world::World;
+use std::sync::{Arc, RwLock, Weak};
+
+use cgmath;
+use cgmath::prelude::*;
+
+type Vec3 = cgmath::Vector3<Real>;
+type Mat4 = cgmath::Matrix4<Real>;
+type Quat = cgmath::Quaternion<Real>;
+
+const MAX_RESOLVE_ITERATIONS: usize = 4;
+const EPSILON: Real = 1e-5;
+const SURFACE_PROBE_DISTANCE: Real = 0.05;
+
+// Something that follows the character, returns false when it does not exist anymore
+struct Follower {
+    sync: Box<Fn(&Vec3) -> bool>,
+}
+
+#[cfg(debug_mode)]
+impl std::fmt::Debug for Follower {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "Character follower")
+    }
+}
+
+// What capsule touched during a movement, normals are toward the capsule
+struct Touches {
+    ground: Option<Vec3>,
+    wall: bool,
+    ceiling: bool,
+}
+
+// A capsule that is moved by user and collides with bodies of world,
+// it is not a body so other bodies are not affected by it.
+#[cfg_attr(debug_mode, derive(Debug))]
+pub struct Controller {
+    collider: Arc<RwLock<Collider>>,
+    radius: Real,
+    half_height: Real,
+    position: Vec3,
+    up: Vec3,
+    rotation: Quat,
+    // Obstacles lower than this are climbed
+    pub step_offset: Real,
+    max_slope_cos: Real,
+    pub jump_speed: Real,
+    walk_velocity: Vec3,
+    vertical_speed: Real,
+    on_ground: bool,
+    ground_normal: Vec3,
+    followers: Vec<Follower>,
+}
+
+impl Controller {
+    // Position is the center of capsule, height is the whole height of capsule
+    pub fn new(radius: Real, height: Real) -> Self {
+        let half_height = (height * 0.5 - radius).max(0.0);
+        let up = Vec3::unit_y();
+        Controller {
+            collider: Arc::new(RwLock::new(Capsule::new(radius, half_height))),
+            radius,
+            half_height,
+            position: Vec3::zero(),
+            up,
+            rotation: Quat::one(),
+            step_offset: 0.3,
+            max_slope_cos: cgmath::Rad::cos(cgmath::Deg(45.0).into()),
+            jump_speed: 5.0,
+            walk_velocity: Vec3::zero(),
+            vertical_speed: 0.0,
+            on_ground: false,
+            ground_normal: up,
+            followers: Vec::new(),
+        }
+    }
+
+    pub fn get_collider(&self) -> &Arc<RwLock<Collider>> {
+        return &self.collider;
+    }
+
+    pub fn set_position(&mut self, position: &Vec3) {
+        self.position = *position;
+        self.sync_followers();
+    }
+
+    pub fn get_position(&self) -> &Vec3 {
+        return &self.position;
+    }
+
+    // Capsule stands along up, gravity of world is applied against it
+    pub fn set_up(&mut self, up: &Vec3) {
+        self.up = up.normalize();
+        self.rotation = Quat::from_arc(Vec3::unit_y(), self.up, None);
+    }
+
+    pub fn get_up(&self) -> &Vec3 {
+        return &self.up;
+    }
+
+    // Steeper surfaces are walls, character can not stand or walk on them
+    pub fn set_max_slope(&mut self, angle: cgmath::Rad<Real>) {
+        self.max_slope_cos = cgmath::Rad::cos(angle);
+    }
+
+    pub fn get_max_slope(&self) -> cgmath::Rad<Real> {
+        return cgmath::Rad::acos(self.max_slope_cos);
+    }
+
+    // The part of velocity that is along up is ignored
+    pub fn set_walk_velocity(&mut self, velocity: &Vec3) {
+        self.walk_velocity = velocity - self.up * velocity.dot(self.up);
+    }
+
+    pub fn get_walk_velocity(&self) -> &Vec3 {
+        return &self.walk_velocity;
+    }
+
+    pub fn get_vertical_speed(&self) -> Real {
+        return self.vertical_speed;
+    }
+
+    pub fn is_on_ground(&self) -> bool {
+        return self.on_ground;
+    }
+
+    pub fn get_ground_normal(&self) -> &Vec3 {
+        return &self.ground_normal;
+    }
+
+    // Returns false when it is not on ground
+    pub fn jump(&mut self) -> bool {
+        if !self.on_ground {
+            return false;
+        }
+        self.vertical_speed = self.jump_speed;
+        self.on_ground = false;
+        return true;
+    }
+
+    // Linked object gets location of character plus offset, e.g. eye height for a camera
+    pub fn link<T>(&mut self, target: Weak<RwLock<T>>, offset: &Vec3)
+    where
+        T: Transferable + ?Sized + 'static,
+    {
+        let offset = *offset;
+        self.followers.push(Follower {
+            sync: Box::new(move |location| {
+                let target = match target.upgrade() {
+                    Some(t) => t,
+                    None => return false,
+                };
+                vxresult!(target.write()).set_location(&(location + offset));
+                return true;
+            }),
+        });
+        self.sync_followers();
+    }
+
+    fn sync_followers(&mut self) {
+        let position = self.position;
+        self.followers.retain(|f| (f.sync)(&position));
+    }
+
+    // Walks, falls and jumps, it must be called every frame
+    pub fn update(&mut self, world: &World, delta: Real) {
+        let gravity = -world.gravity.dot(self.up);
+        if self.on_ground && self.vertical_speed < 0.0 {
+            self.vertical_speed = 0.0;
+        }
+        self.vertical_speed -= gravity * delta;
+        let walk = self.walk_velocity * delta;
+        let vertical = self.vertical_speed * delta;
+        let was_on_ground = self.on_ground;
+        self.move_horizontally(world, &walk);
+        self.move_vertically(world, vertical, was_on_ground);
+        self.sync_followers();
+    }
+
+    // Moves without gravity, useful for flying characters
+    pub fn move_and_slide(&mut self, world: &World, displacement: &Vec3) {
+        let vertical = displacement.dot(self.up);
+        let horizontal = displacement - self.up * vertical;
+        let was_on_ground = self.on_ground;
+        self.move_horizontally(world, &horizontal);
+        self.move_vertically(world, vertical, was_on_ground);
+        self.sync_followers();
+    }
+
+    fn move_horizontally(&mut self, world: &World, displacement: &Vec3) {
+        let length = displacement.magnitude();
+        if length < EPSILON {
+            return;
+        }
+        let direction = displacement / length;
+        let start = self.position;
+        let touches = self.slide(world, displacement);
+        if !touches.wall || !self.on_ground || self.step_offset <= 0.0 {
+            return;
+        }
+        // blocked by something, maybe it is a step
+        let progress = (self.position - start).dot(direction);
+        let blocked_position = self.position;
+        self.position = start;
+        let up = self.up * self.step_offset;
+        self.slide(world, &up);
+        let climbed = (self.position - start).dot(self.up);
+        self.slide(world, displacement);
+        let down = self.up * -climbed;
+        let touches = self.slide(world, &down);
+        let stepped_progress = (self.position - start).dot(direction);
+        if touches.ground.is_none() || stepped_progress <= progress + EPSILON {
+            self.position = blocked_position;
+        }
+    }
+
+    fn move_vertically(&mut self, world: &World, displacement: Real, was_on_ground: bool) {
+        let d = self.up * displacement;
+        let touches = self.slide(world, &d);
+        if touches.ceiling && self.vertical_speed > 0.0 {
+            self.vertical_speed = 0.0;
+        }
+        self.on_ground = false;
+        if self.vertical_speed <= 0.0 {
+            if let Some(n) = touches.ground {
+                self.on_ground = true;
+                self.ground_normal = n;
+            } else if was_on_ground && self.step_offset > 0.0 {
+                // keeps character on ground while going down of steps and slopes
+                let start = self.position;
+                let down = self.up * -self.step_offset;
+                match self.slide(world, &down).ground {
+                    Some(n) => {
+                        self.on_ground = true;
+                        self.ground_normal = n;
+                    }
+                    None => self.position = start,
+                }
+            }
+        }
+        if self.on_ground {
+            self.vertical_speed = 0.0;
+        }
+    }
+
+    // Movement is divided to steps smaller than radius, so thin obstacles are not tunneled
+    fn slide(&mut self, world: &World, displacement: &Vec3) -> Touches {
+        let mut touches = Touches {
+            ground: None,
+            wall: false,
+            ceiling: false,
+        };
+        let length = displacement.magnitude();
+        let steps = (length / (self.radius * 0.5)).ceil().max(1.0) as usize;
+        let step = displacement / steps as Real;
+        for _ in 0..steps {
+            self.position += step;
+            self.resolve(world, &mut touches);
+        }
+        return touches;
+    }
+
+    // Pushes capsule out of obstacles, walls only push horizontally
+    // and grounds only push along up, so character does not slide on walkable slopes
+    // and can not climb steep ones.
+    fn resolve(&mut self, world: &World, touches: &mut Touches) {
+        let collider = vxresult!(self.collider.read());
+        for _ in 0..MAX_RESOLVE_ITERATIONS {
+            let transform = Mat4::from_translation(self.position) * Mat4::from(self.rotation);
+            let aabb = collider.get_aabb(&transform);
+            let bottom = self.position - self.up * (self.half_height + self.radius);
+            let mut push = Vec3::zero();
+            world.query_aabb(&aabb, &mut |body| {
+                let other = vxresult!(body.get_collider().read());
+                let other_transform = body.get_transform();
+                let contacts = get_contacts(&*collider, &transform, &*other, &other_transform);
+                for c in contacts {
+                    if c.depth <= 0.0 {
+                        continue;
+                    }
+                    let n = -c.normal;
+                    let n_up = n.dot(self.up);
+                    let mut ground = None;
+                    let mut lift = c.depth / n_up;
+                    if n_up >= self.max_slope_cos {
+                        ground = Some(n);
+                    } else if n_up > 0.0 && (c.point_b - bottom).dot(self.up) <= self.step_offset {
+                        // rounded bottom of capsule sees edges as slopes
+                        let s = self.get_surface_normal(&*other, &other_transform, &c.point_b);
+                        if s.dot(self.up) >= self.max_slope_cos {
+                            ground = Some(s);
+                            lift = self.get_edge_lift(&c.point_b);
+                        }
+                    }
+                    let (direction, needed) = if let Some(g) = ground {
+                        touches.ground = Some(g);
+                        (self.up, lift)
+                    } else if n_up > -self.max_slope_cos {
+                        touches.wall = true;
+                        let h = n - self.up * n_up;
+                        let l = h.magnitude();
+                        if l > EPSILON {
+                            (h / l, c.depth / l)
+                        } else {
+                            (n, c.depth)
+                        }
+                    } else {
+                        touches.ceiling = true;
+                        (n, c.depth)
+                    };
+                    // overlapping contacts share the push
+                    let remaining = needed - push.dot(direction);
+                    if remaining > 0.0 {
+                        push += direction * remaining;
+                    }
+                }
+            });
+            if push.magnitude2() < EPSILON * EPSILON {
+                return;
+            }
+            self.position += push;
+        }
+    }
+
+    // How much the lower sphere of capsule must go up to be over the edge point
+    fn get_edge_lift(&self, point: &Vec3) -> Real {
+        let center = self.position - self.up * self.half_height;
+        let d = point - center;
+        let d_up = d.dot(self.up);
+        let horizontal2 = (d - self.up * d_up).magnitude2();
+        let r2 = self.radius * self.radius;
+        if horizontal2 >= r2 {
+            return 0.0;
+        }
+        return d_up + (r2 - horizontal2).sqrt();
+    }
+
+    // Normal of the surface under the point, it is toward the closest point of collider
+    // from a point a bit above, zero when there is no surface under the point.
+    fn get_surface_normal(&self, collider: &Collider, transform: &Mat4, point: &Vec3) -> Vec3 {
+        let probe = Mat4::from_translation(point + self.up * SURFACE_PROBE_DISTANCE);
+        return match get_distance(&Sphere::new(0.0), &probe, collider, transform) {
+            Some((d, p, s)) if d > EPSILON => (p - s) / d,
+            _ => Vec3::zero(),
+        };
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::body::{BodyType, RigidBody};
+    use super::super::collider::Cuboid;
+    use super::*;
+
+    fn add_box(world: &mut World, center: Vec3, half_extents: Vec3, rotation: Quat) {
+        let shape = Arc::new(RwLock::new(Cuboid::new(half_extents)));
+        let mut body = RigidBody::new(BodyType::Static, shape);
+        body.set_position(&center);
+        body.set_orientation(&rotation);
+        world.add_body(body);
+    }
+
+    fn create_world() -> World {
+        let mut world = World::new();
+        add_box(
+            &mut world,
+            Vec3::new(0.0, -1.0, 0.0),
+            Vec3::new(20.0, 1.0, 20.0),
+            Quat::one(),
+        );
+        return world;
+    }
+
+    fn walk(c: &mut Controller, world: &World, velocity: Vec3, seconds: Real) {
+        c.set_walk_velocity(&velocity);
+        for _ in 0..(seconds * 60.0) as usize {
+            c.update(world, 1.0 / 60.0);
+        }
+    }
+
+    fn create_controller(world: &World) -> Controller {
+        let mut c = Controller::new(0.4, 1.8);
+        c.set_position(&Vec3::new(0.0, 1.0, 0.0));
+        walk(&mut c, world, Vec3::zero(), 0.5);
+        return c;
+    }
+
+    #[test]
+    fn ground_test() {
+        let world = create_world();
+        let c = create_controller(&world);
+        assert!(c.is_on_ground());
+        assert!((c.get_position().y - 0.9).abs() < 0.02);
+        assert!(c.get_ground_normal().dot(Vec3::unit_y()) > 0.99);
+    }
+
+    #[test]
+    fn wall_test() {
+        let mut world = create_world();
+        add_box(
+            &mut world,
+            Vec3::new(3.0, 1.0, 0.0),
+            Vec3::new(0.5, 1.0, 5.0),
+            Quat::one(),
+        );
+        let mut c = create_controller(&world);
+        // it slides along the wall
+        walk(&mut c, &world, Vec3::new(3.0, 0.0, 1.0), 2.0);
+        let p = *c.get_position();
+        assert!((p.x - 2.1).abs() < 0.02);
+        assert!(p.z > 1.5);
+        assert!(c.is_on_ground());
+    }
+
+    #[test]
+    fn step_test() {
+        let mut world = create_world();
+        add_box(
+            &mut world,
+            Vec3::new(2.0, 0.1, 0.0),
+            Vec3::new(0.5, 0.1, 5.0),
+            Quat::one(),
+        );
+        add_box(
+            &mut world,
+            Vec3::new(-2.0, 0.5, 0.0),
+            Vec3::new(0.5, 0.5, 5.0),
+            Quat::one(),
+        );
+        let mut c = create_controller(&world);
+        walk(&mut c, &world, Vec3::new(1.0, 0.0, 0.0), 2.0);
+        let p = *c.get_position();
+        assert!((p.x - 2.0).abs() < 0.05);
+        assert!((p.y - 1.1).abs() < 0.02);
+        walk(&mut c, &world, Vec3::new(-1.0, 0.0, 0.0), 6.0);
+        let p = *c.get_position();
+        assert!((p.x + 1.1).abs() < 0.02);
+        assert!((p.y - 0.9).abs() < 0.02);
+        // higher than radius needs stepping
+        add_box(
+            &mut world,
+            Vec3::new(0.0, 0.225, 3.0),
+            Vec3::new(1.0, 0.225, 0.5),
+            Quat::one(),
+        );
+        let mut c = create_controller(&world);
+        walk(&mut c, &world, Vec3::new(0.0, 0.0, 1.0), 3.0);
+        assert!(c.get_position().z < 2.2);
+        c.step_offset = 0.5;
+        walk(&mut c, &world, Vec3::new(0.0, 0.0, 1.0), 1.0);
+        let p = *c.get_position();
+        assert!(p.z > 2.6);
+        assert!((p.y - 1.35).abs() < 0.02);
+    }
+
+    #[test]
+    fn slope_test() {
+        let mut world = create_world();
+        let gentle = Quat::from_angle_z(cgmath::Deg(20.0));
+        add_box(
+            &mut world,
+            Vec3::new(5.0, 0.0, 0.0),
+            Vec3::new(3.0, 0.5, 3.0),
+            gentle,
+        );
+        let steep = Quat::from_angle_z(cgmath::Deg(-60.0));
+        add_box(
+            &mut world,
+            Vec3::new(-5.0, 0.0, 0.0),
+            Vec3::new(3.0, 0.5, 3.0),
+            steep,
+        );
+        let mut c = create_controller(&world);
+        walk(&mut c, &world, Vec3::new(1.0, 0.0, 0.0), 6.0);
+        assert!(c.get_position().y > 1.5);
+        assert!(c.is_on_ground());
+        // it does not slide down on gentle slope
+        let p = *c.get_position();
+        walk(&mut c, &world, Vec3::zero(), 1.0);
+        assert!((c.get_position() - p).magnitude() < 0.01);
+        let mut c = create_controller(&world);
+        walk(&mut c, &world, Vec3::new(-1.0, 0.0, 0.0), 6.0);
+        assert!(c.get_position().y < 1.2);
+    }
+
+    #[test]
+    fn jump_test() {
+        let world = create_world();
+        let mut c = create_controller(&world);
+        assert!(c.jump());
+        assert!(!c.jump());
+        let mut max = 0.0;
+        for _ in 0..120 {
+            c.update(&world, 1.0 / 60.0);
+            max = c.get_position().y.max(max);
+        }
+        // v^2 / 2g
+        assert!((max - 0.9 - 25.0 / (2.0 * 9.81)).abs() < 0.1);
+        assert!(c.is_on_ground());
+        assert!((c.get_position().y - 0.9).abs() < 0.02);
+    }
+}
@@ -1,16 +1,81 @@
+use super::super::collision::aabb::Aabb3;
+use super::super::collision::tree::DynamicTree;
 use super::super::core::debug::Debug;
 use super::super::core::gx3d::Gx3DReader;
+use super::super::core::types::Real;
+use std::f32::MIN as F32MIN;
 use std::sync::{Arc, RwLock};
 
 use cgmath;
+use cgmath::prelude::*;
 
-pub trait Collider: Debug {}
+type Vec3 = cgmath::Vector3<Real>;
+type Mat4 = cgmath::Matrix4<Real>;
+
+const EPSILON: Real = 1e-12;
+
+// Shapes are in their local space, a transformation places them in world.
+// Support function returns the farthest point of shape in the given direction.
+pub trait Collider: Debug {
+    fn get_local_support(&self, &Vec3) -> Vec3;
+
+    fn get_support(&self, transform: &Mat4, direction: &Vec3) -> Vec3 {
+        let m = cgmath::Matrix3::from_cols(
+            transform.x.truncate(),
+            transform.y.truncate(),
+            transform.z.truncate(),
+        );
+        let d = m.transpose() * direction;
+        return (transform * self.get_local_support(&d).extend(1.0)).truncate();
+    }
+
+    fn get_aabb(&self, transform: &Mat4) -> Aabb3 {
+        let mut aabb = Aabb3::new();
+        for i in 0..3 {
+            let mut d = Vec3::new(0.0, 0.0, 0.0);
+            d[i] = 1.0;
+            aabb.insert(&self.get_support(transform, &d));
+            aabb.insert(&self.get_support(transform, &-d));
+        }
+        return aabb;
+    }
+
+    // Diagonal of local inertia tensor, by default the box around shape is used
+    fn get_inertia(&self, mass: Real) -> Vec3 {
+        let aabb = self.get_aabb(&Mat4::identity());
+        let d = aabb.get_max() - aabb.get_min();
+        return get_box_inertia(mass, &(d * 0.5));
+    }
+
+    // Non-convex colliders must be handled specially in narrowphase
+    fn to_mesh(&self) -> Option<&Mesh> {
+        return None;
+    }
+
+    fn to_compound(&self) -> Option<&Compound> {
+        return None;
+    }
+
+    fn to_ghost(&self) -> Option<&Ghost> {
+        return None;
+    }
+
+    fn is_ghost(&self) -> bool {
+        return false;
+    }
+}
 
 #[cfg_attr(debug_mode, derive(Debug))]
 #[repr(u8)]
 pub enum TypeId {
-    Mesh = 2,
     Ghost = 1,
+    Mesh = 2,
+    Sphere = 3,
+    Box = 4,
+    Capsule = 5,
+    Cylinder = 6,
+    ConvexHull = 7,
+    Compound = 8,
 }
 
 pub fn read(reader: &mut Gx3DReader) -> Arc<RwLock<Collider>> {
@@ -19,38 +84,495 @@ pub fn read(reader: &mut Gx3DReader) -> Arc<RwLock<Collider>> {
         return Arc::new(RwLock::new(Ghost::new()));
     } else if t == TypeId::Mesh as u8 {
         return Arc::new(RwLock::new(Mesh::new_with_gx3d(reader)));
+    } else if t == TypeId::Sphere as u8 {
+        return Arc::new(RwLock::new(Sphere::new_with_gx3d(reader)));
+    } else if t == TypeId::Box as u8 {
+        return Arc::new(RwLock::new(Cuboid::new_with_gx3d(reader)));
+    } else if t == TypeId::Capsule as u8 {
+        return Arc::new(RwLock::new(Capsule::new_with_gx3d(reader)));
+    } else if t == TypeId::Cylinder as u8 {
+        return Arc::new(RwLock::new(Cylinder::new_with_gx3d(reader)));
+    } else if t == TypeId::ConvexHull as u8 {
+        return Arc::new(RwLock::new(ConvexHull::new_with_gx3d(reader)));
+    } else if t == TypeId::Compound as u8 {
+        return Arc::new(RwLock::new(Compound::new_with_gx3d(reader)));
     } else {
         vxunexpected!();
     }
 }
 
+fn read_points(reader: &mut Gx3DReader) -> Vec<Vec3> {
+    let count: u64 = reader.read();
+    let mut points = vec![Vec3::new(0.0, 0.0, 0.0); count as usize];
+    for i in 0..count as usize {
+        points[i] = Vec3::new(reader.read(), reader.read(), reader.read());
+    }
+    return points;
+}
+
+fn get_farthest_point(points: &[Vec3], d: &Vec3) -> Vec3 {
+    let mut result = Vec3::new(0.0, 0.0, 0.0);
+    let mut max = F32MIN;
+    for p in points {
+        let dot = p.dot(*d);
+        if dot > max {
+            max = dot;
+            result = *p;
+        }
+    }
+    return result;
+}
+
+fn normalize_or_zero(v: Vec3) -> Vec3 {
+    let l2 = v.magnitude2();
+    if l2 < EPSILON {
+        return Vec3::new(0.0, 0.0, 0.0);
+    }
+    return v / l2.sqrt();
+}
+
+fn sign(v: Real) -> Real {
+    if v < 0.0 {
+        return -1.0;
+    }
+    return 1.0;
+}
+
+fn get_box_inertia(mass: Real, h: &Vec3) -> Vec3 {
+    let (x, y, z) = (h.x * h.x, h.y * h.y, h.z * h.z);
+    return Vec3::new(y + z, x + z, x + y) * (mass / 3.0);
+}
+
+fn get_cylinder_inertia(mass: Real, radius: Real, half_height: Real) -> Vec3 {
+    let r = radius * radius;
+    let side = mass * (3.0 * r + 4.0 * half_height * half_height) / 12.0;
+    return Vec3::new(side, 0.5 * mass * r, side);
+}
+
+// Triangles are kept in a bounding volume hierarchy for narrowphase queries,
+// vertices and indices must not change after creation.
+// Mesh against mesh is tested triangle by triangle, so contacts only come from crossing
+// triangles and a mesh that is completely inside another one is not detected. It is slow
+// too, use meshes for static bodies and convex or compound colliders for moving ones.
 #[cfg_attr(debug_mode, derive(Debug))]
 pub struct Mesh {
     pub vertices: Vec<cgmath::Vector3<f32>>,
     pub indices: Vec<u32>,
+    tree: DynamicTree<usize>,
 }
 
 impl Mesh {
-    pub fn new_with_gx3d(reader: &mut Gx3DReader) -> Self {
-        let count: u64 = reader.read();
-        let mut vertices = vec![cgmath::Vector3::new(0.0, 0.0, 0.0); count as usize];
-        for i in 0..count as usize {
-            vertices[i] = cgmath::Vector3::new(reader.read(), reader.read(), reader.read());
+    pub fn new(vertices: Vec<Vec3>, indices: Vec<u32>) -> Self {
+        let tree = Self::create_tree(&vertices, &indices);
+        Mesh {
+            vertices,
+            indices,
+            tree,
         }
+    }
+
+    pub fn new_with_gx3d(reader: &mut Gx3DReader) -> Self {
+        let vertices = read_points(reader);
         let indices = reader.read_array();
-        Mesh { vertices, indices }
+        Self::new(vertices, indices)
+    }
+
+    fn create_tree(vertices: &[Vec3], indices: &[u32]) -> DynamicTree<usize> {
+        let mut tree = DynamicTree::new(0.0);
+        for i in 0..(indices.len() / 3) {
+            let mut aabb = Aabb3::new();
+            for j in 0..3 {
+                aabb.insert(&vertices[indices[i * 3 + j] as usize]);
+            }
+            tree.insert(&aabb, i);
+        }
+        return tree;
+    }
+
+    pub fn get_triangle(&self, index: usize) -> [Vec3; 3] {
+        let i = index * 3;
+        return [
+            self.vertices[self.indices[i] as usize],
+            self.vertices[self.indices[i + 1] as usize],
+            self.vertices[self.indices[i + 2] as usize],
+        ];
+    }
+
+    pub fn get_triangles_count(&self) -> usize {
+        return self.indices.len() / 3;
+    }
+
+    // AABB must be in the local space of mesh
+    pub fn get_triangles_in_aabb(&self, aabb: &Aabb3) -> Vec<usize> {
+        let mut result = Vec::new();
+        self.tree.query_aabb(aabb, &mut |i: &usize| result.push(*i));
+        return result;
     }
 }
 
-impl Collider for Mesh {}
+// Mesh is not convex, its support is the support of its convex hull
+impl Collider for Mesh {
+    fn get_local_support(&self, d: &Vec3) -> Vec3 {
+        return get_farthest_point(&self.vertices, d);
+    }
+
+    fn to_mesh(&self) -> Option<&Mesh> {
+        return Some(self);
+    }
+}
 
+// Ghost does not collide with anything, if it has a volume it works as a trigger
+// and world reports the colliders that overlap the volume. GX3D ghosts do not have
+// a volume, bodies that are created for their models use the box around the model.
 #[cfg_attr(debug_mode, derive(Debug))]
-pub struct Ghost {}
+pub struct Ghost {
+    pub volume: Option<Arc<RwLock<Collider>>>,
+}
 
 impl Ghost {
     pub fn new() -> Self {
-        Ghost {}
+        Ghost { volume: None }
+    }
+
+    pub fn new_with_volume(volume: Arc<RwLock<Collider>>) -> Self {
+        Ghost {
+            volume: Some(volume),
+        }
+    }
+}
+
+impl Collider for Ghost {
+    fn get_local_support(&self, d: &Vec3) -> Vec3 {
+        if let Some(volume) = &self.volume {
+            return vxresult!(volume.read()).get_local_support(d);
+        }
+        return Vec3::new(0.0, 0.0, 0.0);
+    }
+
+    fn to_ghost(&self) -> Option<&Ghost> {
+        return Some(self);
+    }
+
+    fn is_ghost(&self) -> bool {
+        return true;
+    }
+}
+
+#[cfg_attr(debug_mode, derive(Debug))]
+pub struct Sphere {
+    pub radius: Real,
+}
+
+impl Sphere {
+    pub fn new(radius: Real) -> Self {
+        Sphere { radius }
+    }
+
+    pub fn new_with_gx3d(reader: &mut Gx3DReader) -> Self {
+        Sphere {
+            radius: reader.read(),
+        }
+    }
+}
+
+impl Collider for Sphere {
+    fn get_local_support(&self, d: &Vec3) -> Vec3 {
+        return normalize_or_zero(*d) * self.radius;
+    }
+
+    fn get_inertia(&self, mass: Real) -> Vec3 {
+        let i = 0.4 * mass * self.radius * self.radius;
+        return Vec3::new(i, i, i);
     }
 }
 
-impl Collider for Ghost {}
+// Box shape
+#[cfg_attr(debug_mode, derive(Debug))]
+pub struct Cuboid {
+    pub half_extents: Vec3,
+}
+
+impl Cuboid {
+    pub fn new(half_extents: Vec3) -> Self {
+        Cuboid { half_extents }
+    }
+
+    pub fn new_with_gx3d(reader: &mut Gx3DReader) -> Self {
+        Cuboid {
+            half_extents: Vec3::new(reader.read(), reader.read(), reader.read()),
+        }
+    }
+}
+
+impl Collider for Cuboid {
+    fn get_local_support(&self, d: &Vec3) -> Vec3 {
+        let h = &self.half_extents;
+        return Vec3::new(sign(d.x) * h.x, sign(d.y) * h.y, sign(d.z) * h.z);
+    }
+
+    fn get_inertia(&self, mass: Real) -> Vec3 {
+        return get_box_inertia(mass, &self.half_extents);
+    }
+}
+
+// Its axis is local y
+#[cfg_attr(debug_mode, derive(Debug))]
+pub struct Capsule {
+    pub radius: Real,
+    pub half_height: Real,
+}
+
+impl Capsule {
+    pub fn new(radius: Real, half_height: Real) -> Self {
+        Capsule {
+            radius,
+            half_height,
+        }
+    }
+
+    pub fn new_with_gx3d(reader: &mut Gx3DReader) -> Self {
+        Capsule {
+            radius: reader.read(),
+            half_height: reader.read(),
+        }
+    }
+}
+
+impl Collider for Capsule {
+    fn get_local_support(&self, d: &Vec3) -> Vec3 {
+        let mut s = normalize_or_zero(*d) * self.radius;
+        s.y += sign(d.y) * self.half_height;
+        return s;
+    }
+
+    // Approximated with a cylinder of the whole height
+    fn get_inertia(&self, mass: Real) -> Vec3 {
+        return get_cylinder_inertia(mass, self.radius, self.half_height + self.radius);
+    }
+}
+
+// Its axis is local y
+#[cfg_attr(debug_mode, derive(Debug))]
+pub struct Cylinder {
+    pub radius: Real,
+    pub half_height: Real,
+}
+
+impl Cylinder {
+    pub fn new(radius: Real, half_height: Real) -> Self {
+        Cylinder {
+            radius,
+            half_height,
+        }
+    }
+
+    pub fn new_with_gx3d(reader: &mut Gx3DReader) -> Self {
+        Cylinder {
+            radius: reader.read(),
+            half_height: reader.read(),
+        }
+    }
+}
+
+impl Collider for Cylinder {
+    fn get_local_support(&self, d: &Vec3) -> Vec3 {
+        let mut s = normalize_or_zero(Vec3::new(d.x, 0.0, d.z)) * self.radius;
+        s.y = sign(d.y) * self.half_height;
+        return s;
+    }
+
+    fn get_inertia(&self, mass: Real) -> Vec3 {
+        return get_cylinder_inertia(mass, self.radius, self.half_height);
+    }
+}
+
+// Points do not need to be only the hull vertices, inner points are ignored by support
+#[cfg_attr(debug_mode, derive(Debug))]
+pub struct ConvexHull {
+    pub points: Vec<Vec3>,
+}
+
+impl ConvexHull {
+    pub fn new(points: Vec<Vec3>) -> Self {
+        ConvexHull { points }
+    }
+
+    pub fn new_with_gx3d(reader: &mut Gx3DReader) -> Self {
+        ConvexHull {
+            points: read_points(reader),
+        }
+    }
+}
+
+impl Collider for ConvexHull {
+    fn get_local_support(&self, d: &Vec3) -> Vec3 {
+        return get_farthest_point(&self.points, d);
+    }
+}
+
+// Used for testing triangles of mesh colliders against convex colliders
+#[cfg_attr(debug_mode, derive(Debug))]
+pub struct Triangle {
+    pub points: [Vec3; 3],
+}
+
+impl Triangle {
+    pub fn new(points: [Vec3; 3]) -> Self {
+        Triangle { points }
+    }
+}
+
+impl Collider for Triangle {
+    fn get_local_support(&self, d: &Vec3) -> Vec3 {
+        return get_farthest_point(&self.points, d);
+    }
+}
+
+// Each child has a local offset transformation,
+// support of compound is the support of convex hull of all children.
+#[cfg_attr(debug_mode, derive(Debug))]
+pub struct Compound {
+    pub children: Vec<(Mat4, Arc<RwLock<Collider>>)>,
+}
+
+impl Compound {
+    pub fn new() -> Self {
+        Compound {
+            children: Vec::new(),
+        }
+    }
+
+    pub fn new_with_gx3d(reader: &mut Gx3DReader) -> Self {
+        let count: u64 = reader.read();
+        let mut children = Vec::with_capacity(count as usize);
+        for _ in 0..count {
+            let offset = Mat4::new(
+                reader.read(),
+                reader.read(),
+                reader.read(),
+                reader.read(),
+                reader.read(),
+                reader.read(),
+                reader.read(),
+                reader.read(),
+                reader.read(),
+                reader.read(),
+                reader.read(),
+                reader.read(),
+                reader.read(),
+                reader.read(),
+                reader.read(),
+                reader.read(),
+            );
+            children.push((offset, read(reader)));
+        }
+        Compound { children }
+    }
+
+    pub fn add_child(&mut self, offset: Mat4, child: Arc<RwLock<Collider>>) {
+        self.children.push((offset, child));
+    }
+}
+
+impl Collider for Compound {
+    fn get_local_support(&self, d: &Vec3) -> Vec3 {
+        return self.get_support(&Mat4::identity(), d);
+    }
+
+    fn to_compound(&self) -> Option<&Compound> {
+        return Some(self);
+    }
+
+    fn get_support(&self, transform: &Mat4, direction: &Vec3) -> Vec3 {
+        let mut result = (transform * Vec3::new(0.0, 0.0, 0.0).extend(1.0)).truncate();
+        let mut max = F32MIN;
+        for (offset, child) in &self.children {
+            let s = vxresult!(child.read()).get_support(&(transform * offset), direction);
+            let dot = s.dot(*direction);
+            if dot > max {
+                max = dot;
+                result = s;
+            }
+        }
+        return result;
+    }
+
+    fn get_aabb(&self, transform: &Mat4) -> Aabb3 {
+        let mut aabb = Aabb3::new();
+        for (offset, child) in &self.children {
+            aabb.insert_aabb(&vxresult!(child.read()).get_aabb(&(transform * offset)));
+        }
+        if self.children.len() == 0 {
+            aabb.insert(&transform.w.truncate());
+        }
+        return aabb;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn near(a: Vec3, b: Vec3) -> bool {
+        return (a - b).magnitude() < 1e-4;
+    }
+
+    #[test]
+    fn support_test() {
+        let d = Vec3::new(1.0, 1.0, 0.0);
+        let s = Sphere::new(2.0);
+        assert!(near(
+            s.get_local_support(&d),
+            Vec3::new(1.414213562, 1.414213562, 0.0)
+        ));
+        let b = Cuboid::new(Vec3::new(1.0, 2.0, 3.0));
+        assert!(near(b.get_local_support(&d), Vec3::new(1.0, 2.0, 3.0)));
+        let c = Capsule::new(1.0, 2.0);
+        assert!(near(
+            c.get_local_support(&Vec3::new(1.0, -1.0, 0.0)),
+            Vec3::new(0.707106781, -2.707106781, 0.0)
+        ));
+        let c = Cylinder::new(1.0, 2.0);
+        assert!(near(
+            c.get_local_support(&Vec3::new(1.0, -1.0, 0.0)),
+            Vec3::new(1.0, -2.0, 0.0)
+        ));
+        let h = ConvexHull::new(vec![
+            Vec3::new(0.0, 0.0, 0.0),
+            Vec3::new(1.0, 0.0, 0.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            Vec3::new(0.2, 0.2, 0.0),
+        ]);
+        assert!(near(
+            h.get_local_support(&Vec3::new(-1.0, 2.0, 0.0)),
+            Vec3::new(0.0, 1.0, 0.0)
+        ));
+    }
+
+    #[test]
+    fn transformed_test() {
+        let m = Mat4::from_translation(Vec3::new(5.0, 0.0, 0.0))
+            * Mat4::from_angle_z(cgmath::Rad(1.570796327 as Real));
+        // capsule axis gets along -x
+        let c = Capsule::new(1.0, 2.0);
+        let aabb = c.get_aabb(&m);
+        assert!(near(aabb.get_min(), Vec3::new(2.0, -1.0, -1.0)));
+        assert!(near(aabb.get_max(), Vec3::new(8.0, 1.0, 1.0)));
+        let s = c.get_support(&m, &Vec3::new(1.0, 0.0, 0.0));
+        assert!(near(s, Vec3::new(8.0, 0.0, 0.0)));
+        let mut compound = Compound::new();
+        compound.add_child(
+            Mat4::from_translation(Vec3::new(0.0, 3.0, 0.0)),
+            Arc::new(RwLock::new(Sphere::new(1.0))),
+        );
+        compound.add_child(
+            Mat4::identity(),
+            Arc::new(RwLock::new(Cuboid::new(Vec3::new(1.0, 1.0, 1.0)))),
+        );
+        let aabb = compound.get_aabb(&m);
+        assert!(near(aabb.get_min(), Vec3::new(1.0, -1.0, -1.0)));
+        assert!(near(aabb.get_max(), Vec3::new(6.0, 1.0, 1.0)));
+        let s = compound.get_support(&m, &Vec3::new(-1.0, 0.0, 0.0));
+        assert!(near(s, Vec3::new(1.0, 0.0, 0.0)));
+    }
+}
@@ -0,0 +1,430 @@
+use super::super::core::types::Real;
+use super::collider::Collider;
+use std::f32::MAX as F32MAX;
+
+use cgmath;
+use cgmath::prelude::*;
+
+type Vec3 = cgmath::Vector3<Real>;
+type Mat4 = cgmath::Matrix4<Real>;
+
+const MAX_ITERATIONS: usize = 64;
+const OVERLAP_EPSILON: Real = 1e-10;
+const CONVERGENCE_EPSILON: Real = 1e-6;
+const EPA_TOLERANCE: Real = 1e-4;
+const DEGENERACY_EPSILON: Real = 1e-10;
+
+// A point of Minkowski difference (A - B) with the support points that made it
+#[derive(Clone, Copy)]
+#[cfg_attr(debug_mode, derive(Debug))]
+pub(super) struct SupportPoint {
+    pub v: Vec3,
+    pub a: Vec3,
+    pub b: Vec3,
+}
+
+pub(super) struct Pair<'a> {
+    a: &'a Collider,
+    ta: &'a Mat4,
+    b: &'a Collider,
+    tb: &'a Mat4,
+}
+
+impl<'a> Pair<'a> {
+    pub fn new(a: &'a Collider, ta: &'a Mat4, b: &'a Collider, tb: &'a Mat4) -> Self {
+        Self { a, ta, b, tb }
+    }
+
+    fn support(&self, d: &Vec3) -> SupportPoint {
+        let a = self.a.get_support(self.ta, d);
+        let b = self.b.get_support(self.tb, &-*d);
+        SupportPoint { v: a - b, a, b }
+    }
+}
+
+#[cfg_attr(debug_mode, derive(Debug))]
+pub(super) enum GjkResult {
+    Overlap(Vec<SupportPoint>),
+    // distance, point on A, point on B
+    Separated(Real, Vec3, Vec3),
+}
+
+// Penetration of two shapes, normal is from A to B
+#[cfg_attr(debug_mode, derive(Debug))]
+pub(super) struct Penetration {
+    pub normal: Vec3,
+    pub depth: Real,
+    pub point_a: Vec3,
+    pub point_b: Vec3,
+}
+
+// Closest point of simplex to origin, simplex gets reduced to the smallest
+// feature that contains the closest point and weights are barycentric coordinates.
+// For a tetrahedron that contains origin it returns None.
+fn solve_simplex(simplex: &mut Vec<SupportPoint>, weights: &mut Vec<Real>) -> Option<Vec3> {
+    let features: Vec<(usize, Real)> = match simplex.len() {
+        1 => vec![(0, 1.0)],
+        2 => closest_on_segment(simplex[0].v, simplex[1].v, 0, 1),
+        3 => closest_on_triangle(&[simplex[0].v, simplex[1].v, simplex[2].v], [0, 1, 2]),
+        4 => match closest_on_tetrahedron(simplex) {
+            Some(f) => f,
+            None => return None,
+        },
+        _ => vxunexpected!(),
+    };
+    let mut reduced = Vec::with_capacity(features.len());
+    weights.clear();
+    let mut closest = Vec3::new(0.0, 0.0, 0.0);
+    for (i, w) in features {
+        reduced.push(simplex[i]);
+        weights.push(w);
+        closest += simplex[i].v * w;
+    }
+    *simplex = reduced;
+    return Some(closest);
+}
+
+fn closest_on_segment(a: Vec3, b: Vec3, ia: usize, ib: usize) -> Vec<(usize, Real)> {
+    let ab = b - a;
+    let l2 = ab.magnitude2();
+    if l2 < DEGENERACY_EPSILON {
+        return vec![(ia, 1.0)];
+    }
+    let t = -a.dot(ab) / l2;
+    if t <= 0.0 {
+        return vec![(ia, 1.0)];
+    }
+    if t >= 1.0 {
+        return vec![(ib, 1.0)];
+    }
+    return vec![(ia, 1.0 - t), (ib, t)];
+}
+
+fn closest_on_triangle(p: &[Vec3; 3], ids: [usize; 3]) -> Vec<(usize, Real)> {
+    let (a, b, c) = (p[0], p[1], p[2]);
+    let ab = b - a;
+    let ac = c - a;
+    let d1 = ab.dot(-a);
+    let d2 = ac.dot(-a);
+    if d1 <= 0.0 && d2 <= 0.0 {
+        return vec![(ids[0], 1.0)];
+    }
+    let d3 = ab.dot(-b);
+    let d4 = ac.dot(-b);
+    if d3 >= 0.0 && d4 <= d3 {
+        return vec![(ids[1], 1.0)];
+    }
+    let vc = d1 * d4 - d3 * d2;
+    if vc <= 0.0 && d1 >= 0.0 && d3 <= 0.0 {
+        let v = d1 / (d1 - d3);
+        return vec![(ids[0], 1.0 - v), (ids[1], v)];
+    }
+    let d5 = ab.dot(-c);
+    let d6 = ac.dot(-c);
+    if d6 >= 0.0 && d5 <= d6 {
+        return vec![(ids[2], 1.0)];
+    }
+    let vb = d5 * d2 - d1 * d6;
+    if vb <= 0.0 && d2 >= 0.0 && d6 <= 0.0 {
+        let w = d2 / (d2 - d6);
+        return vec![(ids[0], 1.0 - w), (ids[2], w)];
+    }
+    let va = d3 * d6 - d5 * d4;
+    if va <= 0.0 && (d4 - d3) >= 0.0 && (d5 - d6) >= 0.0 {
+        let w = (d4 - d3) / ((d4 - d3) + (d5 - d6));
+        return vec![(ids[1], 1.0 - w), (ids[2], w)];
+    }
+    let sum = va + vb + vc;
+    if sum.abs() < DEGENERACY_EPSILON {
+        // degenerate triangle, its closest edge is the answer
+        let mut best = closest_on_segment(a, b, ids[0], ids[1]);
+        let mut best_d = get_distance2(p, &ids, &best);
+        for &(i, j) in &[(0, 2), (1, 2)] {
+            let f = closest_on_segment(p[i], p[j], ids[i], ids[j]);
+            let d = get_distance2(p, &ids, &f);
+            if d < best_d {
+                best_d = d;
+                best = f;
+            }
+        }
+        return best;
+    }
+    let v = vb / sum;
+    let w = vc / sum;
+    return vec![(ids[0], 1.0 - v - w), (ids[1], v), (ids[2], w)];
+}
+
+fn get_distance2(p: &[Vec3; 3], ids: &[usize; 3], f: &[(usize, Real)]) -> Real {
+    let mut c = Vec3::new(0.0, 0.0, 0.0);
+    for &(i, w) in f {
+        for j in 0..3 {
+            if ids[j] == i {
+                c += p[j] * w;
+            }
+        }
+    }
+    return c.magnitude2();
+}
+
+fn closest_on_tetrahedron(s: &[SupportPoint]) -> Option<Vec<(usize, Real)>> {
+    const FACES: [[usize; 4]; 4] = [[0, 1, 2, 3], [0, 1, 3, 2], [0, 2, 3, 1], [1, 2, 3, 0]];
+    let mut result = None;
+    let mut best_d = F32MAX;
+    for f in &FACES {
+        let (a, b, c, d) = (s[f[0]].v, s[f[1]].v, s[f[2]].v, s[f[3]].v);
+        let n = (b - a).cross(c - a);
+        let sign_o = n.dot(-a);
+        let sign_d = n.dot(d - a);
+        if sign_o * sign_d >= 0.0 && sign_d.abs() > DEGENERACY_EPSILON {
+            continue;
+        }
+        let p = [a, b, c];
+        let ids = [f[0], f[1], f[2]];
+        let feature = closest_on_triangle(&p, ids);
+        let dist = get_distance2(&p, &ids, &feature);
+        if dist < best_d {
+            best_d = dist;
+            result = Some(feature);
+        }
+    }
+    return result;
+}
+
+pub(super) fn gjk(pair: &Pair) -> GjkResult {
+    let mut v = pair.tb.w.truncate() - pair.ta.w.truncate();
+    if v.magnitude2() < OVERLAP_EPSILON {
+        v = Vec3::new(1.0, 0.0, 0.0);
+    }
+    let mut simplex = vec![pair.support(&-v)];
+    let mut weights = vec![1.0];
+    v = simplex[0].v;
+    for _ in 0..MAX_ITERATIONS {
+        let vv = v.magnitude2();
+        if vv < OVERLAP_EPSILON {
+            return GjkResult::Overlap(simplex);
+        }
+        let w = pair.support(&-v);
+        if vv - v.dot(w.v) <= CONVERGENCE_EPSILON * vv {
+            break;
+        }
+        let mut repeated = false;
+        for s in &simplex {
+            if (s.v - w.v).magnitude2() < OVERLAP_EPSILON {
+                repeated = true;
+            }
+        }
+        if repeated {
+            break;
+        }
+        let mut new_simplex = simplex.clone();
+        new_simplex.push(w);
+        let mut new_weights = Vec::with_capacity(4);
+        let closest = match solve_simplex(&mut new_simplex, &mut new_weights) {
+            Some(c) => c,
+            None => return GjkResult::Overlap(new_simplex),
+        };
+        if closest.magnitude2() >= vv {
+            break;
+        }
+        simplex = new_simplex;
+        weights = new_weights;
+        v = closest;
+    }
+    let mut point_a = Vec3::new(0.0, 0.0, 0.0);
+    let mut point_b = Vec3::new(0.0, 0.0, 0.0);
+    for i in 0..simplex.len() {
+        point_a += simplex[i].a * weights[i];
+        point_b += simplex[i].b * weights[i];
+    }
+    return GjkResult::Separated(v.magnitude(), point_a, point_b);
+}
+
+// Makes a tetrahedron from the simplex of touching shapes
+fn blow_up(pair: &Pair, simplex: &mut Vec<SupportPoint>) -> bool {
+    if simplex.len() == 1 {
+        let axes = [
+            Vec3::new(1.0, 0.0, 0.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            Vec3::new(0.0, 0.0, 1.0),
+        ];
+        for a in &axes {
+            for d in &[*a, -*a] {
+                let p = pair.support(d);
+                if (p.v - simplex[0].v).magnitude2() > DEGENERACY_EPSILON {
+                    simplex.push(p);
+                    break;
+                }
+            }
+            if simplex.len() == 2 {
+                break;
+            }
+        }
+        if simplex.len() < 2 {
+            return false;
+        }
+    }
+    if simplex.len() == 2 {
+        let l = (simplex[1].v - simplex[0].v).normalize();
+        let axis = if l.x.abs() < 0.57 {
+            Vec3::new(1.0, 0.0, 0.0)
+        } else if l.y.abs() < 0.57 {
+            Vec3::new(0.0, 1.0, 0.0)
+        } else {
+            Vec3::new(0.0, 0.0, 1.0)
+        };
+        let mut d = l.cross(axis);
+        let r = cgmath::Matrix3::from_axis_angle(l, cgmath::Deg(60.0 as Real));
+        for _ in 0..6 {
+            let p = pair.support(&d);
+            let e = p.v - simplex[0].v;
+            if (e - l * e.dot(l)).magnitude2() > DEGENERACY_EPSILON {
+                simplex.push(p);
+                break;
+            }
+            d = r * d;
+        }
+        if simplex.len() < 3 {
+            return false;
+        }
+    }
+    if simplex.len() == 3 {
+        let n = (simplex[1].v - simplex[0].v).cross(simplex[2].v - simplex[0].v);
+        let mut p = pair.support(&n);
+        if (p.v - simplex[0].v).dot(n).abs() < DEGENERACY_EPSILON {
+            p = pair.support(&-n);
+        }
+        if (p.v - simplex[0].v).dot(n).abs() < DEGENERACY_EPSILON {
+            return false;
+        }
+        simplex.push(p);
+    }
+    return true;
+}
+
+struct Face {
+    ids: [usize; 3],
+    normal: Vec3,
+    distance: Real,
+}
+
+fn create_face(points: &[SupportPoint], i: usize, j: usize, k: usize) -> Face {
+    let n = (points[j].v - points[i].v).cross(points[k].v - points[i].v);
+    let l = n.magnitude();
+    if l < DEGENERACY_EPSILON {
+        return Face {
+            ids: [i, j, k],
+            normal: Vec3::new(0.0, 0.0, 0.0),
+            distance: F32MAX,
+        };
+    }
+    let normal = n / l;
+    Face {
+        ids: [i, j, k],
+        normal,
+        distance: normal.dot(points[i].v),
+    }
+}
+
+fn get_closest_face(faces: &[Face]) -> usize {
+    let mut closest = 0;
+    for i in 1..faces.len() {
+        if faces[i].distance < faces[closest].distance {
+            closest = i;
+        }
+    }
+    return closest;
+}
+
+// Expanding polytope algorithm, it needs the simplex of an overlap in GJK
+pub(super) fn epa(pair: &Pair, simplex: Vec<SupportPoint>) -> Option<Penetration> {
+    let mut points = simplex;
+    if !blow_up(pair, &mut points) {
+        return None;
+    }
+    let mut faces = Vec::new();
+    for &(i, j, k, l) in &[(0, 1, 2, 3), (0, 3, 1, 2), (0, 2, 3, 1), (1, 3, 2, 0)] {
+        let n = (points[j].v - points[i].v).cross(points[k].v - points[i].v);
+        if n.dot(points[l].v - points[i].v) > 0.0 {
+            faces.push(create_face(&points, i, k, j));
+        } else {
+            faces.push(create_face(&points, i, j, k));
+        }
+    }
+    for _ in 0..MAX_ITERATIONS {
+        let closest = get_closest_face(&faces);
+        let normal = faces[closest].normal;
+        let distance = faces[closest].distance;
+        let w = pair.support(&normal);
+        if w.v.dot(normal) - distance < EPA_TOLERANCE {
+            break;
+        }
+        let wi = points.len();
+        points.push(w);
+        let mut edges: Vec<(usize, usize)> = Vec::new();
+        let mut i = 0;
+        while i < faces.len() {
+            let f = &faces[i];
+            if f.normal.dot(w.v - points[f.ids[0]].v) > 0.0 {
+                for e in 0..3 {
+                    let edge = (f.ids[e], f.ids[(e + 1) % 3]);
+                    // shared edges between removed faces are not on horizon
+                    if let Some(pos) = edges.iter().position(|x| *x == (edge.1, edge.0)) {
+                        edges.swap_remove(pos);
+                    } else {
+                        edges.push(edge);
+                    }
+                }
+                faces.swap_remove(i);
+            } else {
+                i += 1;
+            }
+        }
+        if edges.len() == 0 {
+            break;
+        }
+        for (a, b) in edges {
+            faces.push(create_face(&points, a, b, wi));
+        }
+    }
+    let f = &faces[get_closest_face(&faces)];
+    if f.distance == F32MAX {
+        return None;
+    }
+    let p = [
+        points[f.ids[0]].v - f.normal * f.distance,
+        points[f.ids[1]].v - f.normal * f.distance,
+        points[f.ids[2]].v - f.normal * f.distance,
+    ];
+    let bary = get_barycentric(&p);
+    let mut point_a = Vec3::new(0.0, 0.0, 0.0);
+    let mut point_b = Vec3::new(0.0, 0.0, 0.0);
+    for i in 0..3 {
+        point_a += points[f.ids[i]].a * bary[i];
+        point_b += points[f.ids[i]].b * bary[i];
+    }
+    return Some(Penetration {
+        normal: f.normal,
+        depth: f.distance.max(0.0),
+        point_a,
+        point_b,
+    });
+}
+
+// Barycentric coordinates of origin in the triangle that contains it
+fn get_barycentric(p: &[Vec3; 3]) -> [Real; 3] {
+    let v0 = p[1] - p[0];
+    let v1 = p[2] - p[0];
+    let v2 = -p[0];
+    let d00 = v0.dot(v0);
+    let d01 = v0.dot(v1);
+    let d11 = v1.dot(v1);
+    let d20 = v2.dot(v0);
+    let d21 = v2.dot(v1);
+    let denom = d00 * d11 - d01 * d01;
+    if denom.abs() < DEGENERACY_EPSILON {
+        return [1.0, 0.0, 0.0];
+    }
+    let v = (d11 * d20 - d01 * d21) / denom;
+    let w = (d00 * d21 - d01 * d20) / denom;
+    return [1.0 - v - w, v, w];
+}
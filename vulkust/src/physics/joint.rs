@@ -0,0 +1,630 @@
+use super::super::core::gx3d::{Gx3DReader, Table as Gx3dTable};
+use super::super::core::object::{create_id, Object as CoreObject};
+use super::super::core::types::{Id, Real};
+use super::body::RigidBody;
+use super::solver::{get_tangents, Row, SolverBody};
+use std::collections::BTreeMap;
+use std::f32::MAX as F32MAX;
+use std::sync::{Arc, RwLock, Weak};
+
+use cgmath;
+use cgmath::prelude::*;
+
+type Vec3 = cgmath::Vector3<Real>;
+type Mat3 = cgmath::Matrix3<Real>;
+type Quat = cgmath::Quaternion<Real>;
+
+// Fraction of error that is fixed in each step by velocity solver
+const BAUMGARTE: Real = 0.2;
+// Errors smaller than this are not fixed by position solver
+const LINEAR_SLOP: Real = 0.001;
+const MAX_LINEAR_CORRECTION: Real = 0.2;
+
+#[cfg_attr(debug_mode, derive(Debug))]
+#[repr(u8)]
+pub enum TypeId {
+    Fixed = 1,
+    Hinge = 2,
+    Ball = 3,
+    Slider = 4,
+    Distance = 5,
+}
+
+// Angles are in radian, and positions are in the unit of world
+#[derive(Clone, Copy)]
+#[cfg_attr(debug_mode, derive(Debug))]
+pub struct Limit {
+    pub lower: Real,
+    pub upper: Real,
+}
+
+impl Limit {
+    fn new_with_gx3d(reader: &mut Gx3DReader) -> Option<Self> {
+        if !reader.read_bool() {
+            return None;
+        }
+        return Some(Limit {
+            lower: reader.read(),
+            upper: reader.read(),
+        });
+    }
+}
+
+// Drives the joint with speed, without applying more than the max force (torque for hinge)
+#[derive(Clone, Copy)]
+#[cfg_attr(debug_mode, derive(Debug))]
+pub struct Motor {
+    pub speed: Real,
+    pub max_force: Real,
+}
+
+impl Motor {
+    fn new_with_gx3d(reader: &mut Gx3DReader) -> Option<Self> {
+        if !reader.read_bool() {
+            return None;
+        }
+        return Some(Motor {
+            speed: reader.read(),
+            max_force: reader.read(),
+        });
+    }
+}
+
+#[derive(Clone, Copy)]
+#[cfg_attr(debug_mode, derive(Debug))]
+pub enum Type {
+    // Keeps the relative location and orientation
+    Fixed,
+    // Rotates around the axis
+    Hinge {
+        limit: Option<Limit>,
+        motor: Option<Motor>,
+    },
+    // Rotates freely around the anchor
+    Ball,
+    // Moves along the axis without rotation
+    Slider {
+        limit: Option<Limit>,
+        motor: Option<Motor>,
+    },
+    // Keeps the distance of anchors in the range
+    Distance {
+        min: Real,
+        max: Real,
+    },
+}
+
+// Anchors and axes are in the local space of bodies.
+// Ends can be either the ids of bodies or the ids of models that are linked to bodies.
+#[derive(Clone)]
+#[cfg_attr(debug_mode, derive(Debug))]
+pub struct Joint {
+    id: Id,
+    pub joint_type: Type,
+    pub body_a: Id,
+    pub body_b: Id,
+    pub anchor_a: Vec3,
+    pub anchor_b: Vec3,
+    pub axis_a: Vec3,
+    pub axis_b: Vec3,
+    pub enabled: bool,
+    // Relative orientation and perpendicular of axes at zero angle,
+    // they are set when joint is added to the world.
+    reference_orientation: Quat,
+    reference_a: Vec3,
+    reference_b: Vec3,
+}
+
+impl Joint {
+    pub fn new(joint_type: Type, body_a: Id, anchor_a: &Vec3, body_b: Id, anchor_b: &Vec3) -> Self {
+        Self::new_with_id(create_id(), joint_type, body_a, anchor_a, body_b, anchor_b)
+    }
+
+    fn new_with_id(
+        id: Id,
+        joint_type: Type,
+        body_a: Id,
+        anchor_a: &Vec3,
+        body_b: Id,
+        anchor_b: &Vec3,
+    ) -> Self {
+        Joint {
+            id,
+            joint_type,
+            body_a,
+            body_b,
+            anchor_a: *anchor_a,
+            anchor_b: *anchor_b,
+            axis_a: Vec3::unit_y(),
+            axis_b: Vec3::unit_y(),
+            enabled: true,
+            reference_orientation: Quat::one(),
+            reference_a: Vec3::unit_x(),
+            reference_b: Vec3::unit_x(),
+        }
+    }
+
+    pub fn new_with_gx3d(reader: &mut Gx3DReader, my_id: Id) -> Self {
+        let t = reader.read_type_id();
+        let body_a = reader.read();
+        let body_b = reader.read();
+        let anchor_a = read_vector(reader);
+        let anchor_b = read_vector(reader);
+        let axis_a = read_vector(reader);
+        let axis_b = read_vector(reader);
+        let joint_type = if t == TypeId::Fixed as u8 {
+            Type::Fixed
+        } else if t == TypeId::Hinge as u8 {
+            let limit = Limit::new_with_gx3d(reader);
+            let motor = Motor::new_with_gx3d(reader);
+            Type::Hinge { limit, motor }
+        } else if t == TypeId::Ball as u8 {
+            Type::Ball
+        } else if t == TypeId::Slider as u8 {
+            let limit = Limit::new_with_gx3d(reader);
+            let motor = Motor::new_with_gx3d(reader);
+            Type::Slider { limit, motor }
+        } else if t == TypeId::Distance as u8 {
+            Type::Distance {
+                min: reader.read(),
+                max: reader.read(),
+            }
+        } else {
+            vxunexpected!()
+        };
+        let mut joint = Self::new_with_id(my_id, joint_type, body_a, &anchor_a, body_b, &anchor_b);
+        joint.axis_a = axis_a.normalize();
+        joint.axis_b = axis_b.normalize();
+        return joint;
+    }
+
+    // Current state of bodies becomes the rest state of joint
+    pub(super) fn initialize(&mut self, a: &RigidBody, b: &RigidBody) {
+        let qa = a.get_orientation();
+        let qb = b.get_orientation();
+        self.reference_orientation = qa.invert() * qb;
+        let axis = qa * self.axis_a;
+        let (reference, _) = get_tangents(&axis);
+        self.reference_a = qa.invert() * reference;
+        self.reference_b = qb.invert() * reference;
+    }
+
+    // Angle of hinge around its axis, relative to the angle that it had when added to world
+    pub fn get_angle(&self, a: &RigidBody, b: &RigidBody) -> Real {
+        let axis = a.get_orientation() * self.axis_a;
+        let ra = a.get_orientation() * self.reference_a;
+        let rb = b.get_orientation() * self.reference_b;
+        return ra.cross(rb).dot(axis).atan2(ra.dot(rb));
+    }
+
+    // Position of B anchor along the slider axis from the A anchor
+    pub fn get_translation(&self, a: &RigidBody, b: &RigidBody) -> Real {
+        let axis = a.get_orientation() * self.axis_a;
+        let pa = a.get_position() + a.get_orientation() * self.anchor_a;
+        let pb = b.get_position() + b.get_orientation() * self.anchor_b;
+        return (pb - pa).dot(axis);
+    }
+
+    pub(super) fn create_rows(
+        &self,
+        a: &RigidBody,
+        b: &RigidBody,
+        ai: usize,
+        bi: usize,
+        bodies: &[SolverBody],
+        dt: Real,
+        rows: &mut Vec<Row>,
+    ) {
+        let ra = a.get_orientation() * self.anchor_a;
+        let rb = b.get_orientation() * self.anchor_b;
+        let d = b.get_position() + rb - a.get_position() - ra;
+        let bias = BAUMGARTE / dt;
+        let point = |rows: &mut Vec<Row>| {
+            for i in 0..3 {
+                let mut e = Vec3::zero();
+                e[i] = 1.0;
+                rows.push(Row::new_linear(
+                    ai,
+                    bi,
+                    &ra,
+                    &rb,
+                    &e,
+                    -bias * d[i],
+                    -F32MAX,
+                    F32MAX,
+                    bodies,
+                ));
+            }
+        };
+        let lock_rotation = |rows: &mut Vec<Row>| {
+            let target = a.get_orientation() * self.reference_orientation;
+            let error = b.get_orientation() * target.invert();
+            let error = if error.s < 0.0 { -error } else { error };
+            let error = error.v * 2.0;
+            for i in 0..3 {
+                let mut e = Vec3::zero();
+                e[i] = 1.0;
+                rows.push(Row::new_angular(
+                    ai,
+                    bi,
+                    &e,
+                    -bias * error[i],
+                    -F32MAX,
+                    F32MAX,
+                    bodies,
+                ));
+            }
+        };
+        // limit and motor along a direction, value is the current angle or translation
+        let drive = |rows: &mut Vec<Row>,
+                     limit: &Option<Limit>,
+                     motor: &Option<Motor>,
+                     value: Real,
+                     linear: bool,
+                     direction: &Vec3| {
+            let row = |target: Real, lower: Real, upper: Real| {
+                if linear {
+                    Row::new_linear(
+                        ai,
+                        bi,
+                        &(ra + d),
+                        &rb,
+                        direction,
+                        target,
+                        lower,
+                        upper,
+                        bodies,
+                    )
+                } else {
+                    Row::new_angular(ai, bi, direction, target, lower, upper, bodies)
+                }
+            };
+            if let Some(m) = motor {
+                let max = m.max_force * dt;
+                rows.push(row(m.speed, -max, max));
+            }
+            if let Some(l) = limit {
+                rows.push(row(-get_limit_target(l.lower - value, dt), 0.0, F32MAX));
+                rows.push(row(get_limit_target(value - l.upper, dt), -F32MAX, 0.0));
+            }
+        };
+        match &self.joint_type {
+            &Type::Fixed => {
+                point(rows);
+                lock_rotation(rows);
+            }
+            &Type::Ball => point(rows),
+            &Type::Hinge { limit, motor } => {
+                point(rows);
+                let axis_a = a.get_orientation() * self.axis_a;
+                let axis_b = b.get_orientation() * self.axis_b;
+                let error = axis_a.cross(axis_b);
+                let (t0, t1) = get_tangents(&axis_a);
+                for t in &[t0, t1] {
+                    rows.push(Row::new_angular(
+                        ai,
+                        bi,
+                        t,
+                        -bias * error.dot(*t),
+                        -F32MAX,
+                        F32MAX,
+                        bodies,
+                    ));
+                }
+                let angle = self.get_angle(a, b);
+                drive(rows, &limit, &motor, angle, false, &axis_a);
+            }
+            &Type::Slider { limit, motor } => {
+                lock_rotation(rows);
+                let axis = a.get_orientation() * self.axis_a;
+                let (t0, t1) = get_tangents(&axis);
+                for t in &[t0, t1] {
+                    rows.push(Row::new_linear(
+                        ai,
+                        bi,
+                        &(ra + d),
+                        &rb,
+                        t,
+                        -bias * d.dot(*t),
+                        -F32MAX,
+                        F32MAX,
+                        bodies,
+                    ));
+                }
+                drive(rows, &limit, &motor, d.dot(axis), true, &axis);
+            }
+            &Type::Distance { min, max } => {
+                let length = d.magnitude();
+                if length < LINEAR_SLOP {
+                    return;
+                }
+                let n = d / length;
+                let row = |target: Real, lower: Real, upper: Real| {
+                    Row::new_linear(ai, bi, &ra, &rb, &n, target, lower, upper, bodies)
+                };
+                if min >= max {
+                    rows.push(row(-bias * (length - min), -F32MAX, F32MAX));
+                } else {
+                    rows.push(row(-get_limit_target(min - length, dt), 0.0, F32MAX));
+                    rows.push(row(get_limit_target(length - max, dt), -F32MAX, 0.0));
+                }
+            }
+        }
+    }
+
+    // Moves bodies directly to remove the drift of anchors that velocity solver leaves,
+    // returns the remaining error.
+    pub(super) fn solve_position(&self, a: &mut RigidBody, b: &mut RigidBody) -> Real {
+        let ra = a.get_orientation() * self.anchor_a;
+        let rb = b.get_orientation() * self.anchor_b;
+        let d = b.get_position() + rb - a.get_position() - ra;
+        let (error, correction) = match &self.joint_type {
+            &Type::Fixed | &Type::Ball | &Type::Hinge { .. } => (d.magnitude(), -d),
+            &Type::Distance { min, max } => {
+                let length = d.magnitude();
+                if length < LINEAR_SLOP {
+                    return 0.0;
+                }
+                let target = length.max(min).min(max);
+                ((length - target).abs(), d * ((target - length) / length))
+            }
+            &Type::Slider { .. } => return 0.0,
+        };
+        if error < LINEAR_SLOP {
+            return error;
+        }
+        let correction = if error > MAX_LINEAR_CORRECTION {
+            correction * (MAX_LINEAR_CORRECTION / error)
+        } else {
+            correction
+        };
+        let skew = |r: &Vec3| Mat3::new(0.0, r.z, -r.y, -r.z, 0.0, r.x, r.y, -r.x, 0.0);
+        let (sa, sb) = (skew(&ra), skew(&rb));
+        let ia = a.get_inverse_inertia();
+        let ib = b.get_inverse_inertia();
+        let k = Mat3::identity() * (a.get_inverse_mass() + b.get_inverse_mass())
+            - sa * ia * sa
+            - sb * ib * sb;
+        let k = match k.invert() {
+            Some(k) => k,
+            None => return error,
+        };
+        let impulse = k * correction;
+        a.apply_position_impulse(&-impulse, &ra);
+        b.apply_position_impulse(&impulse, &rb);
+        return error;
+    }
+}
+
+impl CoreObject for Joint {
+    fn get_id(&self) -> Id {
+        self.id
+    }
+}
+
+// Target velocity of a limit that error is its violation, limits are speculative,
+// so a velocity that only reaches the limit in this step is allowed.
+fn get_limit_target(error: Real, dt: Real) -> Real {
+    if error > 0.0 {
+        return -BAUMGARTE * error / dt;
+    }
+    return -error / dt;
+}
+
+fn read_vector(reader: &mut Gx3DReader) -> Vec3 {
+    return Vec3::new(reader.read(), reader.read(), reader.read());
+}
+
+#[cfg_attr(debug_mode, derive(Debug))]
+pub struct Manager {
+    joints: BTreeMap<Id, Weak<RwLock<Joint>>>,
+    gx3d_table: Option<Gx3dTable>,
+}
+
+impl Manager {
+    pub fn new() -> Self {
+        Manager {
+            joints: BTreeMap::new(),
+            gx3d_table: None,
+        }
+    }
+
+    pub fn load_gx3d(&mut self, id: Id) -> Arc<RwLock<Joint>> {
+        if let Some(joint) = self.joints.get(&id) {
+            if let Some(joint) = joint.upgrade() {
+                return joint;
+            }
+        }
+        let gx3d_table = vxunwrap!(self.gx3d_table.as_mut());
+        gx3d_table.goto(id);
+        let reader = gx3d_table.get_mut_reader();
+        let joint = Arc::new(RwLock::new(Joint::new_with_gx3d(reader, id)));
+        self.joints.insert(id, Arc::downgrade(&joint));
+        return joint;
+    }
+
+    pub(crate) fn set_gx3d_table(&mut self, gx3d_table: Gx3dTable) {
+        self.gx3d_table = Some(gx3d_table);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::body::BodyType;
+    use super::super::collider::{Cuboid, Sphere};
+    use super::super::world::World;
+    use super::*;
+
+    fn add_body(world: &mut World, body_type: BodyType, position: Vec3) -> Id {
+        let collider = Arc::new(RwLock::new(Cuboid::new(Vec3::new(0.2, 0.2, 0.2))));
+        let mut body = RigidBody::new(body_type, collider);
+        body.set_position(&position);
+        return world.add_body(body);
+    }
+
+    const DT: Real = 1.0 / 60.0;
+
+    fn run(world: &mut World, seconds: Real) {
+        for _ in 0..(seconds / DT) as usize {
+            world.step(DT);
+        }
+    }
+
+    fn get_distance(world: &World, a: Id, b: Id) -> Real {
+        let a = vxunwrap!(world.get_body(a)).get_position();
+        let b = vxunwrap!(world.get_body(b)).get_position();
+        return (a - b).magnitude();
+    }
+
+    #[test]
+    fn ball_test() {
+        let mut world = World::new();
+        let a = add_body(&mut world, BodyType::Static, Vec3::new(0.0, 5.0, 0.0));
+        let collider = Arc::new(RwLock::new(Sphere::new(0.2)));
+        let mut body = RigidBody::new(BodyType::Dynamic, collider);
+        body.set_position(&Vec3::new(2.0, 5.0, 0.0));
+        let b = world.add_body(body);
+        // ids start from 1, so 0 is not in the world
+        let joint = Joint::new(Type::Ball, a, &Vec3::zero(), 0, &Vec3::zero());
+        assert!(world.add_joint(joint).is_none());
+        let joint = Joint::new(Type::Ball, a, &Vec3::zero(), b, &Vec3::new(-2.0, 0.0, 0.0));
+        vxunwrap!(world.add_joint(joint));
+        let mut lowest = F32MAX;
+        for _ in 0..6 {
+            run(&mut world, 0.5);
+            assert!((get_distance(&world, a, b) - 2.0).abs() < 0.05);
+            lowest = lowest.min(vxunwrap!(world.get_body(b)).get_position().y);
+        }
+        assert!(lowest < 4.0);
+    }
+
+    #[test]
+    fn same_body_test() {
+        let mut world = World::new();
+        let a = add_body(&mut world, BodyType::Dynamic, Vec3::zero());
+        let joint = Joint::new(Type::Fixed, a, &Vec3::zero(), a, &Vec3::unit_x());
+        assert!(world.add_joint(joint).is_none());
+        run(&mut world, 0.1);
+    }
+
+    #[test]
+    fn hinge_limit_test() {
+        let mut world = World::new();
+        let a = add_body(&mut world, BodyType::Static, Vec3::zero());
+        let b = add_body(&mut world, BodyType::Dynamic, Vec3::new(1.0, 0.0, 0.0));
+        let mut joint = Joint::new(
+            Type::Hinge {
+                limit: Some(Limit {
+                    lower: -0.5,
+                    upper: 0.5,
+                }),
+                motor: None,
+            },
+            a,
+            &Vec3::zero(),
+            b,
+            &Vec3::new(-1.0, 0.0, 0.0),
+        );
+        joint.axis_a = Vec3::unit_z();
+        joint.axis_b = Vec3::unit_z();
+        let id = vxunwrap!(world.add_joint(joint));
+        run(&mut world, 3.0);
+        let joint = vxunwrap!(world.get_joint(id));
+        let angle = joint.get_angle(vxunwrap!(world.get_body(a)), vxunwrap!(world.get_body(b)));
+        assert!((angle + 0.5).abs() < 0.05);
+        assert!((get_distance(&world, a, b) - 1.0).abs() < 0.05);
+    }
+
+    #[test]
+    fn hinge_motor_test() {
+        let mut world = World::new();
+        world.gravity = Vec3::zero();
+        let a = add_body(&mut world, BodyType::Static, Vec3::zero());
+        let b = add_body(&mut world, BodyType::Dynamic, Vec3::zero());
+        let mut joint = Joint::new(
+            Type::Hinge {
+                limit: None,
+                motor: Some(Motor {
+                    speed: 2.0,
+                    max_force: 100.0,
+                }),
+            },
+            a,
+            &Vec3::zero(),
+            b,
+            &Vec3::zero(),
+        );
+        joint.axis_a = Vec3::unit_x();
+        joint.axis_b = Vec3::unit_x();
+        vxunwrap!(world.add_joint(joint));
+        run(&mut world, 1.0);
+        let w = vxunwrap!(world.get_body(b)).get_angular_velocity();
+        assert!((w.x - 2.0).abs() < 0.05);
+        assert!(w.y.abs() < 0.05 && w.z.abs() < 0.05);
+    }
+
+    #[test]
+    fn slider_test() {
+        let mut world = World::new();
+        let a = add_body(&mut world, BodyType::Static, Vec3::zero());
+        let b = add_body(&mut world, BodyType::Dynamic, Vec3::zero());
+        let mut joint = Joint::new(
+            Type::Slider {
+                limit: Some(Limit {
+                    lower: -1.0,
+                    upper: 1.0,
+                }),
+                motor: None,
+            },
+            a,
+            &Vec3::zero(),
+            b,
+            &Vec3::zero(),
+        );
+        joint.axis_a = Vec3::unit_x();
+        joint.axis_b = Vec3::unit_x();
+        let id = vxunwrap!(world.add_joint(joint));
+        vxunwrap!(world.get_body_mut(b)).set_linear_velocity(&Vec3::new(3.0, 0.0, 0.0));
+        vxunwrap!(world.get_body_mut(b)).set_angular_velocity(&Vec3::new(0.0, 1.0, 0.0));
+        run(&mut world, 2.0);
+        let body = vxunwrap!(world.get_body(b));
+        let joint = vxunwrap!(world.get_joint(id));
+        let translation = joint.get_translation(vxunwrap!(world.get_body(a)), body);
+        assert!((translation - 1.0).abs() < 0.05);
+        assert!(body.get_position().y.abs() < 0.05);
+        assert!(body.get_position().z.abs() < 0.05);
+        assert!(body.get_orientation().s > 0.999);
+    }
+
+    #[test]
+    fn fixed_test() {
+        let mut world = World::new();
+        let a = add_body(&mut world, BodyType::Static, Vec3::zero());
+        let b = add_body(&mut world, BodyType::Dynamic, Vec3::new(1.0, 0.0, 0.0));
+        let joint = Joint::new(Type::Fixed, a, &Vec3::new(1.0, 0.0, 0.0), b, &Vec3::zero());
+        vxunwrap!(world.add_joint(joint));
+        run(&mut world, 2.0);
+        let body = vxunwrap!(world.get_body(b));
+        assert!((body.get_position() - Vec3::new(1.0, 0.0, 0.0)).magnitude() < 0.05);
+        assert!(body.get_orientation().s > 0.999);
+    }
+
+    #[test]
+    fn distance_test() {
+        let mut world = World::new();
+        let a = add_body(&mut world, BodyType::Static, Vec3::zero());
+        let b = add_body(&mut world, BodyType::Dynamic, Vec3::new(0.0, -1.0, 0.0));
+        let joint = Joint::new(
+            Type::Distance { min: 0.0, max: 2.0 },
+            a,
+            &Vec3::zero(),
+            b,
+            &Vec3::zero(),
+        );
+        vxunwrap!(world.add_joint(joint));
+        run(&mut world, 0.2);
+        assert!(get_distance(&world, a, b) < 2.0);
+        run(&mut world, 2.0);
+        assert!((get_distance(&world, a, b) - 2.0).abs() < 0.05);
+    }
+}
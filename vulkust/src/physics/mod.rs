@@ -1 +1,8 @@
+pub mod body;
+pub mod character;
 pub mod collider;
+mod gjk;
+pub mod joint;
+pub mod narrowphase;
+mod solver;
+pub mod world;
@@ -0,0 +1,466 @@
+use super::super::collision::aabb::Aabb3;
+use super::super::core::types::Real;
+use super::collider::{Collider, Mesh, Triangle};
+use super::gjk::{epa, gjk, GjkResult, Pair};
+use std::f32::MAX as F32MAX;
+
+use cgmath;
+use cgmath::prelude::*;
+
+type Vec3 = cgmath::Vector3<Real>;
+type Mat4 = cgmath::Matrix4<Real>;
+
+const MAX_MANIFOLD_POINTS: usize = 4;
+// Points that move more than this get removed from manifold
+const CONTACT_BREAKING_THRESHOLD: Real = 0.02;
+
+// Normal is from A to B, moving B along it by depth separates them
+#[derive(Clone, Copy)]
+#[cfg_attr(debug_mode, derive(Debug))]
+pub struct Contact {
+    pub point_a: Vec3,
+    pub point_b: Vec3,
+    pub normal: Vec3,
+    pub depth: Real,
+}
+
+// Calls f for every pair of convex parts of colliders, for mesh colliders only
+// triangles whose box is closer than margin to the other collider are considered.
+// Two meshes are paired triangle by triangle. Iteration stops when f returns false.
+fn for_each_convex_pair(
+    a: &Collider,
+    ta: &Mat4,
+    b: &Collider,
+    tb: &Mat4,
+    margin: Real,
+    f: &mut FnMut(&Collider, &Mat4, &Collider, &Mat4) -> bool,
+) -> bool {
+    if a.is_ghost() || b.is_ghost() {
+        return true;
+    }
+    if let Some(c) = a.to_compound() {
+        for (offset, child) in &c.children {
+            let child = vxresult!(child.read());
+            if !for_each_convex_pair(&*child, &(ta * offset), b, tb, margin, f) {
+                return false;
+            }
+        }
+        return true;
+    }
+    if let Some(c) = b.to_compound() {
+        for (offset, child) in &c.children {
+            let child = vxresult!(child.read());
+            if !for_each_convex_pair(a, ta, &*child, &(tb * offset), margin, f) {
+                return false;
+            }
+        }
+        return true;
+    }
+    if let Some(mesh) = a.to_mesh() {
+        if b.to_mesh().is_none() {
+            return for_each_convex_pair(b, tb, a, ta, margin, &mut |b, tb, a, ta| f(a, ta, b, tb));
+        }
+        for t in get_near_triangles(mesh, ta, b, tb, margin) {
+            let triangle = Triangle::new(mesh.get_triangle(t));
+            if !for_each_convex_pair(&triangle, ta, b, tb, margin, f) {
+                return false;
+            }
+        }
+        return true;
+    }
+    if let Some(mesh) = b.to_mesh() {
+        for t in get_near_triangles(mesh, tb, a, ta, margin) {
+            let triangle = Triangle::new(mesh.get_triangle(t));
+            if !f(a, ta, &triangle, tb) {
+                return false;
+            }
+        }
+        return true;
+    }
+    return f(a, ta, b, tb);
+}
+
+fn get_near_triangles(mesh: &Mesh, tm: &Mat4, c: &Collider, tc: &Mat4, margin: Real) -> Vec<usize> {
+    if margin == F32MAX {
+        return (0..mesh.get_triangles_count()).collect();
+    }
+    let itm = match tm.invert() {
+        Some(m) => m,
+        None => return Vec::new(),
+    };
+    let aabb = c.get_aabb(&(itm * tc));
+    let m = Vec3::new(margin, margin, margin);
+    let aabb = Aabb3::new_with_points(&(aabb.get_min() - m), &(aabb.get_max() + m));
+    return mesh.get_triangles_in_aabb(&aabb);
+}
+
+// Ghosts do not collide, so their meshes are not counted
+pub(super) fn has_mesh(c: &Collider) -> bool {
+    if c.is_ghost() {
+        return false;
+    }
+    if let Some(c) = c.to_compound() {
+        return c
+            .children
+            .iter()
+            .any(|(_, child)| has_mesh(&*vxresult!(child.read())));
+    }
+    return c.to_mesh().is_some();
+}
+
+pub fn intersects(a: &Collider, ta: &Mat4, b: &Collider, tb: &Mat4) -> bool {
+    let mut result = false;
+    for_each_convex_pair(a, ta, b, tb, 0.0, &mut |a, ta, b, tb| {
+        if let GjkResult::Overlap(_) = gjk(&Pair::new(a, ta, b, tb)) {
+            result = true;
+            return false;
+        }
+        return true;
+    });
+    return result;
+}
+
+// Returns distance and the closest points on A and B, None when they overlap
+pub fn get_distance(
+    a: &Collider,
+    ta: &Mat4,
+    b: &Collider,
+    tb: &Mat4,
+) -> Option<(Real, Vec3, Vec3)> {
+    let mut result: Option<(Real, Vec3, Vec3)> = None;
+    let mut overlap = false;
+    for_each_convex_pair(a, ta, b, tb, F32MAX, &mut |a, ta, b, tb| {
+        match gjk(&Pair::new(a, ta, b, tb)) {
+            GjkResult::Overlap(_) => {
+                overlap = true;
+                return false;
+            }
+            GjkResult::Separated(d, pa, pb) => {
+                let closer = match &result {
+                    Some(r) => d < r.0,
+                    None => true,
+                };
+                if closer {
+                    result = Some((d, pa, pb));
+                }
+            }
+        }
+        return true;
+    });
+    if overlap {
+        return None;
+    }
+    return result;
+}
+
+pub fn get_contacts(a: &Collider, ta: &Mat4, b: &Collider, tb: &Mat4) -> Vec<Contact> {
+    let mut result = Vec::new();
+    for_each_convex_pair(a, ta, b, tb, 0.0, &mut |a, ta, b, tb| {
+        let pair = Pair::new(a, ta, b, tb);
+        if let GjkResult::Overlap(simplex) = gjk(&pair) {
+            if let Some(p) = epa(&pair, simplex) {
+                result.push(Contact {
+                    point_a: p.point_a,
+                    point_b: p.point_b,
+                    normal: p.normal,
+                    depth: p.depth,
+                });
+            }
+        }
+        return true;
+    });
+    return result;
+}
+
+#[derive(Clone, Copy)]
+#[cfg_attr(debug_mode, derive(Debug))]
+pub struct ManifoldPoint {
+    pub local_a: Vec3,
+    pub local_b: Vec3,
+    pub contact: Contact,
+}
+
+// Contact points of a pair of colliders that persist between frames,
+// so resting shapes keep enough points while narrowphase finds one point per pair.
+#[cfg_attr(debug_mode, derive(Debug))]
+pub struct Manifold {
+    pub points: Vec<ManifoldPoint>,
+}
+
+impl Manifold {
+    pub fn new() -> Self {
+        Self {
+            points: Vec::with_capacity(MAX_MANIFOLD_POINTS + 1),
+        }
+    }
+
+    pub fn update(&mut self, a: &Collider, ta: &Mat4, b: &Collider, tb: &Mat4) {
+        self.refresh(ta, tb);
+        let contacts = get_contacts(a, ta, b, tb);
+        if contacts.len() == 0 {
+            self.points.clear();
+            return;
+        }
+        let ita = match ta.invert() {
+            Some(m) => m,
+            None => return,
+        };
+        let itb = match tb.invert() {
+            Some(m) => m,
+            None => return,
+        };
+        for c in contacts {
+            let p = ManifoldPoint {
+                local_a: (ita * c.point_a.extend(1.0)).truncate(),
+                local_b: (itb * c.point_b.extend(1.0)).truncate(),
+                contact: c,
+            };
+            self.add_point(p);
+        }
+    }
+
+    fn refresh(&mut self, ta: &Mat4, tb: &Mat4) {
+        let t2 = CONTACT_BREAKING_THRESHOLD * CONTACT_BREAKING_THRESHOLD;
+        self.points.retain(|p| {
+            let pa = (ta * p.local_a.extend(1.0)).truncate();
+            let pb = (tb * p.local_b.extend(1.0)).truncate();
+            let d = pa - pb;
+            let depth = d.dot(p.contact.normal);
+            if depth < -CONTACT_BREAKING_THRESHOLD {
+                return false;
+            }
+            let tangent = d - p.contact.normal * depth;
+            return tangent.magnitude2() <= t2;
+        });
+        for p in &mut self.points {
+            p.contact.point_a = (ta * p.local_a.extend(1.0)).truncate();
+            p.contact.point_b = (tb * p.local_b.extend(1.0)).truncate();
+            p.contact.depth = (p.contact.point_a - p.contact.point_b).dot(p.contact.normal);
+        }
+    }
+
+    fn add_point(&mut self, p: ManifoldPoint) {
+        let t2 = CONTACT_BREAKING_THRESHOLD * CONTACT_BREAKING_THRESHOLD;
+        for q in &mut self.points {
+            if (q.local_a - p.local_a).magnitude2() < t2 {
+                *q = p;
+                return;
+            }
+        }
+        self.points.push(p);
+        if self.points.len() > MAX_MANIFOLD_POINTS {
+            self.reduce();
+        }
+        // the latest normal is the most accurate one
+        for q in &mut self.points {
+            q.contact.normal = p.contact.normal;
+        }
+    }
+
+    // Keeps the deepest point and the points that maximize contact area
+    fn reduce(&mut self) {
+        let mut deepest = 0;
+        for i in 1..self.points.len() {
+            if self.points[i].contact.depth > self.points[deepest].contact.depth {
+                deepest = i;
+            }
+        }
+        let mut kept = vec![deepest];
+        let p = |i: usize, points: &[ManifoldPoint]| points[i].contact.point_a;
+        // farthest from deepest
+        let mut best = None;
+        let mut best_v = -1.0;
+        for i in 0..self.points.len() {
+            let v = (p(i, &self.points) - p(deepest, &self.points)).magnitude2();
+            if !kept.contains(&i) && v > best_v {
+                best_v = v;
+                best = Some(i);
+            }
+        }
+        kept.push(vxunwrap!(best));
+        // largest triangle
+        let (a, b) = (p(kept[0], &self.points), p(kept[1], &self.points));
+        let mut best = None;
+        let mut best_v = -1.0;
+        for i in 0..self.points.len() {
+            let v = (b - a).cross(p(i, &self.points) - a).magnitude2();
+            if !kept.contains(&i) && v > best_v {
+                best_v = v;
+                best = Some(i);
+            }
+        }
+        kept.push(vxunwrap!(best));
+        // the point that is farthest outside of triangle
+        let c = p(kept[2], &self.points);
+        let mut best = None;
+        let mut best_v = -1.0;
+        for i in 0..self.points.len() {
+            let q = p(i, &self.points);
+            let v = (b - a).cross(q - a).magnitude2()
+                + (c - b).cross(q - b).magnitude2()
+                + (a - c).cross(q - c).magnitude2();
+            if !kept.contains(&i) && v > best_v {
+                best_v = v;
+                best = Some(i);
+            }
+        }
+        kept.push(vxunwrap!(best));
+        let mut points = Vec::with_capacity(MAX_MANIFOLD_POINTS + 1);
+        for i in kept {
+            points.push(self.points[i]);
+        }
+        self.points = points;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::collider::{Capsule, Compound, Cuboid, Ghost, Mesh, Sphere};
+    use super::*;
+    use std::sync::{Arc, RwLock};
+
+    fn at(x: Real, y: Real, z: Real) -> Mat4 {
+        return Mat4::from_translation(Vec3::new(x, y, z));
+    }
+
+    #[test]
+    fn sphere_sphere_test() {
+        let a = Sphere::new(1.0);
+        let b = Sphere::new(1.0);
+        let (ta, tb) = (at(0.0, 0.0, 0.0), at(1.5, 0.0, 0.0));
+        assert!(intersects(&a, &ta, &b, &tb));
+        let c = get_contacts(&a, &ta, &b, &tb);
+        assert_eq!(c.len(), 1);
+        assert!((c[0].depth - 0.5).abs() < 1e-2);
+        assert!((c[0].normal - Vec3::new(1.0, 0.0, 0.0)).magnitude() < 1e-2);
+        assert!((c[0].point_a - Vec3::new(1.0, 0.0, 0.0)).magnitude() < 1e-2);
+        assert!((c[0].point_b - Vec3::new(0.5, 0.0, 0.0)).magnitude() < 1e-2);
+        let tb = at(0.0, 3.0, 0.0);
+        assert!(!intersects(&a, &ta, &b, &tb));
+        let (d, pa, pb) = get_distance(&a, &ta, &b, &tb).unwrap();
+        assert!((d - 1.0).abs() < 1e-3);
+        assert!((pa - Vec3::new(0.0, 1.0, 0.0)).magnitude() < 1e-2);
+        assert!((pb - Vec3::new(0.0, 2.0, 0.0)).magnitude() < 1e-2);
+    }
+
+    #[test]
+    fn box_box_test() {
+        let a = Cuboid::new(Vec3::new(1.0, 1.0, 1.0));
+        let b = Cuboid::new(Vec3::new(0.5, 0.5, 0.5));
+        let (ta, tb) = (at(0.0, 0.0, 0.0), at(0.2, 1.4, 0.1));
+        let c = get_contacts(&a, &ta, &b, &tb);
+        assert_eq!(c.len(), 1);
+        assert!((c[0].depth - 0.1).abs() < 1e-3);
+        assert!((c[0].normal - Vec3::new(0.0, 1.0, 0.0)).magnitude() < 1e-3);
+        let tb = at(0.0, 1.6, 0.0) * Mat4::from_angle_y(cgmath::Rad(0.5 as Real));
+        assert!(!intersects(&a, &ta, &b, &tb));
+        let (d, _, _) = get_distance(&a, &ta, &b, &tb).unwrap();
+        assert!((d - 0.1).abs() < 1e-3);
+    }
+
+    #[test]
+    fn capsule_box_test() {
+        let a = Cuboid::new(Vec3::new(5.0, 1.0, 5.0));
+        let b = Capsule::new(0.5, 1.0);
+        let (ta, tb) = (at(0.0, -1.0, 0.0), at(1.0, 1.3, 0.0));
+        let c = get_contacts(&a, &ta, &b, &tb);
+        assert_eq!(c.len(), 1);
+        assert!((c[0].depth - 0.2).abs() < 1e-2);
+        assert!((c[0].normal - Vec3::new(0.0, 1.0, 0.0)).magnitude() < 1e-2);
+    }
+
+    #[test]
+    fn mesh_compound_test() {
+        let ground = Mesh::new(
+            vec![
+                Vec3::new(-10.0, 0.0, -10.0),
+                Vec3::new(10.0, 0.0, -10.0),
+                Vec3::new(10.0, 0.0, 10.0),
+                Vec3::new(-10.0, 0.0, 10.0),
+            ],
+            vec![0, 2, 1, 0, 3, 2],
+        );
+        let mut body = Compound::new();
+        body.add_child(at(-2.0, 0.0, 0.0), Arc::new(RwLock::new(Sphere::new(0.5))));
+        body.add_child(at(2.0, 0.0, 0.0), Arc::new(RwLock::new(Sphere::new(0.5))));
+        let (tg, tb) = (at(0.0, 0.0, 0.0), at(0.0, 0.4, 0.0));
+        let c = get_contacts(&ground, &tg, &body, &tb);
+        // each sphere touches two triangles or one triangle
+        assert!(c.len() >= 2);
+        for c in &c {
+            assert!((c.depth - 0.1).abs() < 1e-2);
+            assert!((c.normal - Vec3::new(0.0, 1.0, 0.0)).magnitude() < 1e-2);
+        }
+        let c = get_contacts(&body, &tb, &ground, &tg);
+        for c in &c {
+            assert!((c.normal - Vec3::new(0.0, -1.0, 0.0)).magnitude() < 1e-2);
+        }
+        let tb = at(0.0, 2.0, 0.0);
+        assert!(!intersects(&ground, &tg, &body, &tb));
+        let (d, _, _) = get_distance(&ground, &tg, &body, &tb).unwrap();
+        assert!((d - 1.5).abs() < 1e-3);
+    }
+
+    #[test]
+    fn mesh_mesh_test() {
+        let ground = Mesh::new(
+            vec![
+                Vec3::new(-10.0, 0.0, -10.0),
+                Vec3::new(10.0, 0.0, -10.0),
+                Vec3::new(10.0, 0.0, 10.0),
+                Vec3::new(-10.0, 0.0, 10.0),
+            ],
+            vec![0, 2, 1, 0, 3, 2],
+        );
+        let wall = Mesh::new(
+            vec![
+                Vec3::new(-1.0, 0.0, 0.0),
+                Vec3::new(1.0, 0.0, 0.0),
+                Vec3::new(1.0, 1.0, 0.0),
+                Vec3::new(-1.0, 1.0, 0.0),
+            ],
+            vec![0, 1, 2, 0, 2, 3],
+        );
+        let (tg, tw) = (at(0.0, 0.0, 0.0), at(2.0, -0.1, 3.0));
+        assert!(intersects(&ground, &tg, &wall, &tw));
+        let c = get_contacts(&ground, &tg, &wall, &tw);
+        assert!(c.len() > 0);
+        for c in &c {
+            assert!(c.depth < 0.2);
+            assert!(c.point_b.y > -0.2 && c.point_b.y < 0.1);
+        }
+        let tw = at(2.0, 0.5, 3.0);
+        assert!(!intersects(&ground, &tg, &wall, &tw));
+        assert!(get_contacts(&wall, &tw, &ground, &tg).is_empty());
+        let (d, _, _) = get_distance(&ground, &tg, &wall, &tw).unwrap();
+        assert!((d - 0.5).abs() < 1e-3);
+        assert!(has_mesh(&ground));
+        let mut compound = Compound::new();
+        compound.add_child(at(0.0, 0.0, 0.0), Arc::new(RwLock::new(wall)));
+        assert!(has_mesh(&compound));
+        let ghost = Ghost::new_with_volume(Arc::new(RwLock::new(ground)));
+        assert!(!has_mesh(&ghost));
+    }
+
+    #[test]
+    fn manifold_test() {
+        let ground = Cuboid::new(Vec3::new(10.0, 1.0, 10.0));
+        let b = Cuboid::new(Vec3::new(0.5, 0.5, 0.5));
+        let tg = at(0.0, -1.0, 0.0);
+        let mut m = Manifold::new();
+        // a slightly wobbling box reveals its corners one by one
+        for i in 0..8 {
+            let ax = if i % 2 == 0 { 0.01 } else { -0.01 };
+            let az = if i % 4 < 2 { 0.01 } else { -0.01 };
+            let tb = at(0.0, 0.49, 0.0)
+                * Mat4::from_angle_x(cgmath::Rad(ax))
+                * Mat4::from_angle_z(cgmath::Rad(az));
+            m.update(&ground, &tg, &b, &tb);
+            assert!(m.points.len() <= MAX_MANIFOLD_POINTS);
+        }
+        assert_eq!(m.points.len(), 4);
+        for p in &m.points {
+            assert!(p.contact.depth > -CONTACT_BREAKING_THRESHOLD);
+        }
+    }
+}
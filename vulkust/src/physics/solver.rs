@@ -0,0 +1,269 @@
+use super::super::core::object::Object as CoreObject;
+use super::super::core::types::{Id, Real};
+use super::body::RigidBody;
+use std::collections::BTreeMap;
+
+use cgmath;
+use cgmath::prelude::*;
+
+type Vec3 = cgmath::Vector3<Real>;
+type Mat3 = cgmath::Matrix3<Real>;
+
+// A copy of body state that solver works on
+pub(super) struct SolverBody {
+    pub linear_velocity: Vec3,
+    pub angular_velocity: Vec3,
+    pub inverse_mass: Real,
+    pub inverse_inertia: Mat3,
+}
+
+impl SolverBody {
+    fn apply_impulse(&mut self, impulse: &Vec3, r: &Vec3) {
+        self.linear_velocity += impulse * self.inverse_mass;
+        self.angular_velocity += self.inverse_inertia * r.cross(*impulse);
+    }
+}
+
+// Bodies that take part in solving, each one is copied once
+pub(super) struct SolverBodies {
+    pub bodies: Vec<SolverBody>,
+    pub indices: BTreeMap<Id, usize>,
+}
+
+impl SolverBodies {
+    pub fn new() -> Self {
+        SolverBodies {
+            bodies: Vec::new(),
+            indices: BTreeMap::new(),
+        }
+    }
+
+    pub fn get_index(&mut self, body: &RigidBody) -> usize {
+        let id = body.get_id();
+        if let Some(i) = self.indices.get(&id) {
+            return *i;
+        }
+        let i = self.bodies.len();
+        self.bodies.push(SolverBody {
+            linear_velocity: *body.get_linear_velocity(),
+            angular_velocity: *body.get_angular_velocity(),
+            inverse_mass: body.get_inverse_mass(),
+            inverse_inertia: body.get_inverse_inertia(),
+        });
+        self.indices.insert(id, i);
+        return i;
+    }
+}
+
+// Two unit vectors that are perpendicular to n and to each other
+pub(super) fn get_tangents(n: &Vec3) -> (Vec3, Vec3) {
+    let t0 = if n.x.abs() > 0.57735 {
+        Vec3::new(n.y, -n.x, 0.0).normalize()
+    } else {
+        Vec3::new(0.0, n.z, -n.y).normalize()
+    };
+    return (t0, n.cross(t0));
+}
+
+pub(super) struct ContactConstraint {
+    pub a: usize,
+    pub b: usize,
+    pub ra: Vec3,
+    pub rb: Vec3,
+    pub normal: Vec3,
+    pub tangents: [Vec3; 2],
+    pub normal_mass: Real,
+    pub tangent_masses: [Real; 2],
+    pub bias: Real,
+    pub friction: Real,
+    pub normal_impulse: Real,
+    pub tangent_impulses: [Real; 2],
+}
+
+impl ContactConstraint {
+    pub fn new(
+        a: usize,
+        b: usize,
+        ra: Vec3,
+        rb: Vec3,
+        normal: Vec3,
+        friction: Real,
+        bodies: &[SolverBody],
+    ) -> Self {
+        let (t0, t1) = get_tangents(&normal);
+        let sa = &bodies[a];
+        let sb = &bodies[b];
+        ContactConstraint {
+            a,
+            b,
+            ra,
+            rb,
+            normal,
+            tangents: [t0, t1],
+            normal_mass: Self::get_effective_mass(sa, sb, &ra, &rb, &normal),
+            tangent_masses: [
+                Self::get_effective_mass(sa, sb, &ra, &rb, &t0),
+                Self::get_effective_mass(sa, sb, &ra, &rb, &t1),
+            ],
+            bias: 0.0,
+            friction,
+            normal_impulse: 0.0,
+            tangent_impulses: [0.0; 2],
+        }
+    }
+
+    pub fn get_relative_velocity(&self, bodies: &[SolverBody]) -> Vec3 {
+        let a = &bodies[self.a];
+        let b = &bodies[self.b];
+        return b.linear_velocity + b.angular_velocity.cross(self.rb)
+            - a.linear_velocity
+            - a.angular_velocity.cross(self.ra);
+    }
+
+    fn get_effective_mass(a: &SolverBody, b: &SolverBody, ra: &Vec3, rb: &Vec3, d: &Vec3) -> Real {
+        let rad = ra.cross(*d);
+        let rbd = rb.cross(*d);
+        let k = a.inverse_mass
+            + b.inverse_mass
+            + rad.dot(a.inverse_inertia * rad)
+            + rbd.dot(b.inverse_inertia * rbd);
+        if k > 0.0 {
+            return 1.0 / k;
+        }
+        return 0.0;
+    }
+
+    fn apply(&self, bodies: &mut [SolverBody], impulse: &Vec3) {
+        bodies[self.a].apply_impulse(&-*impulse, &self.ra);
+        bodies[self.b].apply_impulse(impulse, &self.rb);
+    }
+
+    pub fn solve(&mut self, bodies: &mut [SolverBody]) {
+        // friction is limited by the current normal impulse, so it is solved first
+        for i in 0..2 {
+            let vt = self.get_relative_velocity(bodies).dot(self.tangents[i]);
+            let max = self.friction * self.normal_impulse;
+            let old = self.tangent_impulses[i];
+            self.tangent_impulses[i] = (old - vt * self.tangent_masses[i]).max(-max).min(max);
+            let impulse = self.tangents[i] * (self.tangent_impulses[i] - old);
+            self.apply(bodies, &impulse);
+        }
+        let vn = self.get_relative_velocity(bodies).dot(self.normal);
+        let old = self.normal_impulse;
+        self.normal_impulse = (old + (self.bias - vn) * self.normal_mass).max(0.0);
+        let impulse = self.normal * (self.normal_impulse - old);
+        self.apply(bodies, &impulse);
+    }
+}
+
+// One dimensional velocity constraint of joints,
+// it drives the relative velocity along its jacobian to target.
+pub(super) struct Row {
+    a: usize,
+    b: usize,
+    linear_a: Vec3,
+    angular_a: Vec3,
+    linear_b: Vec3,
+    angular_b: Vec3,
+    target: Real,
+    lower: Real,
+    upper: Real,
+    mass: Real,
+    impulse: Real,
+}
+
+impl Row {
+    // Jacobian of A is the negative of given linear and the angular_a
+    pub fn new(
+        a: usize,
+        b: usize,
+        linear: &Vec3,
+        angular_a: &Vec3,
+        angular_b: &Vec3,
+        target: Real,
+        lower: Real,
+        upper: Real,
+        bodies: &[SolverBody],
+    ) -> Self {
+        let sa = &bodies[a];
+        let sb = &bodies[b];
+        let k = (sa.inverse_mass + sb.inverse_mass) * linear.magnitude2()
+            + angular_a.dot(sa.inverse_inertia * angular_a)
+            + angular_b.dot(sb.inverse_inertia * angular_b);
+        Row {
+            a,
+            b,
+            linear_a: -*linear,
+            angular_a: *angular_a,
+            linear_b: *linear,
+            angular_b: *angular_b,
+            target,
+            lower,
+            upper,
+            mass: if k > 0.0 { 1.0 / k } else { 0.0 },
+            impulse: 0.0,
+        }
+    }
+
+    // Relative point velocity along d
+    pub fn new_linear(
+        a: usize,
+        b: usize,
+        ra: &Vec3,
+        rb: &Vec3,
+        d: &Vec3,
+        target: Real,
+        lower: Real,
+        upper: Real,
+        bodies: &[SolverBody],
+    ) -> Self {
+        return Self::new(
+            a,
+            b,
+            d,
+            &-ra.cross(*d),
+            &rb.cross(*d),
+            target,
+            lower,
+            upper,
+            bodies,
+        );
+    }
+
+    // Relative angular velocity around d
+    pub fn new_angular(
+        a: usize,
+        b: usize,
+        d: &Vec3,
+        target: Real,
+        lower: Real,
+        upper: Real,
+        bodies: &[SolverBody],
+    ) -> Self {
+        return Self::new(a, b, &Vec3::zero(), &-*d, d, target, lower, upper, bodies);
+    }
+
+    pub fn solve(&mut self, bodies: &mut [SolverBody]) {
+        let v = {
+            let a = &bodies[self.a];
+            let b = &bodies[self.b];
+            self.linear_a.dot(a.linear_velocity)
+                + self.angular_a.dot(a.angular_velocity)
+                + self.linear_b.dot(b.linear_velocity)
+                + self.angular_b.dot(b.angular_velocity)
+        };
+        let old = self.impulse;
+        self.impulse = (old + (self.target - v) * self.mass)
+            .max(self.lower)
+            .min(self.upper);
+        let lambda = self.impulse - old;
+        {
+            let a = &mut bodies[self.a];
+            a.linear_velocity += self.linear_a * (a.inverse_mass * lambda);
+            a.angular_velocity += a.inverse_inertia * self.angular_a * lambda;
+        }
+        let b = &mut bodies[self.b];
+        b.linear_velocity += self.linear_b * (b.inverse_mass * lambda);
+        b.angular_velocity += b.inverse_inertia * self.angular_b * lambda;
+    }
+}
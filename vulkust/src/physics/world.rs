@@ -0,0 +1,773 @@
+use super::super::collision::aabb::Aabb3;
+use super::super::collision::tree::DynamicTree;
+use super::super::core::event::{Event, TriggerAction, Type as EventType};
+use super::super::core::object::Object as CoreObject;
+use super::super::core::types::{Id, Real};
+use super::super::render::engine::Engine;
+use super::super::render::scene::Scene;
+use super::body::{BodyType, RigidBody};
+use super::joint::Joint;
+use super::narrowphase::{has_mesh, intersects, Manifold};
+use super::solver::{ContactConstraint, SolverBodies};
+use std::collections::{BTreeMap, BTreeSet};
+use std::mem::replace;
+use std::sync::{Arc, Mutex};
+
+use cgmath;
+use cgmath::prelude::*;
+
+type Vec3 = cgmath::Vector3<Real>;
+
+const FAT_MARGIN: Real = 0.1;
+const DEFAULT_VELOCITY_ITERATIONS: usize = 10;
+const DEFAULT_POSITION_ITERATIONS: usize = 4;
+// Fraction of penetration that is resolved in each step
+const BAUMGARTE: Real = 0.2;
+// Allowed penetration, it keeps contacts alive between steps
+const PENETRATION_SLOP: Real = 0.005;
+// Slower approaching contacts do not bounce
+const RESTITUTION_THRESHOLD: Real = 0.5;
+const SLEEP_LINEAR_VELOCITY: Real = 0.05;
+const SLEEP_ANGULAR_VELOCITY: Real = 0.05;
+const TIME_TO_SLEEP: Real = 0.5;
+// Position solver stops when all of joints are closer than this
+const JOINT_SLOP: Real = 0.001;
+
+#[cfg_attr(debug_mode, derive(Debug))]
+pub struct World {
+    bodies: BTreeMap<Id, RigidBody>,
+    tree: DynamicTree<Id>,
+    // Key is the pair of body ids, the smaller one is first
+    manifolds: BTreeMap<(Id, Id), Manifold>,
+    joints: BTreeMap<Id, Joint>,
+    // Pairs of trigger and the body that overlaps it
+    overlaps: BTreeSet<(Id, Id)>,
+    events: Vec<Event>,
+    engine_events: Option<Arc<Mutex<Vec<Event>>>>,
+    // Slowness of mesh against mesh collision is logged once for each world
+    mesh_pair_logged: bool,
+    pub gravity: Vec3,
+    pub velocity_iterations: usize,
+    pub position_iterations: usize,
+}
+
+impl World {
+    pub fn new() -> Self {
+        World {
+            bodies: BTreeMap::new(),
+            tree: DynamicTree::new(FAT_MARGIN),
+            manifolds: BTreeMap::new(),
+            joints: BTreeMap::new(),
+            overlaps: BTreeSet::new(),
+            events: Vec::new(),
+            engine_events: None,
+            mesh_pair_logged: false,
+            gravity: Vec3::new(0.0, -9.81, 0.0),
+            velocity_iterations: DEFAULT_VELOCITY_ITERATIONS,
+            position_iterations: DEFAULT_POSITION_ITERATIONS,
+        }
+    }
+
+    pub fn add_body(&mut self, mut body: RigidBody) -> Id {
+        let id = body.get_id();
+        let aabb = vxresult!(body.get_collider().read()).get_aabb(&body.get_transform());
+        if !self.mesh_pair_logged && has_mesh(&*vxresult!(body.get_collider().read())) {
+            let dynamic = body.is_dynamic();
+            self.mesh_pair_logged = self.bodies.values().any(|b| {
+                (dynamic || b.is_dynamic()) && has_mesh(&*vxresult!(b.get_collider().read()))
+            });
+            if self.mesh_pair_logged {
+                vxlogi!("Mesh against mesh collision is tested triangle by triangle, it is slow.");
+            }
+        }
+        body.proxy = Some(self.tree.insert(&aabb, id));
+        body.sync_links(1.0);
+        self.bodies.insert(id, body);
+        return id;
+    }
+
+    pub fn remove_body(&mut self, id: Id) -> Option<RigidBody> {
+        let mut body = self.bodies.remove(&id)?;
+        if let Some(proxy) = body.proxy.take() {
+            self.tree.remove(proxy);
+        }
+        self.manifolds.retain(|k, _| k.0 != id && k.1 != id);
+        self.joints.retain(|_, j| j.body_a != id && j.body_b != id);
+        let removed: Vec<(Id, Id)> = self
+            .overlaps
+            .iter()
+            .filter(|o| o.0 == id || o.1 == id)
+            .cloned()
+            .collect();
+        for overlap in removed {
+            self.overlaps.remove(&overlap);
+            self.add_trigger_event(overlap, TriggerAction::Exit);
+        }
+        return Some(body);
+    }
+
+    // Trigger events of each step are handed to engine, and engine posts them to the event
+    // dispatcher after fixed updates
+    pub fn set_engine(&mut self, engine: &Engine) {
+        self.engine_events = Some(engine.get_fixed_update_events().clone());
+    }
+
+    // Events that have not been sent yet, it is useful when world does not have engine
+    pub fn take_events(&mut self) -> Vec<Event> {
+        return replace(&mut self.events, Vec::new());
+    }
+
+    pub fn is_overlapping(&self, trigger: Id, other: Id) -> bool {
+        return self.overlaps.contains(&(trigger, other));
+    }
+
+    // Ends of joint can be ids of bodies or models that bodies are created for,
+    // current poses of bodies become the rest pose of joint.
+    // Returns None when any end of joint does not have a body in this world
+    // or both ends are the same body.
+    pub fn add_joint(&mut self, mut joint: Joint) -> Option<Id> {
+        joint.body_a = match self.find_body(joint.body_a) {
+            Some(id) => id,
+            None => return None,
+        };
+        joint.body_b = match self.find_body(joint.body_b) {
+            Some(id) => id,
+            None => return None,
+        };
+        if joint.body_a == joint.body_b {
+            vxloge!(
+                "Joint {} connects body {} to itself.",
+                joint.get_id(),
+                joint.body_a
+            );
+            return None;
+        }
+        {
+            let a = vxunwrap!(self.bodies.get(&joint.body_a));
+            let b = vxunwrap!(self.bodies.get(&joint.body_b));
+            joint.initialize(a, b);
+        }
+        vxunwrap!(self.bodies.get_mut(&joint.body_a)).wake_up();
+        vxunwrap!(self.bodies.get_mut(&joint.body_b)).wake_up();
+        let id = joint.get_id();
+        self.joints.insert(id, joint);
+        return Some(id);
+    }
+
+    // Adds joints of scene whose models have bodies in this world
+    pub fn add_scene_joints(&mut self, scene: &Scene) {
+        for (id, joint) in scene.get_joints() {
+            let joint = vxresult!(joint.read()).clone();
+            if self.add_joint(joint).is_none() {
+                vxloge!("Joint {} of scene is not added to the world.", id);
+            }
+        }
+    }
+
+    pub fn remove_joint(&mut self, id: Id) -> Option<Joint> {
+        return self.joints.remove(&id);
+    }
+
+    pub fn get_joint(&self, id: Id) -> Option<&Joint> {
+        return self.joints.get(&id);
+    }
+
+    pub fn get_joint_mut(&mut self, id: Id) -> Option<&mut Joint> {
+        return self.joints.get_mut(&id);
+    }
+
+    // Body itself or the body of a model
+    pub fn find_body(&self, id: Id) -> Option<Id> {
+        if self.bodies.contains_key(&id) {
+            return Some(id);
+        }
+        for (body_id, body) in &self.bodies {
+            if body.get_model_id() == Some(id) {
+                return Some(*body_id);
+            }
+        }
+        return None;
+    }
+
+    pub fn get_body(&self, id: Id) -> Option<&RigidBody> {
+        return self.bodies.get(&id);
+    }
+
+    pub fn get_body_mut(&mut self, id: Id) -> Option<&mut RigidBody> {
+        return self.bodies.get_mut(&id);
+    }
+
+    pub fn get_bodies(&self) -> &BTreeMap<Id, RigidBody> {
+        return &self.bodies;
+    }
+
+    // Calls f for bodies that their boxes overlap the aabb
+    pub fn query_aabb<G>(&self, aabb: &Aabb3, f: &mut G)
+    where
+        G: FnMut(&RigidBody),
+    {
+        let bodies = &self.bodies;
+        self.tree.query_aabb(aabb, &mut |id: &Id| {
+            f(vxunwrap!(bodies.get(id)));
+        });
+    }
+
+    pub fn get_manifold(&self, a: Id, b: Id) -> Option<&Manifold> {
+        return self.manifolds.get(&Self::get_pair_key(a, b));
+    }
+
+    // Moves linked objects between the last two states of bodies, it must be called in
+    // Application::update, alpha usually comes from Timing::get_interpolation_alpha.
+    pub fn interpolate(&mut self, alpha: Real) {
+        for (_, body) in &mut self.bodies {
+            if body.is_moving() || body.outdated_links {
+                body.sync_links(alpha);
+            }
+        }
+    }
+
+    // Advances simulation by one step, it must be called in Application::fixed_update
+    // with its constant dt. Trigger events are sent to engine at the end of step.
+    pub fn step(&mut self, dt: Real) {
+        let gravity = self.gravity;
+        for (_, body) in &mut self.bodies {
+            body.integrate_velocity(&gravity, dt);
+        }
+        self.update_tree();
+        self.update_triggers();
+        self.update_manifolds();
+        self.solve_velocities(dt);
+        for (_, body) in &mut self.bodies {
+            body.integrate_position(dt);
+        }
+        self.solve_positions();
+        self.update_sleeping(dt);
+        self.send_events();
+    }
+
+    fn send_events(&mut self) {
+        if self.events.len() == 0 {
+            return;
+        }
+        if let Some(engine_events) = &self.engine_events {
+            vxresult!(engine_events.lock()).append(&mut self.events);
+        }
+    }
+
+    fn get_pair_key(a: Id, b: Id) -> (Id, Id) {
+        if a < b {
+            return (a, b);
+        }
+        return (b, a);
+    }
+
+    fn update_tree(&mut self) {
+        for (_, body) in &mut self.bodies {
+            if !body.is_moving() && !body.teleported {
+                continue;
+            }
+            body.teleported = false;
+            let aabb = vxresult!(body.get_collider().read()).get_aabb(&body.get_transform());
+            let proxy = vxunwrap!(body.proxy);
+            self.tree.update(proxy, &aabb);
+        }
+    }
+
+    // Stay is reported in every step for bodies that remain in trigger
+    fn update_triggers(&mut self) {
+        let mut overlaps = BTreeSet::new();
+        for (id, body) in &self.bodies {
+            let collider = vxresult!(body.get_collider().read());
+            let volume = match collider.to_ghost() {
+                Some(ghost) => match &ghost.volume {
+                    Some(volume) => volume.clone(),
+                    None => continue,
+                },
+                None => continue,
+            };
+            let volume = vxresult!(volume.read());
+            let transform = body.get_transform();
+            let aabb = volume.get_aabb(&transform);
+            let bodies = &self.bodies;
+            self.tree.query_aabb(&aabb, &mut |other: &Id| {
+                if *other == *id {
+                    return;
+                }
+                let o = vxunwrap!(bodies.get(other));
+                let oc = vxresult!(o.get_collider().read());
+                if intersects(&*volume, &transform, &*oc, &o.get_transform()) {
+                    overlaps.insert((*id, *other));
+                }
+            });
+        }
+        let exited: Vec<(Id, Id)> = self.overlaps.difference(&overlaps).cloned().collect();
+        for overlap in exited {
+            self.add_trigger_event(overlap, TriggerAction::Exit);
+        }
+        for overlap in &overlaps {
+            let action = if self.overlaps.contains(overlap) {
+                TriggerAction::Stay
+            } else {
+                TriggerAction::Enter
+            };
+            self.add_trigger_event(*overlap, action);
+        }
+        self.overlaps = overlaps;
+    }
+
+    fn add_trigger_event(&mut self, overlap: (Id, Id), action: TriggerAction) {
+        self.events.push(Event::new(EventType::Trigger {
+            trigger: overlap.0,
+            other: overlap.1,
+            action,
+        }));
+    }
+
+    fn update_manifolds(&mut self) {
+        let mut pairs = BTreeSet::new();
+        for (id, body) in &self.bodies {
+            if !body.is_moving() {
+                continue;
+            }
+            let aabb = self.tree.get_fat_aabb(vxunwrap!(body.proxy)).clone();
+            let bodies = &self.bodies;
+            self.tree.query_aabb(&aabb, &mut |other: &Id| {
+                if *other == *id {
+                    return;
+                }
+                let o = vxunwrap!(bodies.get(other));
+                if body.is_dynamic() || o.is_dynamic() {
+                    pairs.insert(Self::get_pair_key(*id, *other));
+                }
+            });
+        }
+        // pairs that are not moving keep their contacts
+        // jointed bodies do not collide with each other
+        let jointed: Vec<(Id, Id)> = pairs
+            .iter()
+            .filter(|p| self.is_jointed(p.0, p.1))
+            .cloned()
+            .collect();
+        for p in jointed {
+            pairs.remove(&p);
+        }
+        let bodies = &self.bodies;
+        self.manifolds.retain(|k, _| {
+            if pairs.contains(k) {
+                return true;
+            }
+            return match (bodies.get(&k.0), bodies.get(&k.1)) {
+                (Some(a), Some(b)) => !a.is_moving() && !b.is_moving(),
+                _ => false,
+            };
+        });
+        let mut woken = Vec::new();
+        for pair in pairs {
+            let a = vxunwrap!(self.bodies.get(&pair.0));
+            let b = vxunwrap!(self.bodies.get(&pair.1));
+            let manifold = self
+                .manifolds
+                .entry(pair)
+                .or_insert_with(|| Manifold::new());
+            manifold.update(
+                &*vxresult!(a.get_collider().read()),
+                &a.get_transform(),
+                &*vxresult!(b.get_collider().read()),
+                &b.get_transform(),
+            );
+            if manifold.points.len() == 0 {
+                continue;
+            }
+            if a.is_sleeping() {
+                woken.push(pair.0);
+            }
+            if b.is_sleeping() {
+                woken.push(pair.1);
+            }
+        }
+        for id in woken {
+            vxunwrap!(self.bodies.get_mut(&id)).wake_up();
+        }
+        self.manifolds.retain(|_, m| m.points.len() > 0);
+    }
+
+    fn solve_velocities(&mut self, dt: Real) {
+        let mut solver_bodies = SolverBodies::new();
+        let mut constraints = Vec::new();
+        for (key, manifold) in &self.manifolds {
+            let a = vxunwrap!(self.bodies.get(&key.0));
+            let b = vxunwrap!(self.bodies.get(&key.1));
+            if !a.is_moving() && !b.is_moving() {
+                continue;
+            }
+            let ai = solver_bodies.get_index(a);
+            let bi = solver_bodies.get_index(b);
+            let friction = (a.friction * b.friction).sqrt();
+            let restitution = a.restitution.max(b.restitution);
+            for p in &manifold.points {
+                let c = &p.contact;
+                let mut constraint = ContactConstraint::new(
+                    ai,
+                    bi,
+                    c.point_a - a.get_position(),
+                    c.point_b - b.get_position(),
+                    c.normal,
+                    friction,
+                    &solver_bodies.bodies,
+                );
+                let vn = constraint
+                    .get_relative_velocity(&solver_bodies.bodies)
+                    .dot(c.normal);
+                let bounce = if vn < -RESTITUTION_THRESHOLD {
+                    -restitution * vn
+                } else {
+                    0.0
+                };
+                let push = BAUMGARTE * (c.depth - PENETRATION_SLOP).max(0.0) / dt;
+                constraint.bias = bounce.max(push);
+                constraints.push(constraint);
+            }
+        }
+        let mut rows = Vec::new();
+        for (_, joint) in &self.joints {
+            if !joint.enabled {
+                continue;
+            }
+            let a = vxunwrap!(self.bodies.get(&joint.body_a));
+            let b = vxunwrap!(self.bodies.get(&joint.body_b));
+            if !a.is_moving() && !b.is_moving() {
+                continue;
+            }
+            let ai = solver_bodies.get_index(a);
+            let bi = solver_bodies.get_index(b);
+            joint.create_rows(a, b, ai, bi, &solver_bodies.bodies, dt, &mut rows);
+        }
+        for _ in 0..self.velocity_iterations {
+            for r in &mut rows {
+                r.solve(&mut solver_bodies.bodies);
+            }
+            for c in &mut constraints {
+                c.solve(&mut solver_bodies.bodies);
+            }
+        }
+        for (id, i) in solver_bodies.indices {
+            let body = vxunwrap!(self.bodies.get_mut(&id));
+            if body.get_type() != BodyType::Dynamic || body.is_sleeping() {
+                continue;
+            }
+            let s = &solver_bodies.bodies[i];
+            body.set_velocities(&s.linear_velocity, &s.angular_velocity);
+        }
+    }
+
+    // Velocity solver leaves a drift in joints, it is removed by moving the bodies
+    fn solve_positions(&mut self) {
+        for _ in 0..self.position_iterations {
+            let mut error: Real = 0.0;
+            for (_, joint) in &self.joints {
+                if !joint.enabled {
+                    continue;
+                }
+                let mut a = vxunwrap!(self.bodies.remove(&joint.body_a));
+                let mut b = vxunwrap!(self.bodies.remove(&joint.body_b));
+                if a.is_moving() || b.is_moving() {
+                    error = error.max(joint.solve_position(&mut a, &mut b));
+                }
+                self.bodies.insert(joint.body_a, a);
+                self.bodies.insert(joint.body_b, b);
+            }
+            if error < JOINT_SLOP {
+                break;
+            }
+        }
+    }
+
+    fn is_jointed(&self, a: Id, b: Id) -> bool {
+        for (_, joint) in &self.joints {
+            if joint.enabled
+                && ((joint.body_a == a && joint.body_b == b)
+                    || (joint.body_a == b && joint.body_b == a))
+            {
+                return true;
+            }
+        }
+        return false;
+    }
+
+    // Touching dynamic bodies form an island, an island sleeps when all of its bodies are
+    // slow enough for a while and wakes up when any of them does not.
+    fn update_sleeping(&mut self, dt: Real) {
+        let mut indices = BTreeMap::new();
+        let mut ids = Vec::new();
+        for (id, body) in &mut self.bodies {
+            if !body.is_dynamic() {
+                continue;
+            }
+            indices.insert(*id, ids.len());
+            ids.push(*id);
+            if body.is_sleeping() {
+                continue;
+            }
+            let slow = body.get_linear_velocity().magnitude2()
+                < SLEEP_LINEAR_VELOCITY * SLEEP_LINEAR_VELOCITY
+                && body.get_angular_velocity().magnitude2()
+                    < SLEEP_ANGULAR_VELOCITY * SLEEP_ANGULAR_VELOCITY;
+            if slow && body.can_sleep {
+                body.sleep_time += dt;
+            } else {
+                body.sleep_time = 0.0;
+            }
+        }
+        let mut parents: Vec<usize> = (0..ids.len()).collect();
+        fn find(parents: &mut Vec<usize>, mut i: usize) -> usize {
+            while parents[i] != i {
+                parents[i] = parents[parents[i]];
+                i = parents[i];
+            }
+            return i;
+        }
+        for (key, _) in &self.manifolds {
+            let a = indices.get(&key.0);
+            let b = indices.get(&key.1);
+            if let (Some(a), Some(b)) = (a, b) {
+                let a = find(&mut parents, *a);
+                let b = find(&mut parents, *b);
+                parents[a] = b;
+            } else {
+                // a moving kinematic body keeps its touching bodies awake
+                let (d, k) = match (a, b) {
+                    (Some(_), None) => (key.0, key.1),
+                    (None, Some(_)) => (key.1, key.0),
+                    _ => continue,
+                };
+                let k = vxunwrap!(self.bodies.get(&k));
+                if k.get_type() == BodyType::Kinematic
+                    && (k.get_linear_velocity().magnitude2() > 0.0
+                        || k.get_angular_velocity().magnitude2() > 0.0)
+                {
+                    vxunwrap!(self.bodies.get_mut(&d)).wake_up();
+                }
+            }
+        }
+        for (_, joint) in &self.joints {
+            if !joint.enabled {
+                continue;
+            }
+            let a = indices.get(&joint.body_a);
+            let b = indices.get(&joint.body_b);
+            if let (Some(a), Some(b)) = (a, b) {
+                let a = find(&mut parents, *a);
+                let b = find(&mut parents, *b);
+                parents[a] = b;
+            }
+        }
+        let mut awake_islands = BTreeSet::new();
+        for i in 0..ids.len() {
+            let body = vxunwrap!(self.bodies.get(&ids[i]));
+            if !body.is_sleeping() && body.sleep_time < TIME_TO_SLEEP {
+                awake_islands.insert(find(&mut parents, i));
+            }
+        }
+        for i in 0..ids.len() {
+            let island = find(&mut parents, i);
+            let body = vxunwrap!(self.bodies.get_mut(&ids[i]));
+            if awake_islands.contains(&island) {
+                if body.is_sleeping() {
+                    body.wake_up();
+                }
+            } else if !body.is_sleeping() {
+                body.sleep();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::super::render::object::Transferable;
+    use super::super::collider::{Cuboid, Ghost, Sphere};
+    use super::*;
+    use std::sync::{Arc, RwLock};
+
+    type Quat = cgmath::Quaternion<Real>;
+
+    fn create_ground(world: &mut World) -> Id {
+        let ground = Arc::new(RwLock::new(Cuboid::new(Vec3::new(10.0, 1.0, 10.0))));
+        let mut ground = RigidBody::new(BodyType::Static, ground);
+        ground.set_position(&Vec3::new(0.0, -1.0, 0.0));
+        return world.add_body(ground);
+    }
+
+    const DT: Real = 1.0 / 60.0;
+
+    fn run(world: &mut World, seconds: Real) {
+        for _ in 0..(seconds / DT) as usize {
+            world.step(DT);
+        }
+    }
+
+    #[test]
+    fn resting_test() {
+        let mut world = World::new();
+        let ground = create_ground(&mut world);
+        let mut ids = Vec::new();
+        for i in 0..3 {
+            let shape = Arc::new(RwLock::new(Cuboid::new(Vec3::new(0.5, 0.5, 0.5))));
+            let mut body = RigidBody::new(BodyType::Dynamic, shape);
+            body.set_position(&Vec3::new(0.0, 0.6 + i as Real * 1.1, 0.0));
+            ids.push(world.add_body(body));
+        }
+        run(&mut world, 4.0);
+        for i in 0..3 {
+            let body = vxunwrap!(world.get_body(ids[i]));
+            let y = body.get_position().y;
+            assert!((y - (0.5 + i as Real)).abs() < 0.05);
+            assert!(body.is_sleeping());
+        }
+        let ground = vxunwrap!(world.get_body(ground));
+        assert!(*ground.get_position() == Vec3::new(0.0, -1.0, 0.0));
+        // a hit wakes the whole stack
+        vxunwrap!(world.get_body_mut(ids[0]))
+            .apply_impulse(&Vec3::new(1.0, 0.0, 0.0), &Vec3::new(0.0, 0.5, 0.0));
+        run(&mut world, 0.1);
+        for id in &ids {
+            assert!(!vxunwrap!(world.get_body(*id)).is_sleeping());
+        }
+    }
+
+    #[test]
+    fn restitution_test() {
+        let mut world = World::new();
+        create_ground(&mut world);
+        let mut heights = Vec::new();
+        for restitution in &[0.0, 0.8] {
+            let shape = Arc::new(RwLock::new(Sphere::new(0.5)));
+            let mut body = RigidBody::new(BodyType::Dynamic, shape);
+            body.set_position(&Vec3::new(3.0 * *restitution, 3.0, 0.0));
+            body.restitution = *restitution;
+            let id = world.add_body(body);
+            heights.push(id);
+        }
+        // falling takes about 0.7s
+        run(&mut world, 1.0);
+        let still = vxunwrap!(world.get_body(heights[0])).get_position().y;
+        let bounced = vxunwrap!(world.get_body(heights[1])).get_position().y;
+        assert!((still - 0.5).abs() < 0.05);
+        assert!(bounced > 1.0);
+    }
+
+    #[test]
+    fn friction_test() {
+        let mut world = World::new();
+        create_ground(&mut world);
+        let mut ids = Vec::new();
+        for friction in &[0.0, 1.0] {
+            let shape = Arc::new(RwLock::new(Cuboid::new(Vec3::new(0.5, 0.5, 0.5))));
+            let mut body = RigidBody::new(BodyType::Dynamic, shape);
+            body.set_position(&Vec3::new(0.0, 0.5, 3.0 * *friction));
+            body.set_linear_velocity(&Vec3::new(2.0, 0.0, 0.0));
+            body.friction = *friction;
+            ids.push(world.add_body(body));
+        }
+        run(&mut world, 1.0);
+        let slippery = vxunwrap!(world.get_body(ids[0])).get_position().x;
+        let rough = vxunwrap!(world.get_body(ids[1])).get_position().x;
+        assert!(slippery > 1.8);
+        assert!(rough < 0.7);
+    }
+
+    #[cfg_attr(debug_mode, derive(Debug))]
+    struct Follower {
+        location: Vec3,
+        orientation: Quat,
+    }
+
+    impl Transferable for Follower {
+        fn set_orientation(&mut self, q: &Quat) {
+            self.orientation = *q;
+        }
+
+        fn set_location(&mut self, l: &Vec3) {
+            self.location = *l;
+        }
+
+        fn get_location(&self) -> Vec3 {
+            return self.location;
+        }
+    }
+
+    #[test]
+    fn link_test() {
+        let mut world = World::new();
+        let follower = Arc::new(RwLock::new(Follower {
+            location: Vec3::zero(),
+            orientation: Quat::one(),
+        }));
+        let shape = Arc::new(RwLock::new(Sphere::new(0.5)));
+        let mut body = RigidBody::new(BodyType::Kinematic, shape);
+        body.set_angular_velocity(&Vec3::new(0.0, 1.0, 0.0));
+        body.set_linear_velocity(&Vec3::new(1.0, 0.0, 0.0));
+        body.link(Arc::downgrade(&follower));
+        let id = world.add_body(body);
+        run(&mut world, 0.5);
+        world.interpolate(0.25);
+        {
+            // follower is between the last two states of body
+            let body = vxunwrap!(world.get_body(id));
+            let follower = vxresult!(follower.read());
+            assert!((body.get_position().x - 0.5).abs() < 0.02);
+            let lag = body.get_position().x - follower.location.x;
+            assert!((lag - DT * 0.75).abs() < 1e-4);
+        }
+        world.interpolate(1.0);
+        let body = vxunwrap!(world.get_body(id));
+        let follower = vxresult!(follower.read());
+        assert!(follower.location == *body.get_position());
+        assert!(follower.orientation == *body.get_orientation());
+    }
+
+    #[test]
+    fn trigger_test() {
+        let mut world = World::new();
+        create_ground(&mut world);
+        let volume = Arc::new(RwLock::new(Cuboid::new(Vec3::new(1.0, 0.5, 1.0))));
+        let ghost = Arc::new(RwLock::new(Ghost::new_with_volume(volume)));
+        let mut trigger = RigidBody::new(BodyType::Static, ghost);
+        trigger.set_position(&Vec3::new(0.0, 2.0, 0.0));
+        let trigger = world.add_body(trigger);
+        let sphere = Arc::new(RwLock::new(Sphere::new(0.2)));
+        let mut sphere = RigidBody::new(BodyType::Dynamic, sphere);
+        sphere.set_position(&Vec3::new(0.0, 4.0, 0.0));
+        let sphere = world.add_body(sphere);
+        let mut actions = Vec::new();
+        for _ in 0..120 {
+            world.step(DT);
+            for e in world.take_events() {
+                match e.event_type {
+                    EventType::Trigger {
+                        trigger: t,
+                        other,
+                        action,
+                    } => {
+                        assert!(t == trigger && other == sphere);
+                        if actions.last() != Some(&action) {
+                            actions.push(action);
+                        }
+                    }
+                    _ => panic!("Unexpected event"),
+                }
+            }
+        }
+        assert!(
+            actions
+                == vec![
+                    TriggerAction::Enter,
+                    TriggerAction::Stay,
+                    TriggerAction::Exit
+                ]
+        );
+        assert!(!world.is_overlapping(trigger, sphere));
+        // landed on the ground, the trigger does not collide
+        assert!((vxunwrap!(world.get_body(sphere)).get_position().y - 0.2).abs() < 0.05);
+    }
+}
@@ -6,3 +6,10 @@ pub(crate) use super::super::d3d12::buffer::*;
 pub(crate) use super::super::metal::buffer::*;
 #[cfg(vulkan_api)]
 pub(crate) use super::super::vulkan::buffer::*;
+
+#[derive(Clone, Copy)]
+#[cfg_attr(debug_mode, derive(Debug))]
+pub enum IndexType {
+    U16,
+    U32,
+}
@@ -1,9 +1,11 @@
 use super::super::collision::aabb::Aabb3;
-use super::super::collision::frustum::Frustum;
+use super::super::collision::frustum::{Frustum, IntersectionStatus};
 use super::super::collision::plane::Plane;
+use super::super::core::event::Move;
 use super::super::core::gx3d::{Gx3DReader, Table as Gx3dTable};
 use super::super::core::object::Object as CoreObject;
 use super::super::core::types::{Id, Real};
+use super::super::math::ray::Ray3;
 use super::engine::Engine;
 use super::object::{Base as ObjectBase, Loadable, Object, Transferable};
 use cgmath;
@@ -61,6 +63,19 @@ impl Uniform {
         }
     }
 
+    pub fn get_x_axis(&self) -> cgmath::Vector3<Real> {
+        return self.x.truncate();
+    }
+
+    pub fn get_y_axis(&self) -> cgmath::Vector3<Real> {
+        return self.y.truncate();
+    }
+
+    // Camera looks toward negative of z axis
+    pub fn get_z_axis(&self) -> cgmath::Vector3<Real> {
+        return self.z.truncate();
+    }
+
     pub(super) fn get_inversed_rotation(&self) -> &cgmath::Matrix4<Real> {
         return &self.inversed_rotation;
     }
@@ -82,9 +97,20 @@ pub trait Camera: Object + Transferable {
     fn get_view_projection(&self) -> &cgmath::Matrix4<Real>;
     fn get_cascaded_shadow_frustum_partitions(&self) -> Vec<[cgmath::Vector3<Real>; 4]>;
     fn is_in_frustum(&self, Real, &cgmath::Vector3<Real>) -> bool;
+    fn intersect_aabb(&self, &Aabb3) -> IntersectionStatus;
     fn update_uniform(&self, &mut Uniform);
     fn get_distance(&self, &cgmath::Vector3<Real>) -> Real;
     fn get_uniform(&self) -> &Uniform;
+    // Position is normalized window coordinate, (0, 0) is top-left and (1, 1) is bottom-right
+    fn create_ray(&self, (Real, Real)) -> Ray3;
+}
+
+pub fn create_ray_with_move_event(camera: &Camera, e: &Move) -> Ray3 {
+    let p = match e {
+        &Move::Mouse { current, .. } => current,
+        &Move::Touch { current, .. } => current,
+    };
+    return camera.create_ray(p);
 }
 
 pub trait DefaultCamera: Camera {
@@ -382,6 +408,10 @@ impl Camera for Base {
         vxunexpected!();
     }
 
+    fn intersect_aabb(&self, _: &Aabb3) -> IntersectionStatus {
+        vxunexpected!();
+    }
+
     fn update_uniform(&self, uniform: &mut Uniform) {
         *uniform = self.uniform;
     }
@@ -393,6 +423,10 @@ impl Camera for Base {
     fn get_uniform(&self) -> &Uniform {
         return &self.uniform;
     }
+
+    fn create_ray(&self, _: (Real, Real)) -> Ray3 {
+        vxunexpected!();
+    }
 }
 
 #[cfg_attr(debug_mode, derive(Debug))]
@@ -667,6 +701,10 @@ impl Camera for Perspective {
         return self.frustum.intersects_center_radius(location, radius);
     }
 
+    fn intersect_aabb(&self, aabb: &Aabb3) -> IntersectionStatus {
+        return self.frustum.intersect_aabb(aabb);
+    }
+
     fn update_uniform(&self, uniform: &mut Uniform) {
         self.base.update_uniform(uniform);
     }
@@ -678,6 +716,13 @@ impl Camera for Perspective {
     fn get_uniform(&self) -> &Uniform {
         return self.base.get_uniform();
     }
+
+    fn create_ray(&self, p: (Real, Real)) -> Ray3 {
+        let x = self.base.uniform.x.truncate() * ((p.0 * 2.0 - 1.0) * self.tanx);
+        let y = self.base.uniform.y.truncate() * ((1.0 - p.1 * 2.0) * self.tany);
+        let d = (x + y - self.base.uniform.z.truncate()).normalize();
+        return Ray3::new(self.base.uniform.position_far.truncate(), d);
+    }
 }
 
 impl DefaultCamera for Perspective {
@@ -905,6 +950,17 @@ impl Camera for Orthographic {
         return self.aabb.intersects_center_radius(&p, radius);
     }
 
+    fn intersect_aabb(&self, aabb: &Aabb3) -> IntersectionStatus {
+        let aabb = aabb.get_transformed(&self.base.uniform.view);
+        if self.aabb.contains_aabb(&aabb) {
+            return IntersectionStatus::Inside;
+        }
+        if self.aabb.intersects_aabb(&aabb) {
+            return IntersectionStatus::Intersecting;
+        }
+        return IntersectionStatus::Outside;
+    }
+
     fn update_uniform(&self, uniform: &mut Uniform) {
         self.base.update_uniform(uniform);
     }
@@ -919,6 +975,18 @@ impl Camera for Orthographic {
     fn get_uniform(&self) -> &Uniform {
         return self.base.get_uniform();
     }
+
+    fn create_ray(&self, p: (Real, Real)) -> Ray3 {
+        let w = self.size * self.base.uniform.near_aspect_ratio_reserved.y;
+        let x = self.base.uniform.x.truncate() * ((p.0 * 2.0 - 1.0) * w);
+        let y = self.base.uniform.y.truncate() * ((1.0 - p.1 * 2.0) * self.size);
+        let z = self.base.uniform.z.truncate();
+        let o = self.base.uniform.position_far.truncate()
+            + z * self.base.uniform.near_aspect_ratio_reserved.x
+            + x
+            + y;
+        return Ray3::new(o, -z);
+    }
 }
 
 impl DefaultCamera for Orthographic {
@@ -926,3 +994,47 @@ impl DefaultCamera for Orthographic {
         Orthographic::new(eng, 1.0)
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn create_base(location: &cgmath::Vector3<Real>) -> Base {
+        let mut uniform = Uniform::new();
+        uniform.near_aspect_ratio_reserved.y = 2.0;
+        let mut base = Base {
+            obj_base: ObjectBase::new(),
+            uniform,
+            cascades_count: 0,
+        };
+        base.set_location(location);
+        return base;
+    }
+
+    #[test]
+    fn perspective_ray_test() {
+        let l = cgmath::Vector3::new(1.0, 2.0, 3.0);
+        let camera = Perspective::new_with_base(create_base(&l));
+        let r = camera.create_ray((0.5, 0.5));
+        assert!((r.o - l).magnitude() < 1e-5);
+        assert!((r.d - cgmath::Vector3::new(0.0, 0.0, -1.0)).magnitude() < 1e-5);
+        // top right corner of screen
+        let r = camera.create_ray((1.0, 0.0));
+        assert!((r.o - l).magnitude() < 1e-5);
+        assert!((r.d.magnitude() - 1.0).abs() < 1e-5);
+        assert!((r.d.x / -r.d.z - (DEFAULT_FOVX * 0.5).tan()).abs() < 1e-5);
+        assert!((r.d.x / r.d.y - 2.0).abs() < 1e-5);
+    }
+
+    #[test]
+    fn orthographic_ray_test() {
+        let l = cgmath::Vector3::new(1.0, 2.0, 3.0);
+        let camera = Orthographic::new_with_base(create_base(&l), 4.0);
+        let r = camera.create_ray((0.5, 0.5));
+        assert!((r.o - cgmath::Vector3::new(1.0, 2.0, 2.0)).magnitude() < 1e-5);
+        assert!((r.d - cgmath::Vector3::new(0.0, 0.0, -1.0)).magnitude() < 1e-5);
+        let r = camera.create_ray((1.0, 0.0));
+        assert!((r.o - cgmath::Vector3::new(5.0, 4.0, 2.0)).magnitude() < 1e-5);
+        assert!((r.d - cgmath::Vector3::new(0.0, 0.0, -1.0)).magnitude() < 1e-5);
+    }
+}
@@ -18,6 +18,8 @@ pub struct Configurations {
     max_shadow_maker_kernel_render_data_count: u64,
     content_width: u32,
     content_height: u32,
+    optimize_imported_meshes: bool,
+    keep_mesh_data_on_cpu: bool,
 }
 
 impl Default for Configurations {
@@ -37,6 +39,8 @@ impl Default for Configurations {
             max_shadow_maker_kernel_render_data_count: 600,
             content_width: 1000,
             content_height: 700,
+            optimize_imported_meshes: false,
+            keep_mesh_data_on_cpu: false,
         }
     }
 }
@@ -97,4 +101,20 @@ impl Configurations {
     pub fn get_content_height(&self) -> u32 {
         return self.content_height;
     }
+
+    pub fn get_optimize_imported_meshes(&self) -> bool {
+        return self.optimize_imported_meshes;
+    }
+
+    pub fn set_optimize_imported_meshes(&mut self, b: bool) {
+        self.optimize_imported_meshes = b;
+    }
+
+    pub fn get_keep_mesh_data_on_cpu(&self) -> bool {
+        return self.keep_mesh_data_on_cpu;
+    }
+
+    pub fn set_keep_mesh_data_on_cpu(&mut self, b: bool) {
+        self.keep_mesh_data_on_cpu = b;
+    }
 }
@@ -1,12 +1,14 @@
 use super::super::core::application::Application as CoreAppTrait;
 use super::super::core::asset::Manager as AssetManager;
+use super::super::core::dispatcher::Dispatcher as EventDispatcher;
 use super::super::core::event::Event;
 use super::super::core::timing::Timing;
 use super::super::system::os::application::Application as OsApp;
 use super::config::Configurations;
 use super::gapi::GraphicApiEngine;
 use super::multithreaded::Engine as MultithreadedEngine;
-use std::sync::{Arc, RwLock, Weak};
+use std::mem::replace;
+use std::sync::{Arc, Mutex, RwLock, Weak};
 
 #[cfg_attr(debug_mode, derive(Debug))]
 pub struct Engine {
@@ -18,6 +20,8 @@ pub struct Engine {
     timing: Arc<RwLock<Timing>>,
     config: Configurations,
     multithreaded_engine: MultithreadedEngine,
+    event_dispatcher: Arc<EventDispatcher>,
+    fixed_update_events: Arc<Mutex<Vec<Event>>>,
 }
 
 impl Engine {
@@ -25,7 +29,10 @@ impl Engine {
         let core_config = &vxresult!(core_app.read()).get_config();
         let asset_manager = AssetManager::new(&core_config);
         let config = core_config.get_render().clone();
+        let mut timing = Timing::new();
+        timing.set_fixed_update_rate(core_config.get_fixed_update_rate());
         let gapi_engine = Arc::new(RwLock::new(GraphicApiEngine::new(os_app, core_config)));
+        let event_dispatcher = vxresult!(os_app.read()).get_event_dispatcher();
         let myself = None;
         let multithreaded_engine =
             MultithreadedEngine::new(gapi_engine.clone(), &asset_manager, &config);
@@ -35,9 +42,11 @@ impl Engine {
             os_app: Arc::downgrade(os_app),
             core_app,
             asset_manager,
-            timing: Arc::new(RwLock::new(Timing::new())),
+            timing: Arc::new(RwLock::new(timing)),
             config,
             multithreaded_engine,
+            event_dispatcher,
+            fixed_update_events: Arc::new(Mutex::new(Vec::new())),
         }
     }
 
@@ -62,8 +71,32 @@ impl Engine {
         return &self.config;
     }
 
+    // Timing goes on and fixed updates are done, it must be called before Application::update
+    // so the application interpolates with the alpha of current frame.
     pub fn update(&self) {
-        vxresult!(self.timing.write()).update();
+        let (fixed_updates_count, dt, real_delta) = {
+            let mut timing = vxresult!(self.timing.write());
+            timing.update();
+            (
+                timing.get_fixed_updates_count(),
+                timing.get_fixed_delta(),
+                timing.get_real_delta(),
+            )
+        };
+        if fixed_updates_count > 0 {
+            let mut core_app = vxresult!(self.core_app.write());
+            for _ in 0..fixed_updates_count {
+                core_app.fixed_update(dt);
+            }
+        }
+        let events = replace(&mut *vxresult!(self.fixed_update_events.lock()), Vec::new());
+        for e in events {
+            self.event_dispatcher.post(e);
+        }
+        vxresult!(self.asset_manager.get_audio_manager().write()).update(real_delta);
+    }
+
+    pub fn render(&self) {
         self.multithreaded_engine.render();
     }
 
@@ -72,6 +105,18 @@ impl Engine {
     }
 
     pub fn on_event(&self, _e: Event) {}
+
+    // Parts that are updated in Application::fixed_update, e.g. physics worlds, push their events
+    // here, because the application is locked there. They are posted after fixed updates.
+    pub fn get_fixed_update_events(&self) -> &Arc<Mutex<Vec<Event>>> {
+        return &self.fixed_update_events;
+    }
+
+    // Events that are made by engine parts, they are posted to the dispatcher of OS application,
+    // so handlers and application receive them with the events of next frame
+    pub fn push_event(&self, e: Event) {
+        self.event_dispatcher.post(e);
+    }
 }
 
 unsafe impl Send for Engine {}
@@ -1,3 +1,4 @@
+use super::super::collision::aabb::Aabb3;
 use super::command::Pool as CmdPool;
 use super::engine::Engine;
 use super::gapi::GraphicApiEngine;
@@ -39,6 +40,8 @@ pub trait Light: Object + Transferable {
 
 pub trait ShadowMaker: Light {
     fn shadow(&self, &mut Model, &Arc<RwLock<Model>>, usize);
+    // Broadphase test for world space AABBs, it must be conservative
+    fn is_in_shadow_range(&self, &Aabb3) -> bool;
     fn begin_secondary_commands(&self, &GraphicApiEngine, &Arc<CmdPool>, &Shadower, usize, usize);
     fn render_shadow_mapper(&self, &Shadower, usize, usize);
     fn submit_shadow_mapper(
@@ -15,6 +15,7 @@ use super::{
     DefaultLighting, Directional, DirectionalUniform, Light, Point,
     ShadowAccumulatorDirectionalUniform, ShadowMaker,
 };
+use std::f32::MAX as F32MAX;
 use std::mem::size_of;
 use std::sync::{Arc, Mutex, RwLock, Weak};
 
@@ -305,8 +306,12 @@ impl Sun {
             wbs.push(b);
         }
         for i in 0..ccdsc {
-            self.cascade_cameras[i].boundary = wbs[i];
-            self.cascade_cameras[i].boundary.insert_aabb(&wbs[i + 1]);
+            let mut b = wbs[i];
+            b.insert_aabb(&wbs[i + 1]);
+            // casters between the light and the cascade must not be culled
+            let bmx = b.get_max();
+            b.insert(&cgmath::Vector3::new(bmx.x, bmx.y, F32MAX));
+            self.cascade_cameras[i].boundary = b;
         }
     }
 }
@@ -399,6 +404,16 @@ impl ShadowMaker for Sun {
         vxresult!(self.kernels_data[kernel_index].lock()).shadow(m, mc);
     }
 
+    fn is_in_shadow_range(&self, aabb: &Aabb3) -> bool {
+        let aabb = aabb.get_transformed(&self.zero_located_view);
+        for c in &self.cascade_cameras {
+            if c.boundary.intersects_aabb(&aabb) {
+                return true;
+            }
+        }
+        return false;
+    }
+
     fn begin_secondary_commands(
         &self,
         geng: &GraphicApiEngine,
@@ -1,12 +1,18 @@
 use super::super::core::gx3d::{Gx3DReader, Table as Gx3dTable};
 use super::super::core::object::Object as CoreObject;
 use super::super::core::types::{Id, Real};
-use super::buffer::Static as StaticBuffer;
+use super::super::math::kdtree::KDTree;
+use super::super::math::ray::Ray3;
+use super::super::math::triangle::{SolidTriangle, Triangle};
+use super::buffer::{
+    Dynamic as DynamicBuffer, IndexType, Manager as BufferManager, Static as StaticBuffer,
+};
 use super::command::Buffer as CmdBuffer;
 use super::engine::Engine;
+use super::mesh_optimizer::{optimize, Indices};
 use super::object::{Base as ObjectBase, Object};
 use std::collections::BTreeMap;
-use std::mem::size_of;
+use std::mem::{replace, size_of};
 use std::sync::{Arc, RwLock, Weak};
 
 use cgmath;
@@ -27,6 +33,8 @@ pub trait Mesh: Object {
     fn render_gbuffer(&self, &mut CmdBuffer, usize);
     fn render_unlit(&self, &mut CmdBuffer, usize);
     fn render_shadow(&self, &mut CmdBuffer, usize);
+    // Ray must be in mesh space, returns distance and normal of the nearest hit
+    fn intersect_ray(&self, &Ray3, Real) -> Option<(Real, cgmath::Vector3<Real>)>;
 }
 
 #[cfg_attr(debug_mode, derive(Debug))]
@@ -106,6 +114,21 @@ impl Manager {
         return mesh;
     }
 
+    pub fn create_dynamic(
+        &mut self,
+        vertices: &[Real],
+        indices: &[u32],
+        occlusion_culling_radius: Real,
+        engine: &Engine,
+    ) -> Arc<RwLock<Dynamic>> {
+        let mesh = Dynamic::new(vertices, indices, occlusion_culling_radius, engine);
+        let mesh_id = mesh.get_id();
+        let mesh = Arc::new(RwLock::new(mesh));
+        let m: Arc<RwLock<Mesh>> = mesh.clone();
+        self.meshes.insert(mesh_id, Arc::downgrade(&m));
+        return mesh;
+    }
+
     pub fn create_cube(&mut self, aspect: Real) -> Arc<RwLock<Mesh>> {
         let eng = vxunwrap!(vxunwrap!(&self.engine).upgrade());
         let eng = vxresult!(eng.read());
@@ -178,7 +201,9 @@ pub struct Base {
     vertex_buffer: StaticBuffer,
     index_buffer: StaticBuffer,
     indices_count: u32,
+    index_type: IndexType,
     occlusion_culling_radius: Real,
+    kdtree: Option<KDTree<SolidTriangle>>,
 }
 
 impl Base {
@@ -280,6 +305,24 @@ impl Base {
         let offset = view.offset();
         let end = view.length() + offset;
         let index_buffer = &data[offset..end];
+        let config = engine.get_config();
+        let mut kdtree = None;
+        if config.get_optimize_imported_meshes() || config.get_keep_mesh_data_on_cpu() {
+            let mut vertices = Vec::with_capacity(vertex_buffer.len() / 4);
+            for b in vertex_buffer.chunks(4) {
+                vertices.push(Real::from_bits(u32::from_le_bytes([
+                    b[0], b[1], b[2], b[3],
+                ])));
+            }
+            let mut indices = Vec::with_capacity(indices_count);
+            for b in index_buffer.chunks(4) {
+                indices.push(u32::from_le_bytes([b[0], b[1], b[2], b[3]]));
+            }
+            if config.get_optimize_imported_meshes() {
+                return Self::new_optimized(ObjectBase::new(), &vertices, &indices, engine);
+            }
+            kdtree = Some(create_kdtree(&vertices, &indices));
+        }
         let indices_count = indices_count as u32;
         let gapi_engine = vxresult!(engine.get_gapi_engine().read());
         let vertex_buffer = vxresult!(gapi_engine.get_buffer_manager().write())
@@ -292,7 +335,9 @@ impl Base {
             vertex_buffer,
             index_buffer,
             indices_count,
+            index_type: IndexType::U32,
             occlusion_culling_radius,
+            kdtree,
         }
     }
 
@@ -307,12 +352,62 @@ impl Base {
         let vertex_buffer = buffer_manager.create_static_buffer_with_vec(vertices);
         let index_buffer = buffer_manager.create_static_buffer_with_vec(indices);
         let obj_base = ObjectBase::new();
+        let kdtree = if engine.get_config().get_keep_mesh_data_on_cpu() {
+            Some(create_kdtree(vertices, indices))
+        } else {
+            None
+        };
         Self {
             obj_base,
             vertex_buffer,
             index_buffer,
             indices_count: indices.len() as u32,
+            index_type: IndexType::U32,
             occlusion_culling_radius,
+            kdtree,
+        }
+    }
+
+    // Welds vertices, reorders them for better GPU cache usage and less overdraw,
+    // it also chooses 16 bit indices whenever it is possible.
+    pub fn new_optimized(
+        obj_base: ObjectBase,
+        vertices: &[Real],
+        indices: &[u32],
+        engine: &Engine,
+    ) -> Self {
+        let optimized = optimize(vertices, indices, VERTEX_ELEMENTS_COUNT);
+        let gapi_engine = vxresult!(engine.get_gapi_engine().read());
+        let mut buffer_manager = vxresult!(gapi_engine.get_buffer_manager().write());
+        let vertex_buffer = buffer_manager.create_static_buffer_with_vec(&optimized.vertices);
+        let index_buffer = match &optimized.indices {
+            &Indices::U16(ref i) => buffer_manager.create_static_buffer_with_vec(i),
+            &Indices::U32(ref i) => buffer_manager.create_static_buffer_with_vec(i),
+        };
+        #[cfg(debug_gx3d)]
+        vxlogi!(
+            "Mesh optimized, vertices: {} -> {}, occlusion culling radius: {}",
+            vertices.len() / VERTEX_ELEMENTS_COUNT,
+            optimized.vertices.len() / VERTEX_ELEMENTS_COUNT,
+            optimized.bounds.radius
+        );
+        let kdtree = if engine.get_config().get_keep_mesh_data_on_cpu() {
+            let indices = match &optimized.indices {
+                &Indices::U16(ref i) => i.iter().map(|i| *i as u32).collect(),
+                &Indices::U32(ref i) => i.clone(),
+            };
+            Some(create_kdtree(&optimized.vertices, &indices))
+        } else {
+            None
+        };
+        Self {
+            obj_base,
+            vertex_buffer,
+            index_buffer,
+            indices_count: optimized.indices.len() as u32,
+            index_type: optimized.indices.get_type(),
+            occlusion_culling_radius: optimized.bounds.radius,
+            kdtree,
         }
     }
 
@@ -335,6 +430,9 @@ impl Base {
         let indices = reader.read_array::<u32>();
         let occlusion_culling_radius = reader.read();
         let obj_base = ObjectBase::new_with_id(my_id);
+        if engine.get_config().get_optimize_imported_meshes() {
+            return Self::new_optimized(obj_base, &vertices, &indices, engine);
+        }
         let gapi_engine = vxresult!(engine.get_gapi_engine().read());
         let mut buffer_manager = vxresult!(gapi_engine.get_buffer_manager().write());
         let vertex_buffer = buffer_manager.create_static_buffer_with_vec(&vertices);
@@ -344,12 +442,19 @@ impl Base {
         vxlogi!("Number of indices is: {}", indices_count);
         #[cfg(debug_gx3d)]
         vxlogi!("Occlusion culling radius is: {}", occlusion_culling_radius);
+        let kdtree = if engine.get_config().get_keep_mesh_data_on_cpu() {
+            Some(create_kdtree(&vertices, &indices))
+        } else {
+            None
+        };
         Self {
             obj_base,
             vertex_buffer,
             index_buffer,
             indices_count,
+            index_type: IndexType::U32,
             occlusion_culling_radius,
+            kdtree,
         }
     }
 }
@@ -401,14 +506,384 @@ impl Mesh for Base {
     fn update(&mut self, _: usize) {}
 
     fn render_gbuffer(&self, cmd: &mut CmdBuffer, _: usize) {
-        cmd.render_gbuff(&self.vertex_buffer, &self.index_buffer, self.indices_count);
+        cmd.render_gbuff(
+            self.vertex_buffer.get_buffer(),
+            self.index_buffer.get_buffer(),
+            self.indices_count,
+            self.index_type,
+        );
     }
 
     fn render_unlit(&self, cmd: &mut CmdBuffer, _: usize) {
-        cmd.render_unlit(&self.vertex_buffer, &self.index_buffer, self.indices_count);
+        cmd.render_unlit(
+            self.vertex_buffer.get_buffer(),
+            self.index_buffer.get_buffer(),
+            self.indices_count,
+            self.index_type,
+        );
     }
 
     fn render_shadow(&self, cmd: &mut CmdBuffer, _: usize) {
-        cmd.render_shadow_mapper(&self.vertex_buffer, &self.index_buffer, self.indices_count);
+        cmd.render_shadow_mapper(
+            self.vertex_buffer.get_buffer(),
+            self.index_buffer.get_buffer(),
+            self.indices_count,
+            self.index_type,
+        );
+    }
+
+    fn intersect_ray(&self, ray: &Ray3, tmax: Real) -> Option<(Real, cgmath::Vector3<Real>)> {
+        let kdtree = match &self.kdtree {
+            &Some(ref kdtree) => kdtree,
+            &None => return None,
+        };
+        match kdtree.hit(ray, tmax) {
+            Some((t, _, _, i)) => Some((t, kdtree.get_triangles()[i].get_normal())),
+            None => None,
+        }
+    }
+}
+
+pub const VERTEX_ELEMENTS_COUNT: usize = 12; // position(3), normal(3), tangent(4), uv(2)
+
+/// A mesh that CPU can rewrite its vertices and indices in every frame.
+/// Each frame has its own buffer, so in-flight frames never get overwritten.
+#[cfg_attr(debug_mode, derive(Debug))]
+pub struct Dynamic {
+    obj_base: ObjectBase,
+    buffer_manager: Arc<RwLock<BufferManager>>,
+    vertices: Vec<Real>,
+    indices: Vec<u32>,
+    vertex_buffer: DynamicBuffer,
+    index_buffer: DynamicBuffer,
+    retired_buffers: RetiredBuffers<DynamicBuffer>,
+    version: u64,
+    frames_version: Vec<u64>,
+    frames_indices_count: Vec<u32>,
+    occlusion_culling_radius: Real,
+    is_shadow_caster: bool,
+    is_transparent: bool,
+}
+
+impl Dynamic {
+    pub fn new(
+        vertices: &[Real],
+        indices: &[u32],
+        occlusion_culling_radius: Real,
+        engine: &Engine,
+    ) -> Self {
+        let vertices_count = vertices.len() / VERTEX_ELEMENTS_COUNT;
+        Self::new_with_capacity(
+            vertices_count,
+            indices.len(),
+            vertices,
+            indices,
+            occlusion_culling_radius,
+            engine,
+        )
+    }
+
+    pub fn new_with_capacity(
+        vertices_capacity: usize,
+        indices_capacity: usize,
+        vertices: &[Real],
+        indices: &[u32],
+        occlusion_culling_radius: Real,
+        engine: &Engine,
+    ) -> Self {
+        #[cfg(debug_mode)]
+        {
+            if vertices.len() % VERTEX_ELEMENTS_COUNT != 0 {
+                vxlogf!("Vertices length must be a multiple of vertex elements count.");
+            }
+        }
+        let vertices_capacity = vertices_capacity
+            .max(vertices.len() / VERTEX_ELEMENTS_COUNT)
+            .max(1);
+        let indices_capacity = indices_capacity.max(indices.len()).max(1);
+        let gapi_engine = vxresult!(engine.get_gapi_engine().read());
+        let frames_count = gapi_engine.get_frames_count();
+        let buffer_manager = gapi_engine.get_buffer_manager().clone();
+        let (vertex_buffer, index_buffer) = {
+            let mut buffer_manager = vxresult!(buffer_manager.write());
+            (
+                buffer_manager.create_dynamic_buffer(
+                    (vertices_capacity * VERTEX_ELEMENTS_COUNT * size_of::<Real>()) as isize,
+                ),
+                buffer_manager
+                    .create_dynamic_buffer((indices_capacity * size_of::<u32>()) as isize),
+            )
+        };
+        Self {
+            obj_base: ObjectBase::new(),
+            buffer_manager,
+            vertices: vertices.to_vec(),
+            indices: indices.to_vec(),
+            vertex_buffer,
+            index_buffer,
+            retired_buffers: RetiredBuffers::new(),
+            version: 1,
+            frames_version: vec![0; frames_count],
+            frames_indices_count: vec![0; frames_count],
+            occlusion_culling_radius,
+            is_shadow_caster: true,
+            is_transparent: false,
+        }
+    }
+
+    pub fn set_vertices(&mut self, vertices: &[Real]) {
+        #[cfg(debug_mode)]
+        {
+            if vertices.len() % VERTEX_ELEMENTS_COUNT != 0 {
+                vxlogf!("Vertices length must be a multiple of vertex elements count.");
+            }
+        }
+        self.vertices.clear();
+        self.vertices.extend_from_slice(vertices);
+        self.version += 1;
+    }
+
+    pub fn set_indices(&mut self, indices: &[u32]) {
+        self.indices.clear();
+        self.indices.extend_from_slice(indices);
+        self.version += 1;
+    }
+
+    pub fn set_data(&mut self, vertices: &[Real], indices: &[u32]) {
+        self.set_vertices(vertices);
+        self.set_indices(indices);
+    }
+
+    pub fn get_mut_vertices(&mut self) -> &mut Vec<Real> {
+        self.version += 1;
+        return &mut self.vertices;
+    }
+
+    pub fn get_mut_indices(&mut self) -> &mut Vec<u32> {
+        self.version += 1;
+        return &mut self.indices;
+    }
+
+    pub fn get_vertices(&self) -> &[Real] {
+        return &self.vertices;
+    }
+
+    pub fn get_indices(&self) -> &[u32] {
+        return &self.indices;
+    }
+
+    pub fn set_occlusion_culling_radius(&mut self, radius: Real) {
+        self.occlusion_culling_radius = radius;
+    }
+
+    pub fn set_shadow_caster(&mut self, b: bool) {
+        self.is_shadow_caster = b;
+    }
+
+    pub fn set_transparent(&mut self, b: bool) {
+        self.is_transparent = b;
+    }
+
+    fn reserve(&mut self, frame_number: usize) {
+        let vertices_size = (self.vertices.len() * size_of::<Real>()) as isize;
+        let indices_size = (self.indices.len() * size_of::<u32>()) as isize;
+        let vertex_size = grown_size(vertices_size, self.vertex_buffer.get_size());
+        let index_size = grown_size(indices_size, self.index_buffer.get_size());
+        if vertex_size.is_none() && index_size.is_none() {
+            return;
+        }
+        let frames_count = self.frames_version.len();
+        let mut buffer_manager = vxresult!(self.buffer_manager.write());
+        if let Some(size) = vertex_size {
+            let old = replace(
+                &mut self.vertex_buffer,
+                buffer_manager.create_dynamic_buffer(size),
+            );
+            self.retired_buffers.retire(old, frames_count, frame_number);
+        }
+        if let Some(size) = index_size {
+            let old = replace(
+                &mut self.index_buffer,
+                buffer_manager.create_dynamic_buffer(size),
+            );
+            self.retired_buffers.retire(old, frames_count, frame_number);
+        }
+        // new buffers do not have data of any frame
+        for v in &mut self.frames_version {
+            *v = 0;
+        }
+    }
+}
+
+// Doubles the size of buffer when the data does not fit in it
+fn grown_size(data_size: isize, buffer_size: isize) -> Option<isize> {
+    if data_size <= buffer_size {
+        return None;
+    }
+    return Some(data_size.max(buffer_size * 2));
+}
+
+// Buffers that are replaced by bigger ones, the command buffers of other in-flight frames may
+// still use them. Each frame is updated after its previous submission is finished, so a buffer
+// is released when all of the other frames are updated.
+#[cfg_attr(debug_mode, derive(Debug))]
+struct RetiredBuffers<T> {
+    buffers: Vec<(T, Vec<bool>)>,
+}
+
+impl<T> RetiredBuffers<T> {
+    fn new() -> Self {
+        Self {
+            buffers: Vec::new(),
+        }
+    }
+
+    fn retire(&mut self, buffer: T, frames_count: usize, frame_number: usize) {
+        let mut pending_frames = vec![true; frames_count];
+        pending_frames[frame_number] = false;
+        self.buffers.push((buffer, pending_frames));
+    }
+
+    fn release(&mut self, frame_number: usize) {
+        for b in &mut self.buffers {
+            b.1[frame_number] = false;
+        }
+        self.buffers.retain(|b| b.1.contains(&true));
+    }
+}
+
+impl CoreObject for Dynamic {
+    fn get_id(&self) -> Id {
+        self.obj_base.get_id()
+    }
+}
+
+impl Object for Dynamic {
+    fn get_name(&self) -> Option<String> {
+        self.obj_base.get_name()
+    }
+
+    fn set_name(&mut self, name: &str) {
+        self.obj_base.set_name(name);
+        vxunimplemented!(); //it must update corresponding manager
+    }
+
+    fn disable_rendering(&mut self) {
+        self.obj_base.disable_rendering()
+    }
+
+    fn enable_rendering(&mut self) {
+        self.obj_base.enable_rendering()
+    }
+
+    fn is_renderable(&self) -> bool {
+        return self.obj_base.is_renderable();
+    }
+}
+
+impl Mesh for Dynamic {
+    fn is_shadow_caster(&self) -> bool {
+        return self.is_shadow_caster;
+    }
+
+    fn is_transparent(&self) -> bool {
+        return self.is_transparent;
+    }
+
+    fn get_occlusion_culling_radius(&self) -> Real {
+        return self.occlusion_culling_radius;
+    }
+
+    fn update(&mut self, frame_number: usize) {
+        self.retired_buffers.release(frame_number);
+        if self.frames_version[frame_number] == self.version {
+            return;
+        }
+        self.reserve(frame_number);
+        self.vertex_buffer
+            .update_with_vec(&self.vertices, frame_number);
+        self.index_buffer
+            .update_with_vec(&self.indices, frame_number);
+        self.frames_indices_count[frame_number] = self.indices.len() as u32;
+        self.frames_version[frame_number] = self.version;
+    }
+
+    fn render_gbuffer(&self, cmd: &mut CmdBuffer, frame_number: usize) {
+        cmd.render_gbuff(
+            self.vertex_buffer.get_buffer(frame_number),
+            self.index_buffer.get_buffer(frame_number),
+            self.frames_indices_count[frame_number],
+            IndexType::U32,
+        );
+    }
+
+    fn render_unlit(&self, cmd: &mut CmdBuffer, frame_number: usize) {
+        cmd.render_unlit(
+            self.vertex_buffer.get_buffer(frame_number),
+            self.index_buffer.get_buffer(frame_number),
+            self.frames_indices_count[frame_number],
+            IndexType::U32,
+        );
+    }
+
+    fn render_shadow(&self, cmd: &mut CmdBuffer, frame_number: usize) {
+        cmd.render_shadow_mapper(
+            self.vertex_buffer.get_buffer(frame_number),
+            self.index_buffer.get_buffer(frame_number),
+            self.frames_indices_count[frame_number],
+            IndexType::U32,
+        );
+    }
+
+    fn intersect_ray(&self, ray: &Ray3, tmax: Real) -> Option<(Real, cgmath::Vector3<Real>)> {
+        let positions = get_positions(&self.vertices);
+        let mut result = None;
+        let mut tmax = tmax;
+        for i in self.indices.chunks(3) {
+            if i.len() < 3 {
+                break;
+            }
+            let t = SolidTriangle::new(&[i[0] as usize, i[1] as usize, i[2] as usize], &positions);
+            if let Some((d, _, _)) = t.intersect(ray, tmax, &positions) {
+                tmax = d;
+                result = Some((d, t.get_normal()));
+            }
+        }
+        return result;
+    }
+}
+
+fn get_positions(vertices: &[Real]) -> Vec<cgmath::Vector3<Real>> {
+    let mut positions = Vec::with_capacity(vertices.len() / VERTEX_ELEMENTS_COUNT);
+    for v in vertices.chunks(VERTEX_ELEMENTS_COUNT) {
+        positions.push(cgmath::Vector3::new(v[0], v[1], v[2]));
+    }
+    return positions;
+}
+
+fn create_kdtree(vertices: &[Real], indices: &[u32]) -> KDTree<SolidTriangle> {
+    return KDTree::new_with_indices(get_positions(vertices), indices);
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn dynamic_growth_test() {
+        // vertices of set_data do not fit in the first buffer
+        assert_eq!(grown_size(64, 64), None);
+        assert_eq!(grown_size(80, 64), Some(128));
+        assert_eq!(grown_size(300, 64), Some(300));
+        // frame 1 grows the buffer while frames 0 and 2 are in flight
+        let mut retired = RetiredBuffers::new();
+        retired.release(1);
+        retired.retire("old", 3, 1);
+        retired.release(2);
+        assert_eq!(retired.buffers.len(), 1);
+        retired.release(1);
+        assert_eq!(retired.buffers.len(), 1);
+        retired.release(0);
+        assert!(retired.buffers.is_empty());
     }
 }
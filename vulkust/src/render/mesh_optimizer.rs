@@ -0,0 +1,549 @@
+use super::super::core::types::Real;
+use super::buffer::IndexType;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+
+use cgmath;
+use cgmath::InnerSpace;
+
+const CACHE_SIZE: usize = 32;
+const CACHE_DECAY_POWER: Real = 1.5;
+const LAST_TRIANGLE_SCORE: Real = 0.75;
+const VALENCE_BOOST_SCALE: Real = 2.0;
+const VALENCE_BOOST_POWER: Real = 0.5;
+
+const OVERDRAW_CACHE_SIZE: u32 = 16;
+const OVERDRAW_THRESHOLD: Real = 1.05;
+
+#[derive(Clone)]
+#[cfg_attr(debug_mode, derive(Debug))]
+pub enum Indices {
+    U16(Vec<u16>),
+    U32(Vec<u32>),
+}
+
+impl Indices {
+    pub fn new(indices: &[u32], vertices_count: usize) -> Self {
+        if vertices_count <= (1 << 16) {
+            let mut result = Vec::with_capacity(indices.len());
+            for i in indices {
+                result.push(*i as u16);
+            }
+            Indices::U16(result)
+        } else {
+            Indices::U32(indices.to_vec())
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        match self {
+            &Indices::U16(ref v) => v.len(),
+            &Indices::U32(ref v) => v.len(),
+        }
+    }
+
+    pub fn get_type(&self) -> IndexType {
+        match self {
+            &Indices::U16(_) => IndexType::U16,
+            &Indices::U32(_) => IndexType::U32,
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+#[cfg_attr(debug_mode, derive(Debug))]
+pub struct Bounds {
+    pub min: cgmath::Vector3<Real>,
+    pub max: cgmath::Vector3<Real>,
+    // distance of the farthest vertex from the origin of mesh
+    pub radius: Real,
+}
+
+#[cfg_attr(debug_mode, derive(Debug))]
+pub struct Optimized {
+    pub vertices: Vec<Real>,
+    pub indices: Indices,
+    pub bounds: Bounds,
+}
+
+pub fn optimize(vertices: &[Real], indices: &[u32], elements_count: usize) -> Optimized {
+    let (vertices, mut indices) = weld(vertices, indices, elements_count);
+    let vertices_count = vertices.len() / elements_count;
+    optimize_vertex_cache(&mut indices, vertices_count);
+    optimize_overdraw(&mut indices, &vertices, elements_count, OVERDRAW_THRESHOLD);
+    let vertices = optimize_vertex_fetch(&vertices, &mut indices, elements_count);
+    let bounds = compute_bounds(&vertices, elements_count);
+    let indices = Indices::new(&indices, vertices.len() / elements_count);
+    Optimized {
+        vertices,
+        indices,
+        bounds,
+    }
+}
+
+pub fn weld(vertices: &[Real], indices: &[u32], elements_count: usize) -> (Vec<Real>, Vec<u32>) {
+    let vertices_count = vertices.len() / elements_count;
+    let mut unique = HashMap::with_capacity(vertices_count);
+    let mut remap = Vec::with_capacity(vertices_count);
+    let mut result = Vec::with_capacity(vertices.len());
+    for vi in 0..vertices_count {
+        let vertex = &vertices[vi * elements_count..(vi + 1) * elements_count];
+        let mut key = Vec::with_capacity(elements_count);
+        for e in vertex {
+            // +0.0 and -0.0 must be same
+            key.push(if *e == 0.0 { 0 } else { e.to_bits() });
+        }
+        let next_index = unique.len() as u32;
+        let index = *unique.entry(key).or_insert(next_index);
+        if index == next_index {
+            result.extend_from_slice(vertex);
+        }
+        remap.push(index);
+    }
+    let mut result_indices = Vec::with_capacity(indices.len());
+    for i in indices {
+        result_indices.push(remap[*i as usize]);
+    }
+    return (result, result_indices);
+}
+
+fn vertex_score(cache_position: i32, remaining_valence: u32) -> Real {
+    if remaining_valence == 0 {
+        return -1.0;
+    }
+    let mut score = 0.0;
+    if cache_position >= 0 {
+        if cache_position < 3 {
+            score = LAST_TRIANGLE_SCORE;
+        } else {
+            let scaler = 1.0 / (CACHE_SIZE - 3) as Real;
+            score = (1.0 - (cache_position - 3) as Real * scaler).powf(CACHE_DECAY_POWER);
+        }
+    }
+    score + VALENCE_BOOST_SCALE * (remaining_valence as Real).powf(-VALENCE_BOOST_POWER)
+}
+
+// Tom Forsyth's linear-speed vertex cache optimisation
+pub fn optimize_vertex_cache(indices: &mut [u32], vertices_count: usize) {
+    let triangles_count = indices.len() / 3;
+    if triangles_count == 0 {
+        return;
+    }
+    let mut remaining = vec![0u32; vertices_count];
+    for i in indices.iter() {
+        remaining[*i as usize] += 1;
+    }
+    let mut offsets = vec![0usize; vertices_count + 1];
+    for v in 0..vertices_count {
+        offsets[v + 1] = offsets[v] + remaining[v] as usize;
+    }
+    let mut adjacency = vec![0usize; indices.len()];
+    {
+        let mut fill = offsets.clone();
+        for (ii, i) in indices.iter().enumerate() {
+            let v = *i as usize;
+            adjacency[fill[v]] = ii / 3;
+            fill[v] += 1;
+        }
+    }
+    let mut cache_position = vec![-1i32; vertices_count];
+    let mut scores = Vec::with_capacity(vertices_count);
+    for v in 0..vertices_count {
+        scores.push(vertex_score(-1, remaining[v]));
+    }
+    let mut triangle_scores = Vec::with_capacity(triangles_count);
+    for t in 0..triangles_count {
+        let i = t * 3;
+        triangle_scores.push(
+            scores[indices[i] as usize]
+                + scores[indices[i + 1] as usize]
+                + scores[indices[i + 2] as usize],
+        );
+    }
+    let mut emitted = vec![false; triangles_count];
+    let mut result = Vec::with_capacity(indices.len());
+    let mut cache: Vec<usize> = Vec::with_capacity(CACHE_SIZE + 3);
+    let mut new_cache: Vec<usize> = Vec::with_capacity(CACHE_SIZE + 3);
+    let mut next_unemitted = 0;
+    let mut best = 0;
+    let mut best_score = triangle_scores[0];
+    for t in 1..triangles_count {
+        if triangle_scores[t] > best_score {
+            best = t;
+            best_score = triangle_scores[t];
+        }
+    }
+    loop {
+        emitted[best] = true;
+        let tri = [
+            indices[best * 3] as usize,
+            indices[best * 3 + 1] as usize,
+            indices[best * 3 + 2] as usize,
+        ];
+        new_cache.clear();
+        for v in &tri {
+            result.push(*v as u32);
+            let start = offsets[*v];
+            let end = start + remaining[*v] as usize;
+            for a in start..end {
+                if adjacency[a] == best {
+                    adjacency.swap(a, end - 1);
+                    break;
+                }
+            }
+            remaining[*v] -= 1;
+            if !new_cache.contains(v) {
+                new_cache.push(*v);
+            }
+        }
+        for v in &cache {
+            if !tri.contains(v) {
+                new_cache.push(*v);
+            }
+        }
+        for (p, v) in new_cache.iter().enumerate() {
+            cache_position[*v] = if p < CACHE_SIZE { p as i32 } else { -1 };
+            let score = vertex_score(cache_position[*v], remaining[*v]);
+            let diff = score - scores[*v];
+            scores[*v] = score;
+            let start = offsets[*v];
+            for a in start..start + remaining[*v] as usize {
+                triangle_scores[adjacency[a]] += diff;
+            }
+        }
+        new_cache.truncate(CACHE_SIZE);
+        ::std::mem::swap(&mut cache, &mut new_cache);
+        let mut found = false;
+        best_score = -1.0;
+        for v in &cache {
+            let start = offsets[*v];
+            for a in start..start + remaining[*v] as usize {
+                let t = adjacency[a];
+                if triangle_scores[t] > best_score {
+                    best = t;
+                    best_score = triangle_scores[t];
+                    found = true;
+                }
+            }
+        }
+        if !found {
+            while next_unemitted < triangles_count && emitted[next_unemitted] {
+                next_unemitted += 1;
+            }
+            if next_unemitted == triangles_count {
+                break;
+            }
+            best = next_unemitted;
+        }
+    }
+    indices.copy_from_slice(&result);
+}
+
+struct FifoCache {
+    timestamps: Vec<u32>,
+    timestamp: u32,
+}
+
+impl FifoCache {
+    fn new(vertices_count: usize) -> Self {
+        Self {
+            timestamps: vec![0; vertices_count],
+            timestamp: OVERDRAW_CACHE_SIZE + 1,
+        }
+    }
+
+    fn reset(&mut self) {
+        self.timestamp += OVERDRAW_CACHE_SIZE + 1;
+    }
+
+    fn misses(&mut self, triangle: &[u32]) -> u32 {
+        let mut misses = 0;
+        for v in triangle {
+            let v = *v as usize;
+            if self.timestamp - self.timestamps[v] > OVERDRAW_CACHE_SIZE {
+                self.timestamps[v] = self.timestamp;
+                self.timestamp += 1;
+                misses += 1;
+            }
+        }
+        return misses;
+    }
+}
+
+// Clusters the cache optimised triangle list and sorts clusters from outside to inside
+// of the mesh, it is an approximation of Sander's Tipsy algorithm.
+// threshold: allowed degradation of vertex cache efficiency
+pub fn optimize_overdraw(
+    indices: &mut [u32],
+    vertices: &[Real],
+    elements_count: usize,
+    threshold: Real,
+) {
+    let triangles_count = indices.len() / 3;
+    if triangles_count < 2 {
+        return;
+    }
+    let vertices_count = vertices.len() / elements_count;
+    let mut cache = FifoCache::new(vertices_count);
+    let mut hard_clusters = Vec::new();
+    for t in 0..triangles_count {
+        if cache.misses(&indices[t * 3..t * 3 + 3]) == 3 {
+            hard_clusters.push(t);
+        }
+    }
+    if hard_clusters.len() == 0 || hard_clusters[0] != 0 {
+        hard_clusters.insert(0, 0);
+    }
+    let mut clusters = Vec::new();
+    for ci in 0..hard_clusters.len() {
+        let start = hard_clusters[ci];
+        let end = if ci + 1 < hard_clusters.len() {
+            hard_clusters[ci + 1]
+        } else {
+            triangles_count
+        };
+        cache.reset();
+        let mut misses = 0;
+        for t in start..end {
+            misses += cache.misses(&indices[t * 3..t * 3 + 3]);
+        }
+        let cluster_acmr = misses as Real / (end - start) as Real;
+        cache.reset();
+        misses = 0;
+        let mut soft_start = start;
+        clusters.push(start);
+        for t in start..end {
+            misses += cache.misses(&indices[t * 3..t * 3 + 3]);
+            let acmr = misses as Real / (t + 1 - soft_start) as Real;
+            if t + 1 < end && acmr <= cluster_acmr * threshold {
+                clusters.push(t + 1);
+                soft_start = t + 1;
+                misses = 0;
+                cache.reset();
+            }
+        }
+    }
+    let position = |i: u32| {
+        let i = i as usize * elements_count;
+        cgmath::Vector3::new(vertices[i], vertices[i + 1], vertices[i + 2])
+    };
+    let mut mesh_centroid = cgmath::Vector3::new(0.0, 0.0, 0.0);
+    let mut mesh_area = 0.0;
+    let mut clusters_data = Vec::with_capacity(clusters.len());
+    for ci in 0..clusters.len() {
+        let start = clusters[ci];
+        let end = if ci + 1 < clusters.len() {
+            clusters[ci + 1]
+        } else {
+            triangles_count
+        };
+        let mut centroid = cgmath::Vector3::new(0.0, 0.0, 0.0);
+        let mut normal = cgmath::Vector3::new(0.0, 0.0, 0.0);
+        let mut area = 0.0;
+        for t in start..end {
+            let p0 = position(indices[t * 3]);
+            let p1 = position(indices[t * 3 + 1]);
+            let p2 = position(indices[t * 3 + 2]);
+            let n = (p1 - p0).cross(p2 - p0);
+            let a = n.magnitude();
+            centroid += (p0 + p1 + p2) * (a / 3.0);
+            normal += n;
+            area += a;
+        }
+        mesh_centroid += centroid;
+        mesh_area += area;
+        clusters_data.push((start, end, centroid, normal, area));
+    }
+    if mesh_area > 0.0 {
+        mesh_centroid /= mesh_area;
+    }
+    let mut sorted = Vec::with_capacity(clusters_data.len());
+    for (start, end, centroid, normal, area) in clusters_data {
+        let centroid = if area > 0.0 {
+            centroid / area
+        } else {
+            mesh_centroid
+        };
+        let l = normal.magnitude();
+        let normal = if l > 0.0 { normal / l } else { normal };
+        sorted.push((start, end, (centroid - mesh_centroid).dot(normal)));
+    }
+    // bad positions of imported data make NaN and they keep their order
+    sorted.sort_by(|a, b| b.2.partial_cmp(&a.2).unwrap_or(Ordering::Equal));
+    let mut result = Vec::with_capacity(indices.len());
+    for (start, end, _) in sorted {
+        result.extend_from_slice(&indices[start * 3..end * 3]);
+    }
+    indices.copy_from_slice(&result);
+}
+
+// Reorders vertices in order of their first use, unused vertices are going to be removed.
+pub fn optimize_vertex_fetch(
+    vertices: &[Real],
+    indices: &mut [u32],
+    elements_count: usize,
+) -> Vec<Real> {
+    let vertices_count = vertices.len() / elements_count;
+    let mut remap = vec![-1i64; vertices_count];
+    let mut result = Vec::with_capacity(vertices.len());
+    let mut next = 0;
+    for i in indices.iter_mut() {
+        let v = *i as usize;
+        if remap[v] < 0 {
+            remap[v] = next;
+            next += 1;
+            result.extend_from_slice(&vertices[v * elements_count..(v + 1) * elements_count]);
+        }
+        *i = remap[v] as u32;
+    }
+    return result;
+}
+
+pub fn compute_bounds(vertices: &[Real], elements_count: usize) -> Bounds {
+    let mut min = cgmath::Vector3::new(0.0, 0.0, 0.0);
+    let mut max = cgmath::Vector3::new(0.0, 0.0, 0.0);
+    let mut radius2 = 0.0;
+    let vertices_count = vertices.len() / elements_count;
+    for vi in 0..vertices_count {
+        let i = vi * elements_count;
+        let p = cgmath::Vector3::new(vertices[i], vertices[i + 1], vertices[i + 2]);
+        if vi == 0 {
+            min = p;
+            max = p;
+        }
+        min.x = min.x.min(p.x);
+        min.y = min.y.min(p.y);
+        min.z = min.z.min(p.z);
+        max.x = max.x.max(p.x);
+        max.y = max.y.max(p.y);
+        max.z = max.z.max(p.z);
+        radius2 = p.magnitude2().max(radius2);
+    }
+    Bounds {
+        min,
+        max,
+        radius: radius2.sqrt(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn acmr(indices: &[u32], vertices_count: usize) -> Real {
+        let mut cache = FifoCache::new(vertices_count);
+        let mut misses = 0;
+        for t in 0..indices.len() / 3 {
+            misses += cache.misses(&indices[t * 3..t * 3 + 3]);
+        }
+        misses as Real / (indices.len() / 3) as Real
+    }
+
+    fn grid(n: usize) -> (Vec<Real>, Vec<u32>) {
+        let mut vertices = Vec::new();
+        for y in 0..n + 1 {
+            for x in 0..n + 1 {
+                vertices.extend_from_slice(&[x as Real, y as Real, 0.0]);
+            }
+        }
+        let mut indices = Vec::new();
+        // column major order to make it cache unfriendly
+        for x in 0..n {
+            for y in 0..n {
+                let i = (y * (n + 1) + x) as u32;
+                let r = (n + 1) as u32;
+                indices.extend_from_slice(&[i, i + 1, i + r, i + 1, i + r + 1, i + r]);
+            }
+        }
+        (vertices, indices)
+    }
+
+    fn sorted_triangles(vertices: &[Real], indices: &[u32]) -> Vec<Vec<u32>> {
+        let mut result = Vec::new();
+        for t in 0..indices.len() / 3 {
+            let mut tri = Vec::new();
+            for k in 0..3 {
+                let i = indices[t * 3 + k] as usize * 3;
+                for e in 0..3 {
+                    tri.push(vertices[i + e].to_bits());
+                }
+            }
+            result.push(tri);
+        }
+        result.sort();
+        result
+    }
+
+    #[test]
+    fn weld_test() {
+        let vertices = [
+            0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0, //
+            1.0, 0.0, 0.0, -0.0, 1.0, 0.0, 1.0, 1.0, 0.0,
+        ];
+        let indices = [0, 1, 2, 3, 5, 4];
+        let (v, i) = weld(&vertices, &indices, 3);
+        assert_eq!(v.len(), 12);
+        assert_eq!(i, vec![0, 1, 2, 1, 3, 2]);
+    }
+
+    #[test]
+    fn vertex_cache_test() {
+        let (vertices, mut indices) = grid(40);
+        let original = indices.clone();
+        let vertices_count = vertices.len() / 3;
+        let before = acmr(&indices, vertices_count);
+        optimize_vertex_cache(&mut indices, vertices_count);
+        assert!(acmr(&indices, vertices_count) < before);
+        assert_eq!(
+            sorted_triangles(&vertices, &original),
+            sorted_triangles(&vertices, &indices)
+        );
+    }
+
+    #[test]
+    fn optimize_test() {
+        let (vertices, indices) = grid(10);
+        let o = optimize(&vertices, &indices, 3);
+        match &o.indices {
+            &Indices::U16(ref i) => {
+                let i: Vec<u32> = i.iter().map(|i| *i as u32).collect();
+                assert_eq!(
+                    sorted_triangles(&vertices, &indices),
+                    sorted_triangles(&o.vertices, &i)
+                );
+            }
+            _ => panic!("Indices must be 16 bit."),
+        }
+        assert_eq!(o.vertices.len(), vertices.len());
+        assert_eq!(o.bounds.max, cgmath::Vector3::new(10.0, 10.0, 0.0));
+        assert_eq!(o.bounds.min, cgmath::Vector3::new(0.0, 0.0, 0.0));
+        assert!((o.bounds.radius - 200f32.sqrt()).abs() < 0.0001);
+    }
+
+    #[test]
+    fn nan_positions_test() {
+        let (mut vertices, indices) = grid(4);
+        for v in vertices.iter_mut().step_by(7) {
+            *v = std::f32::NAN;
+        }
+        let mut optimized = indices.clone();
+        optimize_overdraw(&mut optimized, &vertices, 3, 1.05);
+        let mut sorted = optimized.clone();
+        sorted.sort();
+        let mut expected = indices.clone();
+        expected.sort();
+        assert_eq!(sorted, expected);
+    }
+
+    #[test]
+    fn indices_type_test() {
+        match Indices::new(&[0, 1, 2], 1 << 16) {
+            Indices::U16(_) => (),
+            _ => panic!("Indices must be 16 bit."),
+        }
+        match Indices::new(&[0, 1, 2], (1 << 16) + 1) {
+            Indices::U32(_) => (),
+            _ => panic!("Indices must be 32 bit."),
+        }
+    }
+}
@@ -14,9 +14,11 @@ pub mod image;
 pub mod light;
 pub mod material;
 pub mod mesh;
+pub mod mesh_optimizer;
 pub mod model;
 pub mod multithreaded;
 pub mod object;
+pub mod particle;
 pub mod pass;
 pub mod pipeline;
 pub mod render_pass;
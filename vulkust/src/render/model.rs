@@ -29,6 +29,11 @@ pub trait Model: Object + Transferable {
     fn get_occlusion_culling_radius(&self) -> Real;
     fn get_distance_from_camera(&self, &Camera) -> Real;
     fn get_uniform(&self) -> &Uniform;
+    fn get_collider(&self) -> &Arc<RwLock<Collider>>;
+    fn set_collider(&mut self, Arc<RwLock<Collider>>);
+    // Transferable setters and bound changes set it, scenes clear it after refitting the model
+    fn is_transformed(&self) -> bool;
+    fn clear_transformed(&mut self);
     fn render_gbuffer(&self, &mut CmdBuffer, usize);
     fn render_shadow(&self, &mut CmdBuffer, usize);
     fn render_unlit(&mut self, &mut CmdBuffer, &Camera, usize);
@@ -160,6 +165,10 @@ impl Uniform {
         return &self.model;
     }
 
+    pub(super) fn set_model(&mut self, model: cgmath::Matrix4<Real>) {
+        self.model = model;
+    }
+
     pub(crate) fn set_model_view_projection(&mut self, mvp: cgmath::Matrix4<Real>) {
         return self.model_view_projection = mvp;
     }
@@ -189,6 +198,7 @@ pub struct Base {
     meshes: BTreeMap<Id, (Arc<RwLock<Mesh>>, Material)>,
     children: BTreeMap<Id, Arc<RwLock<Model>>>,
     scales: cgmath::Vector3<Real>,
+    is_transformed: bool,
 }
 
 impl Base {}
@@ -269,6 +279,7 @@ impl Loadable for Base {
             meshes,
             children: BTreeMap::new(),
             scales: cgmath::Vector3::new(1.0, 1.0, 1.0),
+            is_transformed: true,
         }
     }
 
@@ -310,17 +321,24 @@ impl Loadable for Base {
             meshes,
             children: BTreeMap::new(),
             scales: cgmath::Vector3::new(1.0, 1.0, 1.0),
+            is_transformed: true,
         }
     }
 }
 
 impl Transferable for Base {
-    fn set_orientation(&mut self, _: &cgmath::Quaternion<Real>) {
-        vxunimplemented!();
+    fn set_orientation(&mut self, q: &cgmath::Quaternion<Real>) {
+        // todo take care of children
+        let r = cgmath::Matrix3::from(*q);
+        self.uniform.model.x = (r.x * self.scales.x).extend(0.0);
+        self.uniform.model.y = (r.y * self.scales.y).extend(0.0);
+        self.uniform.model.z = (r.z * self.scales.z).extend(0.0);
+        self.is_transformed = true;
     }
 
-    fn set_location(&mut self, _: &cgmath::Vector3<Real>) {
-        vxunimplemented!();
+    fn set_location(&mut self, l: &cgmath::Vector3<Real>) {
+        let t = l - self.get_location();
+        self.translate(&t);
     }
 
     fn get_location(&self) -> cgmath::Vector3<Real> {
@@ -345,6 +363,7 @@ impl Transferable for Base {
 
     fn translate(&mut self, t: &cgmath::Vector3<Real>) {
         self.uniform.model.w += t.extend(0.0);
+        self.is_transformed = true;
         // todo take care of collider
         for (_, c) in &self.children {
             vxresult!(c.write()).translate(t);
@@ -356,6 +375,7 @@ impl Transferable for Base {
         self.occlusion_culling_radius *= s;
         let s = cgmath::Matrix4::from_scale(s);
         self.uniform.model = self.uniform.model * s;
+        self.is_transformed = true;
     }
 }
 
@@ -363,12 +383,15 @@ impl Model for Base {
     fn update(&mut self, _: &Scene, camera: &Camera, frame_number: usize) {
         let location = self.uniform.model.w.truncate();
         self.is_visible = camera.is_in_frustum(self.occlusion_culling_radius, &location);
+        // invisible meshes still can cast shadow, so their data must be ready
+        for (_, m) in &self.meshes {
+            vxresult!(m.0.write()).update(frame_number);
+        }
         if !self.is_visible {
             return;
         }
         self.uniform_buffer.update(&self.uniform, frame_number);
         for (_, m) in &mut self.meshes {
-            vxresult!(m.0.write()).update(frame_number);
             m.1.update_uniform_buffer(frame_number);
         }
     }
@@ -379,12 +402,29 @@ impl Model for Base {
         self.has_transparent_mesh = false;
         self.occlusion_culling_radius = 0.0;
         self.is_visible = false;
+        self.is_transformed = true;
     }
 
     fn get_uniform(&self) -> &Uniform {
         return &self.uniform;
     }
 
+    fn get_collider(&self) -> &Arc<RwLock<Collider>> {
+        return &self.collider;
+    }
+
+    fn set_collider(&mut self, collider: Arc<RwLock<Collider>>) {
+        self.collider = collider;
+    }
+
+    fn is_transformed(&self) -> bool {
+        return self.is_transformed;
+    }
+
+    fn clear_transformed(&mut self) {
+        self.is_transformed = false;
+    }
+
     fn get_meshes(&self) -> &BTreeMap<Id, (Arc<RwLock<Mesh>>, Material)> {
         return &self.meshes;
     }
@@ -395,6 +435,7 @@ impl Model for Base {
             let radius = mesh.get_occlusion_culling_radius();
             if self.occlusion_culling_radius < radius {
                 self.occlusion_culling_radius = radius;
+                self.is_transformed = true;
             }
             mesh.get_id()
         };
@@ -481,6 +522,7 @@ impl DefaultModel for Base {
             meshes: BTreeMap::new(),
             children: BTreeMap::new(),
             scales: cgmath::Vector3::new(1.0, 1.0, 1.0),
+            is_transformed: true,
         }
     }
 }
@@ -0,0 +1,101 @@
+use super::super::super::core::types::Real;
+
+use cgmath;
+
+pub trait Interpolatable: Copy {
+    fn interpolate(&self, other: &Self, t: Real) -> Self;
+}
+
+impl Interpolatable for Real {
+    fn interpolate(&self, other: &Self, t: Real) -> Self {
+        return self + (other - self) * t;
+    }
+}
+
+impl Interpolatable for cgmath::Vector3<Real> {
+    fn interpolate(&self, other: &Self, t: Real) -> Self {
+        return self + (other - self) * t;
+    }
+}
+
+// Keys are (time, value) and time is the normalized age of particle, between zero and one.
+// Values are linearly interpolated and they are clamped outside of keys.
+#[derive(Clone)]
+#[cfg_attr(debug_mode, derive(Debug))]
+pub struct Curve<T>
+where
+    T: Interpolatable,
+{
+    keys: Vec<(Real, T)>,
+}
+
+impl<T> Curve<T>
+where
+    T: Interpolatable,
+{
+    pub fn new(value: T) -> Self {
+        Curve {
+            keys: vec![(0.0, value)],
+        }
+    }
+
+    pub fn new_with_keys(keys: &[(Real, T)]) -> Self {
+        if keys.len() == 0 {
+            vxlogf!("Curve must have at least one key.");
+        }
+        let mut curve = Curve { keys: Vec::new() };
+        for k in keys {
+            curve.add_key(k.0, k.1);
+        }
+        return curve;
+    }
+
+    pub fn add_key(&mut self, time: Real, value: T) {
+        let mut i = self.keys.len();
+        while i > 0 && self.keys[i - 1].0 > time {
+            i -= 1;
+        }
+        self.keys.insert(i, (time, value));
+    }
+
+    pub fn get_keys(&self) -> &[(Real, T)] {
+        return &self.keys;
+    }
+
+    pub fn evaluate(&self, time: Real) -> T {
+        let first = &self.keys[0];
+        if time <= first.0 {
+            return first.1;
+        }
+        for i in 1..self.keys.len() {
+            let next = &self.keys[i];
+            if time < next.0 {
+                let previous = &self.keys[i - 1];
+                let t = (time - previous.0) / (next.0 - previous.0);
+                return previous.1.interpolate(&next.1, t);
+            }
+        }
+        return self.keys[self.keys.len() - 1].1;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn curve_test() {
+        let curve = Curve::new_with_keys(&[(1.0, 4.0), (0.0, 2.0), (0.5, 0.0)]);
+        assert_eq!(curve.evaluate(-1.0), 2.0);
+        assert_eq!(curve.evaluate(0.25), 1.0);
+        assert_eq!(curve.evaluate(0.5), 0.0);
+        assert_eq!(curve.evaluate(0.75), 2.0);
+        assert_eq!(curve.evaluate(2.0), 4.0);
+        let offset = Curve::new_with_keys(&[
+            (0.0, cgmath::Vector3::new(1.0, 1.0, 1.0)),
+            (1.0, cgmath::Vector3::new(1.0, 0.0, 0.0)),
+        ]);
+        assert!(offset.evaluate(0.5) == cgmath::Vector3::new(1.0, 0.5, 0.5));
+        assert_eq!(Curve::new(3.0).evaluate(0.7), 3.0);
+    }
+}
@@ -0,0 +1,574 @@
+use super::super::super::core::types::Real;
+use super::super::mesh::VERTEX_ELEMENTS_COUNT;
+use super::curve::Curve;
+use rand::distributions::{Distribution, Uniform};
+use rand::Rng;
+use std::cmp::Ordering;
+use std::f32::consts::PI;
+
+use cgmath;
+use cgmath::prelude::*;
+
+type Vec3 = cgmath::Vector3<Real>;
+type Mat4 = cgmath::Matrix4<Real>;
+type Quat = cgmath::Quaternion<Real>;
+
+const DEFAULT_MAX_PARTICLES: usize = 1000;
+
+fn random<R: Rng>(rng: &mut R) -> Real {
+    return Uniform::from(0.0..1.0).sample(rng);
+}
+
+fn random_between<R: Rng>(rng: &mut R, range: &(Real, Real)) -> Real {
+    return range.0 + (range.1 - range.0) * random(rng);
+}
+
+fn random_direction<R: Rng>(rng: &mut R) -> Vec3 {
+    let z = 2.0 * random(rng) - 1.0;
+    let phi = 2.0 * PI * random(rng);
+    let r = (1.0 - z * z).max(0.0).sqrt();
+    return Vec3::new(r * phi.cos(), r * phi.sin(), z);
+}
+
+// Triangles of a mesh with their accumulated areas, so points are uniformly distributed over surface
+#[derive(Clone)]
+#[cfg_attr(debug_mode, derive(Debug))]
+pub struct Surface {
+    triangles: Vec<[Vec3; 3]>,
+    normals: Vec<Vec3>,
+    areas: Vec<Real>,
+}
+
+impl Surface {
+    // Vertices are in the layout of mesh vertices
+    pub fn new(vertices: &[Real], indices: &[u32]) -> Self {
+        let position = |i: u32| {
+            let i = i as usize * VERTEX_ELEMENTS_COUNT;
+            Vec3::new(vertices[i], vertices[i + 1], vertices[i + 2])
+        };
+        let mut triangles = Vec::with_capacity(indices.len() / 3);
+        let mut normals = Vec::with_capacity(indices.len() / 3);
+        let mut areas = Vec::with_capacity(indices.len() / 3);
+        let mut area = 0.0;
+        for i in indices.chunks(3) {
+            if i.len() < 3 {
+                break;
+            }
+            let t = [position(i[0]), position(i[1]), position(i[2])];
+            let n = (t[1] - t[0]).cross(t[2] - t[0]);
+            let l = n.magnitude();
+            if l <= 0.0 {
+                continue;
+            }
+            area += l * 0.5;
+            triangles.push(t);
+            normals.push(n / l);
+            areas.push(area);
+        }
+        if triangles.len() == 0 {
+            vxlogf!("Surface of particle emitter must have at least one triangle.");
+        }
+        Surface {
+            triangles,
+            normals,
+            areas,
+        }
+    }
+
+    fn sample<R: Rng>(&self, rng: &mut R) -> (Vec3, Vec3) {
+        let last = self.areas.len() - 1;
+        let a = random(rng) * self.areas[last];
+        let i = match self
+            .areas
+            .binary_search_by(|v| v.partial_cmp(&a).unwrap_or(Ordering::Equal))
+        {
+            Ok(i) => i,
+            Err(i) => i.min(last),
+        };
+        let t = &self.triangles[i];
+        let s = random(rng).sqrt();
+        let v = random(rng);
+        let p = t[0] * (1.0 - s) + t[1] * (s * (1.0 - v)) + t[2] * (s * v);
+        return (p, self.normals[i]);
+    }
+}
+
+// Particles are emitted in the local space of emitter, cone opens around the y axis
+#[derive(Clone)]
+#[cfg_attr(debug_mode, derive(Debug))]
+pub enum Shape {
+    Point,
+    Sphere { radius: Real },
+    // Angle is the half of cone opening in radian and radius is for its base disk
+    Cone { angle: Real, radius: Real },
+    MeshSurface(Surface),
+}
+
+impl Shape {
+    // Local position and direction of a new particle
+    pub fn sample<R: Rng>(&self, rng: &mut R) -> (Vec3, Vec3) {
+        return match self {
+            &Shape::Point => (Vec3::zero(), random_direction(rng)),
+            &Shape::Sphere { radius } => {
+                let d = random_direction(rng);
+                (d * (radius * random(rng).cbrt()), d)
+            }
+            &Shape::Cone { angle, radius } => {
+                let cos_theta = 1.0 - random(rng) * (1.0 - angle.cos());
+                let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+                let phi = 2.0 * PI * random(rng);
+                let d = Vec3::new(sin_theta * phi.cos(), cos_theta, sin_theta * phi.sin());
+                let r = radius * random(rng).sqrt();
+                let phi = 2.0 * PI * random(rng);
+                (Vec3::new(r * phi.cos(), 0.0, r * phi.sin()), d)
+            }
+            &Shape::MeshSurface(ref s) => s.sample(rng),
+        };
+    }
+}
+
+#[derive(Clone, Copy)]
+#[cfg_attr(debug_mode, derive(Debug))]
+pub struct Particle {
+    pub position: Vec3,
+    pub velocity: Vec3,
+    pub age: Real,
+    pub lifetime: Real,
+    pub size: Real,
+}
+
+// Particles are simulated in world space, so they stay where they are emitted when emitter moves.
+#[derive(Clone)]
+#[cfg_attr(debug_mode, derive(Debug))]
+pub struct Emitter {
+    pub shape: Shape,
+    // Particles per second
+    pub rate: Real,
+    // Ranges of lifetime and initial speed, each particle gets a random value between them
+    pub lifetime: (Real, Real),
+    pub speed: (Real, Real),
+    pub gravity: Vec3,
+    pub drag: Real,
+    // Size over the normalized age of particle
+    pub size: Curve<Real>,
+    pub max_particles: usize,
+    // Placement relative to the particle system
+    pub location: Vec3,
+    pub orientation: Quat,
+    emitting: bool,
+    // Fraction of a particle that is not emitted yet
+    remainder: Real,
+    particles: Vec<Particle>,
+}
+
+impl Emitter {
+    pub fn new(shape: Shape) -> Self {
+        Emitter {
+            shape,
+            rate: 10.0,
+            lifetime: (1.0, 1.0),
+            speed: (1.0, 1.0),
+            gravity: Vec3::zero(),
+            drag: 0.0,
+            size: Curve::new(1.0),
+            max_particles: DEFAULT_MAX_PARTICLES,
+            location: Vec3::zero(),
+            orientation: Quat::one(),
+            emitting: true,
+            remainder: 0.0,
+            particles: Vec::new(),
+        }
+    }
+
+    pub fn start(&mut self) {
+        self.emitting = true;
+    }
+
+    // Already emitted particles live until the end of their lifetime
+    pub fn stop(&mut self) {
+        self.emitting = false;
+        self.remainder = 0.0;
+    }
+
+    pub fn is_emitting(&self) -> bool {
+        return self.emitting;
+    }
+
+    pub fn is_alive(&self) -> bool {
+        return self.emitting || self.particles.len() > 0;
+    }
+
+    pub fn clear(&mut self) {
+        self.particles.clear();
+        self.remainder = 0.0;
+    }
+
+    pub fn get_particles(&self) -> &[Particle] {
+        return &self.particles;
+    }
+
+    // Transform is the world transformation of particle system
+    pub fn burst(&mut self, count: usize, transform: &Mat4) {
+        let mut rng = rand::thread_rng();
+        self.emit(count, transform, &mut rng);
+    }
+
+    pub fn update(&mut self, dt: Real, transform: &Mat4) {
+        let mut rng = rand::thread_rng();
+        self.update_with_rng(dt, transform, &mut rng);
+    }
+
+    fn update_with_rng<R: Rng>(&mut self, dt: Real, transform: &Mat4, rng: &mut R) {
+        let damping = 1.0 / (1.0 + dt * self.drag);
+        let gravity = self.gravity * dt;
+        self.particles.retain(|p| p.age + dt < p.lifetime);
+        for p in &mut self.particles {
+            p.age += dt;
+            p.velocity += gravity;
+            p.velocity *= damping;
+            p.position += p.velocity * dt;
+        }
+        for p in &mut self.particles {
+            p.size = self.size.evaluate(p.age / p.lifetime);
+        }
+        if !self.emitting {
+            return;
+        }
+        self.remainder += self.rate * dt;
+        let count = self.remainder.floor();
+        self.remainder -= count;
+        self.emit(count as usize, transform, rng);
+    }
+
+    fn emit<R: Rng>(&mut self, count: usize, transform: &Mat4, rng: &mut R) {
+        let transform =
+            transform * Mat4::from_translation(self.location) * Mat4::from(self.orientation);
+        let count = count.min(self.max_particles.saturating_sub(self.particles.len()));
+        let size = self.size.evaluate(0.0);
+        for _ in 0..count {
+            let (position, direction) = self.shape.sample(rng);
+            let position = (transform * position.extend(1.0)).truncate();
+            let direction = (transform * direction.extend(0.0)).truncate().normalize();
+            let lifetime = random_between(rng, &self.lifetime);
+            if lifetime <= 0.0 {
+                continue;
+            }
+            self.particles.push(Particle {
+                position,
+                velocity: direction * random_between(rng, &self.speed),
+                age: 0.0,
+                lifetime,
+                size,
+            });
+        }
+    }
+}
+
+// Each line of a descriptor is a key and its values, a new emitter starts with an 'emitter' line
+// and '#' starts a comment, e.g.:
+//     emitter
+//     shape cone 30 0.1          # half angle in degree and radius
+//     rate 40
+//     lifetime 1 2
+//     speed 2 3
+//     gravity 0 -9.81 0
+//     drag 0.5
+//     max-particles 500
+//     location 0 1 0
+//     size 0 0.1                 # time size
+//     size 1 0.5
+// Mesh surface emitters can only be created in code.
+pub fn read_descriptor(descriptor: &str) -> Vec<Emitter> {
+    let mut emitters: Vec<Emitter> = Vec::new();
+    let mut size_keys = Vec::new();
+    macro_rules! finish {
+        () => {{
+            if let Some(e) = emitters.last_mut() {
+                if size_keys.len() > 0 {
+                    e.size = Curve::new_with_keys(&size_keys);
+                }
+            }
+            size_keys.clear();
+        }};
+    }
+    for line in descriptor.lines() {
+        let line = match line.find('#') {
+            Some(i) => &line[..i],
+            None => line,
+        };
+        let mut words = line.split_whitespace();
+        let key = match words.next() {
+            Some(k) => k,
+            None => continue,
+        };
+        let words: Vec<&str> = words.collect();
+        if key == "emitter" {
+            finish!();
+            emitters.push(Emitter::new(Shape::Point));
+            continue;
+        }
+        let emitter = match emitters.last_mut() {
+            Some(e) => e,
+            None => vxlogf!("Particle descriptor must start with an emitter."),
+        };
+        let skipped = if key == "shape" { 1 } else { 0 };
+        let values: Vec<Real> = words
+            .iter()
+            .skip(skipped)
+            .map(|w| vxresult!(w.parse()))
+            .collect();
+        let value = |i: usize| -> Real {
+            if i >= values.len() {
+                vxlogf!("Particle descriptor key '{}' needs more values.", key);
+            }
+            values[i]
+        };
+        // One value is both ends of range
+        let range = || (value(0), value(if values.len() > 1 { 1 } else { 0 }));
+        match key {
+            "shape" => {
+                emitter.shape = match words.get(0) {
+                    Some(&"point") => Shape::Point,
+                    Some(&"sphere") => Shape::Sphere { radius: value(0) },
+                    Some(&"cone") => Shape::Cone {
+                        angle: value(0).to_radians(),
+                        radius: value(1),
+                    },
+                    _ => vxlogf!("Unsupported particle emitter shape in '{}'.", line),
+                };
+            }
+            "rate" => emitter.rate = value(0),
+            "lifetime" => emitter.lifetime = range(),
+            "speed" => emitter.speed = range(),
+            "gravity" => emitter.gravity = Vec3::new(value(0), value(1), value(2)),
+            "drag" => emitter.drag = value(0),
+            "max-particles" => emitter.max_particles = value(0) as usize,
+            "location" => emitter.location = Vec3::new(value(0), value(1), value(2)),
+            "size" => size_keys.push((value(0), value(1))),
+            _ => vxlogf!("Unknown particle descriptor key '{}'.", key),
+        }
+    }
+    finish!();
+    return emitters;
+}
+
+// Writes camera facing quads of particles, farthest ones first.
+// Positions are relative to center.
+// Returns the radius of sphere around center that contains all of quads.
+pub fn write_billboards(
+    emitters: &[Emitter],
+    center: &Vec3,
+    eye: &Vec3,
+    right: &Vec3,
+    up: &Vec3,
+    vertices: &mut Vec<Real>,
+    indices: &mut Vec<u32>,
+) -> Real {
+    vertices.clear();
+    indices.clear();
+    let mut particles: Vec<(Real, &Particle)> = Vec::new();
+    for e in emitters {
+        for p in &e.particles {
+            particles.push(((p.position - eye).magnitude2(), p));
+        }
+    }
+    particles.sort_by(|a, b| b.0.partial_cmp(&a.0).unwrap_or(Ordering::Equal));
+    let normal = right.cross(*up).normalize();
+    let tangent = right.normalize();
+    let corners = [(-0.5, -0.5), (0.5, -0.5), (0.5, 0.5), (-0.5, 0.5)];
+    let uvs = [(0.0, 1.0), (1.0, 1.0), (1.0, 0.0), (0.0, 0.0)];
+    let mut radius: Real = 0.0;
+    for (_, p) in particles {
+        let first = (vertices.len() / VERTEX_ELEMENTS_COUNT) as u32;
+        let position = p.position - center;
+        radius = radius.max(position.magnitude() + p.size * 0.75);
+        for i in 0..4 {
+            let v = position + (right * corners[i].0 + up * corners[i].1) * p.size;
+            vertices.extend_from_slice(&[v.x, v.y, v.z, normal.x, normal.y, normal.z]);
+            vertices.extend_from_slice(&[tangent.x, tangent.y, tangent.z, 1.0]);
+            vertices.extend_from_slice(&[uvs[i].0, uvs[i].1]);
+        }
+        indices.extend_from_slice(&[first, first + 1, first + 2, first, first + 2, first + 3]);
+    }
+    return radius;
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn shapes_test() {
+        let mut rng = rand::thread_rng();
+        let sphere = Shape::Sphere { radius: 2.0 };
+        let cone = Shape::Cone {
+            angle: 0.5,
+            radius: 1.0,
+        };
+        #[cfg_attr(rustfmt, rustfmt_skip)]
+        let vertices = [
+            0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0,
+            1.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0,
+            0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0,
+        ];
+        let surface = Shape::MeshSurface(Surface::new(&vertices, &[0, 2, 1]));
+        for _ in 0..1000 {
+            let (p, d) = sphere.sample(&mut rng);
+            assert!(p.magnitude() <= 2.0 + 1e-4);
+            assert!((d.magnitude() - 1.0).abs() < 1e-4);
+            let (p, d) = cone.sample(&mut rng);
+            assert!(p.y == 0.0 && p.magnitude() <= 1.0 + 1e-4);
+            assert!(d.y >= 0.5f32.cos() - 1e-4);
+            let (p, d) = surface.sample(&mut rng);
+            assert!(p.x >= 0.0 && p.z >= 0.0 && p.x + p.z <= 1.0 + 1e-4 && p.y == 0.0);
+            assert!(d == Vec3::new(0.0, 1.0, 0.0));
+        }
+    }
+
+    #[test]
+    fn simulation_test() {
+        let mut rng = rand::thread_rng();
+        let mut emitter = Emitter::new(Shape::Point);
+        emitter.rate = 10.0;
+        emitter.lifetime = (1.0, 1.0);
+        emitter.speed = (0.0, 0.0);
+        emitter.gravity = Vec3::new(0.0, -10.0, 0.0);
+        emitter.size = Curve::new_with_keys(&[(0.0, 1.0), (1.0, 3.0)]);
+        let transform = Mat4::from_translation(Vec3::new(0.0, 5.0, 0.0));
+        emitter.update_with_rng(0.25, &transform, &mut rng);
+        assert_eq!(emitter.get_particles().len(), 2);
+        assert!(emitter.get_particles()[0].position == Vec3::new(0.0, 5.0, 0.0));
+        emitter.update_with_rng(0.25, &transform, &mut rng);
+        assert_eq!(emitter.get_particles().len(), 5);
+        let p = emitter.get_particles()[0];
+        assert!((p.velocity.y + 2.5).abs() < 1e-5);
+        assert!((p.position.y - (5.0 - 0.625)).abs() < 1e-5);
+        assert!((p.size - 1.5).abs() < 1e-5);
+        emitter.stop();
+        for _ in 0..3 {
+            emitter.update_with_rng(0.25, &transform, &mut rng);
+        }
+        assert_eq!(emitter.get_particles().len(), 3);
+        emitter.update_with_rng(0.25, &transform, &mut rng);
+        assert!(!emitter.is_alive());
+        emitter.max_particles = 4;
+        emitter.burst(10, &transform);
+        assert_eq!(emitter.get_particles().len(), 4);
+    }
+
+    #[test]
+    fn drag_test() {
+        let mut emitter = Emitter::new(Shape::Point);
+        emitter.rate = 0.0;
+        emitter.lifetime = (10.0, 10.0);
+        emitter.speed = (4.0, 4.0);
+        emitter.drag = 1.0;
+        emitter.burst(1, &Mat4::one());
+        for _ in 0..100 {
+            emitter.update(0.01, &Mat4::one());
+        }
+        let v = emitter.get_particles()[0].velocity.magnitude();
+        assert!((v - 4.0 * (-1.0 as Real).exp()).abs() < 0.01);
+    }
+
+    #[test]
+    fn descriptor_test() {
+        let emitters = read_descriptor(
+            "# fire\n\
+             emitter\n\
+             shape cone 90 0.5\n\
+             rate 40 # per second\n\
+             lifetime 1 2\n\
+             gravity 0 -1 0\n\
+             \n\
+             emitter\n\
+             shape sphere 2\n\
+             speed 3\n\
+             size 0 0.5\n\
+             emitter\n\
+             shape point\n",
+        );
+        assert_eq!(emitters.len(), 3);
+        match emitters[0].shape {
+            Shape::Cone { angle, radius } => {
+                assert!((angle - PI * 0.5).abs() < 1e-5);
+                assert_eq!(radius, 0.5);
+            }
+            _ => panic!("Wrong shape"),
+        }
+        assert_eq!(emitters[0].rate, 40.0);
+        assert_eq!(emitters[0].lifetime, (1.0, 2.0));
+        assert!(emitters[0].gravity == Vec3::new(0.0, -1.0, 0.0));
+        assert_eq!(emitters[0].size.evaluate(0.5), 1.0);
+        match emitters[1].shape {
+            Shape::Sphere { radius } => assert_eq!(radius, 2.0),
+            _ => panic!("Wrong shape"),
+        }
+        assert_eq!(emitters[1].speed, (3.0, 3.0));
+        assert_eq!(emitters[1].size.evaluate(0.7), 0.5);
+        match emitters[2].shape {
+            Shape::Point => (),
+            _ => panic!("Wrong shape"),
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "Unsupported particle emitter shape")]
+    fn descriptor_bare_shape_test() {
+        read_descriptor("emitter\nshape\n");
+    }
+
+    #[test]
+    fn billboards_test() {
+        let mut emitter = Emitter::new(Shape::Point);
+        emitter.speed = (0.0, 0.0);
+        emitter.lifetime = (10.0, 10.0);
+        emitter.size = Curve::new(2.0);
+        emitter.burst(1, &Mat4::from_translation(Vec3::new(0.0, 0.0, -1.0)));
+        emitter.burst(1, &Mat4::from_translation(Vec3::new(0.0, 0.0, -5.0)));
+        emitter.burst(1, &Mat4::from_translation(Vec3::new(0.0, 0.0, -3.0)));
+        let mut vertices = Vec::new();
+        let mut indices = Vec::new();
+        let radius = write_billboards(
+            &[emitter],
+            &Vec3::new(0.0, 0.0, -3.0),
+            &Vec3::zero(),
+            &Vec3::unit_x(),
+            &Vec3::unit_y(),
+            &mut vertices,
+            &mut indices,
+        );
+        assert_eq!(vertices.len(), 12 * VERTEX_ELEMENTS_COUNT);
+        assert_eq!(indices.len(), 18);
+        assert!((radius - 3.5).abs() < 1e-5);
+        // farthest first
+        let zs: Vec<Real> = (0..3)
+            .map(|i| vertices[i * 4 * VERTEX_ELEMENTS_COUNT + 2])
+            .collect();
+        assert_eq!(zs, vec![-2.0, 0.0, 2.0]);
+        // corners are camera facing
+        assert_eq!(vertices[0], -1.0);
+        assert_eq!(vertices[1], -1.0);
+        assert_eq!(vertices[5], 1.0);
+        assert_eq!(indices[6..12].to_vec(), vec![4, 5, 6, 4, 6, 7]);
+    }
+
+    #[test]
+    fn billboards_nan_test() {
+        let mut emitter = Emitter::new(Shape::Point);
+        emitter.burst(2, &Mat4::one());
+        emitter.burst(1, &Mat4::from_translation(Vec3::new(Real::NAN, 0.0, 0.0)));
+        let mut vertices = Vec::new();
+        let mut indices = Vec::new();
+        write_billboards(
+            &[emitter],
+            &Vec3::zero(),
+            &Vec3::new(0.0, 0.0, 5.0),
+            &Vec3::unit_x(),
+            &Vec3::unit_y(),
+            &mut vertices,
+            &mut indices,
+        );
+        assert_eq!(indices.len(), 18);
+    }
+}
@@ -0,0 +1,402 @@
+pub mod curve;
+pub mod emitter;
+
+use self::emitter::{read_descriptor, write_billboards, Emitter};
+use super::super::core::object::Object as CoreObject;
+use super::super::core::timing::Timing;
+use super::super::core::types::{Id, Real};
+use super::super::physics::collider::{Collider, Ghost as GhostCollider};
+use super::buffer::Dynamic as DynamicBuffer;
+use super::camera::Camera;
+use super::command::Buffer as CmdBuffer;
+use super::descriptor::Set as DescriptorSet;
+use super::engine::Engine;
+use super::material::Material;
+use super::mesh::{Dynamic as DynamicMesh, Mesh};
+use super::model::{Model, Uniform};
+use super::object::{Base as ObjectBase, Object, Transferable};
+use super::scene::Scene;
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::Read;
+use std::mem::size_of;
+use std::sync::{Arc, RwLock, Weak};
+
+use cgmath;
+use cgmath::prelude::*;
+
+type Vec3 = cgmath::Vector3<Real>;
+type Mat4 = cgmath::Matrix4<Real>;
+type Quat = cgmath::Quaternion<Real>;
+
+// A model that simulates its emitters with the game clock and renders their particles
+// as camera facing billboards with the sprite of material in g-buffer and unlit passes.
+#[cfg_attr(debug_mode, derive(Debug))]
+pub struct System {
+    obj_base: ObjectBase,
+    emitters: Vec<Emitter>,
+    // When system is attached these are relative to the model
+    location: Vec3,
+    orientation: Quat,
+    attachment: Option<Weak<RwLock<Model>>>,
+    timing: Arc<RwLock<Timing>>,
+    mesh: Arc<RwLock<DynamicMesh>>,
+    mesh_id: Id,
+    meshes: BTreeMap<Id, (Arc<RwLock<Mesh>>, Material)>,
+    vertices: Vec<Real>,
+    indices: Vec<u32>,
+    center: Vec3,
+    occlusion_culling_radius: Real,
+    is_visible: bool,
+    is_transformed: bool,
+    collider: Arc<RwLock<Collider>>,
+    uniform: Uniform,
+    uniform_buffer: DynamicBuffer,
+    descriptor_set: Arc<DescriptorSet>,
+}
+
+impl System {
+    pub fn new(eng: &Engine, emitters: Vec<Emitter>) -> Self {
+        let mesh = vxresult!(eng.get_asset_manager().get_mesh_manager().write()).create_dynamic(
+            &[],
+            &[],
+            0.0,
+            eng,
+        );
+        let mesh_id = {
+            let mut mesh = vxresult!(mesh.write());
+            mesh.set_shadow_caster(false);
+            mesh.get_id()
+        };
+        let mut meshes = BTreeMap::new();
+        let m: Arc<RwLock<Mesh>> = mesh.clone();
+        meshes.insert(mesh_id, (m, Material::default(eng)));
+        let gapi_engine = vxresult!(eng.get_gapi_engine().read());
+        let uniform_buffer = vxresult!(gapi_engine.get_buffer_manager().write())
+            .create_dynamic_buffer(size_of::<Uniform>() as isize);
+        let mut descriptor_manager = vxresult!(gapi_engine.get_descriptor_manager().write());
+        let descriptor_set = descriptor_manager.create_buffer_only_set(&uniform_buffer);
+        System {
+            obj_base: ObjectBase::new(),
+            emitters,
+            location: Vec3::zero(),
+            orientation: Quat::one(),
+            attachment: None,
+            timing: eng.get_timing().clone(),
+            mesh,
+            mesh_id,
+            meshes,
+            vertices: Vec::new(),
+            indices: Vec::new(),
+            center: Vec3::zero(),
+            occlusion_culling_radius: 0.0,
+            is_visible: false,
+            is_transformed: true,
+            collider: Arc::new(RwLock::new(GhostCollider::new())),
+            uniform: Uniform::default(),
+            uniform_buffer,
+            descriptor_set,
+        }
+    }
+
+    pub fn add_emitter(&mut self, emitter: Emitter) {
+        self.emitters.push(emitter);
+    }
+
+    pub fn get_emitters(&self) -> &[Emitter] {
+        return &self.emitters;
+    }
+
+    pub fn get_mut_emitters(&mut self) -> &mut Vec<Emitter> {
+        return &mut self.emitters;
+    }
+
+    // Base color texture of material is the sprite of particles
+    pub fn get_mut_material(&mut self) -> &mut Material {
+        let id = self.mesh_id;
+        return &mut vxunwrap!(self.meshes.get_mut(&id)).1;
+    }
+
+    // System follows the model and its location and orientation become relative to the model.
+    // When the model is dropped, emitters stop and the remaining particles fade out.
+    pub fn attach(&mut self, model: &Arc<RwLock<Model>>) {
+        self.attachment = Some(Arc::downgrade(model));
+    }
+
+    pub fn detach(&mut self) {
+        self.attachment = None;
+    }
+
+    pub fn start(&mut self) {
+        for e in &mut self.emitters {
+            e.start();
+        }
+    }
+
+    pub fn stop(&mut self) {
+        for e in &mut self.emitters {
+            e.stop();
+        }
+    }
+
+    pub fn burst(&mut self, count: usize) {
+        let transform = self.get_transform();
+        for e in &mut self.emitters {
+            e.burst(count, &transform);
+        }
+    }
+
+    // False when all of emitters are stopped and their particles are dead
+    pub fn is_alive(&self) -> bool {
+        for e in &self.emitters {
+            if e.is_alive() {
+                return true;
+            }
+        }
+        return false;
+    }
+
+    pub fn get_transform(&self) -> Mat4 {
+        let local = Mat4::from_translation(self.location) * Mat4::from(self.orientation);
+        if let Some(model) = &self.attachment {
+            if let Some(model) = model.upgrade() {
+                return vxresult!(model.read()).get_uniform().get_model() * local;
+            }
+        }
+        return local;
+    }
+
+    fn update_attachment(&mut self) {
+        let is_dropped = match &self.attachment {
+            Some(model) => model.upgrade().is_none(),
+            None => false,
+        };
+        if is_dropped {
+            self.stop();
+        }
+    }
+}
+
+impl CoreObject for System {
+    fn get_id(&self) -> Id {
+        self.obj_base.get_id()
+    }
+}
+
+impl Object for System {
+    fn get_name(&self) -> Option<String> {
+        self.obj_base.get_name()
+    }
+
+    fn set_name(&mut self, name: &str) {
+        self.obj_base.set_name(name);
+        vxunimplemented!(); //it must update corresponding manager
+    }
+
+    fn disable_rendering(&mut self) {
+        self.obj_base.disable_rendering()
+    }
+
+    fn enable_rendering(&mut self) {
+        self.obj_base.enable_rendering()
+    }
+
+    fn is_renderable(&self) -> bool {
+        return self.obj_base.is_renderable();
+    }
+}
+
+impl Transferable for System {
+    fn set_orientation(&mut self, q: &cgmath::Quaternion<Real>) {
+        self.orientation = q.normalize();
+        self.is_transformed = true;
+    }
+
+    fn set_location(&mut self, l: &cgmath::Vector3<Real>) {
+        self.location = *l;
+        self.is_transformed = true;
+    }
+
+    fn get_location(&self) -> cgmath::Vector3<Real> {
+        return self.location;
+    }
+
+    fn move_local_z(&mut self, _: Real) {
+        vxunimplemented!();
+    }
+
+    fn move_local_x(&mut self, _: Real) {
+        vxunimplemented!();
+    }
+
+    fn rotate_local_x(&mut self, _: Real) {
+        vxunimplemented!();
+    }
+
+    fn rotate_global_z(&mut self, _: Real) {
+        vxunimplemented!();
+    }
+
+    fn translate(&mut self, t: &cgmath::Vector3<Real>) {
+        self.location += *t;
+        self.is_transformed = true;
+    }
+}
+
+impl Model for System {
+    fn update(&mut self, _: &Scene, camera: &Camera, frame_number: usize) {
+        self.update_attachment();
+        let dt = vxresult!(self.timing.read()).get_game_delta();
+        let transform = self.get_transform();
+        for e in &mut self.emitters {
+            e.update(dt, &transform);
+        }
+        // vertices are relative to the system, so the model matrix is only a translation
+        let center = transform.w.truncate();
+        let camera_uniform = camera.get_uniform();
+        let radius = write_billboards(
+            &self.emitters,
+            &center,
+            &camera.get_location(),
+            &camera_uniform.get_x_axis(),
+            &camera_uniform.get_y_axis(),
+            &mut self.vertices,
+            &mut self.indices,
+        );
+        // particles move the bounds, and an attached system moves with its model
+        if center != self.center || radius != self.occlusion_culling_radius {
+            self.is_transformed = true;
+        }
+        self.center = center;
+        self.occlusion_culling_radius = radius;
+        {
+            let mut mesh = vxresult!(self.mesh.write());
+            mesh.set_data(&self.vertices, &self.indices);
+            mesh.set_occlusion_culling_radius(self.occlusion_culling_radius);
+            mesh.update(frame_number);
+        }
+        self.uniform.set_model(Mat4::from_translation(self.center));
+        self.is_visible = self.indices.len() > 0
+            && camera.is_in_frustum(self.occlusion_culling_radius, &self.center);
+        if !self.is_visible {
+            return;
+        }
+        self.uniform_buffer.update(&self.uniform, frame_number);
+        for (_, m) in &mut self.meshes {
+            m.1.update_uniform_buffer(frame_number);
+        }
+    }
+
+    fn add_mesh(&mut self, _: Arc<RwLock<Mesh>>, _: Material) {
+        vxlogf!("Particle system only renders its own particles.");
+    }
+
+    fn clear_meshes(&mut self) {
+        for e in &mut self.emitters {
+            e.clear();
+        }
+    }
+
+    fn get_meshes(&self) -> &BTreeMap<Id, (Arc<RwLock<Mesh>>, Material)> {
+        return &self.meshes;
+    }
+
+    fn bring_all_child_models(&self) -> Vec<(Id, Arc<RwLock<Model>>)> {
+        return Vec::new();
+    }
+
+    fn has_shadow(&self) -> bool {
+        return false;
+    }
+
+    fn has_transparent(&self) -> bool {
+        return false;
+    }
+
+    fn get_occlusion_culling_radius(&self) -> Real {
+        return self.occlusion_culling_radius;
+    }
+
+    fn get_distance_from_camera(&self, c: &Camera) -> Real {
+        return c.get_distance(&self.center);
+    }
+
+    fn get_uniform(&self) -> &Uniform {
+        return &self.uniform;
+    }
+
+    fn get_collider(&self) -> &Arc<RwLock<Collider>> {
+        return &self.collider;
+    }
+
+    fn set_collider(&mut self, collider: Arc<RwLock<Collider>>) {
+        self.collider = collider;
+    }
+
+    fn is_transformed(&self) -> bool {
+        return self.is_transformed;
+    }
+
+    fn clear_transformed(&mut self) {
+        self.is_transformed = false;
+    }
+
+    fn render_gbuffer(&self, cmd: &mut CmdBuffer, frame_number: usize) {
+        if !self.is_visible {
+            return;
+        }
+        let buffer = self.uniform_buffer.get_buffer(frame_number);
+        cmd.bind_gbuff_model_descriptor(&*self.descriptor_set, &*vxresult!(buffer.read()));
+        for (_, mesh) in &self.meshes {
+            mesh.1.bind_gbuffer(cmd, frame_number);
+            vxresult!(mesh.0.read()).render_gbuffer(cmd, frame_number);
+        }
+    }
+
+    fn render_shadow(&self, _: &mut CmdBuffer, _: usize) {}
+
+    fn render_unlit(&mut self, cmd: &mut CmdBuffer, camera: &Camera, frame_number: usize) {
+        if !self.is_visible {
+            return;
+        }
+        self.uniform
+            .set_model_view_projection(camera.get_view_projection() * self.uniform.get_model());
+        self.uniform_buffer.update(&self.uniform, frame_number);
+        let buffer = self.uniform_buffer.get_buffer(frame_number);
+        cmd.bind_unlit_model_descriptor(&*self.descriptor_set, &*vxresult!(buffer.read()));
+        for (_, mesh) in &self.meshes {
+            mesh.1.bind_unlit(cmd, frame_number);
+            vxresult!(mesh.0.read()).render_unlit(cmd, frame_number);
+        }
+    }
+}
+
+// Exporter does not write particle systems, so GX3D files do not have a table for them.
+// When it does, the table must be read only for files of that format version.
+#[cfg_attr(debug_mode, derive(Debug))]
+pub struct Manager {
+    systems: BTreeMap<Id, Weak<RwLock<System>>>,
+}
+
+impl Manager {
+    pub fn new() -> Self {
+        Manager {
+            systems: BTreeMap::new(),
+        }
+    }
+
+    // Format of descriptor file is explained in emitter::read_descriptor
+    pub fn load_descriptor(&mut self, engine: &Engine, file_name: &str) -> Arc<RwLock<System>> {
+        let mut descriptor = String::new();
+        vxresult!(vxresult!(File::open(file_name)).read_to_string(&mut descriptor));
+        return self.create(engine, read_descriptor(&descriptor));
+    }
+
+    pub fn create(&mut self, engine: &Engine, emitters: Vec<Emitter>) -> Arc<RwLock<System>> {
+        let system = System::new(engine, emitters);
+        let id = system.get_id();
+        let system = Arc::new(RwLock::new(system));
+        self.systems.insert(id, Arc::downgrade(&system));
+        return system;
+    }
+}
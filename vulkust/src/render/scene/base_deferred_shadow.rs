@@ -3,6 +3,7 @@ use super::super::super::core::constants::{MAX_DIRECTIONAL_LIGHTS_COUNT, MAX_POI
 use super::super::super::core::gx3d::Gx3DReader;
 use super::super::super::core::object::Object as CoreObject;
 use super::super::super::core::types::{Id, Real};
+use super::super::super::physics::joint::Joint;
 use super::super::buffer::Dynamic as DynamicBuffer;
 use super::super::camera::{Camera, Uniform as CameraUniform};
 use super::super::command::{Buffer as CmdBuffer, Pool as CmdPool};
@@ -21,8 +22,9 @@ use super::super::shadower::Shadower;
 use super::super::skybox::Skybox;
 use super::super::ssao::SSAO;
 use super::super::sync::Semaphore;
-use super::{DefaultScene, Scene};
-use std::collections::BTreeMap;
+use super::models_tree::ModelsTree;
+use super::{DefaultScene, Hit, Scene};
+use std::collections::{BTreeMap, BTreeSet};
 use std::mem::size_of;
 use std::sync::{Arc, Mutex, RwLock, Weak};
 
@@ -115,6 +117,8 @@ pub(super) struct Base {
     lights: BTreeMap<Id, Arc<RwLock<Light>>>,
     models: BTreeMap<Id, Arc<RwLock<Model>>>,
     all_models: BTreeMap<Id, Weak<RwLock<Model>>>,
+    models_tree: ModelsTree,
+    renderable_models: BTreeSet<Id>,
     descriptor_set: Arc<DescriptorSet>,
     kernels_data: Vec<Arc<Mutex<BaseKernelData>>>,
     distance_transparent_models: Vec<(Real, Weak<RwLock<Model>>)>,
@@ -123,7 +127,7 @@ pub(super) struct Base {
     render_pass: Arc<RenderPass>,
     framebuffers: Vec<Arc<Framebuffer>>,
     unlit_pipeline: Arc<Pipeline>,
-    // constraints: BTreeMap<Id, Arc<RwLock<Constraint>>>, // todo
+    joints: BTreeMap<Id, Arc<RwLock<Joint>>>,
 }
 
 impl Base {
@@ -176,6 +180,7 @@ impl Base {
                 distance_transparent_models: Vec::new(),
             })));
         }
+        let models_tree = ModelsTree::new_with_models(&all_models);
         Self {
             obj_base,
             uniform,
@@ -187,6 +192,8 @@ impl Base {
             lights,
             models,
             all_models,
+            models_tree,
+            renderable_models: BTreeSet::new(),
             kernels_data,
             distance_transparent_models: Vec::new(),
             frames_data: Vec::new(),
@@ -194,6 +201,7 @@ impl Base {
             unlit_pipeline,
             framebuffers,
             render_pass,
+            joints: BTreeMap::new(),
         }
     }
 
@@ -211,7 +219,7 @@ impl Base {
         } else {
             None
         };
-        let _constraits_ids = reader.read_array::<Id>(); // todo
+        let joints_ids = reader.read_array::<Id>();
         if reader.read_bool() {
             vxunimplemented!(); // todo
         }
@@ -247,6 +255,13 @@ impl Base {
                 models.insert(id, model);
             }
         }
+        let mut joints = BTreeMap::new();
+        {
+            let mut mgr = vxresult!(asset_manager.get_joint_manager().write());
+            for id in joints_ids {
+                joints.insert(id, mgr.load_gx3d(id));
+            }
+        }
         let mut lights = BTreeMap::new();
         let mut shadow_maker_lights = BTreeMap::new();
         {
@@ -283,6 +298,7 @@ impl Base {
                 distance_transparent_models: Vec::new(),
             })));
         }
+        let models_tree = ModelsTree::new_with_models(&all_models);
         Self {
             obj_base: ObjectBase::new_with_id(my_id),
             uniform,
@@ -292,6 +308,8 @@ impl Base {
             active_camera,
             models,
             all_models,
+            models_tree,
+            renderable_models: BTreeSet::new(),
             shadow_maker_lights,
             lights,
             kernels_data,
@@ -301,6 +319,7 @@ impl Base {
             render_pass,
             framebuffers,
             unlit_pipeline,
+            joints,
         }
     }
 
@@ -379,16 +398,16 @@ impl Scene for Base {
     }
 
     fn add_model(&mut self, model: Arc<RwLock<Model>>) {
-        let id = {
+        let (id, child_models) = {
             let model = vxresult!(model.read());
-            let child_models = model.bring_all_child_models();
-            for (id, model) in child_models {
-                self.all_models.insert(id, Arc::downgrade(&model));
-            }
-            let id = model.get_id();
-            id
+            (model.get_id(), model.bring_all_child_models())
         };
+        for (id, model) in child_models {
+            self.all_models.insert(id, Arc::downgrade(&model));
+            self.models_tree.insert(id, &model);
+        }
         self.all_models.insert(id, Arc::downgrade(&model));
+        self.models_tree.insert(id, &model);
         self.models.insert(id, model);
     }
 
@@ -411,6 +430,7 @@ impl Scene for Base {
     }
 
     fn update(&mut self, frame_number: usize) {
+        self.models_tree.update();
         if !self.is_renderable() {
             return;
         }
@@ -449,6 +469,24 @@ impl Scene for Base {
                 continue;
             }
         }
+        {
+            let shms: Vec<_> = self
+                .shadow_maker_lights
+                .values()
+                .map(|l| vxresult!(l.read()))
+                .collect();
+            let mut shadow_makers = Vec::with_capacity(shms.len());
+            for shm in &shms {
+                if shm.is_renderable() {
+                    shadow_makers.push(vxunwrap!(shm.to_shadow_maker()));
+                }
+            }
+            self.models_tree.gather_renderables(
+                &*camera,
+                &shadow_makers,
+                &mut self.renderable_models,
+            );
+        }
         for (_, l) in &self.lights {
             let mut l = vxresult!(l.read());
             if !l.is_renderable() {
@@ -525,7 +563,7 @@ impl Scene for Base {
         let camera = vxunwrap!(camera);
         let camera = vxresult!(camera.read());
         let mut task_index = 0;
-        for (_, mw) in &self.all_models {
+        for (id, mw) in &self.all_models {
             task_index += 1;
             task_index %= kernels_count;
             if task_index != kernel_index {
@@ -541,7 +579,11 @@ impl Scene for Base {
             if !model.is_renderable() {
                 continue;
             }
+            // Off screen models are updated too, so their simulations go on
             model.update(self, &*camera, frame_number);
+            if !self.renderable_models.contains(id) {
+                continue;
+            }
             model.render_gbuffer(
                 &mut kernel_data.frames_data[frame_number].gbuff,
                 frame_number,
@@ -586,6 +628,10 @@ impl Scene for Base {
         return &self.all_models;
     }
 
+    fn get_joints(&self) -> &BTreeMap<Id, Arc<RwLock<Joint>>> {
+        return &self.joints;
+    }
+
     fn clean(&mut self) {
         let mut ids = Vec::<Id>::new();
         for (id, model) in &self.all_models {
@@ -595,9 +641,23 @@ impl Scene for Base {
         }
         for id in ids {
             self.all_models.remove(&id);
+            self.models_tree.remove(id);
         }
     }
 
+    fn raycast(
+        &self,
+        origin: &cgmath::Vector3<Real>,
+        direction: &cgmath::Vector3<Real>,
+        max_distance: Real,
+    ) -> Option<Hit> {
+        return self.models_tree.raycast(origin, direction, max_distance);
+    }
+
+    fn get_models_in_radius(&self, center: &cgmath::Vector3<Real>, radius: Real) -> Vec<Id> {
+        return self.models_tree.get_models_in_radius(center, radius);
+    }
+
     fn submit(
         &mut self,
         geng: &GraphicApiEngine,
@@ -737,6 +797,8 @@ impl DefaultScene for Base {
             active_camera: None,
             models: BTreeMap::new(),
             all_models: BTreeMap::new(),
+            models_tree: ModelsTree::new(),
+            renderable_models: BTreeSet::new(),
             lights: BTreeMap::new(),
             shadow_maker_lights: BTreeMap::new(),
             kernels_data,
@@ -746,6 +808,7 @@ impl DefaultScene for Base {
             render_pass,
             framebuffers,
             unlit_pipeline,
+            joints: BTreeMap::new(),
         }
     }
 }
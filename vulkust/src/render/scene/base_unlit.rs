@@ -1,6 +1,7 @@
 use super::super::super::core::gx3d::Gx3DReader;
 use super::super::super::core::object::Object as CoreObject;
-use super::super::super::core::types::Id;
+use super::super::super::core::types::{Id, Real};
+use super::super::super::physics::joint::Joint;
 use super::super::camera::Camera;
 use super::super::command::{Buffer as CmdBuffer, Pool as CmdPool};
 use super::super::deferred::Deferred;
@@ -16,8 +17,9 @@ use super::super::render_pass::RenderPass;
 use super::super::shadower::Shadower;
 use super::super::ssao::SSAO;
 use super::super::sync::Semaphore;
-use super::{DefaultScene, Scene};
-use std::collections::BTreeMap;
+use super::models_tree::ModelsTree;
+use super::{DefaultScene, Hit, Scene};
+use std::collections::{BTreeMap, BTreeSet};
 use std::sync::{Arc, Mutex, RwLock, Weak};
 
 #[cfg_attr(debug_mode, derive(Debug))]
@@ -54,11 +56,14 @@ pub(super) struct Base {
     active_camera: Option<Weak<RwLock<Camera>>>,
     models: BTreeMap<Id, Arc<RwLock<Model>>>,
     all_models: BTreeMap<Id, Weak<RwLock<Model>>>,
+    models_tree: ModelsTree,
+    renderable_models: BTreeSet<Id>,
     kernels_data: Vec<Arc<Mutex<BaseKernelData>>>,
     frames_data: Vec<BaseFramedata>,
     render_pass: Arc<RenderPass>,
     framebuffers: Vec<Arc<Framebuffer>>,
     unlit_pipeline: Arc<Pipeline>,
+    joints: BTreeMap<Id, Arc<RwLock<Joint>>>,
 }
 
 impl Base {
@@ -104,17 +109,21 @@ impl Base {
             PipelineType::Unlit,
             engine.get_config(),
         );
+        let models_tree = ModelsTree::new_with_models(&all_models);
         Self {
             obj_base,
             cameras,
             active_camera,
             models,
             all_models,
+            models_tree,
+            renderable_models: BTreeSet::new(),
             kernels_data,
             frames_data: Vec::new(),
             render_pass,
             framebuffers,
             unlit_pipeline,
+            joints: BTreeMap::new(),
         }
     }
 
@@ -126,7 +135,7 @@ impl Base {
         if reader.read_bool() {
             let _skybox_id: Id = reader.read();
         }
-        let _constraits_ids = reader.read_array::<Id>(); // todo
+        let joints_ids = reader.read_array::<Id>();
         if reader.read_bool() {
             vxunimplemented!(); // todo
         }
@@ -163,6 +172,13 @@ impl Base {
                 models.insert(id, model);
             }
         }
+        let mut joints = BTreeMap::new();
+        {
+            let mut mgr = vxresult!(asset_manager.get_joint_manager().write());
+            for id in joints_ids {
+                joints.insert(id, mgr.load_gx3d(id));
+            }
+        }
         let mut lights = BTreeMap::new();
         let mut shadow_maker_lights = BTreeMap::new();
         {
@@ -193,17 +209,21 @@ impl Base {
             PipelineType::Unlit,
             eng.get_config(),
         );
+        let models_tree = ModelsTree::new_with_models(&all_models);
         Self {
             obj_base: ObjectBase::new_with_id(my_id),
             cameras,
             active_camera,
             models,
             all_models,
+            models_tree,
+            renderable_models: BTreeSet::new(),
             kernels_data,
             frames_data: Vec::new(),
             render_pass,
             framebuffers,
             unlit_pipeline,
+            joints,
         }
     }
 }
@@ -254,16 +274,16 @@ impl Scene for Base {
     }
 
     fn add_model(&mut self, model: Arc<RwLock<Model>>) {
-        let id = {
+        let (id, child_models) = {
             let model = vxresult!(model.read());
-            let child_models = model.bring_all_child_models();
-            for (id, model) in child_models {
-                self.all_models.insert(id, Arc::downgrade(&model));
-            }
-            let id = model.get_id();
-            id
+            (model.get_id(), model.bring_all_child_models())
         };
+        for (id, model) in child_models {
+            self.all_models.insert(id, Arc::downgrade(&model));
+            self.models_tree.insert(id, &model);
+        }
         self.all_models.insert(id, Arc::downgrade(&model));
+        self.models_tree.insert(id, &model);
         self.models.insert(id, model);
     }
 
@@ -275,7 +295,17 @@ impl Scene for Base {
         return &self.active_camera;
     }
 
-    fn update(&mut self, _: usize) {}
+    fn update(&mut self, _: usize) {
+        self.models_tree.update();
+        if !self.is_renderable() {
+            return;
+        }
+        let camera = vxunwrap!(&self.active_camera);
+        let camera = vxunwrap!(camera.upgrade());
+        let camera = vxresult!(camera.read());
+        self.models_tree
+            .gather_renderables(&*camera, &[], &mut self.renderable_models);
+    }
 
     fn update_shadow_makers(&self) {}
 
@@ -308,7 +338,7 @@ impl Scene for Base {
         let camera = vxunwrap!(&self.active_camera).upgrade();
         let camera = vxunwrap!(camera);
         let camera = vxresult!(camera.read());
-        for (_, model) in &self.all_models {
+        for (id, model) in &self.all_models {
             task_index += 1;
             if task_index % kernels_count != kernel_index {
                 continue;
@@ -322,8 +352,11 @@ impl Scene for Base {
             if !model.is_renderable() {
                 continue;
             }
+            // Off screen models are updated too, so their simulations go on
             model.update(self, &*camera, frame_number);
-            model.render_unlit(cmd, &*camera, frame_number);
+            if self.renderable_models.contains(id) {
+                model.render_unlit(cmd, &*camera, frame_number);
+            }
         }
         cmd.end();
     }
@@ -338,6 +371,10 @@ impl Scene for Base {
         return &self.all_models;
     }
 
+    fn get_joints(&self) -> &BTreeMap<Id, Arc<RwLock<Joint>>> {
+        return &self.joints;
+    }
+
     fn clean(&mut self) {
         let mut ids = Vec::<Id>::new();
         for (id, model) in &self.all_models {
@@ -347,9 +384,23 @@ impl Scene for Base {
         }
         for id in ids {
             self.all_models.remove(&id);
+            self.models_tree.remove(id);
         }
     }
 
+    fn raycast(
+        &self,
+        origin: &cgmath::Vector3<Real>,
+        direction: &cgmath::Vector3<Real>,
+        max_distance: Real,
+    ) -> Option<Hit> {
+        return self.models_tree.raycast(origin, direction, max_distance);
+    }
+
+    fn get_models_in_radius(&self, center: &cgmath::Vector3<Real>, radius: Real) -> Vec<Id> {
+        return self.models_tree.get_models_in_radius(center, radius);
+    }
+
     fn submit(
         &mut self,
         geng: &GraphicApiEngine,
@@ -409,11 +460,14 @@ impl DefaultScene for Base {
             active_camera: None,
             models: BTreeMap::new(),
             all_models: BTreeMap::new(),
+            models_tree: ModelsTree::new(),
+            renderable_models: BTreeSet::new(),
             kernels_data,
             frames_data: Vec::new(),
             render_pass,
             framebuffers,
             unlit_pipeline,
+            joints: BTreeMap::new(),
         }
     }
 }
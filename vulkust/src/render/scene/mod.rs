@@ -1,5 +1,6 @@
 use super::super::core::gx3d::Gx3DReader;
-use super::super::core::types::Id;
+use super::super::core::types::{Id, Real};
+use super::super::physics::joint::Joint;
 use super::camera::Camera;
 use super::command::Pool as CmdPool;
 use super::deferred::Deferred;
@@ -19,12 +20,17 @@ mod base_deferred_shadow;
 mod base_unlit;
 pub mod game;
 pub mod manager;
+mod models_tree;
 pub mod ui;
 
 pub use self::game::Game;
 pub use self::manager::Manager;
 pub use self::ui::Ui;
 
+use self::models_tree::raycast_model;
+
+use cgmath;
+use cgmath::prelude::*;
 use gltf;
 
 #[repr(u8)]
@@ -34,6 +40,16 @@ pub enum TypeId {
     UI = 2,
 }
 
+#[derive(Clone, Copy)]
+#[cfg_attr(debug_mode, derive(Debug))]
+pub struct Hit {
+    pub model_id: Id,
+    pub mesh_id: Id,
+    pub point: cgmath::Vector3<Real>,
+    pub normal: cgmath::Vector3<Real>,
+    pub distance: Real,
+}
+
 pub trait Scene: Object {
     fn add_camera(&mut self, Arc<RwLock<Camera>>);
     fn add_model(&mut self, Arc<RwLock<Model>>);
@@ -41,6 +57,7 @@ pub trait Scene: Object {
     fn get_active_camera(&self) -> &Option<Weak<RwLock<Camera>>>;
     fn get_models(&self) -> &BTreeMap<Id, Arc<RwLock<Model>>>;
     fn get_all_models(&self) -> &BTreeMap<Id, Weak<RwLock<Model>>>;
+    fn get_joints(&self) -> &BTreeMap<Id, Arc<RwLock<Joint>>>;
     fn update(&mut self, usize);
     fn render_gbuffer_shadow_maps(
         &self,
@@ -53,6 +70,9 @@ pub trait Scene: Object {
     fn update_shadow_makers(&self);
     fn render_shadow_maps(&self, &Shadower, usize, usize);
     fn clean(&mut self);
+    // Spatial queries use the state of models in the last update of scene
+    fn raycast(&self, &cgmath::Vector3<Real>, &cgmath::Vector3<Real>, Real) -> Option<Hit>;
+    fn get_models_in_radius(&self, &cgmath::Vector3<Real>, Real) -> Vec<Id>;
     fn submit(
         &mut self,
         &GraphicApiEngine,
@@ -65,6 +85,43 @@ pub trait Scene: Object {
     ) -> Arc<Semaphore>;
 }
 
+// Tests models without any spatial index, for models that are not in a scene.
+// First models are culled by their bounding spheres, then their meshes are tested exactly.
+// Meshes need their data on CPU, see keep_mesh_data_on_cpu in render configurations.
+pub fn raycast_models(
+    models: &BTreeMap<Id, Weak<RwLock<Model>>>,
+    origin: &cgmath::Vector3<Real>,
+    direction: &cgmath::Vector3<Real>,
+    max_distance: Real,
+) -> Option<Hit> {
+    let direction = direction.normalize();
+    let mut result: Option<Hit> = None;
+    let mut tmax = max_distance;
+    for (model_id, model) in models {
+        let model = match model.upgrade() {
+            Some(model) => model,
+            None => continue,
+        };
+        let model = vxresult!(model.read());
+        if !model.is_renderable() {
+            continue;
+        }
+        let center = model.get_uniform().get_model().w.truncate();
+        let radius = model.get_occlusion_culling_radius();
+        let oc = center - origin;
+        let tc = oc.dot(direction);
+        let d2 = oc.magnitude2() - tc * tc;
+        if d2 > radius * radius || tc + radius < 0.0 || tc - radius > tmax {
+            continue;
+        }
+        if let Some(hit) = raycast_model(*model_id, &*model, origin, &direction, tmax) {
+            tmax = hit.distance;
+            result = Some(hit);
+        }
+    }
+    return result;
+}
+
 pub trait Loadable: Scene + Sized {
     fn new_with_gltf(&Engine, &gltf::Scene, &[u8]) -> Self;
     fn new_with_gx3d(&Engine, &mut Gx3DReader, Id) -> Self;
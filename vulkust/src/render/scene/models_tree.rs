@@ -0,0 +1,361 @@
+use super::super::super::collision::aabb::Aabb3;
+use super::super::super::collision::frustum::IntersectionStatus;
+use super::super::super::collision::tree::DynamicTree;
+use super::super::super::core::types::{Id, Real};
+use super::super::super::math::ray::Ray3;
+use super::super::camera::Camera;
+use super::super::light::ShadowMaker;
+use super::super::mesh::Mesh;
+use super::super::model::Model;
+use super::super::object::Object;
+use super::Hit;
+use cgmath;
+use cgmath::prelude::*;
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::{Arc, RwLock, Weak};
+
+// Models move a little in each frame, so their boxes are fattened to
+// prevent rebuilding the tree in every frame
+const FAT_MARGIN: Real = 0.2;
+
+// Spatial index of the models of a scene, scene inserts its models when they are added and
+// in the update of scene only transformed models get refitted
+#[cfg_attr(debug_mode, derive(Debug))]
+pub(super) struct ModelsTree {
+    tree: DynamicTree<(Id, Weak<RwLock<Model>>)>,
+    proxies: BTreeMap<Id, (usize, Weak<RwLock<Model>>)>,
+}
+
+fn get_model_center(model: &Model) -> cgmath::Vector3<Real> {
+    return model.get_uniform().get_model().w.truncate();
+}
+
+fn get_model_aabb(model: &Model) -> Aabb3 {
+    return Aabb3::new_with_center_radius(
+        &get_model_center(model),
+        model.get_occlusion_culling_radius(),
+    );
+}
+
+impl ModelsTree {
+    pub(super) fn new() -> Self {
+        Self {
+            tree: DynamicTree::new(FAT_MARGIN),
+            proxies: BTreeMap::new(),
+        }
+    }
+
+    pub(super) fn new_with_models(models: &BTreeMap<Id, Weak<RwLock<Model>>>) -> Self {
+        let mut myself = Self::new();
+        for (id, model) in models {
+            if let Some(model) = model.upgrade() {
+                myself.insert(*id, &model);
+            }
+        }
+        return myself;
+    }
+
+    pub(super) fn insert(&mut self, id: Id, model: &Arc<RwLock<Model>>) {
+        let aabb = {
+            let mut model = vxresult!(model.write());
+            model.clear_transformed();
+            get_model_aabb(&*model)
+        };
+        if let Some(&(proxy, _)) = self.proxies.get(&id) {
+            self.tree.update(proxy, &aabb);
+            return;
+        }
+        let model = Arc::downgrade(model);
+        let proxy = self.tree.insert(&aabb, (id, model.clone()));
+        self.proxies.insert(id, (proxy, model));
+    }
+
+    pub(super) fn update(&mut self) {
+        for &(proxy, ref model) in self.proxies.values() {
+            let model = match model.upgrade() {
+                Some(model) => model,
+                None => continue,
+            };
+            if !vxresult!(model.read()).is_transformed() {
+                continue;
+            }
+            let mut model = vxresult!(model.write());
+            model.clear_transformed();
+            self.tree.update(proxy, &get_model_aabb(&*model));
+        }
+    }
+
+    pub(super) fn remove(&mut self, id: Id) {
+        if let Some((proxy, _)) = self.proxies.remove(&id) {
+            self.tree.remove(proxy);
+        }
+    }
+
+    // Gathers ids of models that are in camera or may cast shadow on what camera sees,
+    // it only decides what is drawn, all of models are updated by scene
+    pub(super) fn gather_renderables(
+        &self,
+        camera: &Camera,
+        shadow_makers: &[&ShadowMaker],
+        result: &mut BTreeSet<Id>,
+    ) {
+        result.clear();
+        self.tree.query(
+            &mut |aabb: &Aabb3| match camera.intersect_aabb(aabb) {
+                IntersectionStatus::Outside => {
+                    for shm in shadow_makers {
+                        if shm.is_in_shadow_range(aabb) {
+                            return IntersectionStatus::Intersecting;
+                        }
+                    }
+                    IntersectionStatus::Outside
+                }
+                status => status,
+            },
+            &mut |m: &(Id, Weak<RwLock<Model>>)| {
+                result.insert(m.0);
+            },
+        );
+    }
+
+    pub(super) fn get_models_in_radius(
+        &self,
+        center: &cgmath::Vector3<Real>,
+        radius: Real,
+    ) -> Vec<Id> {
+        let mut result = Vec::new();
+        self.tree
+            .query_sphere(center, radius, &mut |m: &(Id, Weak<RwLock<Model>>)| {
+                let model = match m.1.upgrade() {
+                    Some(model) => model,
+                    None => return,
+                };
+                let model = vxresult!(model.read());
+                let r = radius + model.get_occlusion_culling_radius();
+                if (get_model_center(&*model) - center).magnitude2() <= r * r {
+                    result.push(m.0);
+                }
+            });
+        return result;
+    }
+
+    // Candidates come from the tree, then their meshes are tested exactly.
+    // Meshes need their data on CPU, see keep_mesh_data_on_cpu in render configurations.
+    pub(super) fn raycast(
+        &self,
+        origin: &cgmath::Vector3<Real>,
+        direction: &cgmath::Vector3<Real>,
+        max_distance: Real,
+    ) -> Option<Hit> {
+        let direction = direction.normalize();
+        let mut result: Option<Hit> = None;
+        let ray = Ray3::new(*origin, direction);
+        self.tree.query_ray(
+            &ray,
+            max_distance,
+            &mut |m: &(Id, Weak<RwLock<Model>>), _| {
+                let tmax = match &result {
+                    Some(hit) => hit.distance,
+                    None => max_distance,
+                };
+                let model = match m.1.upgrade() {
+                    Some(model) => model,
+                    None => return tmax,
+                };
+                let model = vxresult!(model.read());
+                if !model.is_renderable() {
+                    return tmax;
+                }
+                if let Some(hit) = raycast_model(m.0, &*model, origin, &direction, tmax) {
+                    let distance = hit.distance;
+                    result = Some(hit);
+                    return distance;
+                }
+                return tmax;
+            },
+        );
+        return result;
+    }
+}
+
+pub(super) fn raycast_model(
+    model_id: Id,
+    model: &Model,
+    origin: &cgmath::Vector3<Real>,
+    direction: &cgmath::Vector3<Real>,
+    max_distance: Real,
+) -> Option<Hit> {
+    return raycast_meshes(
+        model_id,
+        model.get_uniform().get_model(),
+        model.get_meshes().iter().map(|(id, m)| (id, &m.0)),
+        origin,
+        direction,
+        max_distance,
+    );
+}
+
+fn raycast_meshes<'a, I>(
+    model_id: Id,
+    transform: &cgmath::Matrix4<Real>,
+    meshes: I,
+    origin: &cgmath::Vector3<Real>,
+    direction: &cgmath::Vector3<Real>,
+    max_distance: Real,
+) -> Option<Hit>
+where
+    I: Iterator<Item = (&'a Id, &'a Arc<RwLock<Mesh>>)>,
+{
+    let inversed = match transform.invert() {
+        Some(m) => m,
+        None => return None,
+    };
+    // Direction is not normalized, so distances remain the same in mesh space
+    let ray = Ray3::new(
+        (inversed * origin.extend(1.0)).truncate(),
+        (inversed * direction.extend(0.0)).truncate(),
+    );
+    let mut tmax = max_distance;
+    let mut result = None;
+    for (mesh_id, mesh) in meshes {
+        let (t, normal) = match vxresult!(mesh.read()).intersect_ray(&ray, tmax) {
+            Some(h) => h,
+            None => continue,
+        };
+        tmax = t;
+        let normal = (inversed.transpose() * normal.extend(0.0))
+            .truncate()
+            .normalize();
+        result = Some(Hit {
+            model_id,
+            mesh_id: *mesh_id,
+            point: origin + direction * t,
+            normal,
+            distance: t,
+        });
+    }
+    return result;
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::super::super::core::object::Object as CoreObject;
+    use super::super::super::super::math::triangle::{SolidTriangle, Triangle};
+    use super::super::super::command::Buffer as CmdBuffer;
+    use super::super::super::object::Base as ObjectBase;
+    use super::*;
+
+    // A unit square on XY plane of mesh space
+    #[cfg_attr(debug_mode, derive(Debug))]
+    struct Square {
+        obj_base: ObjectBase,
+        positions: Vec<cgmath::Vector3<Real>>,
+    }
+
+    impl Square {
+        fn new() -> Self {
+            Self {
+                obj_base: ObjectBase::new(),
+                positions: vec![
+                    cgmath::Vector3::new(-0.5, -0.5, 0.0),
+                    cgmath::Vector3::new(0.5, -0.5, 0.0),
+                    cgmath::Vector3::new(0.5, 0.5, 0.0),
+                    cgmath::Vector3::new(-0.5, 0.5, 0.0),
+                ],
+            }
+        }
+    }
+
+    impl CoreObject for Square {
+        fn get_id(&self) -> Id {
+            self.obj_base.get_id()
+        }
+    }
+
+    impl Object for Square {
+        fn get_name(&self) -> Option<String> {
+            self.obj_base.get_name()
+        }
+
+        fn set_name(&mut self, name: &str) {
+            self.obj_base.set_name(name);
+        }
+
+        fn disable_rendering(&mut self) {
+            self.obj_base.disable_rendering();
+        }
+
+        fn enable_rendering(&mut self) {
+            self.obj_base.enable_rendering();
+        }
+
+        fn is_renderable(&self) -> bool {
+            return self.obj_base.is_renderable();
+        }
+    }
+
+    impl Mesh for Square {
+        fn is_shadow_caster(&self) -> bool {
+            return false;
+        }
+
+        fn is_transparent(&self) -> bool {
+            return false;
+        }
+
+        fn get_occlusion_culling_radius(&self) -> Real {
+            return 0.8;
+        }
+
+        fn update(&mut self, _: usize) {}
+        fn render_gbuffer(&self, _: &mut CmdBuffer, _: usize) {}
+        fn render_unlit(&self, _: &mut CmdBuffer, _: usize) {}
+        fn render_shadow(&self, _: &mut CmdBuffer, _: usize) {}
+
+        fn intersect_ray(&self, ray: &Ray3, tmax: Real) -> Option<(Real, cgmath::Vector3<Real>)> {
+            let mut result = None;
+            let mut tmax = tmax;
+            for i in &[[0, 1, 2], [0, 2, 3]] {
+                let t = SolidTriangle::new(i, &self.positions);
+                if let Some((d, _, _)) = t.intersect(ray, tmax, &self.positions) {
+                    tmax = d;
+                    result = Some((d, t.get_normal()));
+                }
+            }
+            return result;
+        }
+    }
+
+    #[test]
+    fn raycast_hit_miss_test() {
+        let mut meshes: BTreeMap<Id, Arc<RwLock<Mesh>>> = BTreeMap::new();
+        let mesh = Square::new();
+        meshes.insert(mesh.get_id(), Arc::new(RwLock::new(mesh)));
+        // square is scaled twice and moved 5 units in front of the origin
+        let transform = cgmath::Matrix4::from_translation(cgmath::Vector3::new(0.0, 0.0, -5.0))
+            * cgmath::Matrix4::from_scale(2.0);
+        let origin = cgmath::Vector3::new(0.0, 0.0, 0.0);
+        let cast = |direction: cgmath::Vector3<Real>, max_distance: Real| {
+            raycast_meshes(
+                7,
+                &transform,
+                meshes.iter(),
+                &origin,
+                &direction,
+                max_distance,
+            )
+        };
+        let hit = cast(cgmath::Vector3::new(0.1, 0.1, -1.0).normalize(), 100.0).unwrap();
+        assert_eq!(hit.model_id, 7);
+        assert_eq!(Some(&hit.mesh_id), meshes.keys().next());
+        assert!((hit.point - cgmath::Vector3::new(0.5, 0.5, -5.0)).magnitude() < 1e-4);
+        assert!((hit.distance - hit.point.magnitude()).abs() < 1e-4);
+        assert!((hit.normal - cgmath::Vector3::new(0.0, 0.0, 1.0)).magnitude() < 1e-4);
+        // outside of the scaled square
+        assert!(cast(cgmath::Vector3::new(0.3, 0.0, -1.0).normalize(), 100.0).is_none());
+        // behind the ray
+        assert!(cast(cgmath::Vector3::new(0.0, 0.0, 1.0), 100.0).is_none());
+        // farther than the max distance
+        assert!(cast(cgmath::Vector3::new(0.0, 0.0, -1.0), 4.0).is_none());
+    }
+}
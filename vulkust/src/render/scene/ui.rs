@@ -1,6 +1,7 @@
 use super::super::super::core::gx3d::Gx3DReader;
 use super::super::super::core::object::Object as CoreObject;
-use super::super::super::core::types::Id;
+use super::super::super::core::types::{Id, Real};
+use super::super::super::physics::joint::Joint;
 use super::super::camera::Camera;
 use super::super::command::Pool as CmdPool;
 use super::super::deferred::Deferred;
@@ -14,7 +15,7 @@ use super::super::shadower::Shadower;
 use super::super::ssao::SSAO;
 use super::super::sync::Semaphore;
 use super::base_unlit::Base;
-use super::{DefaultScene, Loadable, Scene};
+use super::{DefaultScene, Hit, Loadable, Scene};
 use std::collections::BTreeMap;
 use std::sync::{Arc, RwLock, Weak};
 
@@ -109,10 +110,27 @@ impl Scene for Ui {
         return self.base.get_all_models();
     }
 
+    fn get_joints(&self) -> &BTreeMap<Id, Arc<RwLock<Joint>>> {
+        return self.base.get_joints();
+    }
+
     fn clean(&mut self) {
         self.base.clean();
     }
 
+    fn raycast(
+        &self,
+        origin: &cgmath::Vector3<Real>,
+        direction: &cgmath::Vector3<Real>,
+        max_distance: Real,
+    ) -> Option<Hit> {
+        return self.base.raycast(origin, direction, max_distance);
+    }
+
+    fn get_models_in_radius(&self, center: &cgmath::Vector3<Real>, radius: Real) -> Vec<Id> {
+        return self.base.get_models_in_radius(center, radius);
+    }
+
     fn submit(
         &mut self,
         geng: &GraphicApiEngine,
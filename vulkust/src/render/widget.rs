@@ -1,5 +1,6 @@
 use super::super::core::object::Object as CoreObject;
 use super::super::core::types::{Id, Real};
+use super::super::physics::collider::Collider;
 use super::camera::Camera;
 use super::command::Buffer as CmdBuffer;
 use super::engine::Engine;
@@ -123,6 +124,22 @@ impl Model for Base {
         return &self.model_base.get_uniform();
     }
 
+    fn get_collider(&self) -> &Arc<RwLock<Collider>> {
+        return self.model_base.get_collider();
+    }
+
+    fn set_collider(&mut self, collider: Arc<RwLock<Collider>>) {
+        self.model_base.set_collider(collider);
+    }
+
+    fn is_transformed(&self) -> bool {
+        return self.model_base.is_transformed();
+    }
+
+    fn clear_transformed(&mut self) {
+        self.model_base.clear_transformed();
+    }
+
     fn render_gbuffer(&self, cmd: &mut CmdBuffer, frame_number: usize) {
         self.model_base.render_gbuffer(cmd, frame_number);
     }
@@ -406,6 +423,22 @@ impl Model for Label {
         return self.base.get_uniform();
     }
 
+    fn get_collider(&self) -> &Arc<RwLock<Collider>> {
+        return self.base.get_collider();
+    }
+
+    fn set_collider(&mut self, collider: Arc<RwLock<Collider>>) {
+        self.base.set_collider(collider);
+    }
+
+    fn is_transformed(&self) -> bool {
+        return self.base.is_transformed();
+    }
+
+    fn clear_transformed(&mut self) {
+        self.base.clear_transformed();
+    }
+
     fn render_gbuffer(&self, cmd: &mut CmdBuffer, frame_number: usize) {
         self.base.render_gbuffer(cmd, frame_number);
     }
@@ -1,4 +1,5 @@
 use super::super::super::core::application::Application as CoreAppTrait;
+use super::super::super::core::dispatcher::Dispatcher as EventDispatcher;
 use super::super::super::core::event::{
     Event, FingerIndexType, Move, Touch, TouchAction, Type as EventType,
 };
@@ -22,6 +23,7 @@ pub struct Application {
     pub and_app: *mut AndroidApp,
     pub events: Arc<RwLock<Vec<Event>>>,
     pub gesture_translator: Arc<RwLock<gesture::Translator>>,
+    pub event_dispatcher: Arc<EventDispatcher>,
 }
 
 impl fmt::Debug for Application {
@@ -43,6 +45,7 @@ impl Application {
             and_app,
             events: Arc::new(RwLock::new(Vec::new())),
             gesture_translator: Arc::new(RwLock::new(gesture::Translator::new())),
+            event_dispatcher: Arc::new(EventDispatcher::new()),
         }
     }
 
@@ -70,12 +73,44 @@ impl Application {
     }
 
     pub fn run(&self) {
+        let core_app = vxunwrap!(&self.core_app);
         loop {
+            for e in self.event_dispatcher.fetch_posted() {
+                self.event_dispatcher.send(e, core_app);
+            }
             let _ = self.fetch_events();
+            let ge = vxresult!(self.gesture_translator.write()).update();
+            for e in ge {
+                self.event_dispatcher.send(e, core_app);
+            }
             vxresult!(vxunwrap!(&self.renderer).read()).update();
+            vxresult!(core_app.write()).update();
+            vxresult!(vxunwrap!(&self.renderer).read()).render();
+        }
+    }
+
+    // Gestures of a touch come after it
+    fn send_touch(&self, e: Event) {
+        let core_app = vxunwrap!(&self.core_app);
+        let ge = vxresult!(self.gesture_translator.write()).receive(&e);
+        self.event_dispatcher.send(e, core_app);
+        for e in ge {
+            self.event_dispatcher.send(e, core_app);
+        }
+    }
+
+    // Fingers that are down will not be released, e.g. system took the touches or focus is lost
+    fn cancel_gestures(&self) {
+        let ge = vxresult!(self.gesture_translator.write()).cancel();
+        for e in ge {
+            self.event_dispatcher.send(e, vxunwrap!(&self.core_app));
         }
     }
 
+    pub fn get_event_dispatcher(&self) -> Arc<EventDispatcher> {
+        return self.event_dispatcher.clone();
+    }
+
     fn handle_cmd(&self, cmd: i32) {
         match unsafe { transmute::<i8, AppCmd>(cmd as i8) } {
             AppCmd::InitWindow => {
@@ -84,6 +119,9 @@ impl Application {
             AppCmd::TermWindow => {
                 vxlogi!("Window has been terminated!");
             }
+            AppCmd::LostFocus => {
+                self.cancel_gestures();
+            }
             c @ _ => {
                 let _ = c;
                 vxlogi!("event {:?} not handled.", c);
@@ -110,12 +148,7 @@ impl Application {
                             unsafe { input::AMotionEvent_getRawY(e, pi as usize) } / wh,
                         ),
                     }));
-                    let ge = vxresult!(self.gesture_translator.write()).receive(&e);
-                    let core_app = vxresult!(vxunwrap!(&self.core_app).read());
-                    core_app.on_event(e);
-                    for e in ge {
-                        core_app.on_event(e);
-                    }
+                    self.send_touch(e);
                     return 1;
                 }
                 input::AMotionEventAction::PointerUp | input::AMotionEventAction::Up => {
@@ -127,12 +160,7 @@ impl Application {
                             unsafe { input::AMotionEvent_getY(e, pi as usize) } / wh,
                         ),
                     }));
-                    let ge = vxresult!(self.gesture_translator.write()).receive(&e);
-                    let core_app = vxresult!(vxunwrap!(&self.core_app).read());
-                    core_app.on_event(e);
-                    for e in ge {
-                        core_app.on_event(e);
-                    }
+                    self.send_touch(e);
                     return 1;
                 }
                 input::AMotionEventAction::Move => {
@@ -159,12 +187,11 @@ impl Application {
                         current,
                         delta: (current.0 - previous.0, current.1 - previous.1),
                     }));
-                    let ge = vxresult!(self.gesture_translator.write()).receive(&e);
-                    let core_app = vxresult!(vxunwrap!(&self.core_app).read());
-                    core_app.on_event(e);
-                    for e in ge {
-                        core_app.on_event(e);
-                    }
+                    self.send_touch(e);
+                    return 1;
+                }
+                input::AMotionEventAction::Cancel => {
+                    self.cancel_gestures();
                     return 1;
                 }
                 _ => (),
@@ -6,7 +6,7 @@ use std::mem::transmute;
 use std::ptr::null_mut;
 
 pub struct File {
-    pub asset: *mut aas::AAsset,
+    asset: *mut aas::AAsset,
 }
 
 impl fmt::Debug for File {
@@ -37,6 +37,17 @@ impl File {
     }
 }
 
+// Asset is only used through '&mut self' of its single owner, so one thread uses it at a time
+unsafe impl Send for File {}
+
+impl Drop for File {
+    fn drop(&mut self) {
+        unsafe {
+            aas::AAsset_close(self.asset);
+        }
+    }
+}
+
 impl Read for File {
     fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
         Ok(
@@ -1,4 +1,5 @@
 use super::super::super::core::application::Application as CoreAppTrait;
+use super::super::super::core::dispatcher::Dispatcher as EventDispatcher;
 use super::super::super::render::engine::Engine as RenderEngine;
 use super::super::apple;
 use std::mem::transmute;
@@ -11,6 +12,7 @@ pub struct Application {
     pub itself: Option<Weak<RwLock<Application>>>,
     pub view: *mut c_void,
     pub renderer: Option<Arc<RwLock<RenderEngine>>>,
+    pub event_dispatcher: Arc<EventDispatcher>,
 }
 
 impl Application {
@@ -20,6 +22,7 @@ impl Application {
             itself: None,
             view: null_mut(),
             renderer: None,
+            event_dispatcher: Arc::new(EventDispatcher::new()),
         }
     }
 
@@ -28,7 +31,16 @@ impl Application {
     }
 
     pub fn update(&self) {
+        for e in self.event_dispatcher.fetch_posted() {
+            self.event_dispatcher.send(e, &self.core_app);
+        }
         vxresult!(vxunwrap!(self.renderer).read()).update();
+        vxresult!(self.core_app.write()).update();
+        vxresult!(vxunwrap!(self.renderer).read()).render();
+    }
+
+    pub fn get_event_dispatcher(&self) -> Arc<EventDispatcher> {
+        return self.event_dispatcher.clone();
     }
 
     pub fn get_window_aspect_ratio(&self) -> f32 {
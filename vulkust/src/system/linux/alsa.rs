@@ -0,0 +1,142 @@
+use super::super::super::audio::backend::{sample_to_i16, Backend};
+use super::super::super::core::types::Real;
+use std::ffi::{CStr, CString};
+use std::os::raw::{c_char, c_int, c_long, c_uint, c_ulong, c_void};
+use std::ptr::null_mut;
+
+pub(super) enum Pcm {}
+
+const STREAM_PLAYBACK: c_int = 0;
+const MODE_NONBLOCK: c_int = 1;
+const FORMAT_S16_LE: c_int = 2;
+const ACCESS_RW_INTERLEAVED: c_int = 3;
+const ERROR_AGAIN: c_long = -11;
+// Amount of audio that device keeps ahead of the playback in microseconds
+const LATENCY: c_uint = 100000;
+
+#[link(name = "asound", kind = "dylib")]
+extern "C" {
+    pub(super) fn snd_pcm_open(
+        pcm: *mut *mut Pcm,
+        name: *const c_char,
+        stream: c_int,
+        mode: c_int,
+    ) -> c_int;
+    pub(super) fn snd_pcm_set_params(
+        pcm: *mut Pcm,
+        format: c_int,
+        access: c_int,
+        channels: c_uint,
+        rate: c_uint,
+        soft_resample: c_int,
+        latency: c_uint,
+    ) -> c_int;
+    pub(super) fn snd_pcm_writei(pcm: *mut Pcm, buffer: *const c_void, size: c_ulong) -> c_long;
+    pub(super) fn snd_pcm_recover(pcm: *mut Pcm, err: c_int, silent: c_int) -> c_int;
+    pub(super) fn snd_pcm_close(pcm: *mut Pcm) -> c_int;
+    pub(super) fn snd_strerror(errnum: c_int) -> *const c_char;
+}
+
+fn error_string(e: c_int) -> String {
+    return unsafe { CStr::from_ptr(snd_strerror(e)) }
+        .to_string_lossy()
+        .into_owned();
+}
+
+// Default playback device of ALSA, writing never blocks and the frames that device has no room
+// for are dropped
+#[cfg_attr(debug_mode, derive(Debug))]
+pub struct Device {
+    pcm: *mut Pcm,
+    sample_rate: u32,
+    channels: usize,
+    buffer: Vec<i16>,
+}
+
+impl Device {
+    // None is returned when there is no usable playback device
+    pub fn new(sample_rate: u32, channels: usize) -> Option<Self> {
+        let name = vxresult!(CString::new("default"));
+        let mut pcm = null_mut();
+        let r = unsafe { snd_pcm_open(&mut pcm, name.as_ptr(), STREAM_PLAYBACK, MODE_NONBLOCK) };
+        if r < 0 {
+            vxloge!("ALSA device can not be opened: {}", error_string(r));
+            return None;
+        }
+        let r = unsafe {
+            snd_pcm_set_params(
+                pcm,
+                FORMAT_S16_LE,
+                ACCESS_RW_INTERLEAVED,
+                channels as c_uint,
+                sample_rate,
+                1,
+                LATENCY,
+            )
+        };
+        if r < 0 {
+            vxloge!("ALSA device can not be configured: {}", error_string(r));
+            unsafe {
+                snd_pcm_close(pcm);
+            }
+            return None;
+        }
+        Some(Device {
+            pcm,
+            sample_rate,
+            channels,
+            buffer: Vec::new(),
+        })
+    }
+}
+
+impl Backend for Device {
+    fn get_sample_rate(&self) -> u32 {
+        return self.sample_rate;
+    }
+
+    fn get_channels(&self) -> usize {
+        return self.channels;
+    }
+
+    fn write(&mut self, samples: &[Real]) {
+        self.buffer.clear();
+        self.buffer
+            .extend(samples.iter().map(|s| sample_to_i16(*s)));
+        let mut written = 0;
+        let frames_count = self.buffer.len() / self.channels;
+        while written < frames_count {
+            let frames = &self.buffer[written * self.channels..];
+            let r = unsafe {
+                snd_pcm_writei(
+                    self.pcm,
+                    frames.as_ptr() as *const c_void,
+                    (frames_count - written) as c_ulong,
+                )
+            };
+            if r == ERROR_AGAIN {
+                break;
+            } else if r < 0 {
+                // underrun or suspend
+                let r = unsafe { snd_pcm_recover(self.pcm, r as c_int, 1) };
+                if r < 0 {
+                    vxloge!("ALSA device failed: {}", error_string(r));
+                    break;
+                }
+            } else {
+                written += r as usize;
+            }
+        }
+    }
+}
+
+impl Drop for Device {
+    fn drop(&mut self) {
+        unsafe {
+            snd_pcm_close(self.pcm);
+        }
+    }
+}
+
+// Device is only used through '&mut self' of its single owner and ALSA does not bind it to a thread
+unsafe impl Send for Device {}
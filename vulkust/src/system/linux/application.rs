@@ -1,11 +1,18 @@
 use super::super::super::core::application::Application as CoreAppTrait;
 use super::super::super::core::constants::{DEFAULT_WINDOW_HEIGHT, DEFAULT_WINDOW_WIDTH};
+use super::super::super::core::dispatcher::Dispatcher as EventDispatcher;
 use super::super::super::core::event;
-use super::super::super::core::event::{Button, Event, Keyboard, Mouse, Type as EventType, Window};
-use super::super::super::core::types::Real;
+use super::super::super::core::event::{
+    Button, Event, Keyboard, Modifiers, Mouse, Type as EventType, Window,
+};
+use super::super::super::core::gesture::Translator as GestureTranslator;
+use super::super::super::core::replay::Session as ReplaySession;
+use super::super::super::core::types::{Id, Real};
 use super::super::super::libc;
 use super::super::super::render::engine::Engine as RenderEngine;
+use super::gamepad::Manager as GamepadManager;
 use super::xcb;
+use super::xkb::{keysym_to_key, Translator as TextTranslator};
 use super::xproto;
 // use super::super::super::core::asset::manager::Manager as AssetManager;
 // use super::super::file::File;
@@ -14,7 +21,8 @@ use std::ffi::CString;
 use std::mem::transmute;
 use std::os::raw::{c_int, c_uint};
 use std::ptr::null_mut;
-use std::sync::{Arc, RwLock};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::Duration;
 
 #[cfg_attr(debug_mode, derive(Debug))]
 pub struct Application {
@@ -28,10 +36,26 @@ pub struct Application {
     window_height: Real,
     window_aspect_ratio: Real,
     current_mouse_position: Arc<RwLock<(Real, Real)>>,
+    gamepads: Mutex<GamepadManager>,
+    text_translator: Option<Mutex<TextTranslator>>,
+    held_keys: Mutex<Vec<xcb::KeyCode>>,
+    gesture_translator: Option<Mutex<GestureTranslator>>,
+    replay_session: Mutex<ReplaySession>,
+    event_dispatcher: Arc<EventDispatcher>,
 }
 
 impl Application {
     pub fn new(core_app: Arc<RwLock<CoreAppTrait>>) -> Self {
+        let config = vxresult!(core_app.read()).get_config();
+        let replay_session = ReplaySession::new(
+            config.get_input_record_file_name(),
+            config.get_input_replay_file_name(),
+        );
+        let gesture_translator = if config.get_mouse_touch_emulation() {
+            Some(Mutex::new(GestureTranslator::new_with_mouse_emulation()))
+        } else {
+            None
+        };
         let mut scr = 0 as c_int;
         let connection: *mut xcb::Connection = unsafe { xcb::xcb_connect(null_mut(), &mut scr) };
         if connection == null_mut() {
@@ -55,6 +79,7 @@ impl Application {
             | xcb::EventMask::POINTER_MOTION
             | xcb::EventMask::BUTTON_PRESS
             | xcb::EventMask::BUTTON_RELEASE
+            | xcb::EventMask::FOCUS_CHANGE
             | xcb::EventMask::RESIZE_REDIRECT)
             .bits();
         let value_mask = (xcb::CW::BACK_PIXEL | xcb::CW::EVENT_MASK).bits();
@@ -132,6 +157,12 @@ impl Application {
             window_height,
             window_aspect_ratio,
             current_mouse_position,
+            gamepads: Mutex::new(GamepadManager::new()),
+            text_translator: TextTranslator::new(connection).map(Mutex::new),
+            held_keys: Mutex::new(Vec::new()),
+            gesture_translator,
+            replay_session: Mutex::new(replay_session),
+            event_dispatcher: Arc::new(EventDispatcher::new()),
         }
     }
 
@@ -143,7 +174,17 @@ impl Application {
 
     pub fn run(&self) {
         'main_loop: loop {
-            let events = self.fetch_events();
+            let events = {
+                let mut session = vxresult!(self.replay_session.lock());
+                let mut events = self.event_dispatcher.fetch_posted();
+                events.extend(
+                    self.fetch_events()
+                        .into_iter()
+                        .filter_map(|e| session.filter(e)),
+                );
+                events.append(&mut session.fetch_replayed());
+                self.emulate_touches(events)
+            };
             for e in events {
                 match e.event_type {
                     EventType::Quit => {
@@ -156,13 +197,24 @@ impl Application {
                     }
                     _ => (),
                 }
-                vxresult!(vxunwrap!(&self.core_app).read()).on_event(e);
+                self.event_dispatcher.send(e, vxunwrap!(&self.core_app));
+            }
+            {
+                let renderer = vxresult!(vxunwrap!(&self.renderer).read());
+                let timing = renderer.get_timing();
+                vxresult!(self.replay_session.lock()).begin_frame(&mut vxresult!(timing.write()));
+                renderer.update();
+                vxresult!(self.replay_session.lock()).end_frame(&vxresult!(timing.read()));
             }
             vxresult!(vxunwrap!(&self.core_app).write()).update();
-            vxresult!(vxunwrap!(&self.renderer).read()).update();
+            vxresult!(vxunwrap!(&self.renderer).read()).render();
         }
     }
 
+    pub fn get_event_dispatcher(&self) -> Arc<EventDispatcher> {
+        return self.event_dispatcher.clone();
+    }
+
     pub fn get_mouse_position(&self) -> (Real, Real) {
         get_mouse_position(self.connection, self.window, self.screen)
     }
@@ -178,18 +230,86 @@ impl Application {
             if xcb_event == null_mut() {
                 break;
             }
-            let e = self.translate(xcb_event);
-            if let Some(e) = e {
-                events.push(Event::new(e));
+            if !self.update_text_translator(xcb_event) {
+                events.append(&mut self.release_held_keys(xcb_event));
+                let is_repeat = self.update_held_keys(xcb_event);
+                let e = self.translate(xcb_event, is_repeat);
+                if let Some(e) = e {
+                    events.push(Event::new(e));
+                }
+                if let Some(text) = self.translate_text(xcb_event) {
+                    events.push(Event::new(EventType::Text(text)));
+                }
             }
             unsafe {
                 libc::free(transmute(xcb_event));
             }
         }
+        for e in vxresult!(self.gamepads.lock()).fetch_events() {
+            events.push(Event::new(e));
+        }
+        return events;
+    }
+
+    // Touches and gestures that mouse emulates come after their mouse events
+    fn emulate_touches(&self, events: Vec<Event>) -> Vec<Event> {
+        let translator = match &self.gesture_translator {
+            Some(translator) => translator,
+            None => return events,
+        };
+        let mut translator = vxresult!(translator.lock());
+        let mut result = Vec::with_capacity(events.len());
+        for e in events {
+            let emulated = translator.receive(&e);
+            result.push(e);
+            result.extend(emulated);
+        }
+        result.extend(translator.update());
+        return result;
+    }
+
+    // Keys that are released while window does not have the focus are never reported,
+    // so held keys are released when focus is lost.
+    fn release_held_keys(&self, e: *mut xcb::GenericEvent) -> Vec<Event> {
+        if unsafe { (*e).response_type as c_uint & 0x7F } != xproto::FOCUS_OUT {
+            return Vec::new();
+        }
+        let mut held_keys = vxresult!(self.held_keys.lock());
+        let mut events = Vec::with_capacity(held_keys.len());
+        for k in held_keys.drain(..) {
+            if let Some(k) = self.translate_key(k) {
+                events.push(Event::new(EventType::Button {
+                    button: Button::Keyboard(k),
+                    action: event::ButtonAction::Release,
+                    modifiers: Modifiers::empty(),
+                    is_repeat: false,
+                }));
+            }
+        }
         return events;
     }
 
-    fn translate(&self, e: *mut xcb::GenericEvent) -> Option<EventType> {
+    // Returns true for the repeated presses of a held key.
+    fn update_held_keys(&self, e: *mut xcb::GenericEvent) -> bool {
+        let pressed = match unsafe { (*e).response_type as c_uint & 0x7F } {
+            xproto::KEY_PRESS => true,
+            xproto::KEY_RELEASE => false,
+            _ => return false,
+        };
+        let key_event: &xcb::KeyPressEvent = unsafe { transmute(e) };
+        let mut held_keys = vxresult!(self.held_keys.lock());
+        if !pressed {
+            held_keys.retain(|k| *k != key_event.detail);
+            return false;
+        }
+        if held_keys.contains(&key_event.detail) {
+            return true;
+        }
+        held_keys.push(key_event.detail);
+        return false;
+    }
+
+    fn translate(&self, e: *mut xcb::GenericEvent, is_repeat: bool) -> Option<EventType> {
         unsafe {
             if (xproto::DESTROY_NOTIFY as u8 == ((*e).response_type & 0x7f))
                 || ((xproto::CLIENT_MESSAGE as u8 == ((*e).response_type & 0x7f))
@@ -209,6 +329,7 @@ impl Application {
                 }
             }
             xproto::MOTION_NOTIFY => {
+                let motion: &xcb::MotionNotifyEvent = unsafe { transmute(e) };
                 let pos = self.get_mouse_position();
                 let pre = *vxresult!(self.current_mouse_position.read());
                 *vxresult!(self.current_mouse_position.write()) = pos;
@@ -216,71 +337,77 @@ impl Application {
                     previous: pre,
                     current: pos,
                     delta: (pos.0 - pre.0, pos.1 - pre.1),
+                    modifiers: translate_modifiers(motion.state),
                 }));
             }
-            xproto::BUTTON_PRESS => {
-                let press: &mut xcb::ButtonPressEvent = unsafe { transmute(e) };
-                let m: xcb::ButtonIndex = unsafe { transmute(press.detail as u32) };
-                let m = match m {
-                    xcb::ButtonIndex::_Index1 => Mouse::Left,
-                    xcb::ButtonIndex::_Index2 => Mouse::Middle,
-                    xcb::ButtonIndex::_Index3 => Mouse::Right,
-                    _ => {
-                        vxlogi!("Unknown mouse button pressed.");
-                        Mouse::Left
+            a @ xproto::BUTTON_PRESS | a @ xproto::BUTTON_RELEASE => {
+                let press: &xcb::ButtonPressEvent = unsafe { transmute(e) };
+                let modifiers = translate_modifiers(press.state);
+                let m = match press.detail {
+                    xproto::BUTTON_LEFT => Mouse::Left,
+                    xproto::BUTTON_MIDDLE => Mouse::Middle,
+                    xproto::BUTTON_RIGHT => Mouse::Right,
+                    xproto::BUTTON_BACK => Mouse::Back,
+                    xproto::BUTTON_FORWARD => Mouse::Forward,
+                    b @ xproto::BUTTON_SCROLL_UP..=xproto::BUTTON_SCROLL_RIGHT => {
+                        // each notch is a press and a release
+                        if a == xproto::BUTTON_RELEASE {
+                            return None;
+                        }
+                        let delta = match b {
+                            xproto::BUTTON_SCROLL_UP => (0.0, 1.0),
+                            xproto::BUTTON_SCROLL_DOWN => (0.0, -1.0),
+                            xproto::BUTTON_SCROLL_LEFT => (-1.0, 0.0),
+                            _ => (1.0, 0.0),
+                        };
+                        return Some(EventType::Scroll { delta, modifiers });
                     }
-                };
-                return Some(EventType::Button {
-                    button: Button::Mouse(m),
-                    action: event::ButtonAction::Press,
-                });
-            }
-            xproto::BUTTON_RELEASE => {
-                let release: &mut xcb::ButtonReleaseEvent = unsafe { transmute(e) };
-                let m: xcb::ButtonIndex = unsafe { transmute(release.detail as u32) };
-                let m = match m {
-                    xcb::ButtonIndex::_Index1 => Mouse::Left,
-                    xcb::ButtonIndex::_Index2 => Mouse::Middle,
-                    xcb::ButtonIndex::_Index3 => Mouse::Right,
-                    _ => {
-                        vxloge!("Unknown mouse button pressed.");
-                        Mouse::Left
+                    b @ _ => {
+                        vxlogi!("Unknown mouse button: {}", b);
+                        return None;
                     }
                 };
                 return Some(EventType::Button {
                     button: Button::Mouse(m),
-                    action: event::ButtonAction::Release,
+                    action: if a == xproto::BUTTON_RELEASE {
+                        event::ButtonAction::Release
+                    } else {
+                        event::ButtonAction::Press
+                    },
+                    modifiers,
+                    is_repeat: false,
                 });
             }
             a @ xproto::KEY_PRESS | a @ xproto::KEY_RELEASE => {
                 let key_event: &xcb::KeyReleaseEvent = unsafe { transmute(e) };
-                let b = Button::Keyboard(match key_event.detail {
-                    xproto::KEY_W => Keyboard::W,
-                    xproto::KEY_S => Keyboard::S,
-                    xproto::KEY_A => Keyboard::A,
-                    xproto::KEY_D => Keyboard::D,
-                    // xproto::KEY_P => { Keyboard::P },
-                    xproto::KEY_F1 => Keyboard::Function(1),
-                    k @ _ => {
-                        vxlogi!("Unknown key: {:?} presse", k);
-                        Keyboard::W
+                let b = match self.translate_key(key_event.detail) {
+                    Some(k) => Button::Keyboard(k),
+                    None => {
+                        vxlogi!("Unknown key: {:?}", key_event.detail);
+                        return None;
                     }
-                });
+                };
+                let modifiers = translate_modifiers(key_event.state);
                 return Some(if a == xproto::KEY_RELEASE {
                     EventType::Button {
                         button: b,
                         action: event::ButtonAction::Release,
+                        modifiers,
+                        is_repeat: false,
                     }
                 } else {
                     EventType::Button {
                         button: b,
                         action: event::ButtonAction::Press,
+                        modifiers,
+                        is_repeat,
                     }
                 });
             }
             xproto::DESTROY_NOTIFY => {
                 return Some(EventType::Quit);
             }
+            xproto::FOCUS_IN | xproto::FOCUS_OUT => (),
             xproto::CONFIGURE_NOTIFY => {
                 let cfg_event: &xcb::ConfigureNotifyEvent = unsafe { transmute(e) };
                 // if cfg_event.width as Real != self.window_aspects.0 ||
@@ -305,6 +432,54 @@ impl Application {
         return None;
     }
 
+    // Without XKB only the key codes of a standard keyboard are known
+    fn translate_key(&self, k: xcb::KeyCode) -> Option<Keyboard> {
+        if let Some(translator) = &self.text_translator {
+            return keysym_to_key(vxresult!(translator.lock()).keysym(k));
+        }
+        return match k {
+            xproto::KEY_W => Some(Keyboard::W),
+            xproto::KEY_S => Some(Keyboard::S),
+            xproto::KEY_A => Some(Keyboard::A),
+            xproto::KEY_D => Some(Keyboard::D),
+            xproto::KEY_SPACE => Some(Keyboard::Space(0)),
+            xproto::KEY_F1 => Some(Keyboard::Function(1)),
+            _ => None,
+        };
+    }
+
+    // Magnitudes are between 0 and 1
+    pub fn rumble(&self, gamepad: Id, strong: Real, weak: Real, duration: Duration) {
+        vxresult!(self.gamepads.lock()).rumble(gamepad, strong, weak, duration);
+    }
+
+    pub fn set_gamepad_dead_zones(&self, stick: Real, trigger: Real) {
+        vxresult!(self.gamepads.lock()).set_dead_zones(stick, trigger);
+    }
+
+    // Returns true for XKB events, they only change the keymap and state of translator
+    fn update_text_translator(&self, e: *mut xcb::GenericEvent) -> bool {
+        return match &self.text_translator {
+            Some(translator) => vxresult!(translator.lock()).event(e),
+            None => false,
+        };
+    }
+
+    // Text comes after the button event of its key
+    fn translate_text(&self, e: *mut xcb::GenericEvent) -> Option<String> {
+        let pressed = match unsafe { (*e).response_type as c_uint & 0x7F } {
+            xproto::KEY_PRESS => true,
+            xproto::KEY_RELEASE => false,
+            _ => return None,
+        };
+        let translator = match &self.text_translator {
+            Some(translator) => translator,
+            None => return None,
+        };
+        let key_event: &xcb::KeyPressEvent = unsafe { transmute(e) };
+        return vxresult!(translator.lock()).key(key_event.detail, pressed);
+    }
+
     pub fn get_window_aspect_ratio(&self) -> f32 {
         self.window_aspect_ratio
     }
@@ -318,6 +493,23 @@ impl Application {
     }
 }
 
+fn translate_modifiers(state: u16) -> Modifiers {
+    let mut modifiers = Modifiers::empty();
+    if state & xproto::MOD_MASK_SHIFT != 0 {
+        modifiers.insert(Modifiers::SHIFT);
+    }
+    if state & xproto::MOD_MASK_CONTROL != 0 {
+        modifiers.insert(Modifiers::CONTROL);
+    }
+    if state & xproto::MOD_MASK_1 != 0 {
+        modifiers.insert(Modifiers::ALT);
+    }
+    if state & xproto::MOD_MASK_4 != 0 {
+        modifiers.insert(Modifiers::SUPER);
+    }
+    return modifiers;
+}
+
 fn get_mouse_position(
     connection: *mut xcb::Connection,
     window: xcb::Window,
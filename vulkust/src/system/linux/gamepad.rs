@@ -0,0 +1,626 @@
+use super::super::super::core::event::{
+    Button, ButtonAction, Gamepad, GamepadAxis, GamepadButton, Modifiers, Type as EventType,
+};
+use super::super::super::core::object::create_id;
+use super::super::super::core::types::{Id, Real};
+use super::super::super::libc;
+use std::collections::BTreeMap;
+use std::ffi::CString;
+use std::fs::read_dir;
+use std::mem::size_of;
+use std::os::raw::{c_int, c_ulong, c_void};
+use std::time::{Duration, Instant};
+
+// Linux input event codes
+const EV_SYN: u16 = 0x00;
+const EV_KEY: u16 = 0x01;
+const EV_ABS: u16 = 0x03;
+const EV_FF: u16 = 0x15;
+const SYN_DROPPED: u16 = 3;
+const BTN_GAMEPAD: u16 = 0x130;
+const ABS_X: u16 = 0x00;
+const ABS_Y: u16 = 0x01;
+const ABS_Z: u16 = 0x02;
+const ABS_RX: u16 = 0x03;
+const ABS_RY: u16 = 0x04;
+const ABS_RZ: u16 = 0x05;
+const ABS_HAT0X: u16 = 0x10;
+const ABS_HAT0Y: u16 = 0x11;
+const FF_RUMBLE: u16 = 0x50;
+const KEY_MAX: usize = 0x2ff;
+const ABS_MAX: usize = 0x3f;
+const FF_MAX: usize = 0x7f;
+
+const BUTTONS: [(u16, GamepadButton); 19] = [
+    (0x130, GamepadButton::South),
+    (0x131, GamepadButton::East),
+    (0x133, GamepadButton::North),
+    (0x134, GamepadButton::West),
+    (0x136, GamepadButton::LeftBumper),
+    (0x137, GamepadButton::RightBumper),
+    (0x138, GamepadButton::LeftTrigger),
+    (0x139, GamepadButton::RightTrigger),
+    (0x13a, GamepadButton::Select),
+    (0x13b, GamepadButton::Start),
+    (0x13c, GamepadButton::Mode),
+    (0x13d, GamepadButton::LeftThumb),
+    (0x13e, GamepadButton::RightThumb),
+    (0x220, GamepadButton::DpadUp),
+    (0x221, GamepadButton::DpadDown),
+    (0x222, GamepadButton::DpadLeft),
+    (0x223, GamepadButton::DpadRight),
+    // some drivers report the face buttons with their old names
+    (0x132, GamepadButton::South),
+    (0x135, GamepadButton::East),
+];
+
+const AXES: [(u16, GamepadAxis); 6] = [
+    (ABS_X, GamepadAxis::LeftX),
+    (ABS_Y, GamepadAxis::LeftY),
+    (ABS_RX, GamepadAxis::RightX),
+    (ABS_RY, GamepadAxis::RightY),
+    (ABS_Z, GamepadAxis::LeftTrigger),
+    (ABS_RZ, GamepadAxis::RightTrigger),
+];
+
+const DEFAULT_STICK_DEAD_ZONE: Real = 0.15;
+const DEFAULT_TRIGGER_DEAD_ZONE: Real = 0.05;
+// Devices are rescanned with this interval for hot-plugging
+const SCAN_INTERVAL: Duration = Duration::from_secs(2);
+const DEVICES_DIRECTORY: &str = "/dev/input";
+
+// Record of evdev without its time
+#[derive(Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(debug_mode, derive(Debug))]
+pub struct InputEvent {
+    pub event_type: u16,
+    pub code: u16,
+    pub value: i32,
+}
+
+// Size of struct input_event, it starts with a timeval
+pub fn get_input_event_size() -> usize {
+    return size_of::<libc::timeval>() + 8;
+}
+
+// Parses the records of an evdev stream, an incomplete record in the end is ignored
+pub fn parse_input_events(data: &[u8]) -> Vec<InputEvent> {
+    let size = get_input_event_size();
+    let time_size = size - 8;
+    let mut events = Vec::with_capacity(data.len() / size);
+    for r in data.chunks(size) {
+        if r.len() < size {
+            break;
+        }
+        let r = &r[time_size..];
+        events.push(InputEvent {
+            event_type: u16::from_ne_bytes([r[0], r[1]]),
+            code: u16::from_ne_bytes([r[2], r[3]]),
+            value: i32::from_ne_bytes([r[4], r[5], r[6], r[7]]),
+        });
+    }
+    return events;
+}
+
+// Range of an absolute axis like struct input_absinfo
+#[derive(Clone, Copy)]
+#[cfg_attr(debug_mode, derive(Debug))]
+pub struct AbsInfo {
+    pub minimum: i32,
+    pub maximum: i32,
+    pub flat: i32,
+}
+
+// Translates the evdev records of a device to events, it does not touch the device,
+// so it is usable with recorded streams.
+#[cfg_attr(debug_mode, derive(Debug))]
+pub struct Translator {
+    id: Id,
+    abs_infos: BTreeMap<u16, AbsInfo>,
+    // Dead zones are in normalized values, the flat of device is used when it is bigger
+    pub stick_dead_zone: Real,
+    pub trigger_dead_zone: Real,
+    hat: (i32, i32),
+    axes: BTreeMap<u16, Real>,
+}
+
+impl Translator {
+    pub fn new(id: Id, abs_infos: BTreeMap<u16, AbsInfo>) -> Self {
+        Translator {
+            id,
+            abs_infos,
+            stick_dead_zone: DEFAULT_STICK_DEAD_ZONE,
+            trigger_dead_zone: DEFAULT_TRIGGER_DEAD_ZONE,
+            hat: (0, 0),
+            axes: BTreeMap::new(),
+        }
+    }
+
+    pub fn get_id(&self) -> Id {
+        return self.id;
+    }
+
+    pub fn translate(&mut self, e: &InputEvent, events: &mut Vec<EventType>) {
+        match e.event_type {
+            EV_KEY => {
+                for &(code, button) in BUTTONS.iter() {
+                    if code == e.code {
+                        self.push_button(button, e.value != 0, events);
+                        return;
+                    }
+                }
+            }
+            EV_ABS => match e.code {
+                ABS_HAT0X => {
+                    let hat = (e.value.signum(), self.hat.1);
+                    self.set_hat(hat, events);
+                }
+                ABS_HAT0Y => {
+                    let hat = (self.hat.0, e.value.signum());
+                    self.set_hat(hat, events);
+                }
+                _ => {
+                    for &(code, axis) in AXES.iter() {
+                        if code == e.code {
+                            self.push_axis(code, axis, e.value, events);
+                            return;
+                        }
+                    }
+                }
+            },
+            _ => (),
+        }
+    }
+
+    fn push_button(&self, button: GamepadButton, pressed: bool, events: &mut Vec<EventType>) {
+        events.push(EventType::Button {
+            button: Button::Gamepad {
+                id: self.id,
+                button,
+            },
+            action: if pressed {
+                ButtonAction::Press
+            } else {
+                ButtonAction::Release
+            },
+            modifiers: Modifiers::empty(),
+            is_repeat: false,
+        });
+    }
+
+    // Hat of d-pad is reported as buttons
+    fn set_hat(&mut self, hat: (i32, i32), events: &mut Vec<EventType>) {
+        let buttons = [
+            (self.hat.0 < 0, hat.0 < 0, GamepadButton::DpadLeft),
+            (self.hat.0 > 0, hat.0 > 0, GamepadButton::DpadRight),
+            (self.hat.1 < 0, hat.1 < 0, GamepadButton::DpadUp),
+            (self.hat.1 > 0, hat.1 > 0, GamepadButton::DpadDown),
+        ];
+        for &(was, is, button) in buttons.iter() {
+            if was != is {
+                self.push_button(button, is, events);
+            }
+        }
+        self.hat = hat;
+    }
+
+    fn push_axis(&mut self, code: u16, axis: GamepadAxis, value: i32, events: &mut Vec<EventType>) {
+        let info = match self.abs_infos.get(&code) {
+            Some(info) => *info,
+            None => return,
+        };
+        let range = (info.maximum - info.minimum) as Real;
+        if range <= 0.0 {
+            return;
+        }
+        let flat = info.flat as Real / range;
+        let value = match axis {
+            GamepadAxis::LeftTrigger | GamepadAxis::RightTrigger => apply_dead_zone(
+                (value - info.minimum) as Real / range,
+                self.trigger_dead_zone.max(flat),
+            ),
+            _ => apply_dead_zone(
+                (value - info.minimum) as Real * 2.0 / range - 1.0,
+                self.stick_dead_zone.max(flat * 2.0),
+            ),
+        };
+        if self.axes.get(&code) == Some(&value) {
+            return;
+        }
+        self.axes.insert(code, value);
+        events.push(EventType::Gamepad(Gamepad::Axis {
+            id: self.id,
+            axis,
+            value,
+        }));
+    }
+}
+
+// Values inside the dead zone become zero and the rest is rescaled to keep the range
+pub fn apply_dead_zone(value: Real, dead_zone: Real) -> Real {
+    let value = value.max(-1.0).min(1.0);
+    if value.abs() <= dead_zone {
+        return 0.0;
+    }
+    return value.signum() * (value.abs() - dead_zone) / (1.0 - dead_zone);
+}
+
+// Linux ioctl request numbers of evdev
+fn ioc(direction: c_ulong, number: c_ulong, size: usize) -> c_ulong {
+    return (direction << 30) | ((size as c_ulong) << 16) | (('E' as c_ulong) << 8) | number;
+}
+
+const IOC_WRITE: c_ulong = 1;
+const IOC_READ: c_ulong = 2;
+
+fn eviocgname(size: usize) -> c_ulong {
+    return ioc(IOC_READ, 0x06, size);
+}
+
+fn eviocgbit(event_type: u16, size: usize) -> c_ulong {
+    return ioc(IOC_READ, 0x20 + event_type as c_ulong, size);
+}
+
+fn eviocgabs(code: u16) -> c_ulong {
+    return ioc(IOC_READ, 0x40 + code as c_ulong, size_of::<[i32; 6]>());
+}
+
+// struct ff_effect has a union that contains a pointer
+fn get_ff_effect_size() -> usize {
+    return 16 + 24 + size_of::<usize>();
+}
+
+fn eviocsff() -> c_ulong {
+    return ioc(IOC_WRITE, 0x80, get_ff_effect_size());
+}
+
+fn has_bit(bits: &[u8], bit: usize) -> bool {
+    return bits[bit / 8] & (1 << (bit % 8)) != 0;
+}
+
+#[cfg_attr(debug_mode, derive(Debug))]
+struct Device {
+    fd: c_int,
+    translator: Translator,
+    can_rumble: bool,
+    rumble_effect: i16,
+}
+
+impl Device {
+    // Returns none when file is not a gamepad or it is not accessible
+    fn open(path: &str) -> Option<(Self, String)> {
+        let cpath = vxresult!(CString::new(path));
+        let mut fd = unsafe { libc::open(cpath.as_ptr(), libc::O_RDWR | libc::O_NONBLOCK) };
+        if fd < 0 {
+            // rumble needs writing, but reading is enough for the rest
+            fd = unsafe { libc::open(cpath.as_ptr(), libc::O_RDONLY | libc::O_NONBLOCK) };
+        }
+        if fd < 0 {
+            return None;
+        }
+        let mut keys = [0u8; KEY_MAX / 8 + 1];
+        if !Self::get_bits(fd, EV_KEY, &mut keys) || !has_bit(&keys, BTN_GAMEPAD as usize) {
+            unsafe {
+                libc::close(fd);
+            }
+            return None;
+        }
+        let mut abs_bits = [0u8; ABS_MAX / 8 + 1];
+        Self::get_bits(fd, EV_ABS, &mut abs_bits);
+        let mut abs_infos = BTreeMap::new();
+        for &(code, _) in AXES.iter() {
+            if !has_bit(&abs_bits, code as usize) {
+                continue;
+            }
+            // value, minimum, maximum, fuzz, flat, resolution
+            let mut info = [0i32; 6];
+            let r =
+                unsafe { libc::ioctl(fd, eviocgabs(code) as _, info.as_mut_ptr() as *mut c_void) };
+            if r >= 0 {
+                abs_infos.insert(
+                    code,
+                    AbsInfo {
+                        minimum: info[1],
+                        maximum: info[2],
+                        flat: info[4],
+                    },
+                );
+            }
+        }
+        let mut ff_bits = [0u8; FF_MAX / 8 + 1];
+        let can_rumble =
+            Self::get_bits(fd, EV_FF, &mut ff_bits) && has_bit(&ff_bits, FF_RUMBLE as usize);
+        let mut name = [0u8; 256];
+        let r = unsafe {
+            libc::ioctl(
+                fd,
+                eviocgname(name.len()) as _,
+                name.as_mut_ptr() as *mut c_void,
+            )
+        };
+        let name = if r > 0 {
+            let end = name.iter().position(|c| *c == 0).unwrap_or(name.len());
+            String::from_utf8_lossy(&name[..end]).to_string()
+        } else {
+            path.to_string()
+        };
+        let device = Device {
+            fd,
+            translator: Translator::new(create_id(), abs_infos),
+            can_rumble,
+            rumble_effect: -1,
+        };
+        return Some((device, name));
+    }
+
+    fn get_bits(fd: c_int, event_type: u16, bits: &mut [u8]) -> bool {
+        let r = unsafe {
+            libc::ioctl(
+                fd,
+                eviocgbit(event_type, bits.len()) as _,
+                bits.as_mut_ptr() as *mut c_void,
+            )
+        };
+        return r >= 0;
+    }
+
+    // Returns false when device is removed
+    fn read(&mut self, buffer: &mut Vec<u8>, events: &mut Vec<EventType>) -> bool {
+        let size = get_input_event_size();
+        buffer.resize(size * 64, 0);
+        loop {
+            let r =
+                unsafe { libc::read(self.fd, buffer.as_mut_ptr() as *mut c_void, buffer.len()) };
+            if r < 0 {
+                let error = vxunwrap!(std::io::Error::last_os_error().raw_os_error());
+                return error == libc::EAGAIN || error == libc::EINTR;
+            }
+            if r == 0 {
+                return false;
+            }
+            for e in parse_input_events(&buffer[..r as usize]) {
+                if e.event_type == EV_SYN && e.code == SYN_DROPPED {
+                    // kernel buffer is overrun, next values will correct the state
+                    continue;
+                }
+                self.translator.translate(&e, events);
+            }
+        }
+    }
+
+    // Magnitudes are between 0 and 1
+    fn rumble(&mut self, strong: Real, weak: Real, duration: Duration) {
+        if !self.can_rumble {
+            return;
+        }
+        let mut effect = vec![0u8; get_ff_effect_size()];
+        let magnitude = |m: Real| (m.max(0.0).min(1.0) * 65535.0) as u16;
+        let length = duration.as_millis().min(0x7fff) as u16;
+        effect[0..2].copy_from_slice(&FF_RUMBLE.to_ne_bytes());
+        effect[2..4].copy_from_slice(&self.rumble_effect.to_ne_bytes());
+        effect[10..12].copy_from_slice(&length.to_ne_bytes());
+        effect[16..18].copy_from_slice(&magnitude(strong).to_ne_bytes());
+        effect[18..20].copy_from_slice(&magnitude(weak).to_ne_bytes());
+        let r =
+            unsafe { libc::ioctl(self.fd, eviocsff() as _, effect.as_mut_ptr() as *mut c_void) };
+        if r < 0 {
+            vxlogi!("Gamepad does not accept rumble effect.");
+            self.can_rumble = false;
+            return;
+        }
+        self.rumble_effect = i16::from_ne_bytes([effect[2], effect[3]]);
+        let size = get_input_event_size();
+        let mut play = vec![0u8; size];
+        let time_size = size - 8;
+        play[time_size..time_size + 2].copy_from_slice(&EV_FF.to_ne_bytes());
+        play[time_size + 2..time_size + 4]
+            .copy_from_slice(&(self.rumble_effect as u16).to_ne_bytes());
+        play[time_size + 4..].copy_from_slice(&1i32.to_ne_bytes());
+        unsafe {
+            libc::write(self.fd, play.as_ptr() as *const c_void, size);
+        }
+    }
+}
+
+impl Drop for Device {
+    fn drop(&mut self) {
+        unsafe {
+            libc::close(self.fd);
+        }
+    }
+}
+
+// Finds the gamepads in evdev devices and reports their connections and disconnections
+#[cfg_attr(debug_mode, derive(Debug))]
+pub struct Manager {
+    devices: BTreeMap<String, Device>,
+    last_scan: Option<Instant>,
+    buffer: Vec<u8>,
+    stick_dead_zone: Real,
+    trigger_dead_zone: Real,
+}
+
+impl Manager {
+    pub fn new() -> Self {
+        Manager {
+            devices: BTreeMap::new(),
+            last_scan: None,
+            buffer: Vec::new(),
+            stick_dead_zone: DEFAULT_STICK_DEAD_ZONE,
+            trigger_dead_zone: DEFAULT_TRIGGER_DEAD_ZONE,
+        }
+    }
+
+    fn scan(&mut self, events: &mut Vec<EventType>) {
+        let entries = match read_dir(DEVICES_DIRECTORY) {
+            Ok(entries) => entries,
+            Err(_) => return,
+        };
+        for entry in entries {
+            let entry = match entry {
+                Ok(entry) => entry,
+                Err(_) => continue,
+            };
+            let file_name = entry.file_name().to_string_lossy().to_string();
+            if !file_name.starts_with("event") {
+                continue;
+            }
+            let path = format!("{}/{}", DEVICES_DIRECTORY, file_name);
+            if self.devices.contains_key(&path) {
+                continue;
+            }
+            if let Some((mut device, name)) = Device::open(&path) {
+                device.translator.stick_dead_zone = self.stick_dead_zone;
+                device.translator.trigger_dead_zone = self.trigger_dead_zone;
+                events.push(EventType::Gamepad(Gamepad::Connected {
+                    id: device.translator.get_id(),
+                    name,
+                }));
+                self.devices.insert(path, device);
+            }
+        }
+    }
+
+    pub fn fetch_events(&mut self) -> Vec<EventType> {
+        let mut events = Vec::new();
+        let now = Instant::now();
+        let scan_time = match self.last_scan {
+            Some(t) => now.duration_since(t) >= SCAN_INTERVAL,
+            None => true,
+        };
+        if scan_time {
+            self.last_scan = Some(now);
+            self.scan(&mut events);
+        }
+        let mut removed = Vec::new();
+        for (path, device) in &mut self.devices {
+            if !device.read(&mut self.buffer, &mut events) {
+                removed.push(path.clone());
+            }
+        }
+        for path in removed {
+            let device = vxunwrap!(self.devices.remove(&path));
+            events.push(EventType::Gamepad(Gamepad::Disconnected {
+                id: device.translator.get_id(),
+            }));
+        }
+        return events;
+    }
+
+    // Magnitudes are between 0 and 1, it does nothing for the gamepads that can not rumble
+    pub fn rumble(&mut self, id: Id, strong: Real, weak: Real, duration: Duration) {
+        for (_, device) in &mut self.devices {
+            if device.translator.get_id() == id {
+                device.rumble(strong, weak, duration);
+            }
+        }
+    }
+
+    // Dead zones are in normalized values
+    pub fn set_dead_zones(&mut self, stick: Real, trigger: Real) {
+        self.stick_dead_zone = stick;
+        self.trigger_dead_zone = trigger;
+        for (_, device) in &mut self.devices {
+            device.translator.stick_dead_zone = stick;
+            device.translator.trigger_dead_zone = trigger;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn record(event_type: u16, code: u16, value: i32) -> Vec<u8> {
+        let mut r = vec![0u8; get_input_event_size() - 8];
+        r.extend_from_slice(&event_type.to_ne_bytes());
+        r.extend_from_slice(&code.to_ne_bytes());
+        r.extend_from_slice(&value.to_ne_bytes());
+        return r;
+    }
+
+    fn translate(stream: &[u8]) -> Vec<EventType> {
+        let mut abs_infos = BTreeMap::new();
+        let stick = AbsInfo {
+            minimum: -32768,
+            maximum: 32767,
+            flat: 128,
+        };
+        abs_infos.insert(ABS_X, stick);
+        abs_infos.insert(
+            ABS_Z,
+            AbsInfo {
+                minimum: 0,
+                maximum: 255,
+                flat: 0,
+            },
+        );
+        let mut translator = Translator::new(7, abs_infos);
+        translator.stick_dead_zone = 0.2;
+        let mut events = Vec::new();
+        for e in parse_input_events(stream) {
+            translator.translate(&e, &mut events);
+        }
+        return events;
+    }
+
+    #[test]
+    fn stream_test() {
+        let mut stream = Vec::new();
+        stream.extend(record(EV_KEY, 0x130, 1));
+        stream.extend(record(EV_SYN, 0, 0));
+        stream.extend(record(EV_KEY, 0x130, 0));
+        stream.extend(record(EV_ABS, ABS_X, 32767));
+        stream.extend(record(EV_ABS, ABS_X, 32767));
+        stream.extend(record(EV_ABS, ABS_X, 1000));
+        stream.extend(record(EV_ABS, ABS_Z, 255));
+        stream.extend(record(EV_ABS, ABS_HAT0X, -1));
+        stream.extend(record(EV_ABS, ABS_HAT0X, 1));
+        // not a gamepad code and an incomplete record
+        stream.extend(record(EV_KEY, 0x2, 1));
+        stream.extend(&[1, 2, 3]);
+        let events = translate(&stream);
+        assert_eq!(events.len(), 8);
+        let button = |e: &EventType| match e {
+            &EventType::Button {
+                button: Button::Gamepad { id, button },
+                ref action,
+                ..
+            } => {
+                assert_eq!(id, 7);
+                (
+                    button,
+                    match action {
+                        &ButtonAction::Press => true,
+                        &ButtonAction::Release => false,
+                    },
+                )
+            }
+            _ => vxunexpected!(),
+        };
+        let axis = |e: &EventType| match e {
+            &EventType::Gamepad(Gamepad::Axis { axis, value, .. }) => (axis, value),
+            _ => vxunexpected!(),
+        };
+        assert!(button(&events[0]) == (GamepadButton::South, true));
+        assert!(button(&events[1]) == (GamepadButton::South, false));
+        let (a, v) = axis(&events[2]);
+        assert!(a == GamepadAxis::LeftX && (v - 1.0).abs() < 1e-4);
+        // repeated value is skipped and a small one is in the dead zone
+        let (_, v) = axis(&events[3]);
+        assert_eq!(v, 0.0);
+        let (a, v) = axis(&events[4]);
+        assert!(a == GamepadAxis::LeftTrigger && v == 1.0);
+        assert!(button(&events[5]) == (GamepadButton::DpadLeft, true));
+        assert!(button(&events[6]) == (GamepadButton::DpadLeft, false));
+        assert!(button(&events[7]) == (GamepadButton::DpadRight, true));
+    }
+
+    #[test]
+    fn dead_zone_test() {
+        assert_eq!(apply_dead_zone(0.1, 0.2), 0.0);
+        assert!((apply_dead_zone(0.6, 0.2) - 0.5).abs() < 1e-6);
+        assert!((apply_dead_zone(-0.6, 0.2) + 0.5).abs() < 1e-6);
+        assert_eq!(apply_dead_zone(-2.0, 0.2), -1.0);
+    }
+}
@@ -1,3 +1,6 @@
+pub mod alsa;
 pub mod application;
+pub mod gamepad;
 pub mod xcb;
+pub mod xkb;
 pub mod xproto;
@@ -136,18 +136,6 @@ pub(super) enum AtomEnum {
     _WmTransientFor = 68,
 }
 
-#[repr(u32)]
-#[derive(Copy, Clone)]
-#[cfg_attr(debug_mode, derive(Debug))]
-pub(super) enum ButtonIndex {
-    _IndexAny = 0,
-    _Index1 = 1,
-    _Index2 = 2,
-    _Index3 = 3,
-    _Index4 = 4,
-    _Index5 = 5,
-}
-
 #[derive(Copy, Clone)]
 #[cfg_attr(debug_mode, derive(Debug))]
 pub(crate) enum Connection {}
@@ -161,6 +149,7 @@ pub(super) type Button = u8;
 pub(super) type TimeStamp = u32;
 pub(super) type ButtonReleaseEvent = ButtonPressEvent;
 pub(super) type KeyReleaseEvent = KeyPressEvent;
+pub(super) type MotionNotifyEvent = ButtonPressEvent;
 
 #[repr(C)]
 #[derive(Copy, Clone)]
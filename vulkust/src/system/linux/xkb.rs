@@ -0,0 +1,594 @@
+use super::super::super::core::event::Keyboard;
+use super::super::super::libc;
+use super::xcb::{Connection, GenericError, GenericEvent, KeyCode, VoidCookie};
+use std::env;
+use std::ffi::CString;
+use std::mem::transmute;
+use std::os::raw::{c_char, c_int, c_uint, c_void};
+use std::ptr::{null, null_mut};
+
+pub(super) enum Context {}
+pub(super) enum Keymap {}
+pub(super) enum State {}
+pub(super) enum ComposeTable {}
+pub(super) enum ComposeState {}
+
+pub(super) type KeySym = u32;
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+#[cfg_attr(debug_mode, derive(Debug))]
+pub(super) struct PerClientFlagsCookie {
+    pub(super) sequence: c_uint,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+#[cfg_attr(debug_mode, derive(Debug))]
+pub(super) struct PerClientFlagsReply {
+    pub(super) response_type: u8,
+    pub(super) device_id: u8,
+    pub(super) sequence: u16,
+    pub(super) length: u32,
+    pub(super) supported: u32,
+    pub(super) value: u32,
+    pub(super) auto_ctrls: u32,
+    pub(super) auto_ctrls_values: u32,
+    pub(super) pad0: [u8; 8],
+}
+
+// Header of all XKB events, the kind of event is in its xkb_type
+#[repr(C)]
+#[derive(Copy, Clone)]
+#[cfg_attr(debug_mode, derive(Debug))]
+pub(super) struct EventHeader {
+    pub(super) response_type: u8,
+    pub(super) xkb_type: u8,
+    pub(super) sequence: u16,
+    pub(super) time: u32,
+    pub(super) device_id: u8,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+#[cfg_attr(debug_mode, derive(Debug))]
+pub(super) struct StateNotifyEvent {
+    pub(super) response_type: u8,
+    pub(super) xkb_type: u8,
+    pub(super) sequence: u16,
+    pub(super) time: u32,
+    pub(super) device_id: u8,
+    pub(super) mods: u8,
+    pub(super) base_mods: u8,
+    pub(super) latched_mods: u8,
+    pub(super) locked_mods: u8,
+    pub(super) group: u8,
+    pub(super) base_group: i16,
+    pub(super) latched_group: i16,
+    pub(super) locked_group: u8,
+}
+
+const X11_MIN_MAJOR_XKB_VERSION: u16 = 1;
+const X11_MIN_MINOR_XKB_VERSION: u16 = 0;
+const COMPOSE_STATUS_NOTHING: c_int = 0;
+const COMPOSE_STATUS_COMPOSING: c_int = 1;
+const COMPOSE_STATUS_COMPOSED: c_int = 2;
+const TEXT_BUFFER_SIZE: usize = 64;
+const ID_USE_CORE_KBD: u16 = 0x100;
+const PER_CLIENT_FLAG_DETECTABLE_AUTO_REPEAT: u32 = 1;
+const NEW_KEYBOARD_NOTIFY: u8 = 0;
+const MAP_NOTIFY: u8 = 1;
+const STATE_NOTIFY: u8 = 2;
+const EVENT_TYPE_NEW_KEYBOARD_NOTIFY: u16 = 1;
+const EVENT_TYPE_MAP_NOTIFY: u16 = 2;
+const EVENT_TYPE_STATE_NOTIFY: u16 = 4;
+// Key types, key syms, modifier map, explicit components, key actions, key behaviors,
+// virtual modifiers and virtual modifier map
+const MAP_PARTS_OF_KEYMAP: u16 = 0xFF;
+
+#[link(name = "xkbcommon", kind = "dylib")]
+extern "C" {
+    pub(super) fn xkb_context_new(flags: c_int) -> *mut Context;
+    pub(super) fn xkb_context_unref(context: *mut Context);
+    pub(super) fn xkb_keymap_unref(keymap: *mut Keymap);
+    pub(super) fn xkb_state_unref(state: *mut State);
+    pub(super) fn xkb_state_update_mask(
+        state: *mut State,
+        depressed_mods: u32,
+        latched_mods: u32,
+        locked_mods: u32,
+        depressed_layout: u32,
+        latched_layout: u32,
+        locked_layout: u32,
+    ) -> c_int;
+    pub(super) fn xkb_state_key_get_one_sym(state: *mut State, key: u32) -> KeySym;
+    pub(super) fn xkb_keymap_key_get_syms_by_level(
+        keymap: *mut Keymap,
+        key: u32,
+        layout: u32,
+        level: u32,
+        syms_out: *mut *const KeySym,
+    ) -> c_int;
+    pub(super) fn xkb_state_key_get_utf8(
+        state: *mut State,
+        key: u32,
+        buffer: *mut c_char,
+        size: usize,
+    ) -> c_int;
+    pub(super) fn xkb_compose_table_new_from_locale(
+        context: *mut Context,
+        locale: *const c_char,
+        flags: c_int,
+    ) -> *mut ComposeTable;
+    pub(super) fn xkb_compose_table_unref(table: *mut ComposeTable);
+    pub(super) fn xkb_compose_state_new(
+        table: *mut ComposeTable,
+        flags: c_int,
+    ) -> *mut ComposeState;
+    pub(super) fn xkb_compose_state_unref(state: *mut ComposeState);
+    pub(super) fn xkb_compose_state_feed(state: *mut ComposeState, keysym: KeySym) -> c_int;
+    pub(super) fn xkb_compose_state_reset(state: *mut ComposeState);
+    pub(super) fn xkb_compose_state_get_status(state: *mut ComposeState) -> c_int;
+    pub(super) fn xkb_compose_state_get_utf8(
+        state: *mut ComposeState,
+        buffer: *mut c_char,
+        size: usize,
+    ) -> c_int;
+}
+
+#[link(name = "xcb-xkb", kind = "dylib")]
+extern "C" {
+    pub(super) fn xcb_xkb_per_client_flags(
+        connection: *mut Connection,
+        device_spec: u16,
+        change: u32,
+        value: u32,
+        ctrls_to_change: u32,
+        auto_ctrls: u32,
+        auto_ctrls_values: u32,
+    ) -> PerClientFlagsCookie;
+    pub(super) fn xcb_xkb_per_client_flags_reply(
+        connection: *mut Connection,
+        cookie: PerClientFlagsCookie,
+        e: *mut *mut GenericError,
+    ) -> *mut PerClientFlagsReply;
+    pub(super) fn xcb_xkb_select_events(
+        connection: *mut Connection,
+        device_spec: u16,
+        affect_which: u16,
+        clear: u16,
+        select_all: u16,
+        affect_map: u16,
+        map: u16,
+        details: *const c_void,
+    ) -> VoidCookie;
+}
+
+#[link(name = "xkbcommon-x11", kind = "dylib")]
+extern "C" {
+    pub(super) fn xkb_x11_setup_xkb_extension(
+        connection: *mut Connection,
+        major_xkb_version: u16,
+        minor_xkb_version: u16,
+        flags: c_int,
+        major_xkb_version_out: *mut u16,
+        minor_xkb_version_out: *mut u16,
+        base_event_out: *mut u8,
+        base_error_out: *mut u8,
+    ) -> c_int;
+    pub(super) fn xkb_x11_get_core_keyboard_device_id(connection: *mut Connection) -> i32;
+    pub(super) fn xkb_x11_keymap_new_from_device(
+        context: *mut Context,
+        connection: *mut Connection,
+        device_id: i32,
+        flags: c_int,
+    ) -> *mut Keymap;
+    pub(super) fn xkb_x11_state_new_from_device(
+        keymap: *mut Keymap,
+        connection: *mut Connection,
+        device_id: i32,
+    ) -> *mut State;
+}
+
+fn get_locale() -> CString {
+    for name in &["LC_ALL", "LC_CTYPE", "LANG"] {
+        if let Ok(locale) = env::var(name) {
+            if locale.len() > 0 {
+                if let Ok(locale) = CString::new(locale) {
+                    return locale;
+                }
+            }
+        }
+    }
+    return vxresult!(CString::new("C"));
+}
+
+// With it, X server does not send a release before each repeated press of a held key
+fn enable_detectable_auto_repeat(connection: *mut Connection) {
+    let flag = PER_CLIENT_FLAG_DETECTABLE_AUTO_REPEAT;
+    unsafe {
+        let cookie = xcb_xkb_per_client_flags(connection, ID_USE_CORE_KBD, flag, flag, 0, 0, 0);
+        let reply = xcb_xkb_per_client_flags_reply(connection, cookie, null_mut());
+        if reply == null_mut() {
+            vxlogi!("Can not set the per client flags of XKB.");
+            return;
+        }
+        if (*reply).supported & flag == 0 {
+            vxlogi!("Detectable auto repeat is not supported, repeated keys are seen as presses.");
+        }
+        libc::free(reply as *mut libc::c_void);
+    }
+}
+
+// Server keeps the state of modifiers and layouts, and tells about the changes of keyboard
+fn select_events(connection: *mut Connection) {
+    let events = EVENT_TYPE_NEW_KEYBOARD_NOTIFY | EVENT_TYPE_MAP_NOTIFY | EVENT_TYPE_STATE_NOTIFY;
+    unsafe {
+        xcb_xkb_select_events(
+            connection,
+            ID_USE_CORE_KBD,
+            events,
+            0,
+            events,
+            MAP_PARTS_OF_KEYMAP,
+            MAP_PARTS_OF_KEYMAP,
+            null_mut(),
+        );
+    }
+}
+
+// Keys that have both sides or are also on keypad get index 0 for the left or main one
+// and 1 for the right or keypad one. Keypad keys are named by their numbers even when
+// num lock is off.
+pub(super) fn keysym_to_key(sym: KeySym) -> Option<Keyboard> {
+    return Some(match sym {
+        0x20 => Keyboard::Space(0),
+        0x27 => Keyboard::Quotem,
+        0x2c => Keyboard::Comma,
+        0x2d => Keyboard::Minus(0),
+        0x2e => Keyboard::Period(0),
+        0x2f => Keyboard::Slash(0),
+        0x30..=0x39 => Keyboard::Number {
+            number: (sym - 0x30) as u8,
+            padd: false,
+        },
+        0x3b => Keyboard::SemiColon,
+        0x3d => Keyboard::Equal,
+        0x41..=0x5a => return keysym_to_key(sym + 0x20),
+        0x5b => Keyboard::SquareBracketLeft,
+        0x5c => Keyboard::BackSlash(0),
+        0x5d => Keyboard::SquareBracketRight,
+        0x60 => Keyboard::BackQuote,
+        0x61 => Keyboard::A,
+        0x62 => Keyboard::B,
+        0x63 => Keyboard::C,
+        0x64 => Keyboard::D,
+        0x65 => Keyboard::E,
+        0x66 => Keyboard::F,
+        0x67 => Keyboard::G,
+        0x68 => Keyboard::H,
+        0x69 => Keyboard::I,
+        0x6a => Keyboard::J,
+        0x6b => Keyboard::K,
+        0x6c => Keyboard::L,
+        0x6d => Keyboard::M,
+        0x6e => Keyboard::N,
+        0x6f => Keyboard::O,
+        0x70 => Keyboard::P,
+        0x71 => Keyboard::Q,
+        0x72 => Keyboard::R,
+        0x73 => Keyboard::S,
+        0x74 => Keyboard::T,
+        0x75 => Keyboard::U,
+        0x76 => Keyboard::V,
+        0x77 => Keyboard::W,
+        0x78 => Keyboard::X,
+        0x79 => Keyboard::Y,
+        0x7a => Keyboard::Z,
+        // ISO level 3 shift is the right alt of many layouts
+        0xfe03 => Keyboard::Alt(1),
+        0xff08 => Keyboard::Backspace,
+        0xff09 => Keyboard::Tab,
+        0xff0d => Keyboard::Enter(0),
+        0xff13 => Keyboard::PauseBreak,
+        0xff14 => Keyboard::ScrollLock,
+        0xff1b => Keyboard::Escape(0),
+        0xff50 => Keyboard::Home,
+        0xff51 => Keyboard::ArrowLeft,
+        0xff52 => Keyboard::ArrowUp,
+        0xff53 => Keyboard::ArrowRight,
+        0xff54 => Keyboard::ArrowDown,
+        0xff55 => Keyboard::PageUp,
+        0xff56 => Keyboard::PageDown,
+        0xff57 => Keyboard::End,
+        0xff61 => Keyboard::PrintScreen,
+        0xff63 => Keyboard::Insert,
+        0xff67 => Keyboard::Properties(0),
+        0xff7f => Keyboard::NumLock,
+        0xff8d => Keyboard::Enter(1),
+        // Keypad home, left, up, right, down, page up, page down, end, begin and insert
+        0xff95..=0xff9e => Keyboard::Number {
+            number: [7, 4, 8, 6, 2, 9, 3, 1, 5, 0][(sym - 0xff95) as usize],
+            padd: true,
+        },
+        0xff9f | 0xffae => Keyboard::Period(1),
+        0xffaa => Keyboard::Star,
+        0xffab => Keyboard::Plus(1),
+        0xffad => Keyboard::Minus(1),
+        0xffaf => Keyboard::Slash(1),
+        0xffb0..=0xffb9 => Keyboard::Number {
+            number: (sym - 0xffb0) as u8,
+            padd: true,
+        },
+        0xffbe..=0xffe0 => Keyboard::Function((sym - 0xffbe + 1) as u8),
+        0xffe1 => Keyboard::Shift(0),
+        0xffe2 => Keyboard::Shift(1),
+        0xffe3 => Keyboard::Control(0),
+        0xffe4 => Keyboard::Control(1),
+        0xffe5 => Keyboard::CapseLock,
+        0xffe7 | 0xffe9 => Keyboard::Alt(0),
+        0xffe8 | 0xffea => Keyboard::Alt(1),
+        0xffeb => Keyboard::Super(0),
+        0xffec => Keyboard::Super(1),
+        0xffff => Keyboard::Delete,
+        _ => return None,
+    });
+}
+
+// Control characters like backspace and enter are left to the key events
+fn to_text(buffer: &[u8], size: c_int) -> Option<String> {
+    if size <= 0 {
+        return None;
+    }
+    let size = (size as usize).min(buffer.len() - 1);
+    let text = match String::from_utf8(buffer[..size].to_vec()) {
+        Ok(text) => text,
+        Err(_) => return None,
+    };
+    if text.chars().any(|c| c.is_control()) {
+        return None;
+    }
+    return Some(text);
+}
+
+// Translates the key codes of the core keyboard to text with the keymap of X server.
+// The dead keys and compose sequences of the locale are supported, input methods that need a
+// separate window are not.
+#[cfg_attr(debug_mode, derive(Debug))]
+pub(super) struct Translator {
+    connection: *mut Connection,
+    device_id: i32,
+    base_event: u8,
+    context: *mut Context,
+    keymap: *mut Keymap,
+    state: *mut State,
+    compose_table: *mut ComposeTable,
+    compose_state: *mut ComposeState,
+}
+
+impl Translator {
+    pub(super) fn new(connection: *mut Connection) -> Option<Self> {
+        let mut base_event = 0u8;
+        let r = unsafe {
+            xkb_x11_setup_xkb_extension(
+                connection,
+                X11_MIN_MAJOR_XKB_VERSION,
+                X11_MIN_MINOR_XKB_VERSION,
+                0,
+                null_mut(),
+                null_mut(),
+                &mut base_event,
+                null_mut(),
+            )
+        };
+        if r == 0 {
+            vxlogi!("X server does not support XKB, text events are disabled.");
+            return None;
+        }
+        enable_detectable_auto_repeat(connection);
+        let context = unsafe { xkb_context_new(0) };
+        if context == null_mut() {
+            vxlogi!("Can not create XKB context, text events are disabled.");
+            return None;
+        }
+        let mut translator = Translator {
+            connection,
+            device_id: -1,
+            base_event,
+            context,
+            keymap: null_mut(),
+            state: null_mut(),
+            compose_table: null_mut(),
+            compose_state: null_mut(),
+        };
+        if !translator.update_keymap() {
+            vxlogi!("Can not get the keymap of core keyboard, text events are disabled.");
+            return None;
+        }
+        select_events(connection);
+        let locale = get_locale();
+        translator.compose_table =
+            unsafe { xkb_compose_table_new_from_locale(context, locale.as_ptr(), 0) };
+        if translator.compose_table != null_mut() {
+            translator.compose_state =
+                unsafe { xkb_compose_state_new(translator.compose_table, 0) };
+        } else {
+            vxlogi!("There is no compose table for locale {:?}.", locale);
+        }
+        return Some(translator);
+    }
+
+    // Keymap and state are created again when the core keyboard or its mapping is changed
+    fn update_keymap(&mut self) -> bool {
+        let device_id = unsafe { xkb_x11_get_core_keyboard_device_id(self.connection) };
+        if device_id < 0 {
+            return false;
+        }
+        let keymap =
+            unsafe { xkb_x11_keymap_new_from_device(self.context, self.connection, device_id, 0) };
+        if keymap == null_mut() {
+            return false;
+        }
+        let state = unsafe { xkb_x11_state_new_from_device(keymap, self.connection, device_id) };
+        if state == null_mut() {
+            unsafe {
+                xkb_keymap_unref(keymap);
+            }
+            return false;
+        }
+        unsafe {
+            if self.state != null_mut() {
+                xkb_state_unref(self.state);
+            }
+            if self.keymap != null_mut() {
+                xkb_keymap_unref(self.keymap);
+            }
+        }
+        self.device_id = device_id;
+        self.keymap = keymap;
+        self.state = state;
+        return true;
+    }
+
+    // Returns false when it is not an XKB event
+    pub(super) fn event(&mut self, e: *const GenericEvent) -> bool {
+        if unsafe { (*e).response_type & 0x7F } != self.base_event {
+            return false;
+        }
+        let header: &EventHeader = unsafe { transmute(e) };
+        if header.device_id as i32 != self.device_id {
+            return true;
+        }
+        match header.xkb_type {
+            NEW_KEYBOARD_NOTIFY | MAP_NOTIFY => {
+                if !self.update_keymap() {
+                    vxloge!("Can not update the keymap, previous one is kept.");
+                }
+            }
+            STATE_NOTIFY => {
+                let state: &StateNotifyEvent = unsafe { transmute(e) };
+                unsafe {
+                    xkb_state_update_mask(
+                        self.state,
+                        state.base_mods as u32,
+                        state.latched_mods as u32,
+                        state.locked_mods as u32,
+                        state.base_group as u32,
+                        state.latched_group as u32,
+                        state.locked_group as u32,
+                    );
+                }
+            }
+            _ => (),
+        }
+        return true;
+    }
+
+    // Symbol of key in the first layout of keymap without modifiers, so keys keep their names
+    // when layout is switched or modifiers are held. It is zero for keys without a symbol.
+    pub(super) fn keysym(&self, key: KeyCode) -> KeySym {
+        let mut syms: *const KeySym = null();
+        let count =
+            unsafe { xkb_keymap_key_get_syms_by_level(self.keymap, key as u32, 0, 0, &mut syms) };
+        if count < 1 {
+            return 0;
+        }
+        return unsafe { *syms };
+    }
+
+    // Returns the typed text of a pressed key, state of modifiers comes from the XKB events.
+    pub(super) fn key(&mut self, key: KeyCode, pressed: bool) -> Option<String> {
+        if !pressed {
+            return None;
+        }
+        return self.translate(key as u32);
+    }
+
+    fn translate(&mut self, key: u32) -> Option<String> {
+        let mut buffer = [0u8; TEXT_BUFFER_SIZE];
+        if self.compose_state != null_mut() {
+            let status = unsafe {
+                let sym = xkb_state_key_get_one_sym(self.state, key);
+                xkb_compose_state_feed(self.compose_state, sym);
+                xkb_compose_state_get_status(self.compose_state)
+            };
+            match status {
+                COMPOSE_STATUS_NOTHING => (),
+                COMPOSE_STATUS_COMPOSING => return None,
+                COMPOSE_STATUS_COMPOSED => {
+                    let size = unsafe {
+                        let size = xkb_compose_state_get_utf8(
+                            self.compose_state,
+                            buffer.as_mut_ptr() as *mut c_char,
+                            buffer.len(),
+                        );
+                        xkb_compose_state_reset(self.compose_state);
+                        size
+                    };
+                    return to_text(&buffer, size);
+                }
+                _ => {
+                    // sequence is canceled
+                    unsafe {
+                        xkb_compose_state_reset(self.compose_state);
+                    }
+                    return None;
+                }
+            }
+        }
+        let size = unsafe {
+            xkb_state_key_get_utf8(
+                self.state,
+                key,
+                buffer.as_mut_ptr() as *mut c_char,
+                buffer.len(),
+            )
+        };
+        return to_text(&buffer, size);
+    }
+}
+
+impl Drop for Translator {
+    fn drop(&mut self) {
+        unsafe {
+            if self.compose_state != null_mut() {
+                xkb_compose_state_unref(self.compose_state);
+            }
+            if self.compose_table != null_mut() {
+                xkb_compose_table_unref(self.compose_table);
+            }
+            if self.state != null_mut() {
+                xkb_state_unref(self.state);
+            }
+            if self.keymap != null_mut() {
+                xkb_keymap_unref(self.keymap);
+            }
+            xkb_context_unref(self.context);
+        }
+    }
+}
+
+// It alone owns its xkbcommon objects that are not bound to a thread, and xcb is thread safe
+unsafe impl Send for Translator {}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn keysym_to_key_test() {
+        assert!(keysym_to_key(0x20) == Some(Keyboard::Space(0)));
+        assert!(keysym_to_key(0x77) == Some(Keyboard::W));
+        assert!(keysym_to_key(0x57) == Some(Keyboard::W));
+        assert!(keysym_to_key(0xffe2) == Some(Keyboard::Shift(1)));
+        assert!(keysym_to_key(0xffc9) == Some(Keyboard::Function(12)));
+        let pad_seven = Keyboard::Number {
+            number: 7,
+            padd: true,
+        };
+        assert!(keysym_to_key(0xff95) == Some(pad_seven.clone()));
+        assert!(keysym_to_key(0xffb7) == Some(pad_seven));
+        assert!(keysym_to_key(0) == None);
+        assert!(keysym_to_key(0x6c0) == None);
+    }
+}
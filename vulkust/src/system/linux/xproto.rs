@@ -4,6 +4,8 @@ pub(super) const KEY_RELEASE: c_uint = 3;
 pub(super) const BUTTON_PRESS: c_uint = 4;
 pub(super) const BUTTON_RELEASE: c_uint = 5;
 pub(super) const MOTION_NOTIFY: c_uint = 6;
+pub(super) const FOCUS_IN: c_uint = 9;
+pub(super) const FOCUS_OUT: c_uint = 10;
 pub(super) const DESTROY_NOTIFY: c_uint = 17;
 pub(super) const CONFIGURE_NOTIFY: c_uint = 22;
 pub(super) const CLIENT_MESSAGE: c_uint = 33;
@@ -45,6 +47,22 @@ pub(super) const KEY_F1: u8 = 67;
 // pub(super) const KEY_F10: u8 = 68;
 // pub(super) const KEY_F11: u8 = 87;
 // pub(super) const KEY_F12: u8 = 88;
+pub(super) const KEY_SPACE: u8 = 65;
+pub(super) const BUTTON_LEFT: u8 = 1;
+pub(super) const BUTTON_MIDDLE: u8 = 2;
+pub(super) const BUTTON_RIGHT: u8 = 3;
+pub(super) const BUTTON_SCROLL_UP: u8 = 4;
+pub(super) const BUTTON_SCROLL_DOWN: u8 = 5;
+pub(super) const BUTTON_SCROLL_LEFT: u8 = 6;
+pub(super) const BUTTON_SCROLL_RIGHT: u8 = 7;
+pub(super) const BUTTON_BACK: u8 = 8;
+pub(super) const BUTTON_FORWARD: u8 = 9;
+pub(super) const MOD_MASK_SHIFT: u16 = 1;
+pub(super) const MOD_MASK_CONTROL: u16 = 4;
+// Alt in the common keymaps
+pub(super) const MOD_MASK_1: u16 = 8;
+// Super in the common keymaps
+pub(super) const MOD_MASK_4: u16 = 64;
 
 #[repr(u32)]
 #[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
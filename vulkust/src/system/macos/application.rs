@@ -1,4 +1,7 @@
 use super::super::super::core::application::Application as CoreAppTrait;
+use super::super::super::core::dispatcher::Dispatcher as EventDispatcher;
+use super::super::super::core::event::Event;
+use super::super::super::core::gesture::Translator as GestureTranslator;
 use super::super::super::objc::runtime::YES;
 use super::super::super::render::engine::Engine as RenderEngine;
 use super::super::apple;
@@ -9,7 +12,7 @@ use super::game_view_controller;
 use std::fmt;
 use std::mem::transmute;
 use std::os::raw::c_void;
-use std::sync::{Arc, RwLock};
+use std::sync::{Arc, Mutex, RwLock};
 
 pub struct Application {
     app: apple::Id,
@@ -19,6 +22,8 @@ pub struct Application {
     core_app: Option<Arc<RwLock<CoreAppTrait>>>,
     renderer: Option<Arc<RwLock<RenderEngine>>>,
     view: *mut c_void,
+    gesture_translator: Option<Mutex<GestureTranslator>>,
+    event_dispatcher: Arc<EventDispatcher>,
 }
 
 #[cfg(debug_mode)]
@@ -30,6 +35,14 @@ impl fmt::Debug for Application {
 
 impl Application {
     pub fn new(core_app: Arc<RwLock<CoreAppTrait>>) -> Self {
+        let gesture_translator = if vxresult!(core_app.read())
+            .get_config()
+            .get_mouse_touch_emulation()
+        {
+            Some(Mutex::new(GestureTranslator::new_with_mouse_emulation()))
+        } else {
+            None
+        };
         let auto_release_pool = Some(apple::NsAutoReleasePool::new());
         app_delegate::register();
         game_view::register();
@@ -54,6 +67,8 @@ impl Application {
             renderer,
             view,
             _controller,
+            gesture_translator,
+            event_dispatcher: Arc::new(EventDispatcher::new()),
         }
     }
 
@@ -83,10 +98,40 @@ impl Application {
     }
 
     pub fn update(&self) {
+        let core_app = vxunwrap!(self.get_core_app());
+        for e in self.event_dispatcher.fetch_posted() {
+            self.event_dispatcher.send(e, core_app);
+        }
+        if let Some(translator) = &self.gesture_translator {
+            let gestures = vxresult!(translator.lock()).update();
+            for e in gestures {
+                self.event_dispatcher.send(e, core_app);
+            }
+        }
         vxresult!(vxunwrap!(&self.renderer).read()).update();
+        vxresult!(core_app.write()).update();
+        vxresult!(vxunwrap!(&self.renderer).read()).render();
         // vxlogi!("reached");
     }
 
+    // Input events of view controller, touches and gestures that mouse emulates come after
+    // their mouse event
+    pub(crate) fn handle_input_event(&self, e: Event) {
+        let emulated = match &self.gesture_translator {
+            Some(translator) => vxresult!(translator.lock()).receive(&e),
+            None => Vec::new(),
+        };
+        let core_app = vxunwrap!(self.get_core_app());
+        self.event_dispatcher.send(e, core_app);
+        for e in emulated {
+            self.event_dispatcher.send(e, core_app);
+        }
+    }
+
+    pub fn get_event_dispatcher(&self) -> Arc<EventDispatcher> {
+        return self.event_dispatcher.clone();
+    }
+
     pub fn get_window_aspect_ratio(&self) -> f32 {
         let view: apple::Id = unsafe { transmute(self.view) };
         let frame: apple::NSRect = unsafe { msg_send![view, frame] };
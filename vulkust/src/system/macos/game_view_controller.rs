@@ -13,6 +13,13 @@ pub const SUPER_CLASS_NAME: &str = "NSViewController";
 pub const DISPLAY_LINK_VAR_NAME: &str = "display_link";
 pub const APP_VAR_NAME: &str = "os_app";
 pub const APP_DATA_VAR_NAME: &str = "app_data";
+// Flags of NSEventModifierFlags
+const SHIFT_KEY_MASK: apple::NSUInteger = 1 << 17;
+const CONTROL_KEY_MASK: apple::NSUInteger = 1 << 18;
+const OPTION_KEY_MASK: apple::NSUInteger = 1 << 19;
+const COMMAND_KEY_MASK: apple::NSUInteger = 1 << 20;
+// Trackpads report points instead of the notches of wheel
+const PRECISE_SCROLL_POINTS_PER_NOTCH: Real = 10.0;
 
 struct AppData {
     previous_mouse_position_x: Real,
@@ -81,20 +88,19 @@ extern "C" fn key_down(_this: &mut Object, _cmd: Sel, _event: apple::Id) {
 extern "C" fn mouse_down(this: &mut Object, _cmd: Sel, e: apple::Id) {
     vxlogi!("PPPPPPP");
     let et: apple::NSUInteger = unsafe { msg_send![e, type] };
-    let mut e: Option<event::Type> = None;
+    let mut event_type: Option<event::Type> = None;
     if et == apple::app_kit::NSEventType::NS_EVENT_TYPE_LEFT_MOUSE_DOWN.bits() {
-        e = Some(event::Type::Button {
+        event_type = Some(event::Type::Button {
             button: event::Button::Mouse(event::Mouse::Left),
             action: event::ButtonAction::Press,
+            modifiers: get_modifiers(e),
+            is_repeat: false,
         });
     }
     let os_app: *mut c_void = unsafe { *this.get_ivar(APP_VAR_NAME) };
     let os_app: &'static mut Arc<RwLock<OsApp>> = unsafe { transmute(os_app) };
-    let core_app = vxresult!(os_app.read());
-    let core_app = core_app.get_core_app();
-    let core_app = vxresult!(vxunwrap!(core_app).read());
-    if let Some(e) = e {
-        core_app.on_event(event::Event::new(e));
+    if let Some(e) = event_type {
+        vxresult!(os_app.read()).handle_input_event(event::Event::new(e));
     }
 }
 
@@ -102,25 +108,24 @@ extern "C" fn mouse_down(this: &mut Object, _cmd: Sel, e: apple::Id) {
 extern "C" fn mouse_up(this: &mut Object, _cmd: Sel, e: apple::Id) {
     vxlogi!("RRRRRRRR");
     let et: apple::NSUInteger = unsafe { msg_send![e, type] };
-    let mut e: Option<event::Type> = None;
+    let mut event_type: Option<event::Type> = None;
     if et == apple::app_kit::NSEventType::NS_EVENT_TYPE_LEFT_MOUSE_UP.bits() {
-        e = Some(event::Type::Button {
+        event_type = Some(event::Type::Button {
             button: event::Button::Mouse(event::Mouse::Left),
             action: event::ButtonAction::Release,
+            modifiers: get_modifiers(e),
+            is_repeat: false,
         });
     }
     let os_app: *mut c_void = unsafe { *this.get_ivar(APP_VAR_NAME) };
     let os_app: &'static mut Arc<RwLock<OsApp>> = unsafe { transmute(os_app) };
-    let core_app = vxresult!(os_app.read());
-    let core_app = core_app.get_core_app();
-    let core_app = vxresult!(vxunwrap!(core_app).read());
-    if let Some(e) = e {
-        core_app.on_event(event::Event::new(e));
+    if let Some(e) = event_type {
+        vxresult!(os_app.read()).handle_input_event(event::Event::new(e));
     }
 }
 
 // - (void)mouseMoved:(NSEvent *)event
-extern "C" fn mouse_moved(this: &mut Object, _cmd: Sel, _event: apple::Id) {
+extern "C" fn mouse_moved(this: &mut Object, _cmd: Sel, e: apple::Id) {
     let app_data: *mut c_void = unsafe { *this.get_ivar(APP_DATA_VAR_NAME) };
     let app_data: &'static mut AppData = unsafe { transmute(app_data) };
     let mp = get_uniform_mouse_position();
@@ -129,20 +134,20 @@ extern "C" fn mouse_moved(this: &mut Object, _cmd: Sel, _event: apple::Id) {
     }
     let os_app: *mut c_void = unsafe { *this.get_ivar(APP_VAR_NAME) };
     let os_app: &'static mut Arc<RwLock<OsApp>> = unsafe { transmute(os_app) };
-    let core_app = vxresult!(os_app.read());
-    let core_app = core_app.get_core_app();
-    let core_app = vxresult!(vxunwrap!(core_app).read());
-    core_app.on_event(event::Event::new(event::Type::Move(event::Move::Mouse {
-        previous: (
-            app_data.previous_mouse_position_x,
-            app_data.previous_mouse_position_y,
-        ),
-        current: mp,
-        delta: (
-            mp.0 - app_data.previous_mouse_position_x,
-            mp.1 - app_data.previous_mouse_position_y,
-        ),
-    })));
+    vxresult!(os_app.read()).handle_input_event(event::Event::new(event::Type::Move(
+        event::Move::Mouse {
+            previous: (
+                app_data.previous_mouse_position_x,
+                app_data.previous_mouse_position_y,
+            ),
+            current: mp,
+            delta: (
+                mp.0 - app_data.previous_mouse_position_x,
+                mp.1 - app_data.previous_mouse_position_y,
+            ),
+            modifiers: get_modifiers(e),
+        },
+    )));
     app_data.previous_mouse_position_x = mp.0;
     app_data.previous_mouse_position_y = mp.1;
 }
@@ -152,6 +157,50 @@ extern "C" fn mouse_dragged(this: &mut Object, cmd: Sel, e: apple::Id) {
     mouse_moved(this, cmd, e);
 }
 
+// - (void)scrollWheel:(NSEvent *)event
+extern "C" fn scroll_wheel(this: &mut Object, _cmd: Sel, e: apple::Id) {
+    let (x, y, precise): (
+        apple::core_graphics::CGFloat,
+        apple::core_graphics::CGFloat,
+        BOOL,
+    ) = unsafe {
+        (
+            msg_send![e, scrollingDeltaX],
+            msg_send![e, scrollingDeltaY],
+            msg_send![e, hasPreciseScrollingDeltas],
+        )
+    };
+    let mut delta = (-x as Real, y as Real);
+    if precise == YES {
+        delta.0 /= PRECISE_SCROLL_POINTS_PER_NOTCH;
+        delta.1 /= PRECISE_SCROLL_POINTS_PER_NOTCH;
+    }
+    let os_app: *mut c_void = unsafe { *this.get_ivar(APP_VAR_NAME) };
+    let os_app: &'static mut Arc<RwLock<OsApp>> = unsafe { transmute(os_app) };
+    vxresult!(os_app.read()).handle_input_event(event::Event::new(event::Type::Scroll {
+        delta,
+        modifiers: get_modifiers(e),
+    }));
+}
+
+fn get_modifiers(e: apple::Id) -> event::Modifiers {
+    let flags: apple::NSUInteger = unsafe { msg_send![e, modifierFlags] };
+    let mut modifiers = event::Modifiers::empty();
+    if flags & SHIFT_KEY_MASK != 0 {
+        modifiers.insert(event::Modifiers::SHIFT);
+    }
+    if flags & CONTROL_KEY_MASK != 0 {
+        modifiers.insert(event::Modifiers::CONTROL);
+    }
+    if flags & OPTION_KEY_MASK != 0 {
+        modifiers.insert(event::Modifiers::ALT);
+    }
+    if flags & COMMAND_KEY_MASK != 0 {
+        modifiers.insert(event::Modifiers::SUPER);
+    }
+    return modifiers;
+}
+
 // -(BOOL) acceptsFirstResponder { return YES; }
 extern "C" fn accepts_first_responder(_this: &mut Object, _cmd: Sel) -> BOOL {
     vxlogi!("Reached");
@@ -200,6 +249,10 @@ pub fn register() {
             sel!(mouseDragged:),
             mouse_dragged as extern "C" fn(&mut Object, Sel, apple::Id),
         );
+        self_class.add_method(
+            sel!(scrollWheel:),
+            scroll_wheel as extern "C" fn(&mut Object, Sel, apple::Id),
+        );
         self_class.add_method(
             sel!(acceptsFirstResponder),
             accepts_first_responder as extern "C" fn(&mut Object, Sel) -> BOOL,
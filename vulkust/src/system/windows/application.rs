@@ -1,6 +1,9 @@
 use super::super::super::core::application::Application as CoreAppTrait;
 use super::super::super::core::constants;
+use super::super::super::core::dispatcher::Dispatcher as EventDispatcher;
 use super::super::super::core::event;
+use super::super::super::core::gesture::Translator as GestureTranslator;
+use super::super::super::core::replay::Session as ReplaySession;
 use super::super::super::core::string::string_to_cwstring;
 use super::super::super::core::types::Real;
 use super::super::super::render::engine::Engine as RenderEngine;
@@ -9,7 +12,7 @@ use super::super::super::winapi;
 use std::fmt;
 use std::mem::{size_of, transmute, zeroed};
 use std::ptr::{null, null_mut};
-use std::sync::{Arc, RwLock};
+use std::sync::{Arc, Mutex, RwLock};
 
 struct AppData {
     width: Real,
@@ -24,6 +27,9 @@ pub struct Application {
     core_app: Option<Arc<RwLock<CoreAppTrait>>>,
     renderer: Option<Arc<RwLock<RenderEngine>>>,
     data: Arc<RwLock<AppData>>,
+    gesture_translator: Option<Mutex<GestureTranslator>>,
+    replay_session: Mutex<ReplaySession>,
+    event_dispatcher: Arc<EventDispatcher>,
 }
 
 extern "system" fn process_callback(
@@ -58,6 +64,16 @@ extern "system" fn process_callback(
 
 impl Application {
     pub fn new(core_app: Arc<RwLock<CoreAppTrait>>) -> Self {
+        let config = vxresult!(core_app.read()).get_config();
+        let replay_session = ReplaySession::new(
+            config.get_input_record_file_name(),
+            config.get_input_replay_file_name(),
+        );
+        let gesture_translator = if config.get_mouse_touch_emulation() {
+            Some(Mutex::new(GestureTranslator::new_with_mouse_emulation()))
+        } else {
+            None
+        };
         Application {
             instance: 0 as winapi::shared::minwindef::HINSTANCE,
             window: 0 as winapi::shared::windef::HWND,
@@ -69,6 +85,9 @@ impl Application {
                 mouse_x: 0.0,
                 mouse_y: 0.0,
             })),
+            gesture_translator,
+            replay_session: Mutex::new(replay_session),
+            event_dispatcher: Arc::new(EventDispatcher::new()),
         }
     }
 
@@ -235,7 +254,11 @@ impl Application {
     }
 
     pub fn run(&self) -> bool {
+        let core_app = vxunwrap!(&self.core_app);
         loop {
+            for e in self.event_dispatcher.fetch_posted() {
+                self.event_dispatcher.send(e, core_app);
+            }
             let mut msg: winapi::um::winuser::MSG = unsafe { zeroed() };
             while unsafe {
                 winapi::um::winuser::PeekMessageW(
@@ -255,11 +278,58 @@ impl Application {
                     return true;
                 }
             }
+            let replayed = vxresult!(self.replay_session.lock()).fetch_replayed();
+            for e in replayed {
+                if let event::Type::Quit = e.event_type {
+                    return true;
+                }
+                for e in self.emulate_touches(e) {
+                    self.event_dispatcher.send(e, core_app);
+                }
+            }
+            if let Some(translator) = &self.gesture_translator {
+                let gestures = vxresult!(translator.lock()).update();
+                for e in gestures {
+                    self.event_dispatcher.send(e, core_app);
+                }
+            }
+            {
+                let renderer = vxresult!(vxunwrap!(&self.renderer).read());
+                let timing = renderer.get_timing();
+                vxresult!(self.replay_session.lock()).begin_frame(&mut vxresult!(timing.write()));
+                renderer.update();
+                vxresult!(self.replay_session.lock()).end_frame(&vxresult!(timing.read()));
+            }
             vxresult!(vxunwrap!(&self.core_app).write()).update();
-            vxresult!(vxunwrap!(&self.renderer).read()).update();
+            vxresult!(vxunwrap!(&self.renderer).read()).render();
+        }
+    }
+
+    // Live events go through the replay session to be recorded or to be replaced in replay
+    fn handle_live_event(&self, e: event::Event) {
+        let e = vxresult!(self.replay_session.lock()).filter(e);
+        if let Some(e) = e {
+            for e in self.emulate_touches(e) {
+                self.event_dispatcher.send(e, vxunwrap!(&self.core_app));
+            }
         }
     }
 
+    // Touches and gestures that mouse emulates come after their mouse event
+    fn emulate_touches(&self, e: event::Event) -> Vec<event::Event> {
+        let emulated = match &self.gesture_translator {
+            Some(translator) => vxresult!(translator.lock()).receive(&e),
+            None => Vec::new(),
+        };
+        let mut events = vec![e];
+        events.extend(emulated);
+        return events;
+    }
+
+    pub fn get_event_dispatcher(&self) -> Arc<EventDispatcher> {
+        return self.event_dispatcher.clone();
+    }
+
     pub fn get_mouse_position(&self) -> (Real, Real) {
         let mut data = vxresult!(self.data.write());
         let mut p = winapi::shared::windef::POINT { x: 0, y: 0 };
@@ -342,52 +412,95 @@ impl Application {
                     winapi::um::winuser::WM_KEYUP => event::ButtonAction::Release,
                     _ => vxunexpected!(),
                 };
-                let e = event::Event::new(event::Type::Button { button, action });
-                let core_app = vxunwrap!(&self.core_app);
-                let core_app = vxresult!(core_app.read());
-                core_app.on_event(e);
+                // bit 30 is the previous state of key
+                let is_repeat = we == winapi::um::winuser::WM_KEYDOWN && (l_param >> 30) & 1 != 0;
+                let e = event::Event::new(event::Type::Button {
+                    button,
+                    action,
+                    modifiers: get_modifiers(),
+                    is_repeat,
+                });
+                self.handle_live_event(e);
             }
             winapi::um::winuser::WM_RBUTTONDOWN => {
                 let action = event::ButtonAction::Press;
                 let button = event::Button::Mouse(event::Mouse::Right);
-                let e = event::Event::new(event::Type::Button { button, action });
-                vxresult!(vxunwrap!(&self.core_app).read()).on_event(e);
+                let e = event::Event::new(event::Type::Button {
+                    button,
+                    action,
+                    modifiers: get_modifiers(),
+                    is_repeat: false,
+                });
+                self.handle_live_event(e);
             }
             winapi::um::winuser::WM_LBUTTONDOWN => {
                 let action = event::ButtonAction::Press;
                 let button = event::Button::Mouse(event::Mouse::Left);
-                let e = event::Event::new(event::Type::Button { button, action });
-                vxresult!(vxunwrap!(&self.core_app).read()).on_event(e);
+                let e = event::Event::new(event::Type::Button {
+                    button,
+                    action,
+                    modifiers: get_modifiers(),
+                    is_repeat: false,
+                });
+                self.handle_live_event(e);
             }
             winapi::um::winuser::WM_MBUTTONDOWN => {
                 let action = event::ButtonAction::Press;
                 let button = event::Button::Mouse(event::Mouse::Middle);
-                let e = event::Event::new(event::Type::Button { button, action });
-                vxresult!(vxunwrap!(&self.core_app).read()).on_event(e);
+                let e = event::Event::new(event::Type::Button {
+                    button,
+                    action,
+                    modifiers: get_modifiers(),
+                    is_repeat: false,
+                });
+                self.handle_live_event(e);
             }
             winapi::um::winuser::WM_RBUTTONUP => {
                 let action = event::ButtonAction::Release;
                 let button = event::Button::Mouse(event::Mouse::Right);
-                let e = event::Event::new(event::Type::Button { button, action });
-                vxresult!(vxunwrap!(&self.core_app).read()).on_event(e);
+                let e = event::Event::new(event::Type::Button {
+                    button,
+                    action,
+                    modifiers: get_modifiers(),
+                    is_repeat: false,
+                });
+                self.handle_live_event(e);
             }
             winapi::um::winuser::WM_LBUTTONUP => {
                 let action = event::ButtonAction::Release;
                 let button = event::Button::Mouse(event::Mouse::Left);
-                let e = event::Event::new(event::Type::Button { button, action });
-                vxresult!(vxunwrap!(&self.core_app).read()).on_event(e);
+                let e = event::Event::new(event::Type::Button {
+                    button,
+                    action,
+                    modifiers: get_modifiers(),
+                    is_repeat: false,
+                });
+                self.handle_live_event(e);
             }
             winapi::um::winuser::WM_MBUTTONUP => {
                 let action = event::ButtonAction::Release;
                 let button = event::Button::Mouse(event::Mouse::Middle);
-                let e = event::Event::new(event::Type::Button { button, action });
-                vxresult!(vxunwrap!(&self.core_app).read()).on_event(e);
+                let e = event::Event::new(event::Type::Button {
+                    button,
+                    action,
+                    modifiers: get_modifiers(),
+                    is_repeat: false,
+                });
+                self.handle_live_event(e);
             }
-            winapi::um::winuser::WM_MOUSEWHEEL => {
-                //short wheelDelta = GET_WHEEL_DELTA_WPARAM(wParam);
-                //zoom += (float)wheelDelta * 0.005f * zoomSpeed;
-                //camera.translate(glm::vec3(0.0f, 0.0f, (float)wheelDelta * 0.005f * zoomSpeed));
-                //viewUpdated = true;
+            we @ winapi::um::winuser::WM_MOUSEWHEEL | we @ winapi::um::winuser::WM_MOUSEHWHEEL => {
+                let notches = ((w_param >> 16) & 0xFFFF) as u16 as i16 as Real
+                    / winapi::um::winuser::WHEEL_DELTA as Real;
+                let delta = if we == winapi::um::winuser::WM_MOUSEWHEEL {
+                    (0.0, notches)
+                } else {
+                    (notches, 0.0)
+                };
+                let e = event::Event::new(event::Type::Scroll {
+                    delta,
+                    modifiers: get_modifiers(),
+                });
+                self.handle_live_event(e);
             }
             winapi::um::winuser::WM_MOUSEMOVE => {
                 let previous = {
@@ -400,9 +513,10 @@ impl Application {
                     previous,
                     current,
                     delta,
+                    modifiers: get_modifiers(),
                 };
                 let e = event::Event::new(event::Type::Move(m));
-                vxresult!(vxunwrap!(&self.core_app).read()).on_event(e);
+                self.handle_live_event(e);
             }
             winapi::um::winuser::WM_SIZE => {
                 //if ((prepared) && (wParam != SIZE_MINIMIZED))
@@ -442,3 +556,21 @@ impl fmt::Debug for Application {
         write!(f, "OS-Application-Windows")
     }
 }
+
+fn get_modifiers() -> event::Modifiers {
+    let is_down = |key| unsafe { winapi::um::winuser::GetKeyState(key) } < 0;
+    let mut modifiers = event::Modifiers::empty();
+    if is_down(winapi::um::winuser::VK_SHIFT) {
+        modifiers.insert(event::Modifiers::SHIFT);
+    }
+    if is_down(winapi::um::winuser::VK_CONTROL) {
+        modifiers.insert(event::Modifiers::CONTROL);
+    }
+    if is_down(winapi::um::winuser::VK_MENU) {
+        modifiers.insert(event::Modifiers::ALT);
+    }
+    if is_down(winapi::um::winuser::VK_LWIN) || is_down(winapi::um::winuser::VK_RWIN) {
+        modifiers.insert(event::Modifiers::SUPER);
+    }
+    return modifiers;
+}
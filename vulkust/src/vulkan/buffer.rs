@@ -228,9 +228,27 @@ impl Dynamic {
         self.update_with_ptr(unsafe { transmute(data) }, frame_number);
     }
 
+    pub(crate) fn update_with_vec<T>(&mut self, data: &[T], frame_number: usize) {
+        let data_len = data.len() * size_of::<T>();
+        #[cfg(debug_mode)]
+        {
+            if data_len > self.actual_size as usize {
+                vxlogf!("Data is bigger than buffer.");
+            }
+        }
+        let ptr = self.buffers[frame_number].1;
+        unsafe {
+            libc::memcpy(transmute(ptr), transmute(data.as_ptr()), data_len);
+        }
+    }
+
     pub(crate) fn get_buffer(&self, frame_number: usize) -> &Arc<RwLock<Buffer>> {
         return &self.buffers[frame_number].0;
     }
+
+    pub(crate) fn get_size(&self) -> isize {
+        return self.actual_size;
+    }
 }
 
 #[cfg_attr(debug_mode, derive(Debug))]
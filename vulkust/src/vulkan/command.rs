@@ -1,5 +1,6 @@
 use super::super::core::allocate::Object as CoreAllocObj;
-use super::buffer::{Buffer as BufBuffer, Static as StaticBuffer};
+use super::super::render::buffer::IndexType;
+use super::buffer::Buffer as BufBuffer;
 use super::descriptor::Set as DescriptorSet;
 use super::device::Logical as LogicalDevice;
 use super::framebuffer::Framebuffer;
@@ -275,7 +276,11 @@ impl Buffer {
         }
     }
 
-    pub(crate) fn bind_index_buffer(&mut self, buffer: &Arc<RwLock<BufBuffer>>) {
+    pub(crate) fn bind_index_buffer(
+        &mut self,
+        buffer: &Arc<RwLock<BufBuffer>>,
+        index_type: IndexType,
+    ) {
         let buffer = vxresult!(buffer.read());
         let vkbuff = buffer.get_data();
         let offset = buffer.get_allocated_memory().get_offset() as vk::DeviceSize;
@@ -284,7 +289,10 @@ impl Buffer {
                 self.vk_data,
                 vkbuff,
                 offset,
-                vk::IndexType::UINT32,
+                match index_type {
+                    IndexType::U16 => vk::IndexType::UINT16,
+                    IndexType::U32 => vk::IndexType::UINT32,
+                },
             );
         }
     }
@@ -355,9 +363,10 @@ impl Buffer {
 
     pub(crate) fn render_gbuff(
         &mut self,
-        vertex_buffer: &StaticBuffer,
-        index_buffer: &StaticBuffer,
+        vertex_buffer: &Arc<RwLock<BufBuffer>>,
+        index_buffer: &Arc<RwLock<BufBuffer>>,
         indices_count: u32,
+        index_type: IndexType,
     ) {
         self.has_render_record = true;
         unsafe {
@@ -370,8 +379,8 @@ impl Buffer {
                 &self.bound_dynamic_buffer_offsets[..GBUFF_DYNAMIC_BUFFER_OFFSETS_COUNT],
             );
         }
-        self.bind_vertex_buffer(vertex_buffer.get_buffer());
-        self.bind_index_buffer(index_buffer.get_buffer());
+        self.bind_vertex_buffer(vertex_buffer);
+        self.bind_index_buffer(index_buffer, index_type);
         self.draw_index(indices_count);
     }
 
@@ -397,9 +406,10 @@ impl Buffer {
 
     pub(crate) fn render_unlit(
         &mut self,
-        vertex_buffer: &StaticBuffer,
-        index_buffer: &StaticBuffer,
+        vertex_buffer: &Arc<RwLock<BufBuffer>>,
+        index_buffer: &Arc<RwLock<BufBuffer>>,
         indices_count: u32,
+        index_type: IndexType,
     ) {
         self.has_render_record = true;
         unsafe {
@@ -412,8 +422,8 @@ impl Buffer {
                 &self.bound_dynamic_buffer_offsets[..UNLIT_DYNAMIC_BUFFER_OFFSETS_COUNT],
             );
         }
-        self.bind_vertex_buffer(vertex_buffer.get_buffer());
-        self.bind_index_buffer(index_buffer.get_buffer());
+        self.bind_vertex_buffer(vertex_buffer);
+        self.bind_index_buffer(index_buffer, index_type);
         self.draw_index(indices_count);
     }
 
@@ -539,9 +549,10 @@ impl Buffer {
 
     pub(crate) fn render_shadow_mapper(
         &mut self,
-        vertex_buffer: &StaticBuffer,
-        index_buffer: &StaticBuffer,
+        vertex_buffer: &Arc<RwLock<BufBuffer>>,
+        index_buffer: &Arc<RwLock<BufBuffer>>,
         indices_count: u32,
+        index_type: IndexType,
     ) {
         self.has_render_record = true;
         unsafe {
@@ -554,8 +565,8 @@ impl Buffer {
                 &self.bound_dynamic_buffer_offsets[..SHADOW_MAPPER_DESCRIPTOR_SETS_COUNT],
             );
         }
-        self.bind_vertex_buffer(vertex_buffer.get_buffer());
-        self.bind_index_buffer(index_buffer.get_buffer());
+        self.bind_vertex_buffer(vertex_buffer);
+        self.bind_index_buffer(index_buffer, index_type);
         self.draw_index(indices_count);
     }
 }